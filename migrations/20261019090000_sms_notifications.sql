@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS sms_notifications
+(
+    uuid                UUID PRIMARY KEY,
+    issue_uuid          UUID NOT NULL REFERENCES issues_reported (uuid) ON DELETE CASCADE,
+    kind                VARCHAR NOT NULL,
+    recipient           VARCHAR NOT NULL,
+    body                TEXT NOT NULL,
+    gateway             VARCHAR NOT NULL,
+    status              VARCHAR NOT NULL,
+    provider_message_id VARCHAR,
+    error               TEXT,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS sms_notifications_issue_uuid_idx ON sms_notifications (issue_uuid);
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS webhooks
+(
+    uuid          UUID PRIMARY KEY,
+    name          VARCHAR NOT NULL,
+    url           VARCHAR NOT NULL,
+    secret        VARCHAR NOT NULL,
+    events        TEXT[] NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries
+(
+    uuid             UUID PRIMARY KEY,
+    webhook_uuid     UUID NOT NULL REFERENCES webhooks (uuid) ON DELETE CASCADE,
+    event            VARCHAR NOT NULL,
+    payload          TEXT NOT NULL,
+    status           VARCHAR NOT NULL DEFAULT 'pending',
+    attempts         INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_status_code INTEGER,
+    last_error       TEXT,
+    delivered_at     TIMESTAMPTZ,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_webhook_uuid_idx ON webhook_deliveries (webhook_uuid);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
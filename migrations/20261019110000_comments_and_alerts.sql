@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS comments
+(
+    uuid          UUID PRIMARY KEY,
+    issue_uuid    UUID NOT NULL REFERENCES issues_reported (uuid) ON DELETE CASCADE,
+    author        VARCHAR NOT NULL,
+    body          TEXT NOT NULL,
+    is_public     BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS comments_issue_uuid_idx ON comments (issue_uuid);
+
+CREATE TABLE IF NOT EXISTS alert_tickets
+(
+    uuid          UUID PRIMARY KEY,
+    fingerprint   VARCHAR NOT NULL,
+    issue_uuid    UUID NOT NULL REFERENCES issues_reported (uuid) ON DELETE CASCADE,
+    source        VARCHAR NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS alert_tickets_fingerprint_idx ON alert_tickets (fingerprint);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS reporters
+(
+    uuid           UUID PRIMARY KEY,
+    email          VARCHAR NOT NULL UNIQUE,
+    name           VARCHAR NOT NULL,
+    contact_number bigint NOT NULL,
+    company_name   VARCHAR NOT NULL,
+    -- NULL until an invited reporter accepts the invitation
+    password_hash  VARCHAR,
+    -- lets the reporter see every ticket of the company, not only the ones from their number
+    company_access BOOLEAN NOT NULL DEFAULT FALSE,
+    status         INTEGER NOT NULL DEFAULT 0,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at     TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS sessions
+(
+    uuid         UUID PRIMARY KEY,
+    -- sha256 of the random id kept in the cookie, a leaked table cannot be replayed
+    token_hash   VARCHAR NOT NULL UNIQUE,
+    user_uuid    UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    ip_address   VARCHAR,
+    user_agent   VARCHAR,
+    -- "remember me" sessions only end at expires_at, the others also end when idle
+    persistent   BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at   TIMESTAMPTZ NOT NULL,
+    revoked_at   TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_uuid_idx ON sessions (user_uuid);
@@ -0,0 +1,9 @@
+-- 0 agent, 1 supervisor, 2 admin
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role INTEGER NOT NULL DEFAULT 0;
+
+-- existing accounts become agents, only the oldest one is made admin so somebody can hand out roles
+UPDATE users SET role = 2
+WHERE uuid = (SELECT uuid FROM users ORDER BY created_at ASC LIMIT 1);
+
+-- another account, or the first account of an empty install once it exists, is made admin with
+-- UPDATE users SET role = 2 WHERE username = '<username>';
@@ -0,0 +1,2 @@
+-- accounts made before email verification had no way to activate, keep them usable
+UPDATE users SET status = 1 WHERE status = 0;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS password_resets
+(
+    uuid       UUID PRIMARY KEY,
+    user_uuid  UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    -- sha256 of the token sent by email
+    token_hash VARCHAR NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at    TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS password_resets_user_uuid_idx ON password_resets (user_uuid);
@@ -0,0 +1,18 @@
+-- base32 TOTP secret, NULL while two-factor authentication is off
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret VARCHAR;
+-- last time step accepted, a code cannot be used twice
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+-- set by an admin, the user must enrol before getting a session
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_required BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS recovery_codes
+(
+    uuid       UUID PRIMARY KEY,
+    user_uuid  UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    -- sha256 of the code shown once to the user
+    code_hash  VARCHAR NOT NULL,
+    used_at    TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_user_uuid_idx ON recovery_codes (user_uuid);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS passkeys
+(
+    uuid          UUID PRIMARY KEY,
+    user_uuid     UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    -- chosen by the user to tell their authenticators apart
+    name          VARCHAR NOT NULL,
+    -- base64url id the authenticator gives back when signing in
+    credential_id VARCHAR NOT NULL UNIQUE,
+    -- serialized credential: public key, signature counter, ...
+    credential    TEXT NOT NULL,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at  TIMESTAMPTZ,
+    UNIQUE (user_uuid, name)
+);
+
+-- registration or sign in waiting for the authenticator, the cookie only holds the uuid
+CREATE TABLE IF NOT EXISTS passkey_ceremonies
+(
+    uuid       UUID PRIMARY KEY,
+    user_uuid  UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    -- name of the passkey being registered, NULL when signing in
+    name       VARCHAR,
+    -- serialized challenge state
+    state      TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,24 @@
+-- consecutive failed password attempts, back to 0 on a successful login or an admin unlock
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_count INTEGER NOT NULL DEFAULT 0;
+-- password logins are refused until then
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
+
+-- audit trail of every login attempt, kept when the user is deleted
+CREATE TABLE IF NOT EXISTS login_attempts
+(
+    uuid       UUID PRIMARY KEY,
+    -- as typed, the account may not exist
+    username   VARCHAR NOT NULL,
+    user_uuid  UUID REFERENCES users (uuid) ON DELETE SET NULL,
+    ip_address VARCHAR,
+    user_agent VARCHAR,
+    -- password, totp or passkey
+    method     VARCHAR NOT NULL,
+    -- succeeded, or why the attempt failed
+    outcome    VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS login_attempts_username_idx ON login_attempts (username, created_at);
+CREATE INDEX IF NOT EXISTS login_attempts_ip_address_idx ON login_attempts (ip_address, created_at);
+CREATE INDEX IF NOT EXISTS login_attempts_user_uuid_idx ON login_attempts (user_uuid, created_at);
@@ -0,0 +1,2 @@
+-- "sub" claim of the company identity provider, NULL for accounts never used with single sign-on
+ALTER TABLE users ADD COLUMN IF NOT EXISTS oidc_subject VARCHAR UNIQUE;
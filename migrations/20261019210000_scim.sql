@@ -0,0 +1,2 @@
+-- id the provisioning client gave the user, echoed back as the SCIM externalId
+ALTER TABLE users ADD COLUMN IF NOT EXISTS scim_external_id VARCHAR;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS user_invitations
+(
+    uuid        UUID PRIMARY KEY,
+    email       VARCHAR NOT NULL,
+    role        INTEGER NOT NULL DEFAULT 0,
+    invited_by  UUID REFERENCES users (uuid) ON DELETE SET NULL,
+    -- the account created on acceptance
+    user_uuid   UUID REFERENCES users (uuid) ON DELETE SET NULL,
+    expires_at  TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ,
+    revoked_at  TIMESTAMPTZ,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS user_invitations_created_at_idx ON user_invitations (created_at);
@@ -0,0 +1,16 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS status_reason VARCHAR;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS status_changed_at TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS user_status_changes
+(
+    uuid        UUID PRIMARY KEY,
+    user_uuid   UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    from_status INTEGER NOT NULL,
+    to_status   INTEGER NOT NULL,
+    reason      VARCHAR,
+    -- NULL when the change did not come from an admin (activation link, provisioning, ...)
+    changed_by  UUID REFERENCES users (uuid) ON DELETE SET NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS user_status_changes_user_uuid_idx ON user_status_changes (user_uuid, created_at);
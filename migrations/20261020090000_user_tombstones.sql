@@ -0,0 +1,2 @@
+-- set when a user is removed, the row stays so history that points at it keeps its author
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS impersonations
+(
+    uuid              UUID PRIMARY KEY,
+    impersonator_uuid UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    -- whoever is impersonated, an agent or a reporter of the portal
+    user_uuid         UUID REFERENCES users (uuid) ON DELETE CASCADE,
+    reporter_uuid     UUID REFERENCES reporters (uuid) ON DELETE CASCADE,
+    reason            VARCHAR,
+    created_at        TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at        TIMESTAMPTZ NOT NULL,
+    ended_at          TIMESTAMPTZ,
+    CHECK ((user_uuid IS NULL) <> (reporter_uuid IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS impersonations_impersonator_uuid_idx ON impersonations (impersonator_uuid);
+CREATE INDEX IF NOT EXISTS impersonations_user_uuid_idx ON impersonations (user_uuid);
+
+CREATE TABLE IF NOT EXISTS impersonation_events
+(
+    uuid               UUID PRIMARY KEY,
+    impersonation_uuid UUID NOT NULL REFERENCES impersonations (uuid) ON DELETE CASCADE,
+    action             VARCHAR NOT NULL,
+    -- request of a write, NULL for start and end
+    method             VARCHAR,
+    path               VARCHAR,
+    status             INTEGER,
+    created_at         TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS impersonation_events_impersonation_uuid_idx ON impersonation_events (impersonation_uuid, created_at);
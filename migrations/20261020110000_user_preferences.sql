@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    user_uuid   UUID PRIMARY KEY REFERENCES users (uuid) ON DELETE CASCADE,
+    -- IANA name, timestamps are stored in UTC and shown in this timezone
+    timezone    VARCHAR NOT NULL DEFAULT 'UTC',
+    date_format INTEGER NOT NULL DEFAULT 0,
+    language    VARCHAR NOT NULL DEFAULT 'en',
+    ticket_view INTEGER NOT NULL DEFAULT 0,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- one row per event and channel the user opted in to, no row means no notification
+CREATE TABLE IF NOT EXISTS user_notification_settings
+(
+    user_uuid  UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    event      VARCHAR NOT NULL,
+    channel    VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_uuid, event, channel)
+);
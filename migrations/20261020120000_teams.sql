@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS teams
+(
+    uuid        UUID PRIMARY KEY,
+    name        VARCHAR NOT NULL UNIQUE,
+    description VARCHAR,
+    -- hours a ticket of the team queue may stay open before it breaches the SLA
+    sla_hours   INTEGER NOT NULL DEFAULT 24,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS team_members
+(
+    team_uuid  UUID NOT NULL REFERENCES teams (uuid) ON DELETE CASCADE,
+    user_uuid  UUID NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (team_uuid, user_uuid)
+);
+
+CREATE INDEX IF NOT EXISTS team_members_user_uuid_idx ON team_members (user_uuid);
+
+-- NULL keeps the ticket out of every team queue, on the shared open page
+ALTER TABLE issues_reported ADD COLUMN IF NOT EXISTS team_uuid UUID REFERENCES teams (uuid) ON DELETE SET NULL;
+-- resolution times for the SLA dashboards, closed tickets so far are taken as closed at their last update
+ALTER TABLE issues_reported ADD COLUMN IF NOT EXISTS closed_at TIMESTAMPTZ;
+UPDATE issues_reported SET closed_at = updated_at WHERE status = 'closed' AND closed_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS issues_reported_team_uuid_idx ON issues_reported (team_uuid, status);
@@ -0,0 +1,65 @@
+CREATE TABLE IF NOT EXISTS organisations
+(
+    uuid                  UUID PRIMARY KEY,
+    name                  VARCHAR NOT NULL,
+    -- first label of the host the organisation is served on, acme for acme.tickets.example.com
+    subdomain             VARCHAR NOT NULL UNIQUE,
+    -- reporters can sign up to the portal by themselves, otherwise only invited ones get in
+    reporter_registration BOOLEAN NOT NULL DEFAULT TRUE,
+    -- number the next ticket of the organisation gets, each organisation counts from 1
+    next_ticket_number    BIGINT NOT NULL DEFAULT 1,
+    created_at            TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at            TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- a new client company is set up by inserting its row, e.g.
+-- INSERT INTO organisations (uuid, name, subdomain) VALUES (gen_random_uuid(), 'Acme', 'acme');
+-- and its first admin is provisioned through SCIM on acme.<tenancy.base_domain>
+
+-- everything so far belonged to the one company the application was run for
+INSERT INTO organisations (uuid, name, subdomain)
+VALUES ('00000000-0000-0000-0000-000000000001', 'Default', 'default')
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+ALTER TABLE issues_reported ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+ALTER TABLE teams ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+ALTER TABLE reporters ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+ALTER TABLE user_invitations ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+
+UPDATE users SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+UPDATE issues_reported SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+UPDATE teams SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+UPDATE reporters SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+UPDATE webhooks SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+UPDATE user_invitations SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+
+ALTER TABLE users ALTER COLUMN organisation_uuid SET NOT NULL;
+ALTER TABLE issues_reported ALTER COLUMN organisation_uuid SET NOT NULL;
+ALTER TABLE teams ALTER COLUMN organisation_uuid SET NOT NULL;
+ALTER TABLE reporters ALTER COLUMN organisation_uuid SET NOT NULL;
+ALTER TABLE webhooks ALTER COLUMN organisation_uuid SET NOT NULL;
+ALTER TABLE user_invitations ALTER COLUMN organisation_uuid SET NOT NULL;
+
+-- names only have to be unique inside their organisation
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_username_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_key;
+ALTER TABLE users ADD CONSTRAINT users_organisation_username_key UNIQUE (organisation_uuid, username);
+ALTER TABLE users ADD CONSTRAINT users_organisation_email_key UNIQUE (organisation_uuid, email);
+ALTER TABLE teams DROP CONSTRAINT IF EXISTS teams_name_key;
+ALTER TABLE teams ADD CONSTRAINT teams_organisation_name_key UNIQUE (organisation_uuid, name);
+ALTER TABLE reporters DROP CONSTRAINT IF EXISTS reporters_email_key;
+ALTER TABLE reporters ADD CONSTRAINT reporters_organisation_email_key UNIQUE (organisation_uuid, email);
+
+-- ticket numbers can still be edited by agents, so they are indexed but not unique
+CREATE INDEX IF NOT EXISTS issues_reported_organisation_ticket_number_idx ON issues_reported (organisation_uuid, ticket_number);
+CREATE INDEX IF NOT EXISTS issues_reported_organisation_created_at_idx ON issues_reported (organisation_uuid, created_at);
+CREATE INDEX IF NOT EXISTS webhooks_organisation_uuid_idx ON webhooks (organisation_uuid);
+CREATE INDEX IF NOT EXISTS user_invitations_organisation_uuid_idx ON user_invitations (organisation_uuid, created_at);
+
+-- numbering carries on from the tickets already there
+UPDATE organisations SET next_ticket_number = COALESCE(
+    (SELECT MAX(ticket_number) + 1 FROM issues_reported WHERE issues_reported.organisation_uuid = organisations.uuid),
+    1
+);
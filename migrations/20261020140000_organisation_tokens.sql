@@ -0,0 +1,16 @@
+-- bearer tokens of the machine clients of an organisation, the token names the organisation
+CREATE TABLE IF NOT EXISTS organisation_tokens
+(
+    uuid              UUID PRIMARY KEY,
+    organisation_uuid UUID NOT NULL REFERENCES organisations (uuid),
+    -- alerts, sms or scim, the endpoints the token opens
+    scope             VARCHAR NOT NULL,
+    -- sha256 of the token, the token itself is only shown once to the admin creating it
+    token_hash        VARCHAR NOT NULL UNIQUE,
+    created_at        TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (organisation_uuid, scope)
+);
+
+-- the first scim token of a new organisation, to provision its first admin, goes in with its row, e.g.
+-- INSERT INTO organisation_tokens (uuid, organisation_uuid, scope, token_hash)
+-- VALUES (gen_random_uuid(), '<organisation uuid>', 'scim', encode(sha256('<token>'), 'hex'));
@@ -0,0 +1,8 @@
+-- tickets are matched to a reporter by contact number only once the reporter proved they own it
+ALTER TABLE reporters ADD COLUMN IF NOT EXISTS contact_number_verified_at TIMESTAMPTZ;
+-- sha256 of the code last texted to the number, NULL when none is outstanding
+ALTER TABLE reporters ADD COLUMN IF NOT EXISTS verification_code_hash VARCHAR;
+ALTER TABLE reporters ADD COLUMN IF NOT EXISTS verification_expires_at TIMESTAMPTZ;
+
+-- numbers typed in by agents when inviting are taken as verified from now on, the accounts
+-- already there cannot tell how they were created and verify their number from the portal
@@ -0,0 +1,13 @@
+-- the same username can exist in several organisations, failures only count against the one tried
+ALTER TABLE login_attempts ADD COLUMN IF NOT EXISTS organisation_uuid UUID REFERENCES organisations (uuid);
+
+UPDATE login_attempts SET organisation_uuid = users.organisation_uuid
+FROM users
+WHERE login_attempts.organisation_uuid IS NULL AND login_attempts.user_uuid = users.uuid;
+-- attempts at unknown usernames were all made when there was only the default organisation
+UPDATE login_attempts SET organisation_uuid = '00000000-0000-0000-0000-000000000001' WHERE organisation_uuid IS NULL;
+
+ALTER TABLE login_attempts ALTER COLUMN organisation_uuid SET NOT NULL;
+
+DROP INDEX IF EXISTS login_attempts_username_idx;
+CREATE INDEX IF NOT EXISTS login_attempts_organisation_username_idx ON login_attempts (organisation_uuid, username, created_at);
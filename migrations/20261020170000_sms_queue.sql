@@ -0,0 +1,12 @@
+-- text messages are queued by the routes and sent by a background worker, with retries
+-- verification codes and replies about no ticket are queued too, they have no issue
+ALTER TABLE sms_notifications ALTER COLUMN issue_uuid DROP NOT NULL;
+ALTER TABLE sms_notifications ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sms_notifications ADD COLUMN IF NOT EXISTS next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE sms_notifications ADD COLUMN IF NOT EXISTS sent_at TIMESTAMPTZ;
+
+-- everything recorded so far was sent, or failed, at once
+UPDATE sms_notifications SET attempts = 1 WHERE attempts = 0;
+UPDATE sms_notifications SET sent_at = created_at WHERE status = 'sent' AND sent_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS sms_notifications_status_next_attempt_at_idx ON sms_notifications (status, next_attempt_at);
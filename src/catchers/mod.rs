@@ -1,5 +1,9 @@
+use crate::routes::scim::ScimResponse;
+use crate::routes::session::login_url;
+use rocket::http::{Method, Status};
 use rocket::request::Request;
 use rocket::response::content::RawHtml;
+use rocket::response::Redirect;
 
 const ERROR_HTML_PREFIX: &str = r#"<!DOCTYPE html>
 <html lang="en">
@@ -20,6 +24,27 @@ pub fn bad_request(_: &Request) -> RawHtml<String> {
     ))
 }
 
+// portal pages send the reporter to the portal login, the rest to the agent login
+// which brings a page that was simply visited back afterwards
+#[catch(401)]
+pub fn unauthorized(req: &Request) -> Redirect {
+    if req.uri().path().starts_with("/portal") {
+        return Redirect::to("/portal/login");
+    }
+    if req.method() == Method::Get {
+        return Redirect::to(login_url(Some(&req.uri().to_string())));
+    }
+    Redirect::to(login_url(None))
+}
+
+#[catch(403)]
+pub fn forbidden(_: &Request) -> RawHtml<String> {
+    RawHtml(format!(
+        "{}{}{}",
+        ERROR_HTML_PREFIX, "You are not allowed to do that", ERROR_HTML_SUFFIX
+    ))
+}
+
 #[catch(404)]
 pub fn not_found(_: &Request) -> RawHtml<String> {
     RawHtml(format!(
@@ -33,6 +58,14 @@ pub fn unprocessable_entity(r: &Request) -> RawHtml<String> {
     bad_request(r)
 }
 
+#[catch(429)]
+pub fn too_many_requests(_: &Request) -> RawHtml<String> {
+    RawHtml(format!(
+        "{}{}{}",
+        ERROR_HTML_PREFIX, "Too many requests, please try again later", ERROR_HTML_SUFFIX
+    ))
+}
+
 #[catch(500)]
 pub fn internal_server_error(_: &Request) -> RawHtml<String> {
     RawHtml(format!(
@@ -40,3 +73,9 @@ pub fn internal_server_error(_: &Request) -> RawHtml<String> {
         ERROR_HTML_PREFIX, "Something went wrong", ERROR_HTML_SUFFIX
     ))
 }
+
+// provisioning clients get SCIM errors, never the login page or an HTML error
+#[catch(default)]
+pub fn scim(status: Status, _: &Request) -> ScimResponse {
+    ScimResponse::error(status, None, status.reason().unwrap_or("Error"))
+}
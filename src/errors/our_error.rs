@@ -46,6 +46,10 @@ impl OurError {
         Self::new_error_with_status(Status::BadRequest, message, debug)
     }
 
+    pub fn new_unauthorized_error(message: String, debug: Option<Box<dyn Error>>) -> Self {
+        Self::new_error_with_status(Status::Unauthorized, message, debug)
+    }
+
     pub fn new_not_found_error(message: String, debug: Option<Box<dyn Error>>) -> Self {
         Self::new_error_with_status(Status::NotFound, message, debug)
     }
@@ -0,0 +1,88 @@
+use crate::states::alert_mapper::AlertMapper;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+
+const CONFIG_KEY: &str = "alerts";
+
+#[derive(Deserialize)]
+struct AlertsConfig {
+    #[serde(default = "default_title_template")]
+    title_template: String,
+    #[serde(default = "default_description_template")]
+    description_template: String,
+    #[serde(default = "default_reported_by_template")]
+    reported_by_template: String,
+    #[serde(default = "default_company_name_template")]
+    company_name_template: String,
+    #[serde(default)]
+    contact_number: i64,
+}
+
+fn default_title_template() -> String {
+    String::from("{alertname}{title}")
+}
+
+fn default_description_template() -> String {
+    String::from("{summary}{description}")
+}
+
+fn default_reported_by_template() -> String {
+    String::from("Monitoring")
+}
+
+fn default_company_name_template() -> String {
+    String::from("{instance}")
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            title_template: default_title_template(),
+            description_template: default_description_template(),
+            reported_by_template: default_reported_by_template(),
+            company_name_template: default_company_name_template(),
+            contact_number: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Alerts {}
+
+impl Alerts {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Alerts {
+    fn info(&self) -> Info {
+        Info {
+            name: "Alerts Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<AlertsConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid alerts configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            AlertsConfig::default()
+        };
+        Ok(rocket.manage(AlertMapper::new(
+            config.title_template,
+            config.description_template,
+            config.reported_by_template,
+            config.company_name_template,
+            config.contact_number,
+        )))
+    }
+}
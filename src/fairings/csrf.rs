@@ -6,6 +6,7 @@ use argon2::{
     Argon2,
 };
 use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::form::FromForm;
 use rocket::http::{Cookie, Status};
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::Serialize;
@@ -35,7 +36,7 @@ impl RequestCsrf for Request<'_> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Csrf {}
 
 impl Csrf {
@@ -90,6 +91,11 @@ impl<'r> FromRequest<'r> for Token {
 }
 
 impl Token {
+    // for markup not rendered from a template
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+
     pub fn generate_hash(&self) -> Result<String, String> {
         let salt = SaltString::generate(&mut OsRng);
         Argon2::default()
@@ -107,3 +113,9 @@ impl Token {
             .map_err(|_| String::from("cannot verify authenticity token"))?)
     }
 }
+
+// form of the buttons that post nothing but the token, logging out, revoking, deleting...
+#[derive(Debug, FromForm)]
+pub struct CsrfForm<'r> {
+    pub authenticity_token: &'r str,
+}
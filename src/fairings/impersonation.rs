@@ -0,0 +1,127 @@
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::impersonation::ActiveImpersonation;
+use crate::models::clean_html;
+use crate::models::impersonation_event::{ImpersonationEvent, WriteRequest, ACTION_WRITE};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::request::Outcome;
+use rocket::{Request, Response};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use std::io::Cursor;
+
+// Marks every page seen while impersonating and logs every write made on behalf of someone else.
+// Only requests where CurrentUser or CurrentReporter applied an impersonation are touched.
+#[derive(Debug, Clone, Default)]
+pub struct Impersonations {}
+
+impl Impersonations {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Impersonations {
+    fn info(&self) -> Info {
+        Info {
+            name: "Impersonation Fairing",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let active = match ActiveImpersonation::of(req) {
+            Some(active) => active,
+            None => return,
+        };
+        if !matches!(req.method(), Method::Get | Method::Head) {
+            log_write(req, res, active).await;
+        }
+        if res.content_type().map_or(false, |ct| ct.is_html()) {
+            show_banner(req, res, active).await;
+        }
+    }
+}
+
+async fn log_write(req: &Request<'_>, res: &Response<'_>, active: &ActiveImpersonation) {
+    let mut db = match req.guard::<Connection<DBConnection>>().await {
+        Outcome::Success(db) => db,
+        _ => {
+            log::error!(
+                "Cannot log write of impersonation {}",
+                active.impersonation.uuid
+            );
+            return;
+        }
+    };
+    let connection = match db.acquire().await {
+        Ok(connection) => connection,
+        Err(e) => {
+            log::error!(
+                "Cannot log write of impersonation {}: {}",
+                active.impersonation.uuid,
+                e
+            );
+            return;
+        }
+    };
+    let path = req.uri().path().to_string();
+    let request = WriteRequest {
+        method: req.method().as_str(),
+        path: &path,
+        status: res.status().code,
+    };
+    if let Err(e) = ImpersonationEvent::create(
+        connection,
+        &active.impersonation.uuid,
+        ACTION_WRITE,
+        Some(&request),
+    )
+    .await
+    {
+        log::error!(
+            "Cannot log write of impersonation {}: {}",
+            active.impersonation.uuid,
+            e
+        );
+    }
+}
+
+async fn show_banner(req: &Request<'_>, res: &mut Response<'_>, active: &ActiveImpersonation) {
+    let csrf_token = match req.guard::<CsrfToken>().await {
+        Outcome::Success(csrf_token) => csrf_token,
+        _ => {
+            log::error!("Cannot add impersonation banner without a CSRF token");
+            return;
+        }
+    };
+    let body = match res.body_mut().to_string().await {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Cannot add impersonation banner: {}", e);
+            return;
+        }
+    };
+    let banner = format!(
+        r#"<div class="card fluid warning">
+  <p>You are viewing as <strong>{target}</strong> until {expires_at}, changes are logged as made by {impersonator} on their behalf.</p>
+  <form accept-charset="UTF-8" action="/impersonation/end" method="POST">
+    <input type="hidden" name="authenticity_token" value="{authenticity_token}"/>
+    <button type="submit" value="Submit">Stop impersonating</button>
+  </form>
+</div>"#,
+        target = clean_html(&active.target),
+        authenticity_token = csrf_token.value(),
+        impersonator = clean_html(&active.impersonator),
+        expires_at = active.impersonation.expires_at.0.format("%H:%M UTC"),
+    );
+    let body = match body.find("<body>") {
+        Some(index) => {
+            let (head, tail) = body.split_at(index + "<body>".len());
+            format!("{}\n{}{}", head, banner, tail)
+        }
+        None => body,
+    };
+    res.set_sized_body(body.len(), Cursor::new(body));
+}
@@ -0,0 +1,116 @@
+use crate::gateways::log_mail::LogMailer;
+use crate::gateways::smtp_mail::SmtpMailer;
+use crate::states::mail_sender::MailSender;
+use crate::traits::mailer::Mailer;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+
+const CONFIG_KEY: &str = "mail";
+
+#[derive(Deserialize)]
+struct MailConfig {
+    #[serde(default = "default_mailer")]
+    mailer: String,
+    #[serde(default = "default_base_url")]
+    base_url: String,
+    host: Option<String>,
+    #[serde(default = "default_port")]
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default = "default_from")]
+    from: String,
+}
+
+fn default_mailer() -> String {
+    String::from("log")
+}
+
+fn default_base_url() -> String {
+    String::from("http://localhost:8000")
+}
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_from() -> String {
+    String::from("Ticket Desk <noreply@localhost>")
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            mailer: default_mailer(),
+            base_url: default_base_url(),
+            host: None,
+            port: default_port(),
+            username: None,
+            password: None,
+            from: default_from(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mail {}
+
+impl Mail {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Mail {
+    fn info(&self) -> Info {
+        Info {
+            name: "Mail Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<MailConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid mail configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            MailConfig::default()
+        };
+
+        let mailer: Box<dyn Mailer> = match config.mailer.as_str() {
+            "log" => Box::new(LogMailer::new()),
+            "smtp" => {
+                let host = match config.host {
+                    Some(host) => host,
+                    None => {
+                        log::error!("Mailer \"smtp\" needs mail.host");
+                        return Err(rocket);
+                    }
+                };
+                match SmtpMailer::new(
+                    &host,
+                    config.port,
+                    config.username,
+                    config.password,
+                    &config.from,
+                ) {
+                    Ok(mailer) => Box::new(mailer),
+                    Err(_) => return Err(rocket),
+                }
+            }
+            other => {
+                log::error!("Unknown mailer: {}", other);
+                return Err(rocket);
+            }
+        };
+
+        Ok(rocket.manage(MailSender::new(mailer, config.base_url)))
+    }
+}
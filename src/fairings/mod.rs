@@ -1,2 +1,13 @@
+pub mod alerts;
 pub mod csrf;
 pub mod db;
+pub mod impersonation;
+pub mod mail;
+pub mod oidc;
+pub mod passkeys;
+pub mod passwords;
+pub mod sessions;
+pub mod signing;
+pub mod sms;
+pub mod tenancy;
+pub mod webhooks;
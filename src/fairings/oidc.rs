@@ -0,0 +1,131 @@
+use crate::models::user_role::UserRole;
+use crate::states::oidc_client::{OidcClient, OidcSettings};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+use std::collections::HashMap;
+
+const CONFIG_KEY: &str = "oidc";
+
+#[derive(Deserialize)]
+struct OidcConfig {
+    #[serde(default = "default_organisation")]
+    organisation: String,
+    issuer_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: String,
+    #[serde(default = "default_scopes")]
+    scopes: String,
+    #[serde(default = "default_username_claim")]
+    username_claim: String,
+    #[serde(default = "default_role_claim")]
+    role_claim: String,
+    // claim value = role name, e.g. "helpdesk-admins" = "admin"
+    #[serde(default)]
+    role_mapping: HashMap<String, String>,
+    default_role: Option<String>,
+    #[serde(default = "default_label")]
+    label: String,
+    #[serde(default = "default_break_glass_only")]
+    break_glass_only: bool,
+}
+
+// organisation created by the migration for the data of single company installs
+fn default_organisation() -> String {
+    String::from("default")
+}
+
+fn default_scopes() -> String {
+    String::from("openid email profile")
+}
+
+fn default_username_claim() -> String {
+    String::from("preferred_username")
+}
+
+fn default_role_claim() -> String {
+    String::from("groups")
+}
+
+fn default_label() -> String {
+    String::from("company account")
+}
+
+fn default_break_glass_only() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Oidc {}
+
+impl Oidc {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Oidc {
+    fn info(&self) -> Info {
+        Info {
+            name: "OIDC Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        // single sign-on stays off without an oidc section
+        let settings = if rocket.figment().contains(CONFIG_KEY) {
+            let config = match rocket.figment().extract_inner::<OidcConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid OIDC configuration: {}", e);
+                    return Err(rocket);
+                }
+            };
+            let mut role_mapping = HashMap::new();
+            for (value, name) in config.role_mapping {
+                match UserRole::from_name(&name) {
+                    Some(role) => {
+                        role_mapping.insert(value, role);
+                    }
+                    None => {
+                        log::error!("Unknown role {} in oidc.role_mapping", name);
+                        return Err(rocket);
+                    }
+                }
+            }
+            let default_role = match config.default_role.as_deref() {
+                Some(name) => match UserRole::from_name(name) {
+                    Some(role) => Some(role),
+                    None => {
+                        log::error!("Unknown role {} in oidc.default_role", name);
+                        return Err(rocket);
+                    }
+                },
+                None => None,
+            };
+            Some(OidcSettings {
+                organisation: config.organisation.trim().to_lowercase(),
+                issuer_url: config.issuer_url,
+                client_id: config.client_id,
+                client_secret: config.client_secret,
+                redirect_url: config.redirect_url,
+                scopes: config.scopes,
+                username_claim: config.username_claim,
+                role_claim: config.role_claim,
+                role_mapping,
+                default_role,
+                label: config.label,
+                break_glass_only: config.break_glass_only,
+            })
+        } else {
+            None
+        };
+        match OidcClient::new(settings) {
+            Ok(client) => Ok(rocket.manage(client)),
+            Err(_) => Err(rocket),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+use webauthn_rs::prelude::Url;
+use webauthn_rs::WebauthnBuilder;
+
+const CONFIG_KEY: &str = "webauthn";
+
+#[derive(Deserialize)]
+struct WebauthnConfig {
+    #[serde(default = "default_rp_id")]
+    rp_id: String,
+    #[serde(default = "default_rp_origin")]
+    rp_origin: String,
+    #[serde(default = "default_rp_name")]
+    rp_name: String,
+}
+
+fn default_rp_id() -> String {
+    String::from("localhost")
+}
+
+fn default_rp_origin() -> String {
+    String::from("http://localhost:8000")
+}
+
+fn default_rp_name() -> String {
+    String::from("Ticket Desk")
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: default_rp_id(),
+            rp_origin: default_rp_origin(),
+            rp_name: default_rp_name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Passkeys {}
+
+impl Passkeys {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Passkeys {
+    fn info(&self) -> Info {
+        Info {
+            name: "Passkeys Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<WebauthnConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid webauthn configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            WebauthnConfig::default()
+        };
+        let rp_origin = match Url::parse(&config.rp_origin) {
+            Ok(url) => url,
+            Err(e) => {
+                log::error!("Invalid webauthn.rp_origin: {}", e);
+                return Err(rocket);
+            }
+        };
+        // refuses an rp_id the origin's host is not equal to or a subdomain of,
+        // browsers would refuse every ceremony anyway
+        let webauthn = match WebauthnBuilder::new(&config.rp_id, &rp_origin)
+            .map(|builder| builder.rp_name(&config.rp_name))
+            .and_then(|builder| builder.build())
+        {
+            Ok(webauthn) => webauthn,
+            Err(e) => {
+                log::error!("Invalid webauthn configuration: {}", e);
+                return Err(rocket);
+            }
+        };
+        Ok(rocket.manage(webauthn))
+    }
+}
@@ -0,0 +1,98 @@
+use crate::states::password_policy::PasswordPolicy;
+use argon2::Params;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+use std::path::PathBuf;
+
+const CONFIG_KEY: &str = "passwords";
+
+#[derive(Deserialize)]
+struct PasswordConfig {
+    // in KiB
+    #[serde(default = "default_memory_cost")]
+    memory_cost: u32,
+    #[serde(default = "default_time_cost")]
+    time_cost: u32,
+    #[serde(default = "default_parallelism")]
+    parallelism: u32,
+    breached_passwords_dir: Option<PathBuf>,
+}
+
+fn default_memory_cost() -> u32 {
+    19 * 1024
+}
+
+fn default_time_cost() -> u32 {
+    2
+}
+
+fn default_parallelism() -> u32 {
+    1
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            memory_cost: default_memory_cost(),
+            time_cost: default_time_cost(),
+            parallelism: default_parallelism(),
+            breached_passwords_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Passwords {}
+
+impl Passwords {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Passwords {
+    fn info(&self) -> Info {
+        Info {
+            name: "Passwords Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<PasswordConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid passwords configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            PasswordConfig::default()
+        };
+        let params = match Params::new(
+            config.memory_cost,
+            config.time_cost,
+            config.parallelism,
+            None,
+        ) {
+            Ok(params) => params,
+            Err(e) => {
+                log::error!("Invalid Argon2 parameters: {}", e);
+                return Err(rocket);
+            }
+        };
+        if let Some(dir) = config.breached_passwords_dir.as_ref() {
+            if !dir.is_dir() {
+                log::error!("passwords.breached_passwords_dir is not a directory");
+                return Err(rocket);
+            }
+        }
+        match PasswordPolicy::new(params, config.breached_passwords_dir) {
+            Ok(policy) => Ok(rocket.manage(policy)),
+            Err(_) => Err(rocket),
+        }
+    }
+}
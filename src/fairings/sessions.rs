@@ -0,0 +1,94 @@
+use crate::states::session_policy::SessionPolicy;
+use chrono::Duration;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+
+const CONFIG_KEY: &str = "sessions";
+
+// all in seconds
+#[derive(Deserialize)]
+struct SessionConfig {
+    #[serde(default = "default_idle_timeout")]
+    idle_timeout: i64,
+    #[serde(default = "default_absolute_timeout")]
+    absolute_timeout: i64,
+    #[serde(default = "default_remember_me_timeout")]
+    remember_me_timeout: i64,
+    #[serde(default = "default_impersonation_timeout")]
+    impersonation_timeout: i64,
+}
+
+fn default_idle_timeout() -> i64 {
+    2 * 60 * 60
+}
+
+fn default_absolute_timeout() -> i64 {
+    12 * 60 * 60
+}
+
+fn default_remember_me_timeout() -> i64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_impersonation_timeout() -> i64 {
+    30 * 60
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: default_idle_timeout(),
+            absolute_timeout: default_absolute_timeout(),
+            remember_me_timeout: default_remember_me_timeout(),
+            impersonation_timeout: default_impersonation_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sessions {}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Sessions {
+    fn info(&self) -> Info {
+        Info {
+            name: "Sessions Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<SessionConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid sessions configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            SessionConfig::default()
+        };
+        if config.idle_timeout <= 0
+            || config.absolute_timeout <= 0
+            || config.remember_me_timeout <= 0
+            || config.impersonation_timeout <= 0
+        {
+            log::error!("Session timeouts must be positive");
+            return Err(rocket);
+        }
+        Ok(rocket.manage(SessionPolicy {
+            idle_timeout: Duration::seconds(config.idle_timeout),
+            absolute_timeout: Duration::seconds(config.absolute_timeout),
+            remember_me_timeout: Duration::seconds(config.remember_me_timeout),
+            impersonation_timeout: Duration::seconds(config.impersonation_timeout),
+        }))
+    }
+}
@@ -0,0 +1,48 @@
+use crate::states::signer::Signer;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::{Build, Rocket};
+
+const CONFIG_KEY: &str = "signing_key";
+const KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Default)]
+pub struct Signing {}
+
+impl Signing {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Signing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Signing Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let key = match rocket.figment().extract_inner::<String>(CONFIG_KEY) {
+            Ok(key) if key.len() >= KEY_LENGTH => key.into_bytes(),
+            Ok(_) => {
+                log::error!("{} must be at least {} characters", CONFIG_KEY, KEY_LENGTH);
+                return Err(rocket);
+            }
+            Err(_) if rocket.config().profile == rocket::Config::DEBUG_PROFILE => {
+                // links signed with a random key stop working on restart, fine for development
+                log::warn!("{} is not set, using a random key", CONFIG_KEY);
+                let mut key = vec![0; KEY_LENGTH];
+                OsRng.fill_bytes(&mut key);
+                key
+            }
+            Err(_) => {
+                log::error!("{} must be set in release", CONFIG_KEY);
+                return Err(rocket);
+            }
+        };
+        Ok(rocket.manage(Signer::new(key)))
+    }
+}
@@ -0,0 +1,250 @@
+use crate::fairings::db::DBConnection;
+use crate::gateways::http_sms::HttpSmsGateway;
+use crate::gateways::log_sms::LogSmsGateway;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::sms_notification::SmsNotification;
+use crate::states::sms_notifier::SmsNotifier;
+use crate::traits::sms_gateway::{OutgoingSms, SmsGateway};
+use chrono::{offset::Utc, Duration};
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::tokio;
+use rocket::{Build, Orbit, Rocket};
+use rocket_db_pools::sqlx::{PgConnection, PgPool};
+use rocket_db_pools::Database;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+const CONFIG_KEY: &str = "sms";
+const BATCH_SIZE: i32 = 20;
+// how long one message may take at the gateway before the lease runs out
+const SEND_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 60 * 60;
+
+#[derive(Deserialize)]
+struct SmsConfig {
+    #[serde(default = "default_gateway")]
+    gateway: String,
+    url: Option<String>,
+    api_key: Option<String>,
+    sender: Option<String>,
+    #[serde(default = "default_max_segments")]
+    max_segments: usize,
+    #[serde(default = "default_created_template")]
+    created_template: String,
+    #[serde(default = "default_closed_template")]
+    closed_template: String,
+    // seconds between two looks at the queue
+    #[serde(default = "default_poll_interval")]
+    poll_interval: u64,
+    #[serde(default = "default_max_attempts")]
+    max_attempts: i32,
+    // first retry waits this many seconds, every following retry waits twice as long
+    #[serde(default = "default_backoff_base")]
+    backoff_base: i64,
+}
+
+fn default_gateway() -> String {
+    String::from("log")
+}
+
+fn default_max_segments() -> usize {
+    2
+}
+
+fn default_created_template() -> String {
+    String::from("Ticket #{ticket_number} received: {issue_name}. We will text you when it is resolved.")
+}
+
+fn default_closed_template() -> String {
+    String::from("Ticket #{ticket_number} ({issue_name}) has been resolved and closed.")
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> i32 {
+    4
+}
+
+fn default_backoff_base() -> i64 {
+    30
+}
+
+impl Default for SmsConfig {
+    fn default() -> Self {
+        Self {
+            gateway: default_gateway(),
+            url: None,
+            api_key: None,
+            sender: None,
+            max_segments: default_max_segments(),
+            created_template: default_created_template(),
+            closed_template: default_closed_template(),
+            poll_interval: default_poll_interval(),
+            max_attempts: default_max_attempts(),
+            backoff_base: default_backoff_base(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sms {}
+
+impl Sms {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Sms {
+    fn info(&self) -> Info {
+        Info {
+            name: "SMS Fairing",
+            kind: Kind::Ignite | Kind::Liftoff,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<SmsConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid SMS configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            SmsConfig::default()
+        };
+
+        let gateway: Arc<dyn SmsGateway> = match config.gateway.as_str() {
+            "log" => Arc::new(LogSmsGateway::new()),
+            "http" => {
+                let url = match config.url {
+                    Some(url) => url,
+                    None => {
+                        log::error!("SMS gateway \"http\" needs sms.url");
+                        return Err(rocket);
+                    }
+                };
+                match HttpSmsGateway::new(url, config.api_key, config.sender) {
+                    Ok(gateway) => Arc::new(gateway),
+                    Err(_) => return Err(rocket),
+                }
+            }
+            other => {
+                log::error!("Unknown SMS gateway: {}", other);
+                return Err(rocket);
+            }
+        };
+
+        Ok(rocket.manage(SmsNotifier::new(
+            gateway,
+            config.created_template,
+            config.closed_template,
+            config.max_segments,
+        )))
+    }
+
+    // the routes only queue text messages, this worker sends them
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        // checked on ignite already, a broken configuration never gets here
+        let config = rocket
+            .figment()
+            .extract_inner::<SmsConfig>(CONFIG_KEY)
+            .unwrap_or_default();
+        let gateway = match rocket.state::<SmsNotifier>() {
+            Some(notifier) => notifier.gateway(),
+            None => return,
+        };
+        let pool = match DBConnection::fetch(rocket) {
+            Some(db) => (**db).clone(),
+            None => {
+                log::error!("SMS worker cannot start without a database");
+                return;
+            }
+        };
+        tokio::spawn(run(pool, gateway, config));
+    }
+}
+
+async fn run(pool: PgPool, gateway: Arc<dyn SmsGateway>, config: SmsConfig) {
+    let mut interval = tokio::time::interval(StdDuration::from_secs(config.poll_interval));
+    loop {
+        interval.tick().await;
+        let mut connection = match pool.acquire().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::error!("SMS worker cannot acquire connection: {}", e);
+                continue;
+            }
+        };
+        let lease = Duration::seconds(SEND_SECONDS * BATCH_SIZE as i64);
+        let notifications =
+            match SmsNotification::claim_due(&mut connection, BATCH_SIZE, lease).await {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    log::error!("SMS worker cannot claim messages: {}", e);
+                    continue;
+                }
+            };
+        for notification in notifications.iter() {
+            send(&mut connection, gateway.as_ref(), &config, notification).await;
+        }
+    }
+}
+
+async fn send(
+    connection: &mut PgConnection,
+    gateway: &dyn SmsGateway,
+    config: &SmsConfig,
+    notification: &SmsNotification,
+) {
+    let message = OutgoingSms {
+        to: notification.recipient.clone(),
+        body: notification.body.clone(),
+    };
+    // matched right away, the error must not live across the next await
+    let outcome = match gateway.send(&message).await {
+        Ok(receipt) => Ok(receipt.message_id),
+        Err(e) => Err(e.message),
+    };
+    let recorded = match outcome {
+        Ok(message_id) => {
+            SmsNotification::mark_sent(connection, &notification.uuid, message_id.as_deref()).await
+        }
+        Err(error) => {
+            let next_attempt_at = next_attempt_at(config, notification.attempts + 1);
+            SmsNotification::mark_attempt_failed(
+                connection,
+                &notification.uuid,
+                &error,
+                next_attempt_at,
+            )
+            .await
+        }
+    };
+    if let Err(e) = recorded {
+        log::error!(
+            "Cannot record SMS notification {}: {}",
+            notification.uuid,
+            e
+        );
+    }
+}
+
+// exponential backoff, None once the message used all its attempts
+fn next_attempt_at(config: &SmsConfig, attempts: i32) -> Option<OurDateTime> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let exponent = (attempts - 1).clamp(0, 30) as u32;
+    let seconds = config
+        .backoff_base
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(MAX_BACKOFF_SECONDS);
+    Some(OurDateTime(Utc::now() + Duration::seconds(seconds)))
+}
@@ -0,0 +1,69 @@
+use crate::states::tenancy::TenancyPolicy;
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::{Build, Rocket};
+
+const CONFIG_KEY: &str = "tenancy";
+
+#[derive(Deserialize)]
+struct TenancyConfig {
+    base_domain: Option<String>,
+    #[serde(default = "default_default_subdomain")]
+    default_subdomain: Option<String>,
+}
+
+// organisation created by the migration for the data of single company installs
+fn default_default_subdomain() -> Option<String> {
+    Some(String::from("default"))
+}
+
+impl Default for TenancyConfig {
+    fn default() -> Self {
+        Self {
+            base_domain: None,
+            default_subdomain: default_default_subdomain(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tenancy {}
+
+impl Tenancy {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Tenancy {
+    fn info(&self) -> Info {
+        Info {
+            name: "Tenancy Fairing",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = if rocket.figment().contains(CONFIG_KEY) {
+            match rocket.figment().extract_inner::<TenancyConfig>(CONFIG_KEY) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::error!("Invalid tenancy configuration: {}", e);
+                    return Err(rocket);
+                }
+            }
+        } else {
+            TenancyConfig::default()
+        };
+        let normalize = |value: Option<String>| {
+            value
+                .map(|value| value.trim().trim_matches('.').to_lowercase())
+                .filter(|value| !value.is_empty())
+        };
+        Ok(rocket.manage(TenancyPolicy {
+            base_domain: normalize(config.base_domain),
+            default_subdomain: normalize(config.default_subdomain),
+        }))
+    }
+}
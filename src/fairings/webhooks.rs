@@ -0,0 +1,260 @@
+use crate::fairings::db::DBConnection;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::webhook::{
+    is_internal_address, is_internal_host, sign, Webhook, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+use crate::models::webhook_delivery::WebhookDelivery;
+use chrono::{offset::Utc, Duration};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::serde::Deserialize;
+use rocket::tokio;
+use rocket::{Orbit, Rocket};
+use rocket_db_pools::sqlx::{PgConnection, PgPool};
+use rocket_db_pools::Database;
+use std::net::SocketAddr;
+use std::time::Duration as StdDuration;
+
+const CONFIG_KEY: &str = "webhooks";
+const HTTP_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+const BATCH_SIZE: i32 = 20;
+const MAX_BACKOFF_SECONDS: i64 = 6 * 60 * 60;
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebhookConfig {
+    // seconds between two looks at the queue
+    #[serde(default = "default_poll_interval")]
+    poll_interval: u64,
+    #[serde(default = "default_max_attempts")]
+    max_attempts: i32,
+    // first retry waits this many seconds, every following retry waits twice as long
+    #[serde(default = "default_backoff_base")]
+    backoff_base: i64,
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> i32 {
+    6
+}
+
+fn default_backoff_base() -> i64 {
+    30
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: default_poll_interval(),
+            max_attempts: default_max_attempts(),
+            backoff_base: default_backoff_base(),
+        }
+    }
+}
+
+// background worker sending the queued webhook deliveries
+#[derive(Debug, Clone, Default)]
+pub struct WebhookWorker {}
+
+impl WebhookWorker {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for WebhookWorker {
+    fn info(&self) -> Info {
+        Info {
+            name: "Webhook Worker",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let config = rocket
+            .figment()
+            .extract_inner::<WebhookConfig>(CONFIG_KEY)
+            .unwrap_or_default();
+        let pool = match DBConnection::fetch(rocket) {
+            Some(db) => (**db).clone(),
+            None => {
+                log::error!("Webhook worker cannot start without a database");
+                return;
+            }
+        };
+        tokio::spawn(run(pool, config));
+    }
+}
+
+async fn run(pool: PgPool, config: WebhookConfig) {
+    let mut interval = tokio::time::interval(StdDuration::from_secs(config.poll_interval));
+    loop {
+        interval.tick().await;
+        let mut connection = match pool.acquire().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::error!("Webhook worker cannot acquire connection: {}", e);
+                continue;
+            }
+        };
+        // lease long enough to cover a full batch of timeouts
+        let lease = Duration::seconds(HTTP_TIMEOUT.as_secs() as i64 * BATCH_SIZE as i64);
+        let deliveries = match WebhookDelivery::claim_due(&mut connection, BATCH_SIZE, lease).await
+        {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                log::error!("Webhook worker cannot claim deliveries: {}", e);
+                continue;
+            }
+        };
+        for delivery in deliveries.iter() {
+            deliver(&mut connection, &config, delivery).await;
+        }
+    }
+}
+
+async fn deliver(
+    connection: &mut PgConnection,
+    config: &WebhookConfig,
+    delivery: &WebhookDelivery,
+) {
+    let webhook = match Webhook::find_for_delivery(connection, &delivery.webhook_uuid).await {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            log::error!("Cannot load webhook {}: {}", delivery.webhook_uuid, e);
+            return;
+        }
+    };
+    let outcome = match client_for(&webhook.url).await {
+        Ok(client) => {
+            let timestamp = Utc::now().timestamp();
+            let response = client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Webhook-Event", &delivery.event)
+                .header("X-Webhook-Delivery", delivery.uuid.to_string())
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(
+                    SIGNATURE_HEADER,
+                    sign(&webhook.secret, timestamp, &delivery.payload),
+                )
+                .body(delivery.payload.clone())
+                .send()
+                .await;
+            match response {
+                Ok(response) if response.status().is_success() => {
+                    Ok(response.status().as_u16() as i32)
+                }
+                Ok(response) => Err((
+                    Some(response.status().as_u16() as i32),
+                    format!("endpoint answered {}", response.status()),
+                )),
+                Err(e) => Err((None, e.to_string())),
+            }
+        }
+        Err(error) => Err((None, error)),
+    };
+
+    let recorded = match outcome {
+        Ok(status_code) => {
+            WebhookDelivery::mark_delivered(connection, &delivery.uuid, status_code).await
+        }
+        Err((status_code, error)) => {
+            let next_attempt_at = next_attempt_at(config, delivery.attempts + 1);
+            WebhookDelivery::mark_attempt_failed(
+                connection,
+                &delivery.uuid,
+                status_code,
+                &error,
+                next_attempt_at,
+            )
+            .await
+        }
+    };
+    if let Err(e) = recorded {
+        log::error!("Cannot record webhook delivery {}: {}", delivery.uuid, e);
+    }
+}
+
+// Resolves the host of the url and refuses it when any address is one of ours,
+// the client is pinned to the checked address so a second lookup cannot swap it for another.
+// Redirects are not followed, they could lead back into our network.
+async fn client_for(url: &str) -> Result<reqwest::Client, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = parsed.host_str().ok_or("url has no host")?;
+    if is_internal_host(host) {
+        return Err(String::from("url points to an internal address"));
+    }
+    let port = parsed.port_or_known_default().ok_or("url has no port")?;
+    let lookup_host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((lookup_host, port))
+        .await
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .collect();
+    if addresses
+        .iter()
+        .any(|address| is_internal_address(&address.ip()))
+    {
+        return Err(String::from("url resolves to an internal address"));
+    }
+    let address = addresses
+        .first()
+        .ok_or_else(|| format!("cannot resolve {}", host))?;
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(host, *address)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// exponential backoff, None once the delivery used all its attempts
+fn next_attempt_at(config: &WebhookConfig, attempts: i32) -> Option<OurDateTime> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let exponent = (attempts - 1).clamp(0, 30) as u32;
+    let seconds = config
+        .backoff_base
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(MAX_BACKOFF_SECONDS);
+    Some(OurDateTime(Utc::now() + Duration::seconds(seconds)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds_until(attempt_at: Option<OurDateTime>) -> i64 {
+        let OurDateTime(at) = attempt_at.expect("a retry");
+        (at - Utc::now()).num_seconds()
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let config = WebhookConfig::default();
+        // a second of slack for the time spent between the two clocks
+        assert!((29..=30).contains(&seconds_until(next_attempt_at(&config, 1))));
+        assert!((59..=60).contains(&seconds_until(next_attempt_at(&config, 2))));
+        assert!((239..=240).contains(&seconds_until(next_attempt_at(&config, 4))));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let config = WebhookConfig {
+            max_attempts: 40,
+            ..WebhookConfig::default()
+        };
+        let seconds = seconds_until(next_attempt_at(&config, 39));
+        assert!((MAX_BACKOFF_SECONDS - 1..=MAX_BACKOFF_SECONDS).contains(&seconds));
+    }
+
+    #[test]
+    fn no_retry_after_the_last_attempt() {
+        let config = WebhookConfig::default();
+        assert!(next_attempt_at(&config, config.max_attempts).is_none());
+        assert!(next_attempt_at(&config, config.max_attempts - 1).is_some());
+    }
+}
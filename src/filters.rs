@@ -0,0 +1,36 @@
+use crate::models::user_preferences::DateFormat;
+use chrono::{offset::Utc, DateTime};
+use chrono_tz::Tz;
+use rocket_dyn_templates::tera::{Result, Value};
+use std::collections::HashMap;
+
+// Shows a stored UTC timestamp in the viewer's timezone and date format:
+// {{ issue.created_at | local_time(preferences=preferences) }}
+// Without preferences, as on the reporter pages, it stays in UTC.
+// Anything that is not a timestamp, like a missing one, goes through untouched.
+pub fn local_time(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let timestamp = match value
+        .as_str()
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+    {
+        Some(timestamp) => timestamp.with_timezone(&Utc),
+        None => return Ok(value.clone()),
+    };
+    let preferences = args.get("preferences");
+    let timezone = preferences
+        .and_then(|preferences| preferences.get("timezone"))
+        .and_then(Value::as_str)
+        .and_then(|timezone| timezone.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC);
+    let date_format = preferences
+        .and_then(|preferences| preferences.get("date_format"))
+        .and_then(Value::as_str)
+        .and_then(DateFormat::from_name)
+        .unwrap_or_default();
+    Ok(Value::String(
+        timestamp
+            .with_timezone(&timezone)
+            .format(date_format.pattern())
+            .to_string(),
+    ))
+}
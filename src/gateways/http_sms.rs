@@ -0,0 +1,89 @@
+use crate::errors::our_error::OurError;
+use crate::traits::sms_gateway::{OutgoingSms, SmsGateway, SmsReceipt};
+use rocket::serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// gateway for providers that accept a JSON POST, the url can point to a local mock server
+pub struct HttpSmsGateway {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    sender: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HttpSmsRequest<'a> {
+    to: &'a str,
+    from: Option<&'a str>,
+    body: &'a str,
+}
+
+#[derive(Deserialize)]
+struct HttpSmsResponse {
+    id: Option<String>,
+}
+
+impl HttpSmsGateway {
+    pub fn new(
+        url: String,
+        api_key: Option<String>,
+        sender: Option<String>,
+    ) -> Result<Self, OurError> {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| {
+                OurError::new_internal_server_error(
+                    String::from("Cannot build SMS client"),
+                    Some(Box::new(e)),
+                )
+            })?;
+        Ok(Self {
+            client,
+            url,
+            api_key,
+            sender,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl SmsGateway for HttpSmsGateway {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn send(&self, message: &OutgoingSms) -> Result<SmsReceipt, OurError> {
+        let payload = HttpSmsRequest {
+            to: &message.to,
+            from: self.sender.as_deref(),
+            body: &message.body,
+        };
+        let mut request = self.client.post(&self.url).json(&payload);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot reach SMS provider"),
+                Some(Box::new(e)),
+            )
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(OurError::new_internal_server_error(
+                format!("SMS provider answered {}", status),
+                None,
+            ));
+        }
+        // providers that do not answer with JSON still accepted the message
+        let message_id = response
+            .json::<HttpSmsResponse>()
+            .await
+            .ok()
+            .and_then(|body| body.id);
+        Ok(SmsReceipt { message_id })
+    }
+}
@@ -0,0 +1,20 @@
+use crate::errors::our_error::OurError;
+use crate::traits::mailer::{Mailer, OutgoingMail};
+
+// mailer that only writes the email to the log, used in development
+#[derive(Default)]
+pub struct LogMailer {}
+
+impl LogMailer {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), OurError> {
+        log::info!("Email to {}: {}\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use crate::errors::our_error::OurError;
+use crate::traits::sms_gateway::{OutgoingSms, SmsGateway, SmsReceipt};
+
+// gateway that only writes the message to the log, used in development
+#[derive(Default)]
+pub struct LogSmsGateway {}
+
+impl LogSmsGateway {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[rocket::async_trait]
+impl SmsGateway for LogSmsGateway {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn send(&self, message: &OutgoingSms) -> Result<SmsReceipt, OurError> {
+        log::info!("SMS to {}: {}", message.to, message.body);
+        Ok(SmsReceipt { message_id: None })
+    }
+}
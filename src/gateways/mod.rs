@@ -0,0 +1,4 @@
+pub mod http_sms;
+pub mod log_mail;
+pub mod log_sms;
+pub mod smtp_mail;
@@ -0,0 +1,69 @@
+use crate::errors::our_error::OurError;
+use crate::traits::mailer::{Mailer, OutgoingMail};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: Option<String>,
+        password: Option<String>,
+        from: &str,
+    ) -> Result<Self, OurError> {
+        let from = from.parse::<Mailbox>().map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Invalid sender address"),
+                Some(Box::new(e)),
+            )
+        })?;
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+            .map_err(|e| {
+                OurError::new_internal_server_error(
+                    String::from("Cannot build SMTP transport"),
+                    Some(Box::new(e)),
+                )
+            })?
+            .port(port);
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), OurError> {
+        let to = mail.to.parse::<Mailbox>().map_err(|e| {
+            OurError::new_bad_request_error(String::from("Invalid email"), Some(Box::new(e)))
+        })?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(mail.subject.clone())
+            .body(mail.body.clone())
+            .map_err(|e| {
+                OurError::new_internal_server_error(
+                    String::from("Cannot build email"),
+                    Some(Box::new(e)),
+                )
+            })?;
+        self.transport.send(message).await.map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot send email"),
+                Some(Box::new(e)),
+            )
+        })?;
+        Ok(())
+    }
+}
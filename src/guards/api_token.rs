@@ -0,0 +1,85 @@
+use super::organisation::host_organisation;
+use crate::fairings::db::DBConnection;
+use crate::models::organisation::Organisation;
+use crate::models::organisation_token::OrganisationToken;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use std::marker::PhantomData;
+
+// which of the tokens of an organisation opens an endpoint
+pub trait TokenScope: Send + Sync + 'static {
+    const NAME: &'static str;
+}
+
+pub struct AlertsScope;
+
+impl TokenScope for AlertsScope {
+    const NAME: &'static str = "alerts";
+}
+
+pub struct InboundSmsScope;
+
+impl TokenScope for InboundSmsScope {
+    const NAME: &'static str = "sms";
+}
+
+pub struct ScimScope;
+
+impl TokenScope for ScimScope {
+    const NAME: &'static str = "scim";
+}
+
+pub const TOKEN_SCOPES: [&str; 3] = [AlertsScope::NAME, InboundSmsScope::NAME, ScimScope::NAME];
+
+// Succeeds when the request carries "Authorization: Bearer <token>" with a token of the scope.
+// The token names the organisation the request acts for, a host naming another one is refused.
+pub struct ApiToken<S: TokenScope> {
+    pub organisation: Organisation,
+    scope: PhantomData<S>,
+}
+
+#[rocket::async_trait]
+impl<'r, S: TokenScope> FromRequest<'r> for ApiToken<S> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let error = Outcome::Failure((Status::Unauthorized, ()));
+        let given = match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+        {
+            Some(given) if !given.is_empty() => given,
+            _ => return error,
+        };
+        let mut db = match req.guard::<Connection<DBConnection>>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        let connection = match db.acquire().await {
+            Ok(connection) => connection,
+            Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        let organisation =
+            match OrganisationToken::find_organisation(connection, S::NAME, given).await {
+                Ok(organisation) => organisation,
+                Err(_) => return error,
+            };
+        match host_organisation(req, connection).await {
+            Ok(Some(named)) if named.uuid != organisation.uuid => error,
+            Ok(_) => Outcome::Success(ApiToken {
+                organisation,
+                scope: PhantomData,
+            }),
+            Err(_) => error,
+        }
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,44 +1,162 @@
+use super::impersonation::{ActiveImpersonation, IMPERSONATION_COOKIE_NAME};
+use super::organisation::host_organisation;
 use crate::fairings::db::DBConnection;
+use crate::models::impersonation::Impersonation;
+use crate::models::organisation::Organisation;
+use crate::models::session::Session;
 use crate::models::user::User;
-use rocket::http::Status;
+use crate::models::user_preferences::UserPreferences;
+use crate::models::user_role::Permissions;
+use crate::states::session_policy::SessionPolicy;
+use rocket::http::{Cookie, Status};
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket::serde::Serialize;
-use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_db_pools::{
+    sqlx::{Acquire, PgConnection},
+    Connection,
+};
 
-pub const LOGIN_COOKIE_NAME: &str = "user_uuid";
+// holds the session token, the user is found through the sessions table
+pub const LOGIN_COOKIE_NAME: &str = "session_id";
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct CurrentUser {
     pub user: User,
+    pub session: Session,
+    // the one really logged in while user is being impersonated, session is theirs
+    pub impersonator: Option<User>,
+    // those of user, an impersonator sees the application the way the agent does
+    pub preferences: UserPreferences,
+    // the one user belongs to, what they do is scoped by it
+    pub organisation: Organisation,
+}
+
+impl CurrentUser {
+    pub fn permissions(&self) -> Permissions {
+        self.user.role.permissions()
+    }
+
+    // own account, or any account for admins
+    pub fn can_manage_user(&self, uuid: &str) -> bool {
+        self.user.uuid.to_string() == uuid || self.permissions().manage_users
+    }
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for CurrentUser {
     type Error = ();
 
+    // several guards of a route need the current user, the lookup runs once per request
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let error = Outcome::Failure((Status::Unauthorized, ()));
-        let parsed_cookie = req.cookies().get_private(LOGIN_COOKIE_NAME);
-        if parsed_cookie.is_none() {
-            return error;
-        }
-        let cookie = parsed_cookie.unwrap();
-        let uuid = cookie.value();
-        let parsed_db = req.guard::<Connection<DBConnection>>().await;
-        if !parsed_db.is_success() {
-            return error;
-        }
-        let mut db = parsed_db.unwrap();
-        let parsed_connection = db.acquire().await;
-        if parsed_connection.is_err() {
-            return error;
+        match req.local_cache_async(find_current_user(req)).await {
+            Some(current_user) => Outcome::Success(current_user.clone()),
+            None => Outcome::Failure((Status::Unauthorized, ())),
         }
-        let connection = parsed_connection.unwrap();
-        let found_user = User::find(connection, uuid).await;
-        if found_user.is_err() {
-            return error;
+    }
+}
+
+async fn find_current_user(req: &Request<'_>) -> Option<CurrentUser> {
+    let cookie = req.cookies().get_private(LOGIN_COOKIE_NAME)?;
+    let token = cookie.value();
+    let policy = req.rocket().state::<SessionPolicy>()?;
+    let mut db = req.guard::<Connection<DBConnection>>().await.succeeded()?;
+    let connection = db.acquire().await.ok()?;
+    // matched right away, the error must not live across the next await
+    let session = match Session::find_active(connection, token, policy.idle_timeout).await {
+        Ok(session) => session,
+        Err(_) => return None,
+    };
+    // the host names the organisation, or the user's membership does
+    let organisation = match host_organisation(req, connection).await {
+        Ok(Some(organisation)) => organisation,
+        Ok(None) => match Organisation::find_by_user(connection, &session.user_uuid).await {
+            Ok(organisation) => organisation,
+            Err(_) => return None,
+        },
+        Err(_) => return None,
+    };
+    // a session is no good on the host of another organisation
+    let user = User::find(
+        connection,
+        &organisation.uuid,
+        &session.user_uuid.to_string(),
+    )
+    .await
+    .ok()?;
+    // a suspended, locked or deactivated user is out at once, whatever sessions they hold
+    if !user.status.can_log_in() {
+        return None;
+    }
+    // someone with a higher role looking at the application as an agent
+    if let Some(cookie) = req.cookies().get_private(IMPERSONATION_COOKIE_NAME) {
+        let impersonation = Impersonation::find_active(connection, cookie.value(), &user.uuid)
+            .await
+            .ok();
+        match impersonation {
+            // reporters are impersonated in the portal, CurrentReporter applies it
+            Some(impersonation) if impersonation.reporter_uuid.is_some() => {}
+            Some(impersonation) => {
+                if let Some(impersonated) =
+                    impersonated_user(connection, &impersonation, &user).await
+                {
+                    let preferences =
+                        match UserPreferences::find(connection, &impersonated.uuid).await {
+                            Ok(preferences) => preferences,
+                            Err(_) => return None,
+                        };
+                    ActiveImpersonation::apply(
+                        req,
+                        ActiveImpersonation {
+                            impersonation,
+                            impersonator: user.username.clone(),
+                            target: impersonated.username.clone(),
+                        },
+                    );
+                    return Some(CurrentUser {
+                        user: impersonated,
+                        session,
+                        impersonator: Some(user),
+                        preferences,
+                        organisation,
+                    });
+                }
+                req.cookies()
+                    .remove_private(Cookie::named(IMPERSONATION_COOKIE_NAME));
+            }
+            None => req
+                .cookies()
+                .remove_private(Cookie::named(IMPERSONATION_COOKIE_NAME)),
         }
-        let user = found_user.unwrap();
-        Outcome::Success(CurrentUser { user })
     }
+    let preferences = match UserPreferences::find(connection, &user.uuid).await {
+        Ok(preferences) => preferences,
+        Err(_) => return None,
+    };
+    Some(CurrentUser {
+        user,
+        session,
+        impersonator: None,
+        preferences,
+        organisation,
+    })
+}
+
+// the impersonator has to still outrank the agent, and the agent to still be able to log in,
+// only agents of the impersonator's own organisation are found
+async fn impersonated_user(
+    connection: &mut PgConnection,
+    impersonation: &Impersonation,
+    impersonator: &User,
+) -> Option<User> {
+    let user_uuid = impersonation.user_uuid?;
+    User::find(
+        connection,
+        &impersonator.organisation_uuid,
+        &user_uuid.to_string(),
+    )
+    .await
+    .ok()
+    .filter(|user| {
+        user.role < impersonator.role && user.status.can_log_in() && user.deleted_at.is_none()
+    })
 }
@@ -0,0 +1,20 @@
+use rocket::request::{FromRequest, Outcome, Request};
+use std::convert::Infallible;
+
+// where a request comes from, recorded with sessions so agents can recognise their devices
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            ip_address: req.client_ip().map(|ip| ip.to_string()),
+            user_agent: req.headers().get_one("User-Agent").map(String::from),
+        })
+    }
+}
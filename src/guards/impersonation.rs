@@ -0,0 +1,45 @@
+use super::auth::CurrentUser;
+use crate::models::impersonation::Impersonation;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+// holds the uuid of the impersonation, next to the session cookie of the impersonator
+pub const IMPERSONATION_COOKIE_NAME: &str = "impersonation";
+
+// impersonation applied to the current request by CurrentUser or CurrentReporter,
+// the impersonation fairing reads it back to show the banner and log writes
+pub struct ActiveImpersonation {
+    pub impersonation: Impersonation,
+    pub impersonator: String,
+    pub target: String,
+}
+
+impl ActiveImpersonation {
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r Option<Self> {
+        req.local_cache(|| None)
+    }
+
+    pub fn apply(req: &Request<'_>, impersonation: Self) {
+        req.local_cache(|| Some(impersonation));
+    }
+}
+
+// Logged in user acting as themselves.
+// Fails with 403 while impersonating, for password changes and other sensitive actions.
+pub struct NotImpersonating;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NotImpersonating {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.guard::<CurrentUser>().await {
+            Outcome::Success(current_user) if current_user.impersonator.is_none() => {
+                Outcome::Success(NotImpersonating)
+            }
+            Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
@@ -1 +1,7 @@
+pub mod api_token;
 pub mod auth;
+pub mod client;
+pub mod impersonation;
+pub mod organisation;
+pub mod reporter;
+pub mod role;
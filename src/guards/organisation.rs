@@ -0,0 +1,94 @@
+use super::auth::CurrentUser;
+use super::reporter::CurrentReporter;
+use crate::fairings::db::DBConnection;
+use crate::models::organisation::Organisation;
+use crate::states::tenancy::TenancyPolicy;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_db_pools::{
+    sqlx::{Acquire, PgConnection},
+    Connection,
+};
+
+// Organisation the request is for, every query of its users and tickets is scoped by it.
+// Named by the subdomain of the host, else the one of whoever is logged in, else the default one.
+pub struct CurrentOrganisation {
+    pub organisation: Organisation,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CurrentOrganisation {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let error = Outcome::Failure((Status::NotFound, ()));
+        let mut db = match req.guard::<Connection<DBConnection>>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        let connection = match db.acquire().await {
+            Ok(connection) => connection,
+            Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        match host_organisation(req, connection).await {
+            Ok(Some(organisation)) => {
+                return Outcome::Success(CurrentOrganisation { organisation })
+            }
+            Ok(None) => {}
+            Err(status) => return Outcome::Failure((status, ())),
+        }
+        if let Outcome::Success(current_user) = req.guard::<CurrentUser>().await {
+            return Outcome::Success(CurrentOrganisation {
+                organisation: current_user.organisation,
+            });
+        }
+        if let Outcome::Success(current_reporter) = req.guard::<CurrentReporter>().await {
+            return match Organisation::find(
+                connection,
+                &current_reporter.reporter.organisation_uuid,
+            )
+            .await
+            {
+                Ok(organisation) => Outcome::Success(CurrentOrganisation { organisation }),
+                Err(_) => error,
+            };
+        }
+        let default_subdomain = req
+            .rocket()
+            .state::<TenancyPolicy>()
+            .and_then(|policy| policy.default_subdomain.as_deref());
+        let default_subdomain = match default_subdomain {
+            Some(default_subdomain) => default_subdomain,
+            None => return error,
+        };
+        match Organisation::find_by_subdomain(connection, default_subdomain).await {
+            Ok(organisation) => Outcome::Success(CurrentOrganisation { organisation }),
+            Err(_) => error,
+        }
+    }
+}
+
+// Organisation named by the subdomain of the host, None when the host names none.
+// A subdomain nobody registered is a 404, the request must not fall back to another organisation.
+pub(crate) async fn host_organisation(
+    req: &Request<'_>,
+    connection: &mut PgConnection,
+) -> Result<Option<Organisation>, Status> {
+    let policy = match req.rocket().state::<TenancyPolicy>() {
+        Some(policy) => policy,
+        None => return Ok(None),
+    };
+    let host = match req.headers().get_one("Host") {
+        Some(host) => host.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let subdomain = match policy.subdomain(&host) {
+        Some(subdomain) => subdomain,
+        None => return Ok(None),
+    };
+    // matched right away, the error must not live across another await
+    match Organisation::find_by_subdomain(connection, subdomain).await {
+        Ok(organisation) => Ok(Some(organisation)),
+        Err(_) => Err(Status::NotFound),
+    }
+}
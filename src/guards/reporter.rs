@@ -0,0 +1,111 @@
+use super::auth::CurrentUser;
+use super::impersonation::{ActiveImpersonation, IMPERSONATION_COOKIE_NAME};
+use super::organisation::host_organisation;
+use crate::fairings::db::DBConnection;
+use crate::models::impersonation::Impersonation;
+use crate::models::reporter::Reporter;
+use crate::models::user_status::UserStatus;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::Serialize;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+
+pub const REPORTER_COOKIE_NAME: &str = "reporter_uuid";
+
+// reporter logged in to the portal, agents use CurrentUser instead
+#[derive(Serialize)]
+pub struct CurrentReporter {
+    pub reporter: Reporter,
+}
+
+impl CurrentReporter {
+    // company name to match tickets on when the reporter may see the whole company
+    pub fn company_scope(&self) -> Option<&str> {
+        if self.reporter.company_access {
+            Some(self.reporter.company_name.as_str())
+        } else {
+            None
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CurrentReporter {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let error = Outcome::Failure((Status::Unauthorized, ()));
+        if let Some(reporter) = impersonated_reporter(req).await {
+            return Outcome::Success(CurrentReporter { reporter });
+        }
+        let parsed_cookie = req.cookies().get_private(REPORTER_COOKIE_NAME);
+        if parsed_cookie.is_none() {
+            return error;
+        }
+        let cookie = parsed_cookie.unwrap();
+        let uuid = cookie.value();
+        let parsed_db = req.guard::<Connection<DBConnection>>().await;
+        if !parsed_db.is_success() {
+            return error;
+        }
+        let mut db = parsed_db.unwrap();
+        let parsed_connection = db.acquire().await;
+        if parsed_connection.is_err() {
+            return error;
+        }
+        let connection = parsed_connection.unwrap();
+        let found_reporter = Reporter::find(connection, uuid).await;
+        if found_reporter.is_err() {
+            return error;
+        }
+        let reporter = found_reporter.unwrap();
+        if !matches!(reporter.status, UserStatus::Active) {
+            return error;
+        }
+        // a reporter is only logged in on the host of their own organisation
+        match host_organisation(req, connection).await {
+            Ok(Some(organisation)) if organisation.uuid != reporter.organisation_uuid => error,
+            Ok(_) => Outcome::Success(CurrentReporter { reporter }),
+            Err(_) => error,
+        }
+    }
+}
+
+// Reporter a supervisor is viewing the portal as, applied over any reporter cookie.
+// The impersonation cookie alone is not enough, its impersonator has to be logged in.
+async fn impersonated_reporter(req: &Request<'_>) -> Option<Reporter> {
+    let cookie = req.cookies().get_private(IMPERSONATION_COOKIE_NAME)?;
+    let current_user = match req.guard::<CurrentUser>().await {
+        Outcome::Success(current_user) if current_user.impersonator.is_none() => current_user,
+        _ => return None,
+    };
+    if !current_user.permissions().impersonate {
+        return None;
+    }
+    let mut db = match req.guard::<Connection<DBConnection>>().await {
+        Outcome::Success(db) => db,
+        _ => return None,
+    };
+    let connection = db.acquire().await.ok()?;
+    let impersonation =
+        Impersonation::find_active(connection, cookie.value(), &current_user.user.uuid)
+            .await
+            .ok()?;
+    let reporter_uuid = impersonation.reporter_uuid?;
+    let reporter = Reporter::find(connection, &reporter_uuid.to_string())
+        .await
+        .ok()
+        .filter(|reporter| {
+            matches!(reporter.status, UserStatus::Active)
+                && reporter.organisation_uuid == current_user.organisation.uuid
+        })?;
+    ActiveImpersonation::apply(
+        req,
+        ActiveImpersonation {
+            impersonation,
+            impersonator: current_user.user.username.clone(),
+            target: reporter.name.clone(),
+        },
+    );
+    Some(reporter)
+}
@@ -0,0 +1,60 @@
+use super::auth::CurrentUser;
+use crate::models::user_role::{Permissions, UserRole};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::marker::PhantomData;
+
+// lowest role a route accepts
+pub trait RoleLevel: Send + Sync + 'static {
+    const ROLE: UserRole;
+}
+
+pub struct Agent;
+
+impl RoleLevel for Agent {
+    const ROLE: UserRole = UserRole::Agent;
+}
+
+pub struct Supervisor;
+
+impl RoleLevel for Supervisor {
+    const ROLE: UserRole = UserRole::Supervisor;
+}
+
+pub struct Admin;
+
+impl RoleLevel for Admin {
+    const ROLE: UserRole = UserRole::Admin;
+}
+
+// Logged in user holding at least the role R.
+// Fails with 401 when nobody is logged in and 403 when the role is too low.
+pub struct RequireRole<R: RoleLevel> {
+    pub current_user: CurrentUser,
+    role: PhantomData<R>,
+}
+
+impl<R: RoleLevel> RequireRole<R> {
+    pub fn permissions(&self) -> Permissions {
+        self.current_user.permissions()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, R: RoleLevel> FromRequest<'r> for RequireRole<R> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.guard::<CurrentUser>().await {
+            Outcome::Success(current_user) if current_user.user.role >= R::ROLE => {
+                Outcome::Success(RequireRole {
+                    current_user,
+                    role: PhantomData,
+                })
+            }
+            Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
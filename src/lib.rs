@@ -4,8 +4,10 @@ extern crate rocket;
 pub mod catchers;
 pub mod errors;
 pub mod fairings;
+pub mod filters;
+pub mod gateways;
 pub mod models;
 pub mod routes;
 pub mod guards;
-// pub mod states;
-// pub mod traits;
+pub mod states;
+pub mod traits;
@@ -2,8 +2,18 @@
 extern crate rocket;
 
 use our_application::catchers;
-use our_application::fairings::{csrf::Csrf, db::DBConnection};
-use our_application::routes::{self, issues_reported, user};
+use our_application::fairings::{
+    alerts::Alerts, csrf::Csrf, db::DBConnection, impersonation::Impersonations, mail::Mail,
+    oidc::Oidc, passkeys::Passkeys, passwords::Passwords, sessions::Sessions, signing::Signing,
+    sms::Sms, tenancy::Tenancy, webhooks::WebhookWorker,
+};
+use our_application::filters;
+use our_application::routes::{
+    self, activation, alert, impersonation, invitation, issues_reported, oidc, organisation,
+    passkey, password_reset, portal, preferences, reporter, scim, session, sms, team, track,
+    two_factor, user, webhook,
+};
+use our_application::states::rate_limiter::RateLimiter;
 use rocket::fs::relative;
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
@@ -14,12 +24,69 @@ use rocket_dyn_templates::Template;
 async fn rocket() -> Rocket<Build> {
     rocket::build()
         .attach(DBConnection::init())
-        .attach(Template::fairing())
+        .attach(Template::custom(|engines| {
+            engines
+                .tera
+                .register_filter("local_time", filters::local_time);
+        }))
         .attach(Csrf::new())
+        .attach(Signing::new())
+        .attach(Sms::new())
+        .attach(Alerts::new())
+        .attach(WebhookWorker::new())
+        .attach(Mail::new())
+        .attach(Sessions::new())
+        .attach(Passkeys::new())
+        .attach(Passwords::new())
+        .attach(Oidc::new())
+        .attach(Impersonations::new())
+        .attach(Tenancy::new())
+        .manage(RateLimiter::new())
         .mount(
             "/",
             routes![
                 routes::shutdown,
+                session::login_form,
+                session::login,
+                session::logout,
+                session::get_sessions,
+                session::revoke_session,
+                two_factor::login_form,
+                two_factor::login,
+                two_factor::login_setup,
+                two_factor::account,
+                two_factor::enable_account,
+                two_factor::regenerate_codes,
+                two_factor::disable_account,
+                two_factor::reset,
+                two_factor::toggle_required,
+                passkey::get_passkeys,
+                passkey::register_start,
+                passkey::register_finish,
+                passkey::delete_passkey,
+                passkey::login_start,
+                passkey::login_finish,
+                oidc::start,
+                oidc::callback,
+                scim::service_provider_config,
+                scim::get_users,
+                scim::get_user,
+                scim::create_user,
+                scim::replace_user,
+                scim::patch_user,
+                scim::delete_user,
+                scim::get_groups,
+                scim::get_group,
+                scim::patch_group,
+                scim::replace_group,
+                activation::activate,
+                activation::resend_form,
+                activation::resend,
+                password_reset::forgot_form,
+                password_reset::forgot,
+                password_reset::reset_form,
+                password_reset::reset,
+                issues_reported::get_tickets,
                 issues_reported::get_issue,
                 issues_reported::get_issues,
                 issues_reported::manage_issues,
@@ -32,17 +99,78 @@ async fn rocket() -> Rocket<Build> {
                 issues_reported::delete_issue,
                 issues_reported::delete_issue_entry_point,
                 issues_reported::get_open,
+                issues_reported::get_queue,
+                issues_reported::claim,
                 issues_reported::complete, 
+                issues_reported::create_comment,
                 user::get_user,
                 user::get_users,
-                user::new_user,
-                user::create_user,
+                user::bulk_update,
+                invitation::get_invitations,
+                invitation::new_invitation,
+                invitation::create_invitation,
+                invitation::revoke_invitation,
+                invitation::invitation_form,
+                invitation::accept_invitation,
                 user::edit_user,
+                preferences::get_preferences,
+                preferences::update_preferences,
+                organisation::get_settings,
+                organisation::update_settings,
+                organisation::create_token,
+                organisation::revoke_token,
                 user::update_user,
                 user::put_user,
                 user::patch_user,
+                user::force_logout,
+                user::unlock_user,
+                user::change_role,
+                user::change_status,
                 user::delete_user,
-                user::delete_user_entry_point,     
+                user::delete_user_entry_point,
+                user::remove_user_form,
+                webhook::get_webhooks,
+                webhook::get_webhook,
+                webhook::new_webhook,
+                webhook::create_webhook,
+                webhook::delete_webhook,
+                webhook::redeliver,
+                alert::alertmanager,
+                alert::generic,
+                sms::inbound_form,
+                sms::inbound_json,
+                track::track_form,
+                track::track_lookup,
+                track::track_issue,
+                portal::register_form,
+                portal::register,
+                portal::login_form,
+                portal::login,
+                portal::verify_form,
+                portal::verify,
+                portal::send_code,
+                portal::logout,
+                portal::get_tickets,
+                portal::get_ticket,
+                portal::reply,
+                portal::reopen,
+                portal::invitation_form,
+                portal::accept_invitation,
+                reporter::get_reporters,
+                reporter::new_reporter,
+                reporter::invite_reporter,
+                team::get_teams,
+                team::new_team,
+                team::create_team,
+                team::get_team,
+                team::edit_team,
+                team::update_team,
+                team::delete_team,
+                team::add_member,
+                team::remove_member,
+                impersonation::impersonate_user,
+                impersonation::impersonate_reporter,
+                impersonation::end,
 
 
             ],
@@ -52,9 +180,13 @@ async fn rocket() -> Rocket<Build> {
             "/",
             catchers![
                 catchers::bad_request,
+                catchers::unauthorized,
+                catchers::forbidden,
                 catchers::not_found,
                 catchers::unprocessable_entity,
+                catchers::too_many_requests,
                 catchers::internal_server_error
             ],
         )
+        .register("/scim", catchers![catchers::scim])
 }
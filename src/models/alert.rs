@@ -0,0 +1,223 @@
+use regex::{Captures, Regex};
+use rocket::serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub const SOURCE_ALERTMANAGER: &str = "alertmanager";
+pub const SOURCE_GENERIC: &str = "generic";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    fn parse(status: Option<&str>) -> Self {
+        match status.map(|s| s.to_lowercase()).as_deref() {
+            Some("resolved") | Some("ok") | Some("closed") => AlertStatus::Resolved,
+            _ => AlertStatus::Firing,
+        }
+    }
+}
+
+// alert normalised from any of the supported payloads
+#[derive(Debug)]
+pub struct Alert {
+    pub fingerprint: String,
+    pub status: AlertStatus,
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+    pub starts_at: Option<String>,
+    pub generator_url: Option<String>,
+}
+
+impl Alert {
+    // Fills {name} placeholders from the labels, then the annotations,
+    // {status} and {fingerprint} are always available. Unknown names become empty.
+    pub fn fill(&self, template: &str) -> String {
+        let placeholder = Regex::new(r"\{([A-Za-z0-9_]+)\}").unwrap();
+        placeholder
+            .replace_all(template, |caps: &Captures| match &caps[1] {
+                "status" => match self.status {
+                    AlertStatus::Firing => String::from("firing"),
+                    AlertStatus::Resolved => String::from("resolved"),
+                },
+                "fingerprint" => self.fingerprint.clone(),
+                name => self
+                    .labels
+                    .get(name)
+                    .or_else(|| self.annotations.get(name))
+                    .cloned()
+                    .unwrap_or_default(),
+            })
+            .to_string()
+    }
+}
+
+// senders that do not fingerprint their alerts get one computed from the sorted labels
+fn compute_fingerprint(labels: &BTreeMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, value) in labels.iter() {
+        hasher.update(name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerPayload {
+    #[serde(default)]
+    pub alerts: Vec<AlertmanagerAlert>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerAlert {
+    pub status: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    pub starts_at: Option<String>,
+    #[serde(rename = "generatorURL")]
+    pub generator_url: Option<String>,
+    pub fingerprint: Option<String>,
+}
+
+impl AlertmanagerPayload {
+    pub fn into_alerts(self) -> Vec<Alert> {
+        self.alerts
+            .into_iter()
+            .map(|alert| Alert {
+                fingerprint: alert
+                    .fingerprint
+                    .filter(|f| !f.is_empty())
+                    .unwrap_or_else(|| compute_fingerprint(&alert.labels)),
+                status: AlertStatus::parse(alert.status.as_deref()),
+                labels: alert.labels,
+                annotations: alert.annotations,
+                starts_at: alert.starts_at,
+                generator_url: alert.generator_url,
+            })
+            .collect()
+    }
+}
+
+// {"title": "...", "description": "...", "status": "firing", "fingerprint": "...", "labels": {}}
+#[derive(Debug, Deserialize)]
+pub struct GenericAlert {
+    pub title: String,
+    pub description: Option<String>,
+    pub status: Option<String>,
+    pub fingerprint: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub url: Option<String>,
+}
+
+// generic senders may post one alert or a list of them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum GenericAlertPayload {
+    One(GenericAlert),
+    Many(Vec<GenericAlert>),
+}
+
+impl GenericAlertPayload {
+    pub fn into_alerts(self) -> Vec<Alert> {
+        let alerts = match self {
+            GenericAlertPayload::One(alert) => vec![alert],
+            GenericAlertPayload::Many(alerts) => alerts,
+        };
+        alerts
+            .into_iter()
+            .map(|alert| {
+                let fingerprint = alert.fingerprint.filter(|f| !f.is_empty()).unwrap_or_else(|| {
+                    let mut labels = alert.labels.clone();
+                    labels.insert(String::from("title"), alert.title.clone());
+                    compute_fingerprint(&labels)
+                });
+                let mut annotations = BTreeMap::new();
+                annotations.insert(String::from("title"), alert.title);
+                annotations.insert(
+                    String::from("description"),
+                    alert.description.unwrap_or_default(),
+                );
+                Alert {
+                    fingerprint,
+                    status: AlertStatus::parse(alert.status.as_deref()),
+                    labels: alert.labels,
+                    annotations,
+                    starts_at: None,
+                    generator_url: alert.url,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::from_str;
+
+    fn alertmanager(body: &str) -> Vec<Alert> {
+        from_str::<AlertmanagerPayload>(body).unwrap().into_alerts()
+    }
+
+    fn generic(body: &str) -> Vec<Alert> {
+        from_str::<GenericAlertPayload>(body).unwrap().into_alerts()
+    }
+
+    #[test]
+    fn sender_fingerprint_is_the_dedupe_key() {
+        let alerts = alertmanager(
+            r#"{"alerts": [{"status": "firing", "fingerprint": "abc123", "labels": {"alertname": "DiskFull"}}]}"#,
+        );
+        assert_eq!(alerts[0].fingerprint, "abc123");
+        assert_eq!(alerts[0].status, AlertStatus::Firing);
+    }
+
+    #[test]
+    fn computed_fingerprint_ignores_label_order() {
+        let first = alertmanager(
+            r#"{"alerts": [{"labels": {"alertname": "DiskFull", "instance": "db1"}, "fingerprint": ""}]}"#,
+        );
+        let second = alertmanager(
+            r#"{"alerts": [{"labels": {"instance": "db1", "alertname": "DiskFull"}}]}"#,
+        );
+        let other = alertmanager(
+            r#"{"alerts": [{"labels": {"instance": "db2", "alertname": "DiskFull"}}]}"#,
+        );
+        assert_eq!(first[0].fingerprint, second[0].fingerprint);
+        assert_ne!(first[0].fingerprint, other[0].fingerprint);
+        assert_eq!(first[0].fingerprint.len(), 64);
+    }
+
+    #[test]
+    fn generic_alerts_fingerprint_their_title() {
+        let alerts = generic(
+            r#"[{"title": "Disk full", "status": "resolved"}, {"title": "Disk full"}, {"title": "CPU hot"}]"#,
+        );
+        assert_eq!(alerts.len(), 3);
+        assert_eq!(alerts[0].fingerprint, alerts[1].fingerprint);
+        assert_ne!(alerts[0].fingerprint, alerts[2].fingerprint);
+        assert_eq!(alerts[0].status, AlertStatus::Resolved);
+        assert_eq!(alerts[1].status, AlertStatus::Firing);
+    }
+
+    #[test]
+    fn templates_read_labels_then_annotations() {
+        let alerts = alertmanager(
+            r#"{"alerts": [{"status": "resolved", "fingerprint": "abc123", "labels": {"alertname": "DiskFull"}, "annotations": {"summary": "/ is full", "alertname": "ignored"}}]}"#,
+        );
+        assert_eq!(
+            alerts[0].fill("{alertname} {status}: {summary} ({fingerprint}) {missing}"),
+            "DiskFull resolved: / is full (abc123) "
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use super::issues_reported::Issue;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// links an alert fingerprint to the ticket opened for it
+#[derive(Debug, FromRow, Serialize)]
+pub struct AlertTicket {
+    pub uuid: Uuid,
+    pub fingerprint: String,
+    pub issue_uuid: Uuid,
+    pub source: String,
+    pub created_at: OurDateTime,
+}
+
+impl AlertTicket {
+    pub async fn create(
+        connection: &mut PgConnection,
+        fingerprint: &str,
+        issue_uuid: &Uuid,
+        source: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO alert_tickets
+(uuid, fingerprint, issue_uuid, source)
+VALUES
+($1, $2, $3, $4)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(fingerprint)
+            .bind(issue_uuid)
+            .bind(source)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // the open ticket of the organisation for a fingerprint, closed tickets are left alone so a new outage gets a new ticket
+    pub async fn find_open_issue(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        fingerprint: &str,
+    ) -> Result<Option<Issue>, OurError> {
+        let query_str = r#"SELECT issues_reported.* FROM issues_reported
+INNER JOIN alert_tickets ON alert_tickets.issue_uuid = issues_reported.uuid
+WHERE issues_reported.organisation_uuid = $1 AND alert_tickets.fingerprint = $2
+AND issues_reported.status = 'open'
+ORDER BY issues_reported.created_at DESC
+LIMIT 1"#;
+        Ok(sqlx::query_as::<_, Issue>(query_str)
+            .bind(organisation_uuid)
+            .bind(fingerprint)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
@@ -1,4 +0,0 @@
-use rocket_db_pools::sqlx::FromRow;
-
-#[derive(FromRow)]
-pub struct BoolWrapper(pub bool);
@@ -0,0 +1,78 @@
+use super::clean_html;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// note appended to a ticket, public ones are visible to the reporter
+#[derive(Debug, FromRow, Serialize)]
+pub struct Comment {
+    pub uuid: Uuid,
+    pub issue_uuid: Uuid,
+    pub author: String,
+    pub body: String,
+    pub is_public: bool,
+    pub created_at: OurDateTime,
+}
+
+impl Comment {
+    pub async fn create(
+        connection: &mut PgConnection,
+        issue_uuid: &Uuid,
+        author: &str,
+        body: &str,
+        is_public: bool,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO comments
+(uuid, issue_uuid, author, body, is_public)
+VALUES
+($1, $2, $3, $4, $5)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(issue_uuid)
+            .bind(clean_html(author))
+            .bind(clean_html(body))
+            .bind(is_public)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_issue(
+        connection: &mut PgConnection,
+        issue_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM comments WHERE issue_uuid = $1 ORDER BY created_at";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(issue_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // replies the reporter is allowed to see
+    pub async fn find_public_by_issue(
+        connection: &mut PgConnection,
+        issue_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM comments WHERE issue_uuid = $1 AND is_public ORDER BY created_at";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(issue_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewComment<'r> {
+    #[field(validate = len(1..2000).or_else(msg!("comment cannot be empty")))]
+    pub body: &'r str,
+    #[field(default = false)]
+    pub is_public: bool,
+    pub authenticity_token: &'r str,
+}
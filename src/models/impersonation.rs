@@ -0,0 +1,95 @@
+use super::clean_html;
+use super::impersonation_event::{ImpersonationEvent, ACTION_END, ACTION_START};
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// someone with a higher role looking at the application as an agent or a reporter
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Impersonation {
+    pub uuid: Uuid,
+    pub impersonator_uuid: Uuid,
+    pub user_uuid: Option<Uuid>,
+    pub reporter_uuid: Option<Uuid>,
+    pub reason: Option<String>,
+    pub created_at: OurDateTime,
+    pub expires_at: OurDateTime,
+    pub ended_at: Option<OurDateTime>,
+}
+
+impl Impersonation {
+    // exactly one of user_uuid and reporter_uuid is given, the start goes to the log with it
+    pub async fn create(
+        connection: &mut PgConnection,
+        impersonator_uuid: &Uuid,
+        user_uuid: Option<&Uuid>,
+        reporter_uuid: Option<&Uuid>,
+        reason: &str,
+        lifetime: Duration,
+    ) -> Result<Self, OurError> {
+        let reason = Some(clean_html(reason.trim())).filter(|reason| !reason.is_empty());
+        let query_str = r#"INSERT INTO impersonations
+(uuid, impersonator_uuid, user_uuid, reporter_uuid, reason, expires_at)
+VALUES
+($1, $2, $3, $4, $5, $6)
+RETURNING *"#;
+        let impersonation = sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(impersonator_uuid)
+            .bind(user_uuid)
+            .bind(reporter_uuid)
+            .bind(reason)
+            .bind(OurDateTime(Utc::now() + lifetime))
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        ImpersonationEvent::create(connection, &impersonation.uuid, ACTION_START, None).await?;
+        Ok(impersonation)
+    }
+
+    // only the impersonator who started it can use it, and only until it ends or expires
+    pub async fn find_active(
+        connection: &mut PgConnection,
+        uuid: &str,
+        impersonator_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"SELECT * FROM impersonations
+WHERE uuid = $1 AND impersonator_uuid = $2 AND ended_at IS NULL AND expires_at > $3"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(impersonator_uuid)
+            .bind(OurDateTime(Utc::now()))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // ending twice does nothing, the end is logged once
+    pub async fn end(connection: &mut PgConnection, uuid: &str) -> Result<Option<Self>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "UPDATE impersonations SET ended_at = $1 WHERE uuid = $2 AND ended_at IS NULL RETURNING *";
+        let impersonation = sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .fetch_optional(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        if let Some(impersonation) = &impersonation {
+            ImpersonationEvent::create(connection, &impersonation.uuid, ACTION_END, None).await?;
+        }
+        Ok(impersonation)
+    }
+}
+
+#[derive(FromForm)]
+pub struct ImpersonationRequest<'r> {
+    #[field(validate = len(..200).or_else(msg!("reason is too long")))]
+    pub reason: &'r str,
+    pub authenticity_token: &'r str,
+}
@@ -0,0 +1,81 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const ACTION_START: &str = "start";
+pub const ACTION_END: &str = "end";
+pub const ACTION_WRITE: &str = "write";
+
+// the request an impersonator made on behalf of someone else
+pub struct WriteRequest<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub status: u16,
+}
+
+// one line of the impersonation log
+#[derive(Debug, FromRow, Serialize)]
+pub struct ImpersonationEvent {
+    pub uuid: Uuid,
+    pub impersonation_uuid: Uuid,
+    pub action: String,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<i32>,
+    pub created_at: OurDateTime,
+    // joined for display
+    pub impersonator_username: String,
+    pub target_name: String,
+}
+
+impl ImpersonationEvent {
+    pub async fn create(
+        connection: &mut PgConnection,
+        impersonation_uuid: &Uuid,
+        action: &str,
+        request: Option<&WriteRequest<'_>>,
+    ) -> Result<(), OurError> {
+        let query_str = r#"INSERT INTO impersonation_events
+(uuid, impersonation_uuid, action, method, path, status)
+VALUES
+($1, $2, $3, $4, $5, $6)"#;
+        sqlx::query(query_str)
+            .bind(Uuid::new_v4())
+            .bind(impersonation_uuid)
+            .bind(action)
+            .bind(request.map(|request| request.method))
+            .bind(request.map(|request| request.path))
+            .bind(request.map(|request| request.status as i32))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // what a user did as someone else and what was done as them, most recent first
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT impersonation_events.*,
+impersonators.username AS impersonator_username,
+COALESCE(users.username, reporters.name) AS target_name
+FROM impersonation_events
+JOIN impersonations ON impersonations.uuid = impersonation_events.impersonation_uuid
+JOIN users AS impersonators ON impersonators.uuid = impersonations.impersonator_uuid
+LEFT JOIN users ON users.uuid = impersonations.user_uuid
+LEFT JOIN reporters ON reporters.uuid = impersonations.reporter_uuid
+WHERE impersonations.impersonator_uuid = $1 OR impersonations.user_uuid = $1
+ORDER BY impersonation_events.created_at DESC
+LIMIT $2"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
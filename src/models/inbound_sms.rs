@@ -0,0 +1,92 @@
+use super::sms_message::parse_contact_number;
+use rocket::form::FromForm;
+use rocket::serde::Deserialize;
+
+// longest ticket name we derive from the first words of a message
+const ISSUE_NAME_LENGTH: usize = 19;
+
+// text message forwarded by the gateway, "from" is the sender number in any common format
+#[derive(Debug, FromForm, Deserialize)]
+pub struct InboundSms {
+    #[field(validate = len(1..))]
+    pub from: String,
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SmsCommand {
+    // STATUS 123
+    Status(i64),
+    // anything else is a report or a follow up on the open ticket
+    Report,
+}
+
+impl InboundSms {
+    // digits of the sender number, which is how contact numbers are stored
+    pub fn contact_number(&self) -> Option<i64> {
+        parse_contact_number(&self.from)
+    }
+
+    pub fn command(&self) -> SmsCommand {
+        let mut words = self.body.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some(keyword), Some(number), None) if keyword.eq_ignore_ascii_case("status") => {
+                match number.trim_start_matches('#').parse::<i64>() {
+                    Ok(ticket_number) => SmsCommand::Status(ticket_number),
+                    Err(_) => SmsCommand::Report,
+                }
+            }
+            _ => SmsCommand::Report,
+        }
+    }
+
+    pub fn issue_name(&self) -> String {
+        let name = self.body.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.chars().count() <= ISSUE_NAME_LENGTH {
+            return name;
+        }
+        name.chars().take(ISSUE_NAME_LENGTH - 3).collect::<String>() + "..."
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sms(body: &str) -> InboundSms {
+        InboundSms {
+            from: String::from("+264 81 123 4567"),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn status_keyword_takes_a_ticket_number() {
+        assert_eq!(sms("STATUS 42").command(), SmsCommand::Status(42));
+        assert_eq!(sms("  status   #42 ").command(), SmsCommand::Status(42));
+        assert_eq!(sms("Status 42").command(), SmsCommand::Status(42));
+    }
+
+    #[test]
+    fn anything_else_is_a_report() {
+        assert_eq!(sms("STATUS").command(), SmsCommand::Report);
+        assert_eq!(sms("STATUS abc").command(), SmsCommand::Report);
+        assert_eq!(sms("STATUS 42 please").command(), SmsCommand::Report);
+        assert_eq!(sms("statuses 42").command(), SmsCommand::Report);
+        assert_eq!(sms("The printer is on fire").command(), SmsCommand::Report);
+    }
+
+    #[test]
+    fn sender_number_keeps_only_digits() {
+        assert_eq!(sms("").contact_number(), Some(264811234567));
+    }
+
+    #[test]
+    fn issue_name_is_the_first_words() {
+        assert_eq!(sms("  Printer\non fire ").issue_name(), "Printer on fire");
+        assert_eq!(
+            sms("The printer on the third floor is on fire").issue_name(),
+            "The printer on t..."
+        );
+    }
+}
@@ -2,6 +2,7 @@ use crate::errors::our_error::OurError;
 use crate::fairings::db::DBConnection;
 
 use super::clean_html;
+use super::organisation::Organisation;
 use super::our_date_time::OurDateTime;
 use chrono::offset::Utc;
 use rocket::form::FromForm;
@@ -10,11 +11,14 @@ use rocket_db_pools::sqlx::{Acquire, FromRow, PgConnection};
 use rocket_db_pools::Connection;
 use uuid::Uuid;
 
+// owner of the tickets nobody has claimed yet
+pub const UNASSIGNED_OWNER: &str = "orphan";
 
 // main Struct for issues reported
 #[derive(Debug, FromRow, FromForm, Serialize)]
 pub struct Issue {
     pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
     pub issue_name: String,
     pub description: String,
     pub reported_by: String,
@@ -23,16 +27,26 @@ pub struct Issue {
     pub ticket_number: i64,
     pub ticket_owner: String,
     pub status: String,
+    // queue of the team the ticket was routed to, if any
+    pub team_uuid: Option<Uuid>,
+    pub closed_at: Option<OurDateTime>,
     pub created_at: OurDateTime,
     pub updated_at: OurDateTime,
 }
 
+// Every query is scoped by the organisation of the request,
+// a ticket of another organisation is as good as missing.
 impl Issue {
     // fn to retrieve row by uuid
-    pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "SELECT * FROM issues_reported WHERE uuid = $1";
+        let query_str = "SELECT * FROM issues_reported WHERE organisation_uuid = $1 AND uuid = $2";
         Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
             .bind(parsed_uuid)
             .fetch_one(connection)
             .await
@@ -42,10 +56,12 @@ impl Issue {
     // fn to retrieve all rows
     pub async fn find_all(
         db: &mut Connection<DBConnection>,
+        organisation_uuid: &Uuid,
     ) -> Result<Vec<Self>, OurError> {
-        let query_str = "SELECT * FROM issues_reported ORDER BY created_at DESC LIMIT $1";
+        let query_str = "SELECT * FROM issues_reported WHERE organisation_uuid = $1 ORDER BY created_at DESC LIMIT $2";
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
         let issues = sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
             .bind(10)
             .fetch_all(connection)
             .await
@@ -53,29 +69,142 @@ impl Issue {
         Ok(issues)
     }
 
-    // fn to retrieve last row of issues_reported, used to check last ticket number
-    pub async fn find_last_token(
-        db: &mut Connection<DBConnection>,
+    // fn to retrieve the open tickets of a team queue, or those in no queue without a team
+    pub async fn find_open(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        team_uuid: Option<&Uuid>,
     ) -> Result<Vec<Self>, OurError> {
-        let query_str = "SELECT * FROM issues_reported ORDER BY created_at DESC LIMIT $1";
-        let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let last_row = sqlx::query_as::<_, Self>(query_str)
-            .bind(1)
+        let query_str = "SELECT * FROM issues_reported WHERE organisation_uuid = $1 AND status = 'open' AND team_uuid IS NOT DISTINCT FROM $2 ORDER BY created_at";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(team_uuid)
             .fetch_all(connection)
             .await
-            .map_err(OurError::from_sqlx_error)?;
-        Ok(last_row)
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to retrieve row by the number given to the reporter
+    pub async fn find_by_ticket_number(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        ticket_number: i64,
+    ) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM issues_reported WHERE organisation_uuid = $1 AND ticket_number = $2 ORDER BY created_at DESC LIMIT 1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(ticket_number)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to retrieve the most recent open ticket reported from a contact number
+    pub async fn find_open_by_contact_number(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        contact_number: i64,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = "SELECT * FROM issues_reported WHERE organisation_uuid = $1 AND contact_number = $2 AND status = 'open' ORDER BY created_at DESC LIMIT 1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(contact_number)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
     }
 
-    // fn to create new instance of Issue strcut
+    // fn to retrieve the tickets a reporter may see, from their number or, with company access, their company
+    pub async fn find_for_reporter(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        contact_number: Option<i64>,
+        company_name: Option<&str>,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT * FROM issues_reported
+WHERE organisation_uuid = $1
+AND (($2::BIGINT IS NOT NULL AND contact_number = $2)
+OR ($3::VARCHAR IS NOT NULL AND LOWER(company_name) = LOWER($3)))
+ORDER BY created_at DESC"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(contact_number)
+            .bind(company_name)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to count the tickets a user owns
+    pub async fn count_by_owner(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        ticket_owner: &str,
+    ) -> Result<i64, OurError> {
+        let query_str = "SELECT COUNT(*) FROM issues_reported WHERE organisation_uuid = $1 AND ticket_owner = $2";
+        Ok(sqlx::query_scalar::<_, i64>(query_str)
+            .bind(organisation_uuid)
+            .bind(ticket_owner)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to hand every ticket of one owner to another, returns the tickets moved
+    // tickets of a team queue only go to a member of the team, else back to the queue unclaimed
+    pub async fn reassign_owner(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        from_owner: &str,
+        to_owner: &str,
+        to_owner_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"UPDATE issues_reported SET ticket_owner = CASE
+WHEN team_uuid IS NULL OR EXISTS (SELECT 1 FROM team_members
+    WHERE team_members.team_uuid = issues_reported.team_uuid AND team_members.user_uuid = $2)
+THEN $1 ELSE $3 END, updated_at = $4
+WHERE organisation_uuid = $5 AND ticket_owner = $6
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(to_owner)
+            .bind(to_owner_uuid)
+            .bind(UNASSIGNED_OWNER)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
+            .bind(from_owner)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to take an unclaimed open ticket, None when someone else got it first
+    pub async fn claim(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        ticket_owner: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = "UPDATE issues_reported SET ticket_owner = $1, updated_at = $2 WHERE organisation_uuid = $3 AND uuid = $4 AND ticket_owner = $5 AND status = 'open' RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(ticket_owner)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
+            .bind(uuid)
+            .bind(UNASSIGNED_OWNER)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fn to create new instance of Issue strcut, numbered after the last ticket of the organisation
     pub async fn create<'r>(
         connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
         new_issue: &'r NewIssue<'r>,
-        ticket_number: i64,
     ) -> Result<Self, OurError> {
         // default values for new ticket
         let uuid = Uuid::new_v4();
-        let ticket_owner = "orphan".to_string();
+        let ticket_owner = UNASSIGNED_OWNER.to_string();
         let status = "open".to_string();
 
         // data provided by user input
@@ -84,18 +213,19 @@ impl Issue {
         let reported_by = &(clean_html(new_issue.reported_by));
         let company_name = &(clean_html(new_issue.company_name));
         let contact_number = &(clean_html(new_issue.contact_number)).parse::<i64>().unwrap();
-        let ticket_number = ticket_number;
+        let ticket_number = Organisation::take_ticket_number(connection, organisation_uuid).await?;
         let ticket_owner = ticket_owner;
         let status = status;
 
         // psql query
         let query_str = r#"INSERT INTO issues_reported
-(uuid, issue_name, description, reported_by, company_name, contact_number, ticket_number, ticket_owner, status )
+(uuid, organisation_uuid, issue_name, description, reported_by, company_name, contact_number, ticket_number, ticket_owner, status )
 VALUES
-($1, $2, $3, $4, $5, $6, $7, $8, $9)
+($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
 RETURNING *"#;
         Ok(sqlx::query_as::<_, Self>(query_str)
             .bind(uuid)
+            .bind(organisation_uuid)
             .bind(issue_name)
             .bind(description)
             .bind(reported_by)
@@ -113,6 +243,7 @@ RETURNING *"#;
     // fn to update a row
     pub async fn update<'r>(
         db: &mut Connection<DBConnection>,
+        organisation_uuid: &Uuid,
         uuid: &'r str,
         issue: &'r EditedIssue<'r>,
     ) -> Result<Self, OurError> {
@@ -136,13 +267,15 @@ RETURNING *"#;
             "ticket_number = $6",
             "ticket_owner = $7",
             "status = $8",
-            "updated_at = $9",
+            "team_uuid = $9",
+            "closed_at = CASE WHEN $8 = 'closed' THEN COALESCE(closed_at, $10) ELSE NULL END",
+            "updated_at = $10",
         ];
-        let where_string = "$10";
+        let where_string = "$12";
 
         // psql query
         let query_str = format!(
-            r#"UPDATE issues_reported SET {} WHERE uuid = {} RETURNING *"#,
+            r#"UPDATE issues_reported SET {} WHERE organisation_uuid = $11 AND uuid = {} RETURNING *"#,
             set_strings.join(", "),
             where_string,
         );
@@ -157,7 +290,9 @@ RETURNING *"#;
             .bind(ticket_number)
             .bind(ticket_owner)
             .bind(status)
-            .bind(&now);
+            .bind(issue.team_uuid)
+            .bind(&now)
+            .bind(organisation_uuid);
 
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
         Ok(binded
@@ -170,10 +305,15 @@ RETURNING *"#;
     }
 
     // fn to delete row from table
-    pub async fn destroy(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
+    pub async fn destroy(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<(), OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "DELETE FROM issues_reported WHERE uuid = $1";
+        let query_str = "DELETE FROM issues_reported WHERE organisation_uuid = $1 AND uuid = $2";
         sqlx::query(query_str)
+            .bind(organisation_uuid)
             .bind(parsed_uuid)
             .execute(connection)
             .await
@@ -181,16 +321,43 @@ RETURNING *"#;
         Ok(())
     }
 
+    // fn to set status back to open
+    pub async fn reopen(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "UPDATE issues_reported SET status = 'open', closed_at = NULL, updated_at = $1 WHERE organisation_uuid = $2 AND uuid = $3 RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
     // fn to set status to complete
-    pub async fn complete(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
+    // fn to close an issue, also tells whether this call closed it or it was closed already
+    pub async fn complete(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<(Self, bool), OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "UPDATE issues_reported SET status = 'closed' WHERE uuid = $1";
-        sqlx::query(query_str)
+        let query_str = "UPDATE issues_reported SET status = 'closed', closed_at = COALESCE(closed_at, $1), updated_at = $1 WHERE organisation_uuid = $2 AND uuid = $3 AND status <> 'closed' RETURNING *";
+        let closed = sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
             .bind(parsed_uuid)
-            .execute(connection)
+            .fetch_optional(&mut *connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
-        Ok(())
+        match closed {
+            Some(issue) => Ok((issue, true)),
+            None => Ok((Self::find(connection, organisation_uuid, uuid).await?, false)),
+        }
     }
         
 }
@@ -230,6 +397,8 @@ pub struct EditedIssue<'r> {
     pub ticket_number: &'r str,
     pub ticket_owner: &'r str,
     pub status: &'r str,
+    // left empty for no team
+    pub team_uuid: Option<Uuid>,
     #[field(default = "")]
     pub authenticity_token: &'r str,
 }
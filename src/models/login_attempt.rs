@@ -0,0 +1,155 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const METHOD_PASSWORD: &str = "password";
+pub const METHOD_TOTP: &str = "totp";
+pub const METHOD_PASSKEY: &str = "passkey";
+pub const METHOD_OIDC: &str = "oidc";
+
+pub const OUTCOME_SUCCEEDED: &str = "succeeded";
+pub const OUTCOME_UNKNOWN_USER: &str = "unknown_user";
+pub const OUTCOME_WRONG_PASSWORD: &str = "wrong_password";
+pub const OUTCOME_WRONG_CODE: &str = "wrong_code";
+pub const OUTCOME_INVALID_PASSKEY: &str = "invalid_passkey";
+pub const OUTCOME_LOCKED: &str = "locked";
+pub const OUTCOME_INACTIVE: &str = "inactive";
+pub const OUTCOME_THROTTLED: &str = "throttled";
+pub const OUTCOME_SSO_REQUIRED: &str = "sso_required";
+pub const OUTCOME_NOT_ALLOWED: &str = "not_allowed";
+
+const USERNAME_LENGTH: usize = 255;
+const USER_AGENT_LENGTH: usize = 255;
+
+// one try at logging in, whatever came out of it
+#[derive(Debug, FromRow, Serialize)]
+pub struct LoginAttempt {
+    pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
+    pub username: String,
+    pub user_uuid: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub outcome: String,
+    pub created_at: OurDateTime,
+}
+
+impl LoginAttempt {
+    pub async fn create(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        username: &str,
+        user_uuid: Option<&Uuid>,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+        method: &str,
+        outcome: &str,
+    ) -> Result<(), OurError> {
+        let username = username.chars().take(USERNAME_LENGTH).collect::<String>();
+        let user_agent = user_agent.map(|user_agent| {
+            user_agent
+                .chars()
+                .take(USER_AGENT_LENGTH)
+                .collect::<String>()
+        });
+        let query_str = r#"INSERT INTO login_attempts
+(uuid, organisation_uuid, username, user_uuid, ip_address, user_agent, method, outcome)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8)"#;
+        sqlx::query(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(username)
+            .bind(user_uuid)
+            .bind(ip_address)
+            .bind(user_agent)
+            .bind(method)
+            .bind(outcome)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // wrong guesses at a username of the organisation within the window, the same for existing and
+    // unknown users
+    pub async fn count_failures_for_username(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        username: &str,
+        window: Duration,
+    ) -> Result<i64, OurError> {
+        let query_str = r#"SELECT COUNT(*) FROM login_attempts
+WHERE organisation_uuid = $1 AND username = $2 AND outcome IN ($3, $4) AND created_at > $5"#;
+        let (count,): (i64,) = sqlx::query_as(query_str)
+            .bind(organisation_uuid)
+            .bind(username)
+            .bind(OUTCOME_UNKNOWN_USER)
+            .bind(OUTCOME_WRONG_PASSWORD)
+            .bind(OurDateTime(Utc::now() - window))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(count)
+    }
+
+    // time of the latest wrong guess at a username of the organisation, None when there was none
+    pub async fn last_failure_for_username(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        username: &str,
+    ) -> Result<Option<OurDateTime>, OurError> {
+        let query_str = r#"SELECT MAX(created_at) FROM login_attempts
+WHERE organisation_uuid = $1 AND username = $2 AND outcome IN ($3, $4)"#;
+        let (last,): (Option<OurDateTime>,) = sqlx::query_as(query_str)
+            .bind(organisation_uuid)
+            .bind(username)
+            .bind(OUTCOME_UNKNOWN_USER)
+            .bind(OUTCOME_WRONG_PASSWORD)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(last)
+    }
+
+    // wrong guesses from an address within the window, whatever username they tried
+    pub async fn count_failures_for_ip(
+        connection: &mut PgConnection,
+        ip_address: &str,
+        window: Duration,
+    ) -> Result<i64, OurError> {
+        let query_str = r#"SELECT COUNT(*) FROM login_attempts
+WHERE ip_address = $1 AND outcome IN ($2, $3) AND created_at > $4"#;
+        let (count,): (i64,) = sqlx::query_as(query_str)
+            .bind(ip_address)
+            .bind(OUTCOME_UNKNOWN_USER)
+            .bind(OUTCOME_WRONG_PASSWORD)
+            .bind(OurDateTime(Utc::now() - window))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(count)
+    }
+
+    // latest attempts on an account, for admins
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT * FROM login_attempts
+WHERE user_uuid = $1
+ORDER BY created_at DESC
+LIMIT $2"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
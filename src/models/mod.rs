@@ -1,12 +1,39 @@
 use ammonia::Builder;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
 use std::collections::hash_set::HashSet;
 
+pub mod alert;
+pub mod alert_ticket;
+pub mod comment;
+pub mod impersonation;
+pub mod impersonation_event;
+pub mod inbound_sms;
+pub mod login_attempt;
+pub mod notification_setting;
+pub mod organisation;
+pub mod organisation_token;
 pub mod our_date_time;
-pub mod bool_wrapper;
 pub mod issues_reported;
 pub mod user;
+pub mod user_invitation;
+pub mod user_preferences;
+pub mod user_role;
 pub mod user_status;
-pub mod pagination;
+pub mod user_status_change;
+pub mod recovery_code;
+pub mod passkey;
+pub mod password_reset;
+pub mod reporter;
+pub mod scim;
+pub mod session;
+pub mod sms_message;
+pub mod sms_notification;
+pub mod team;
+pub mod team_member;
+pub mod totp;
+pub mod webhook;
+pub mod webhook_delivery;
 
 pub fn clean_html(src: &str) -> String {
     Builder::default()
@@ -14,3 +41,17 @@ pub fn clean_html(src: &str) -> String {
         .clean(src)
         .to_string()
 }
+
+const TOKEN_LENGTH: usize = 32;
+
+// random url safe token for cookies and emailed links
+pub(crate) fn generate_token() -> String {
+    let mut key = vec![0; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut key);
+    base64::encode_config(key, base64::URL_SAFE_NO_PAD)
+}
+
+// what we store instead of a token, a leaked table cannot be replayed
+pub(crate) fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,111 @@
+use super::our_date_time::OurDateTime;
+use super::user::User;
+use super::user_status::UserStatus;
+use crate::errors::our_error::OurError;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const EVENT_TICKET_ASSIGNED: &str = "ticket_assigned";
+pub const EVENT_TICKET_COMMENTED: &str = "ticket_commented";
+pub const EVENT_TICKET_CLOSED: &str = "ticket_closed";
+
+pub const CHANNEL_EMAIL: &str = "email";
+
+// listed on the preferences page, event and what it means for the ticket owner
+pub const ALL_EVENTS: [(&str, &str); 3] = [
+    (EVENT_TICKET_ASSIGNED, "A ticket is assigned to me"),
+    (EVENT_TICKET_COMMENTED, "Someone comments on my ticket"),
+    (EVENT_TICKET_CLOSED, "Someone closes my ticket"),
+];
+
+pub const ALL_CHANNELS: [&str; 1] = [CHANNEL_EMAIL];
+
+// a user opting in to hear about an event through a channel
+#[derive(Debug, FromRow, Serialize)]
+pub struct NotificationSetting {
+    pub user_uuid: Uuid,
+    pub event: String,
+    pub channel: String,
+    pub created_at: OurDateTime,
+}
+
+impl NotificationSetting {
+    // how the preferences form names an event on a channel
+    pub fn key(event: &str, channel: &str) -> String {
+        format!("{}:{}", event, channel)
+    }
+
+    // known events and channels only, whatever else a form sends is dropped
+    pub fn parse(key: &str) -> Option<(&'static str, &'static str)> {
+        let (event, channel) = key.split_once(':')?;
+        let event = ALL_EVENTS.iter().map(|(e, _)| *e).find(|e| *e == event)?;
+        let channel = ALL_CHANNELS.iter().copied().find(|c| *c == channel)?;
+        Some((event, channel))
+    }
+
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM user_notification_settings WHERE user_uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // swaps all the opt-ins of a user, meant to run in the caller's transaction
+    pub async fn replace(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        settings: &[(&str, &str)],
+    ) -> Result<(), OurError> {
+        let query_str = "DELETE FROM user_notification_settings WHERE user_uuid = $1";
+        sqlx::query(query_str)
+            .bind(user_uuid)
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let query_str = r#"INSERT INTO user_notification_settings
+(user_uuid, event, channel)
+VALUES
+($1, $2, $3)
+ON CONFLICT DO NOTHING"#;
+        for (event, channel) in settings {
+            sqlx::query(query_str)
+                .bind(user_uuid)
+                .bind(event)
+                .bind(channel)
+                .execute(&mut *connection)
+                .await
+                .map_err(OurError::from_sqlx_error)?;
+        }
+        Ok(())
+    }
+
+    // The user of the organisation behind a ticket owner's name when they opted in to the event on the channel.
+    // Users who cannot log in any more are not notified.
+    pub async fn find_subscriber(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        username: &str,
+        event: &str,
+        channel: &str,
+    ) -> Result<Option<User>, OurError> {
+        let query_str = r#"SELECT users.* FROM users
+JOIN user_notification_settings ON user_notification_settings.user_uuid = users.uuid
+WHERE users.organisation_uuid = $5 AND users.username = $1 AND user_notification_settings.event = $2
+AND user_notification_settings.channel = $3 AND users.status = $4 AND users.deleted_at IS NULL"#;
+        Ok(sqlx::query_as::<_, User>(query_str)
+            .bind(username)
+            .bind(event)
+            .bind(channel)
+            .bind(UserStatus::Active)
+            .bind(organisation_uuid)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
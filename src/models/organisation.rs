@@ -0,0 +1,102 @@
+use super::clean_html;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::offset::Utc;
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// client company the application is run for, its users, tickets and settings are its own
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Organisation {
+    pub uuid: Uuid,
+    pub name: String,
+    pub subdomain: String,
+    pub reporter_registration: bool,
+    pub next_ticket_number: i64,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+}
+
+impl Organisation {
+    pub async fn find(connection: &mut PgConnection, uuid: &Uuid) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM organisations WHERE uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_subdomain(
+        connection: &mut PgConnection,
+        subdomain: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM organisations WHERE subdomain = LOWER($1)";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(subdomain)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // the organisation a user is a member of, for requests whose host does not name one
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"SELECT organisations.* FROM organisations
+INNER JOIN users ON users.organisation_uuid = organisations.uuid
+WHERE users.uuid = $1"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // Hands out the next ticket number of the organisation.
+    // The row stays locked until the caller's transaction ends, two tickets never get the same number.
+    pub async fn take_ticket_number(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<i64, OurError> {
+        let query_str = r#"UPDATE organisations SET next_ticket_number = next_ticket_number + 1
+WHERE uuid = $1
+RETURNING next_ticket_number - 1"#;
+        Ok(sqlx::query_scalar::<_, i64>(query_str)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn update_settings<'r>(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        settings: &'r OrganisationSettings<'r>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE organisations SET name = $1, reporter_registration = $2, updated_at = $3
+WHERE uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(clean_html(settings.name.trim()))
+            .bind(settings.reporter_registration)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+// what the admins of an organisation can change about it, the subdomain is set up with the host
+#[derive(Debug, FromForm)]
+pub struct OrganisationSettings<'r> {
+    #[field(validate = len(1..50).or_else(msg!("name cannot be empty")))]
+    pub name: &'r str,
+    #[field(default = false)]
+    pub reporter_registration: bool,
+    pub authenticity_token: &'r str,
+}
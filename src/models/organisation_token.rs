@@ -0,0 +1,99 @@
+use super::organisation::Organisation;
+use super::our_date_time::OurDateTime;
+use super::{generate_token, hash_token};
+use crate::errors::our_error::OurError;
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// Bearer token of a machine client (monitoring, SMS gateway, provisioning) of one organisation.
+// Only the hash is stored, whoever holds the token acts for that organisation and no other.
+#[derive(Debug, FromRow, Serialize)]
+pub struct OrganisationToken {
+    pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
+    pub scope: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: OurDateTime,
+}
+
+impl OrganisationToken {
+    // returns the token to show once, the earlier token of the scope stops working
+    pub async fn issue(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        scope: &str,
+    ) -> Result<String, OurError> {
+        let token = generate_token();
+        let query_str = r#"INSERT INTO organisation_tokens
+(uuid, organisation_uuid, scope, token_hash)
+VALUES
+($1, $2, $3, $4)
+ON CONFLICT (organisation_uuid, scope)
+DO UPDATE SET token_hash = EXCLUDED.token_hash, created_at = CURRENT_TIMESTAMP"#;
+        sqlx::query(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(scope)
+            .bind(hash_token(&token))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(token)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM organisation_tokens WHERE organisation_uuid = $1 ORDER BY scope";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn revoke(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        scope: &str,
+    ) -> Result<(), OurError> {
+        let query_str =
+            "DELETE FROM organisation_tokens WHERE organisation_uuid = $1 AND scope = $2";
+        sqlx::query(query_str)
+            .bind(organisation_uuid)
+            .bind(scope)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // the organisation a presented token of the scope belongs to
+    pub async fn find_organisation(
+        connection: &mut PgConnection,
+        scope: &str,
+        token: &str,
+    ) -> Result<Organisation, OurError> {
+        let query_str = r#"SELECT organisations.* FROM organisations
+INNER JOIN organisation_tokens ON organisation_tokens.organisation_uuid = organisations.uuid
+WHERE organisation_tokens.scope = $1 AND organisation_tokens.token_hash = $2"#;
+        Ok(sqlx::query_as::<_, Organisation>(query_str)
+            .bind(scope)
+            .bind(hash_token(token))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+// scope of the token an admin creates or revokes
+#[derive(Debug, FromForm)]
+pub struct TokenForm<'r> {
+    pub scope: &'r str,
+    pub authenticity_token: &'r str,
+}
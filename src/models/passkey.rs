@@ -0,0 +1,203 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::{json, Deserialize, Serialize};
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+use webauthn_rs::prelude::{Passkey as WebauthnPasskey, RegisterPublicKeyCredential};
+
+pub const NAME_LENGTH: usize = 64;
+
+// WebAuthn credential registered by an agent, several per user each with its own name
+#[derive(Debug, FromRow, Serialize)]
+pub struct Passkey {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub credential_id: String,
+    #[serde(skip_serializing)]
+    pub credential: String,
+    pub created_at: OurDateTime,
+    pub last_used_at: Option<OurDateTime>,
+}
+
+impl Passkey {
+    pub async fn create(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        name: &str,
+        credential: &WebauthnPasskey,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO passkeys
+(uuid, user_uuid, name, credential_id, credential)
+VALUES
+($1, $2, $3, $4, $5)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .bind(name)
+            .bind(encode_credential_id(credential))
+            .bind(serialize_credential(credential)?)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM passkeys WHERE user_uuid = $1 ORDER BY created_at";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn to_credential(&self) -> Result<WebauthnPasskey, OurError> {
+        json::from_str(&self.credential).map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot read passkey"),
+                Some(Box::new(e)),
+            )
+        })
+    }
+
+    // stores the new signature counter after a sign in, when the authenticator keeps one
+    pub async fn record_use(
+        connection: &mut PgConnection,
+        credential: &WebauthnPasskey,
+    ) -> Result<(), OurError> {
+        let query_str =
+            "UPDATE passkeys SET credential = $1, last_used_at = $2 WHERE credential_id = $3";
+        sqlx::query(query_str)
+            .bind(serialize_credential(credential)?)
+            .bind(OurDateTime(Utc::now()))
+            .bind(encode_credential_id(credential))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // scoped to the user so nobody can remove someone else's passkey by uuid
+    pub async fn destroy(
+        connection: &mut PgConnection,
+        uuid: &str,
+        user_uuid: &Uuid,
+    ) -> Result<(), OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "DELETE FROM passkeys WHERE uuid = $1 AND user_uuid = $2";
+        sqlx::query(query_str)
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
+
+fn encode_credential_id(credential: &WebauthnPasskey) -> String {
+    base64::encode_config(&credential.cred_id().0, base64::URL_SAFE_NO_PAD)
+}
+
+fn serialize_credential(credential: &WebauthnPasskey) -> Result<String, OurError> {
+    json::to_string(credential).map_err(|e| {
+        OurError::new_internal_server_error(String::from("Cannot store passkey"), Some(Box::new(e)))
+    })
+}
+
+// challenge handed to the browser, valid for one answer only
+#[derive(Debug, FromRow)]
+pub struct PasskeyCeremony {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub name: Option<String>,
+    pub state: String,
+    pub expires_at: OurDateTime,
+}
+
+impl PasskeyCeremony {
+    // also clears the ceremonies nobody finished
+    pub async fn create<T: Serialize>(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        name: Option<&str>,
+        state: &T,
+        lifetime: Duration,
+    ) -> Result<Self, OurError> {
+        let state = json::to_string(state).map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot store passkey challenge"),
+                Some(Box::new(e)),
+            )
+        })?;
+        let now = Utc::now();
+        sqlx::query("DELETE FROM passkey_ceremonies WHERE expires_at <= $1")
+            .bind(OurDateTime(now))
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let query_str = r#"INSERT INTO passkey_ceremonies
+(uuid, user_uuid, name, state, expires_at)
+VALUES
+($1, $2, $3, $4, $5)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .bind(name)
+            .bind(state)
+            .bind(OurDateTime(now + lifetime))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // removes the ceremony while reading it, a challenge cannot be answered twice
+    pub async fn take(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "DELETE FROM passkey_ceremonies WHERE uuid = $1 AND expires_at > $2 RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(OurDateTime(Utc::now()))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn to_state<T: for<'de> Deserialize<'de>>(&self) -> Result<T, OurError> {
+        json::from_str(&self.state).map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot read passkey challenge"),
+                Some(Box::new(e)),
+            )
+        })
+    }
+}
+
+// JSON sent by the page before asking the authenticator to create a passkey
+#[derive(Deserialize)]
+pub struct NewPasskey {
+    pub name: String,
+    pub authenticity_token: String,
+}
+
+// JSON sent by the page with the answer of the authenticator
+#[derive(Deserialize)]
+pub struct FinishedRegistration {
+    pub credential: RegisterPublicKeyCredential,
+    pub authenticity_token: String,
+}
+
+// JSON sent by the login page before asking the authenticator to sign
+#[derive(Deserialize)]
+pub struct PasskeyLogin {
+    pub username: String,
+    pub authenticity_token: String,
+}
@@ -0,0 +1,92 @@
+use super::our_date_time::OurDateTime;
+use super::user::validate_password;
+use super::{generate_token, hash_token};
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::form::FromForm;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// single use token letting a user choose a new password without the old one
+#[derive(Debug, FromRow)]
+pub struct PasswordReset {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub token_hash: String,
+    pub expires_at: OurDateTime,
+    pub used_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+}
+
+impl PasswordReset {
+    // returns the token to email, earlier unused tokens of the user stop working
+    pub async fn create(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        lifetime: Duration,
+    ) -> Result<String, OurError> {
+        let now = OurDateTime(Utc::now());
+        let query_str =
+            "UPDATE password_resets SET used_at = $1 WHERE user_uuid = $2 AND used_at IS NULL";
+        sqlx::query(query_str)
+            .bind(&now)
+            .bind(user_uuid)
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let token = generate_token();
+        let query_str = r#"INSERT INTO password_resets
+(uuid, user_uuid, token_hash, expires_at)
+VALUES
+($1, $2, $3, $4)"#;
+        sqlx::query(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .bind(hash_token(&token))
+            .bind(OurDateTime(Utc::now() + lifetime))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(token)
+    }
+
+    // unused and unexpired reset behind a token, without using it up
+    pub async fn find_valid(connection: &mut PgConnection, token: &str) -> Result<Self, OurError> {
+        let query_str = r#"SELECT * FROM password_resets
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > $2"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(hash_token(token))
+            .bind(OurDateTime(Utc::now()))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // marks the token used, only one request can ever succeed with it
+    pub async fn consume(connection: &mut PgConnection, token: &str) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE password_resets SET used_at = $1
+WHERE token_hash = $2 AND used_at IS NULL AND expires_at > $1
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(hash_token(token))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+#[derive(FromForm)]
+pub struct ResetRequest<'r> {
+    pub email: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewPassword<'r> {
+    #[field(validate = validate_password().or_else(msg!("weak password")))]
+    pub password: &'r str,
+    #[field(validate = eq(self.password).or_else(msg!("password confirmation mismatch")))]
+    pub password_confirmation: &'r str,
+    pub authenticity_token: &'r str,
+}
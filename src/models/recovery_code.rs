@@ -0,0 +1,114 @@
+use super::hash_token;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::offset::Utc;
+use rocket_db_pools::sqlx::PgConnection;
+use uuid::Uuid;
+
+const CODE_COUNT: usize = 10;
+
+// one-time codes logging in when the authenticator is lost, only their hashes are stored
+pub struct RecoveryCode {}
+
+impl RecoveryCode {
+    // replaces the codes of the user, the returned ones are shown once and never again
+    pub async fn regenerate(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<String>, OurError> {
+        Self::destroy_all(&mut *connection, user_uuid).await?;
+        let codes = (0..CODE_COUNT).map(|_| generate_code()).collect::<Vec<_>>();
+        let query_str =
+            "INSERT INTO recovery_codes (uuid, user_uuid, code_hash) VALUES ($1, $2, $3)";
+        for code in codes.iter() {
+            sqlx::query(query_str)
+                .bind(Uuid::new_v4())
+                .bind(user_uuid)
+                .bind(hash_token(code))
+                .execute(&mut *connection)
+                .await
+                .map_err(OurError::from_sqlx_error)?;
+        }
+        Ok(codes)
+    }
+
+    // true when the code was an unused code of the user, it cannot be used again
+    pub async fn consume(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        code: &str,
+    ) -> Result<bool, OurError> {
+        let query_str = r#"UPDATE recovery_codes SET used_at = $1
+WHERE user_uuid = $2 AND code_hash = $3 AND used_at IS NULL"#;
+        let result = sqlx::query(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(user_uuid)
+            .bind(hash_token(&normalize(code)))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn destroy_all(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<(), OurError> {
+        let query_str = "DELETE FROM recovery_codes WHERE user_uuid = $1";
+        sqlx::query(query_str)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
+
+// xxxxx-xxxxx, lowercase hex
+fn generate_code() -> String {
+    let mut bytes = [0u8; 5];
+    OsRng.fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+// users type codes with spaces, capitals or without the dash
+fn normalize(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    if code.len() == 10 {
+        format!("{}-{}", &code[..5], &code[5..])
+    } else {
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_two_groups_of_five_hex_digits() {
+        let code = generate_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert!(code
+            .chars()
+            .filter(|c| *c != '-')
+            .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+        assert_ne!(generate_code(), code);
+    }
+
+    #[test]
+    fn typed_codes_are_normalized_to_the_stored_form() {
+        assert_eq!(normalize("abcde-01234"), "abcde-01234");
+        assert_eq!(normalize(" ABCDE 01234 "), "abcde-01234");
+        assert_eq!(normalize("abcde01234"), "abcde-01234");
+        // anything else is kept as typed and matches no code
+        assert_eq!(normalize("abc"), "abc");
+    }
+}
@@ -0,0 +1,293 @@
+use super::clean_html;
+use super::hash_token;
+use super::our_date_time::OurDateTime;
+use super::sms_message::parse_contact_number;
+use super::user::{validate_email, validate_password, validate_password_for};
+use super::user_status::UserStatus;
+use crate::errors::our_error::OurError;
+use crate::states::password_policy::PasswordPolicy;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{offset::Utc, Duration};
+use rocket::form::{self, Error as FormError, FromForm};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// customer with a portal account, separate from the agents in users
+#[derive(Debug, FromRow, Serialize)]
+pub struct Reporter {
+    pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
+    pub email: String,
+    pub name: String,
+    pub contact_number: i64,
+    pub company_name: String,
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    pub company_access: bool,
+    pub status: UserStatus,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+    pub contact_number_verified_at: Option<OurDateTime>,
+    #[serde(skip_serializing)]
+    pub verification_code_hash: Option<String>,
+    #[serde(skip_serializing)]
+    pub verification_expires_at: Option<OurDateTime>,
+}
+
+// texted verification codes stop working after this many minutes
+const VERIFICATION_MINUTES: i64 = 15;
+
+impl Reporter {
+    // the number tickets are matched on, None until the reporter proved they own it
+    pub fn verified_contact_number(&self) -> Option<i64> {
+        self.contact_number_verified_at
+            .as_ref()
+            .map(|_| self.contact_number)
+    }
+
+    pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM reporters WHERE uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM reporters WHERE organisation_uuid = $1 ORDER BY created_at DESC";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_login<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        login: &'r ReporterLogin<'r>,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        // pending registrations log in to finish verifying their number
+        let query_str = r#"SELECT * FROM reporters
+WHERE organisation_uuid = $1 AND email = $2 AND status IN ($3, $4)"#;
+        let reporter = sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(login.email.to_lowercase())
+            .bind(UserStatus::Active)
+            .bind(UserStatus::Pending)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let password_hash = reporter.password_hash.as_ref().ok_or_else(|| {
+            OurError::new_bad_request_error(String::from("Invitation not accepted"), None)
+        })?;
+        policy.verify(password_hash, login.password)?;
+        Ok(reporter)
+    }
+
+    // self registration, pending until the reporter enters the code texted to their number
+    pub async fn create<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        new_reporter: &'r NewReporter<'r>,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        let password_hash = policy.hash(new_reporter.password)?;
+        let contact_number = parse_contact_number(new_reporter.contact_number).ok_or_else(|| {
+            OurError::new_bad_request_error(String::from("Invalid contact number"), None)
+        })?;
+        let query_str = r#"INSERT INTO reporters
+(uuid, organisation_uuid, email, name, contact_number, company_name, password_hash, status)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(new_reporter.email.to_lowercase())
+            .bind(clean_html(new_reporter.name))
+            .bind(contact_number)
+            .bind(clean_html(new_reporter.company_name))
+            .bind(password_hash)
+            .bind(UserStatus::Pending)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // account created by an agent, inactive until the reporter picks a password,
+    // the agent typing in the number vouches for it
+    pub async fn invite<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        invitation: &'r ReporterInvitation<'r>,
+    ) -> Result<Self, OurError> {
+        let contact_number = parse_contact_number(invitation.contact_number).ok_or_else(|| {
+            OurError::new_bad_request_error(String::from("Invalid contact number"), None)
+        })?;
+        let query_str = r#"INSERT INTO reporters
+(uuid, organisation_uuid, email, name, contact_number, company_name, company_access, status,
+contact_number_verified_at)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8, $9)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(invitation.email.to_lowercase())
+            .bind(clean_html(invitation.name))
+            .bind(contact_number)
+            .bind(clean_html(invitation.company_name))
+            .bind(invitation.company_access)
+            .bind(UserStatus::Pending)
+            .bind(OurDateTime(Utc::now()))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // only works once, an accepted invitation has a password
+    pub async fn accept_invitation(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+        password: &str,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let password_hash = policy.hash(password)?;
+        let query_str = r#"UPDATE reporters SET password_hash = $1, status = $2, updated_at = $3
+WHERE uuid = $4 AND organisation_uuid = $5 AND password_hash IS NULL
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(password_hash)
+            .bind(UserStatus::Active)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // returns the code to text to the contact number, earlier codes stop working
+    pub async fn start_verification(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<String, OurError> {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        let query_str = r#"UPDATE reporters SET verification_code_hash = $1, verification_expires_at = $2
+WHERE uuid = $3"#;
+        sqlx::query(query_str)
+            .bind(hash_token(&code))
+            .bind(OurDateTime(
+                Utc::now() + Duration::minutes(VERIFICATION_MINUTES),
+            ))
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(code)
+    }
+
+    // Marks the number verified when the code matches, a pending registration becomes active.
+    // The code only works once.
+    pub async fn verify_contact_number(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        code: &str,
+    ) -> Result<Self, OurError> {
+        let now = OurDateTime(Utc::now());
+        let query_str = r#"UPDATE reporters SET contact_number_verified_at = $1,
+status = CASE WHEN status = $2 THEN $3 ELSE status END,
+verification_code_hash = NULL, verification_expires_at = NULL, updated_at = $1
+WHERE uuid = $4 AND organisation_uuid = $5
+AND verification_code_hash = $6 AND verification_expires_at > $1
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(&now)
+            .bind(UserStatus::Pending)
+            .bind(UserStatus::Active)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .bind(hash_token(code.trim()))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewReporter<'r> {
+    #[field(validate = len(1..50).or_else(msg!("name cannot be empty")))]
+    pub name: &'r str,
+    #[field(validate = validate_email().or_else(msg!("invalid email")))]
+    pub email: &'r str,
+    #[field(validate = validate_contact_number())]
+    pub contact_number: &'r str,
+    #[field(validate = len(1..20).or_else(msg!("company name cannot be empty")))]
+    pub company_name: &'r str,
+    #[field(validate = validate_password_for(self.name, self.email).or_else(msg!("weak password")))]
+    pub password: &'r str,
+    #[field(validate = eq(self.password).or_else(msg!("password confirmation mismatch")))]
+    pub password_confirmation: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ReporterInvitation<'r> {
+    #[field(validate = len(1..50).or_else(msg!("name cannot be empty")))]
+    pub name: &'r str,
+    #[field(validate = validate_email().or_else(msg!("invalid email")))]
+    pub email: &'r str,
+    #[field(validate = validate_contact_number())]
+    pub contact_number: &'r str,
+    #[field(validate = len(1..20).or_else(msg!("company name cannot be empty")))]
+    pub company_name: &'r str,
+    #[field(default = false)]
+    pub company_access: bool,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Debug, FromForm)]
+pub struct AcceptedInvitation<'r> {
+    #[field(validate = validate_password().or_else(msg!("weak password")))]
+    pub password: &'r str,
+    #[field(validate = eq(self.password).or_else(msg!("password confirmation mismatch")))]
+    pub password_confirmation: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct VerificationCode<'r> {
+    pub code: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct VerificationRequest<'r> {
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct ReporterLogin<'r> {
+    pub email: &'r str,
+    pub password: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+fn validate_contact_number(contact_number: &str) -> form::Result<'_, ()> {
+    if parse_contact_number(contact_number).is_none() {
+        return Err(FormError::validation("invalid contact number").into());
+    }
+    Ok(())
+}
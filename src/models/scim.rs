@@ -0,0 +1,517 @@
+use super::user::User;
+use super::user_role::{UserRole, ALL_ROLES};
+use super::user_status::UserStatus;
+use crate::errors::our_error::OurError;
+use regex::Regex;
+use rocket::serde::json::{json, Value};
+use rocket::serde::Deserialize;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const CONFIG_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+const FILTER_PATTERN: &str = r#"^\s*([A-Za-z0-9_.:\[\]]+)\s+(?i:eq)\s+"((?:[^"\\]|\\.)*)"\s*$"#;
+
+// the attributes a provisioning client manages, every other attribute it sends is ignored
+#[derive(Debug, Clone)]
+pub struct ProvisionedUser {
+    pub username: String,
+    pub email: String,
+    pub external_id: Option<String>,
+    pub status: UserStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "camelCase")]
+pub struct ScimUserRequest {
+    pub user_name: String,
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    pub external_id: Option<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScimMember {
+    pub value: String,
+}
+
+// PUT on a group, only its members can change
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ScimGroupRequest {
+    #[serde(default)]
+    pub members: Vec<ScimMember>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+// what a PATCH on a group does to the role of users
+pub enum MemberChange {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+    Replace(Vec<String>),
+}
+
+// eq is the only operator provisioning clients use to look users up
+#[derive(Debug)]
+pub enum UserFilter {
+    Id(String),
+    UserName(String),
+    Email(String),
+    ExternalId(String),
+}
+
+impl ScimUserRequest {
+    // current is the status of the replaced user, None for a new one
+    pub fn to_provisioned(&self, current: Option<UserStatus>) -> Result<ProvisionedUser, OurError> {
+        let email = self
+            .emails
+            .iter()
+            .find(|email| email.primary)
+            .or_else(|| self.emails.first())
+            .map(|email| email.value.clone())
+            .ok_or_else(|| invalid_value("emails is required"))?;
+        let user = ProvisionedUser {
+            username: self.user_name.trim().to_string(),
+            email: email.trim().to_string(),
+            external_id: self.external_id.clone(),
+            status: active_status(current, self.active),
+        };
+        user.validate()?;
+        Ok(user)
+    }
+}
+
+impl ProvisionedUser {
+    pub fn from_user(user: &User) -> Self {
+        Self {
+            username: user.username.clone(),
+            email: user.email.clone(),
+            external_id: user.scim_external_id.clone(),
+            status: user.status,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), OurError> {
+        if self.username.is_empty() {
+            return Err(invalid_value("userName is required"));
+        }
+        if !self.email.contains('@') {
+            return Err(invalid_value("emails must hold an email address"));
+        }
+        Ok(())
+    }
+
+    pub fn apply(&mut self, operation: &PatchOperation) -> Result<(), OurError> {
+        let op = operation.op.to_lowercase();
+        match (op.as_str(), operation.path.as_deref()) {
+            ("remove", Some(path)) => {
+                if attribute_name(path) == "externalid" {
+                    self.external_id = None;
+                    Ok(())
+                } else {
+                    Err(invalid_value("only externalId can be removed"))
+                }
+            }
+            ("add", Some(path)) | ("replace", Some(path)) => {
+                let value = operation
+                    .value
+                    .as_ref()
+                    .ok_or_else(|| invalid_value("value is required"))?;
+                self.set_attribute(path, value)
+            }
+            // no path, the value holds the attributes to set
+            ("add", None) | ("replace", None) => match operation.value.as_ref() {
+                Some(Value::Object(attributes)) => {
+                    for (name, value) in attributes {
+                        self.set_attribute(name, value)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(invalid_value("value must be an object without path")),
+            },
+            _ => Err(invalid_value("unsupported operation")),
+        }
+    }
+
+    fn set_attribute(&mut self, path: &str, value: &Value) -> Result<(), OurError> {
+        let name = attribute_name(path);
+        match name.as_str() {
+            "username" => self.username = string_value(value)?.trim().to_string(),
+            "externalid" => self.external_id = Some(string_value(value)?),
+            "active" => self.status = active_status(Some(self.status), bool_value(value)?),
+            "emails" => {
+                let emails = value
+                    .as_array()
+                    .ok_or_else(|| invalid_value("emails must be a list"))?;
+                let email = emails
+                    .iter()
+                    .find(|email| email.get("primary").and_then(Value::as_bool) == Some(true))
+                    .or_else(|| emails.first())
+                    .and_then(|email| email.get("value"))
+                    .ok_or_else(|| invalid_value("emails must hold an email address"))?;
+                self.email = string_value(email)?.trim().to_string();
+            }
+            // emails[type eq "work"].value and the like, the user has a single address
+            _ if name.starts_with("emails") && name.ends_with("value") => {
+                self.email = string_value(value)?.trim().to_string()
+            }
+            _ => {}
+        }
+        self.validate()
+    }
+}
+
+// The client decides whether the account exists, a pending account is simply activated.
+// Suspending and locking are decisions of our admins, the client does not lift them.
+fn active_status(current: Option<UserStatus>, active: bool) -> UserStatus {
+    match (current, active) {
+        (_, false) => UserStatus::Deactivated,
+        (Some(UserStatus::Suspended), true) => UserStatus::Suspended,
+        (Some(UserStatus::Locked), true) => UserStatus::Locked,
+        (_, true) => UserStatus::Active,
+    }
+}
+
+// lowercase attribute name without the core schema prefix
+fn attribute_name(path: &str) -> String {
+    let prefix = format!("{}:", USER_SCHEMA);
+    let path = if path.len() > prefix.len() && path[..prefix.len()].eq_ignore_ascii_case(&prefix) {
+        &path[prefix.len()..]
+    } else {
+        path
+    };
+    path.trim().to_lowercase()
+}
+
+fn string_value(value: &Value) -> Result<String, OurError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| invalid_value("a string is expected"))
+}
+
+// some clients send booleans as "True" and "False"
+fn bool_value(value: &Value) -> Result<bool, OurError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(invalid_value("a boolean is expected")),
+    }
+}
+
+fn invalid_value(message: &str) -> OurError {
+    OurError::new_bad_request_error(String::from(message), None)
+}
+
+fn parse_eq_filter(filter: &str) -> Result<(String, String), OurError> {
+    let invalid = || OurError::new_bad_request_error(String::from("Unsupported filter"), None);
+    let regex = Regex::new(FILTER_PATTERN).map_err(|_| invalid())?;
+    let captures = regex.captures(filter).ok_or_else(invalid)?;
+    let value = captures[2].replace("\\\"", "\"").replace("\\\\", "\\");
+    Ok((attribute_name(&captures[1]), value))
+}
+
+impl UserFilter {
+    pub fn parse(filter: &str) -> Result<Self, OurError> {
+        let (attribute, value) = parse_eq_filter(filter)?;
+        match attribute.as_str() {
+            "id" => Ok(UserFilter::Id(value)),
+            "username" => Ok(UserFilter::UserName(value)),
+            "emails" | "emails.value" => Ok(UserFilter::Email(value)),
+            "externalid" => Ok(UserFilter::ExternalId(value)),
+            _ => Err(OurError::new_bad_request_error(
+                String::from("Unsupported filter attribute"),
+                None,
+            )),
+        }
+    }
+}
+
+// groups are the roles, the group id is the lowercase role name
+pub fn group_id(role: UserRole) -> String {
+    role.to_string().to_lowercase()
+}
+
+pub fn find_group(id: &str) -> Option<UserRole> {
+    UserRole::from_name(id)
+}
+
+// every role without a filter, none for a filter no role matches
+pub fn filter_groups(filter: Option<&str>) -> Result<Vec<UserRole>, OurError> {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return Ok(ALL_ROLES.to_vec()),
+    };
+    let (attribute, value) = parse_eq_filter(filter)?;
+    match attribute.as_str() {
+        "id" | "displayname" => Ok(UserRole::from_name(&value).into_iter().collect()),
+        _ => Err(OurError::new_bad_request_error(
+            String::from("Unsupported filter attribute"),
+            None,
+        )),
+    }
+}
+
+pub fn member_changes(request: &PatchRequest) -> Result<Vec<MemberChange>, OurError> {
+    let mut changes = Vec::new();
+    for operation in request.operations.iter() {
+        let op = operation.op.to_lowercase();
+        let path = operation.path.as_deref().map(attribute_name);
+        let members = operation.value.as_ref().map(member_ids).unwrap_or_default();
+        match (op.as_str(), path.as_deref()) {
+            ("add", Some("members")) => changes.push(MemberChange::Add(members)),
+            ("replace", Some("members")) => changes.push(MemberChange::Replace(members)),
+            ("remove", Some("members")) if members.is_empty() => {
+                changes.push(MemberChange::Replace(Vec::new()))
+            }
+            ("remove", Some("members")) => changes.push(MemberChange::Remove(members)),
+            // members[value eq "<id>"], the raw path keeps the case of the id
+            ("remove", Some(path)) if path.starts_with("members[") && path.ends_with(']') => {
+                let raw = operation.path.as_deref().unwrap_or("").trim();
+                let (_, value) = parse_eq_filter(&raw["members[".len()..raw.len() - 1])?;
+                changes.push(MemberChange::Remove(vec![value]));
+            }
+            ("add", None) | ("replace", None) => {
+                if let Some(members) = operation.value.as_ref().and_then(|v| v.get("members")) {
+                    changes.push(MemberChange::Add(member_ids(members)));
+                }
+            }
+            // the name of a role does not change, renaming it at the client is ignored
+            (_, Some("displayname")) => {}
+            _ => return Err(invalid_value("unsupported operation")),
+        }
+    }
+    Ok(changes)
+}
+
+fn member_ids(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|member| member.get("value").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn user_resource(user: &User) -> Value {
+    let location = format!("/scim/v2/Users/{}", user.uuid);
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": user.uuid.to_string(),
+        "externalId": user.scim_external_id,
+        "userName": user.username,
+        "active": user.status.can_log_in(),
+        "emails": [{ "value": user.email, "primary": true, "type": "work" }],
+        "groups": [{ "value": group_id(user.role), "display": user.role.to_string() }],
+        "meta": {
+            "resourceType": "User",
+            "created": user.created_at.0.to_rfc3339(),
+            "lastModified": user.updated_at.0.to_rfc3339(),
+            "location": location,
+        },
+    })
+}
+
+pub fn group_resource(role: UserRole, members: &[User]) -> Value {
+    let members = members
+        .iter()
+        .map(|user| json!({ "value": user.uuid.to_string(), "display": user.username }))
+        .collect::<Vec<_>>();
+    json!({
+        "schemas": [GROUP_SCHEMA],
+        "id": group_id(role),
+        "displayName": role.to_string(),
+        "members": members,
+        "meta": {
+            "resourceType": "Group",
+            "location": format!("/scim/v2/Groups/{}", group_id(role)),
+        },
+    })
+}
+
+pub fn list_response(resources: Vec<Value>, total: i64, start_index: i64) -> Value {
+    json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": total,
+        "startIndex": start_index,
+        "itemsPerPage": resources.len(),
+        "Resources": resources,
+    })
+}
+
+pub fn error_response(status: u16, scim_type: Option<&str>, detail: &str) -> Value {
+    let mut body = json!({
+        "schemas": [ERROR_SCHEMA],
+        "status": status.to_string(),
+        "detail": detail,
+    });
+    if let Some(scim_type) = scim_type {
+        body["scimType"] = json!(scim_type);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::from_value;
+
+    fn patch(operations: Value) -> PatchRequest {
+        from_value(json!({ "schemas": [PATCH_SCHEMA], "Operations": operations })).unwrap()
+    }
+
+    fn provisioned() -> ProvisionedUser {
+        ProvisionedUser {
+            username: String::from("jane"),
+            email: String::from("jane@example.com"),
+            external_id: Some(String::from("ext-1")),
+            status: UserStatus::Active,
+        }
+    }
+
+    #[test]
+    fn user_filters_take_eq_on_known_attributes() {
+        assert!(matches!(
+            UserFilter::parse(r#"userName eq "jane""#).unwrap(),
+            UserFilter::UserName(name) if name == "jane"
+        ));
+        assert!(matches!(
+            UserFilter::parse(r#"  userName EQ "jane \"j\" doe"  "#).unwrap(),
+            UserFilter::UserName(name) if name == r#"jane "j" doe"#
+        ));
+        let filter = format!(r#"{}:emails.value eq "jane@example.com""#, USER_SCHEMA);
+        assert!(matches!(
+            UserFilter::parse(&filter).unwrap(),
+            UserFilter::Email(email) if email == "jane@example.com"
+        ));
+        assert!(matches!(
+            UserFilter::parse(r#"externalId eq "ext-1""#).unwrap(),
+            UserFilter::ExternalId(id) if id == "ext-1"
+        ));
+    }
+
+    #[test]
+    fn other_filters_are_bad_requests() {
+        for filter in [
+            r#"userName co "jane""#,
+            r#"userName eq jane"#,
+            r#"userName eq "jane" and active eq "true""#,
+            r#"title eq "boss""#,
+        ] {
+            let error = UserFilter::parse(filter).unwrap_err();
+            assert_eq!(error.status, rocket::http::Status::BadRequest);
+        }
+    }
+
+    #[test]
+    fn group_filters_match_role_names() {
+        assert_eq!(filter_groups(None).unwrap(), ALL_ROLES.to_vec());
+        assert_eq!(
+            filter_groups(Some(r#"displayName eq "Admin""#)).unwrap(),
+            vec![UserRole::Admin]
+        );
+        assert!(filter_groups(Some(r#"id eq "janitor""#))
+            .unwrap()
+            .is_empty());
+        assert!(filter_groups(Some(r#"members eq "x""#)).is_err());
+    }
+
+    #[test]
+    fn user_patch_sets_and_removes_attributes() {
+        let request = patch(json!([
+            {"op": "Replace", "path": "active", "value": "False"},
+            {"op": "replace", "value": {"userName": " janed ", "emails": [
+                {"value": "other@example.com"},
+                {"value": "jane.doe@example.com", "primary": true}
+            ]}},
+            {"op": "remove", "path": "externalId"}
+        ]));
+        let mut user = provisioned();
+        for operation in request.operations.iter() {
+            user.apply(operation).unwrap();
+        }
+        assert_eq!(user.status, UserStatus::Deactivated);
+        assert_eq!(user.username, "janed");
+        assert_eq!(user.email, "jane.doe@example.com");
+        assert_eq!(user.external_id, None);
+    }
+
+    #[test]
+    fn user_patch_refuses_what_it_cannot_apply() {
+        let request = patch(json!([
+            {"op": "remove", "path": "userName"},
+            {"op": "replace", "path": "emails[type eq \"work\"].value", "value": "not an address"},
+            {"op": "move", "path": "userName", "value": "jane"}
+        ]));
+        for operation in request.operations.iter() {
+            assert!(provisioned().apply(operation).is_err());
+        }
+    }
+
+    #[test]
+    fn reactivation_keeps_admin_decisions() {
+        assert_eq!(active_status(None, true), UserStatus::Active);
+        assert_eq!(
+            active_status(Some(UserStatus::Suspended), true),
+            UserStatus::Suspended
+        );
+        assert_eq!(
+            active_status(Some(UserStatus::Locked), false),
+            UserStatus::Deactivated
+        );
+    }
+
+    #[test]
+    fn group_patch_lists_member_changes() {
+        let request = patch(json!([
+            {"op": "add", "path": "members", "value": [{"value": "a"}, {"value": "b"}]},
+            {"op": "remove", "path": "members[value eq \"C-Id\"]"},
+            {"op": "remove", "path": "members"},
+            {"op": "replace", "path": "displayName", "value": "Renamed"}
+        ]));
+        let changes = member_changes(&request).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(&changes[0], MemberChange::Add(ids) if ids == &["a", "b"]));
+        // the id keeps its case
+        assert!(matches!(&changes[1], MemberChange::Remove(ids) if ids == &["C-Id"]));
+        assert!(matches!(&changes[2], MemberChange::Replace(ids) if ids.is_empty()));
+        assert!(member_changes(&patch(json!([{"op": "add", "path": "owner"}]))).is_err());
+    }
+}
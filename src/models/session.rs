@@ -0,0 +1,151 @@
+use super::our_date_time::OurDateTime;
+use super::{generate_token, hash_token};
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+const USER_AGENT_LENGTH: usize = 255;
+
+// logged in device of an agent, the cookie only holds the random token
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Session {
+    pub uuid: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub user_uuid: Uuid,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub persistent: bool,
+    pub created_at: OurDateTime,
+    pub last_seen_at: OurDateTime,
+    pub expires_at: OurDateTime,
+    pub revoked_at: Option<OurDateTime>,
+}
+
+impl Session {
+    // returns the session and the token to put in the cookie, only its hash is stored
+    pub async fn create(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+        persistent: bool,
+        lifetime: Duration,
+    ) -> Result<(Self, String), OurError> {
+        let token = generate_token();
+        let user_agent = user_agent.map(|user_agent| {
+            user_agent
+                .chars()
+                .take(USER_AGENT_LENGTH)
+                .collect::<String>()
+        });
+        let query_str = r#"INSERT INTO sessions
+(uuid, token_hash, user_uuid, ip_address, user_agent, persistent, expires_at)
+VALUES
+($1, $2, $3, $4, $5, $6, $7)
+RETURNING *"#;
+        let session = sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(hash_token(&token))
+            .bind(user_uuid)
+            .bind(ip_address)
+            .bind(user_agent)
+            .bind(persistent)
+            .bind(OurDateTime(Utc::now() + lifetime))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok((session, token))
+    }
+
+    // session behind a cookie token, refused once revoked, expired or idle for too long;
+    // a found session is marked as seen now
+    pub async fn find_active(
+        connection: &mut PgConnection,
+        token: &str,
+        idle_timeout: Duration,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE sessions SET last_seen_at = $1
+WHERE token_hash = $2
+AND revoked_at IS NULL
+AND expires_at > $1
+AND (persistent OR last_seen_at > $3)
+RETURNING *"#;
+        let now = Utc::now();
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(now))
+            .bind(hash_token(token))
+            .bind(OurDateTime(now - idle_timeout))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // sessions of a user that can still be used, most recently seen first
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT * FROM sessions
+WHERE user_uuid = $1 AND revoked_at IS NULL AND expires_at > $2
+ORDER BY last_seen_at DESC"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .bind(OurDateTime(Utc::now()))
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // scoped to the user so nobody can revoke someone else's session by uuid
+    pub async fn revoke(
+        connection: &mut PgConnection,
+        uuid: &str,
+        user_uuid: &Uuid,
+    ) -> Result<(), OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "UPDATE sessions SET revoked_at = $1 WHERE uuid = $2 AND user_uuid = $3 AND revoked_at IS NULL";
+        sqlx::query(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn revoke_by_token(
+        connection: &mut PgConnection,
+        token: &str,
+    ) -> Result<(), OurError> {
+        let query_str =
+            "UPDATE sessions SET revoked_at = $1 WHERE token_hash = $2 AND revoked_at IS NULL";
+        sqlx::query(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(hash_token(token))
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // logs the user out everywhere, returns how many sessions ended
+    pub async fn revoke_all_for_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<u64, OurError> {
+        let query_str =
+            "UPDATE sessions SET revoked_at = $1 WHERE user_uuid = $2 AND revoked_at IS NULL";
+        let result = sqlx::query(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+}
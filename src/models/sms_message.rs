@@ -0,0 +1,217 @@
+use super::issues_reported::Issue;
+
+// a single GSM-7 segment holds 160 characters, concatenated segments lose 7 to the header
+const GSM7_SINGLE_LENGTH: usize = 160;
+const GSM7_MULTI_LENGTH: usize = 153;
+// anything outside GSM-7 is sent as UCS-2
+const UCS2_SINGLE_LENGTH: usize = 70;
+const UCS2_MULTI_LENGTH: usize = 67;
+const ELLIPSIS: &str = "...";
+
+const GSM7_CHARS: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+// these take two septets because they are sent through the escape table
+const GSM7_EXTENDED_CHARS: &str = "^{}\\[~]|€\x0c";
+
+pub fn is_gsm7(text: &str) -> bool {
+    text.chars()
+        .all(|c| GSM7_CHARS.contains(c) || GSM7_EXTENDED_CHARS.contains(c))
+}
+
+// number of characters the message occupies on the wire
+fn encoded_length(text: &str) -> usize {
+    if is_gsm7(text) {
+        text.chars()
+            .map(|c| if GSM7_EXTENDED_CHARS.contains(c) { 2 } else { 1 })
+            .sum()
+    } else {
+        text.chars().count()
+    }
+}
+
+pub fn segment_count(text: &str) -> usize {
+    let length = encoded_length(text);
+    let (single, multi) = if is_gsm7(text) {
+        (GSM7_SINGLE_LENGTH, GSM7_MULTI_LENGTH)
+    } else {
+        (UCS2_SINGLE_LENGTH, UCS2_MULTI_LENGTH)
+    };
+    if length <= single {
+        1
+    } else {
+        length.div_ceil(multi)
+    }
+}
+
+fn fits(text: &str, max_segments: usize) -> bool {
+    segment_count(text) <= max_segments
+}
+
+fn truncate_chars(text: &str, length: usize) -> String {
+    text.chars().take(length).collect()
+}
+
+fn fill(template: &str, issue: &Issue, issue_name: &str) -> String {
+    template
+        .replace("{ticket_number}", &issue.ticket_number.to_string())
+        .replace("{issue_name}", issue_name)
+        .replace("{reported_by}", &issue.reported_by)
+        .replace("{company_name}", &issue.company_name)
+        .replace("{status}", &issue.status)
+}
+
+// Fills the template with the issue values and keeps the result within max_segments.
+// The issue name is shortened first since it is the only free text the reporter controls,
+// the whole message is cut only when the template itself is too long.
+pub fn render(template: &str, issue: &Issue, max_segments: usize) -> String {
+    let max_segments = max_segments.max(1);
+    let body = fill(template, issue, &issue.issue_name);
+    if fits(&body, max_segments) {
+        return body;
+    }
+
+    let name_length = issue.issue_name.chars().count();
+    for keep in (0..name_length).rev() {
+        let shortened = format!("{}{}", truncate_chars(&issue.issue_name, keep), ELLIPSIS);
+        let body = fill(template, issue, &shortened);
+        if fits(&body, max_segments) {
+            return body;
+        }
+    }
+
+    truncate(&fill(template, issue, ""), max_segments)
+}
+
+// cuts a free text message so it fits within max_segments
+pub fn truncate(text: &str, max_segments: usize) -> String {
+    let max_segments = max_segments.max(1);
+    let mut body = text.to_string();
+    while !fits(&body, max_segments) {
+        let length = body.chars().count();
+        body = truncate_chars(&body, length - 1);
+    }
+    body
+}
+
+// contact numbers are stored without the leading + or 00
+pub fn recipient(contact_number: i64) -> String {
+    format!("+{}", contact_number)
+}
+
+// inverse of recipient, accepts +264..., 00264... or 264... with any separators
+pub fn parse_contact_number(number: &str) -> Option<i64> {
+    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return None;
+    }
+    digits.parse::<i64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::our_date_time::OurDateTime;
+    use chrono::offset::Utc;
+    use uuid::Uuid;
+
+    fn issue(issue_name: &str) -> Issue {
+        Issue {
+            uuid: Uuid::new_v4(),
+            organisation_uuid: Uuid::new_v4(),
+            issue_name: issue_name.to_string(),
+            description: String::from("Printer on fire"),
+            reported_by: String::from("Jane"),
+            company_name: String::from("Acme"),
+            contact_number: 264811234567,
+            ticket_number: 42,
+            ticket_owner: String::from("orphan"),
+            status: String::from("open"),
+            team_uuid: None,
+            closed_at: None,
+            created_at: OurDateTime(Utc::now()),
+            updated_at: OurDateTime(Utc::now()),
+        }
+    }
+
+    #[test]
+    fn gsm7_texts_are_told_from_ucs2_ones() {
+        assert!(is_gsm7("Ticket #42: Printer on fire, 50% done @ Acme"));
+        assert!(is_gsm7("Grüße à Zoé {€}"));
+        assert!(!is_gsm7("Принтер"));
+        assert!(!is_gsm7("Printer 🔥"));
+    }
+
+    #[test]
+    fn gsm7_segments() {
+        assert_eq!(segment_count(""), 1);
+        assert_eq!(segment_count(&"a".repeat(160)), 1);
+        assert_eq!(segment_count(&"a".repeat(161)), 2);
+        assert_eq!(segment_count(&"a".repeat(306)), 2);
+        assert_eq!(segment_count(&"a".repeat(307)), 3);
+    }
+
+    #[test]
+    fn gsm7_extended_characters_count_twice() {
+        assert_eq!(segment_count(&"€".repeat(80)), 1);
+        assert_eq!(segment_count(&"€".repeat(81)), 2);
+        assert_eq!(segment_count(&format!("{}[", "a".repeat(159))), 2);
+    }
+
+    #[test]
+    fn ucs2_segments() {
+        assert_eq!(segment_count(&"ж".repeat(70)), 1);
+        assert_eq!(segment_count(&"ж".repeat(71)), 2);
+        assert_eq!(segment_count(&"ж".repeat(134)), 2);
+        assert_eq!(segment_count(&"ж".repeat(135)), 3);
+        // one character outside GSM-7 turns the whole message into UCS-2
+        assert_eq!(segment_count(&format!("{}ж", "a".repeat(69))), 1);
+        assert_eq!(segment_count(&format!("{}ж", "a".repeat(70))), 2);
+    }
+
+    #[test]
+    fn truncate_keeps_within_max_segments() {
+        assert_eq!(truncate("Short reply", 1), "Short reply");
+        assert_eq!(truncate(&"a".repeat(400), 2), "a".repeat(306));
+        assert_eq!(truncate(&"ж".repeat(200), 2), "ж".repeat(134));
+        assert_eq!(truncate(&"€".repeat(100), 1), "€".repeat(80));
+        // no segment at all is taken as one
+        assert_eq!(truncate(&"a".repeat(400), 0), "a".repeat(160));
+    }
+
+    #[test]
+    fn render_fills_the_template() {
+        let template =
+            "#{ticket_number} {issue_name} by {reported_by} of {company_name} is {status}";
+        assert_eq!(
+            render(template, &issue("Printer on fire"), 1),
+            "#42 Printer on fire by Jane of Acme is open"
+        );
+    }
+
+    #[test]
+    fn render_shortens_the_issue_name_first() {
+        let template = "Ticket #{ticket_number} received: {issue_name}.";
+        let body = render(template, &issue(&"x".repeat(500)), 1);
+        assert_eq!(body.chars().count(), 160);
+        assert!(body.starts_with("Ticket #42 received: xxx"));
+        assert!(body.ends_with("x...."));
+
+        let body = render(template, &issue(&"x".repeat(500)), 2);
+        assert_eq!(segment_count(&body), 2);
+        assert_eq!(body.chars().count(), 306);
+
+        // a name outside GSM-7 gets the shorter UCS-2 segments
+        let body = render(template, &issue(&"ж".repeat(100)), 1);
+        assert_eq!(body.chars().count(), 70);
+        assert!(body.ends_with("ж...."));
+    }
+
+    #[test]
+    fn render_cuts_a_template_too_long_by_itself() {
+        let template = format!("{}{{issue_name}}", "a".repeat(200));
+        assert_eq!(
+            render(&template, &issue("Printer on fire"), 1),
+            "a".repeat(160)
+        );
+    }
+}
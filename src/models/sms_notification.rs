@@ -0,0 +1,152 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const KIND_TICKET_CREATED: &str = "ticket_created";
+pub const KIND_TICKET_CLOSED: &str = "ticket_closed";
+pub const KIND_REPLY: &str = "reply";
+pub const KIND_VERIFICATION: &str = "verification";
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+
+// one row per text message, queued by the routes and sent by the SMS worker
+#[derive(Debug, FromRow, Serialize)]
+pub struct SmsNotification {
+    pub uuid: Uuid,
+    // None for verification codes and replies that are about no ticket
+    pub issue_uuid: Option<Uuid>,
+    pub kind: String,
+    pub recipient: String,
+    pub body: String,
+    pub gateway: String,
+    pub status: String,
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub next_attempt_at: OurDateTime,
+    pub sent_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+}
+
+pub struct NewSmsNotification<'r> {
+    pub issue_uuid: Option<Uuid>,
+    pub kind: &'r str,
+    pub recipient: &'r str,
+    pub body: &'r str,
+    pub gateway: &'r str,
+}
+
+impl SmsNotification {
+    // queued to be sent right away
+    pub async fn create<'r>(
+        connection: &mut PgConnection,
+        notification: &'r NewSmsNotification<'r>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO sms_notifications
+(uuid, issue_uuid, kind, recipient, body, gateway, status)
+VALUES
+($1, $2, $3, $4, $5, $6, $7)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(notification.issue_uuid)
+            .bind(notification.kind)
+            .bind(notification.recipient)
+            .bind(notification.body)
+            .bind(notification.gateway)
+            .bind(STATUS_QUEUED)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_issue(
+        connection: &mut PgConnection,
+        issue_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM sms_notifications WHERE issue_uuid = $1 ORDER BY created_at DESC";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(issue_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // Picks due messages and leases them by pushing next_attempt_at forward,
+    // a worker that dies mid-send leaves them to be picked up again once the lease ends.
+    pub async fn claim_due(
+        connection: &mut PgConnection,
+        limit: i32,
+        lease: Duration,
+    ) -> Result<Vec<Self>, OurError> {
+        let now = Utc::now();
+        let query_str = r#"UPDATE sms_notifications SET next_attempt_at = $1
+WHERE uuid IN (
+    SELECT uuid FROM sms_notifications
+    WHERE status = $2 AND next_attempt_at <= $3
+    ORDER BY next_attempt_at
+    LIMIT $4
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(now + lease))
+            .bind(STATUS_QUEUED)
+            .bind(OurDateTime(now))
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn mark_sent(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        provider_message_id: Option<&str>,
+    ) -> Result<(), OurError> {
+        let query_str = r#"UPDATE sms_notifications
+SET status = $1, attempts = attempts + 1, provider_message_id = $2, error = NULL, sent_at = $3
+WHERE uuid = $4"#;
+        sqlx::query(query_str)
+            .bind(STATUS_SENT)
+            .bind(provider_message_id)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // records a failed attempt, next_attempt_at is None once we gave up on the message
+    pub async fn mark_attempt_failed(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        error: &str,
+        next_attempt_at: Option<OurDateTime>,
+    ) -> Result<(), OurError> {
+        let status = if next_attempt_at.is_some() {
+            STATUS_QUEUED
+        } else {
+            STATUS_FAILED
+        };
+        let query_str = r#"UPDATE sms_notifications
+SET status = $1, attempts = attempts + 1, error = $2, next_attempt_at = COALESCE($3, next_attempt_at)
+WHERE uuid = $4"#;
+        sqlx::query(query_str)
+            .bind(status)
+            .bind(error)
+            .bind(next_attempt_at)
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use super::clean_html;
+use super::issues_reported::UNASSIGNED_OWNER;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// closed tickets older than this do not count in the SLA dashboards
+pub const SLA_WINDOW_DAYS: i64 = 30;
+
+// group of agents sharing a ticket queue
+#[derive(Debug, FromRow, Serialize)]
+pub struct Team {
+    pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub sla_hours: i32,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+}
+
+impl Team {
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM teams WHERE organisation_uuid = $1 AND uuid = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM teams WHERE organisation_uuid = $1 ORDER BY name";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_member(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT teams.* FROM teams
+INNER JOIN team_members ON team_members.team_uuid = teams.uuid
+WHERE team_members.user_uuid = $1
+ORDER BY teams.name"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn create<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        new_team: &'r NewTeam<'r>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO teams
+(uuid, organisation_uuid, name, description, sla_hours)
+VALUES
+($1, $2, $3, $4, $5)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(clean_html(new_team.name))
+            .bind(new_team.description.map(clean_html))
+            .bind(new_team.sla_hours)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn update<'r>(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        edited: &'r NewTeam<'r>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE teams SET name = $1, description = $2, sla_hours = $3, updated_at = $4
+WHERE uuid = $5 RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(clean_html(edited.name))
+            .bind(edited.description.map(clean_html))
+            .bind(edited.sla_hours)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // its tickets go back to the shared open page
+    pub async fn destroy(connection: &mut PgConnection, uuid: &Uuid) -> Result<(), OurError> {
+        let query_str = "DELETE FROM teams WHERE uuid = $1";
+        sqlx::query(query_str)
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewTeam<'r> {
+    #[field(validate = len(1..50).or_else(msg!("name cannot be empty")))]
+    pub name: &'r str,
+    #[field(default = "")]
+    pub description: Option<&'r str>,
+    #[field(validate = range(1..=8760).or_else(msg!("SLA must be between 1 hour and a year")))]
+    pub sla_hours: i32,
+    pub authenticity_token: &'r str,
+}
+
+// how a team keeps up with its queue, open tickets now and tickets closed over the window
+#[derive(Debug, FromRow, Serialize)]
+pub struct TeamSla {
+    pub team_uuid: Uuid,
+    pub name: String,
+    pub sla_hours: i32,
+    pub open: i64,
+    pub unclaimed: i64,
+    // open for longer than the SLA allows
+    pub breaching: i64,
+    pub closed: i64,
+    pub closed_in_time: i64,
+    pub average_resolution_hours: Option<f64>,
+    // percentage of the closed tickets that made it within the SLA, None when none were closed
+    pub compliance: Option<i64>,
+}
+
+impl TeamSla {
+    // every team of the organisation when team_uuid is None
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        team_uuid: Option<&Uuid>,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT *, closed_in_time * 100 / NULLIF(closed, 0) AS compliance FROM (
+SELECT teams.uuid AS team_uuid, teams.name, teams.sla_hours,
+COUNT(issues_reported.uuid) FILTER (WHERE issues_reported.status = 'open') AS open,
+COUNT(issues_reported.uuid) FILTER (WHERE issues_reported.status = 'open' AND issues_reported.ticket_owner = $3) AS unclaimed,
+COUNT(issues_reported.uuid) FILTER (WHERE issues_reported.status = 'open'
+    AND issues_reported.created_at + make_interval(hours => teams.sla_hours) < $4) AS breaching,
+COUNT(issues_reported.uuid) FILTER (WHERE issues_reported.closed_at >= $2) AS closed,
+COUNT(issues_reported.uuid) FILTER (WHERE issues_reported.closed_at >= $2
+    AND issues_reported.closed_at <= issues_reported.created_at + make_interval(hours => teams.sla_hours)) AS closed_in_time,
+(AVG(EXTRACT(EPOCH FROM issues_reported.closed_at - issues_reported.created_at) / 3600)
+    FILTER (WHERE issues_reported.closed_at >= $2))::FLOAT8 AS average_resolution_hours
+FROM teams
+LEFT JOIN issues_reported ON issues_reported.team_uuid = teams.uuid
+WHERE teams.organisation_uuid = $5 AND ($1::UUID IS NULL OR teams.uuid = $1)
+GROUP BY teams.uuid, teams.name, teams.sla_hours
+) AS sla
+ORDER BY name"#;
+        let now = Utc::now();
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(team_uuid)
+            .bind(OurDateTime(now - Duration::days(SLA_WINDOW_DAYS)))
+            .bind(UNASSIGNED_OWNER)
+            .bind(OurDateTime(now))
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
@@ -0,0 +1,100 @@
+use super::issues_reported::UNASSIGNED_OWNER;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// an agent in a team, with what they hold of the team queue for the dashboard
+#[derive(Debug, FromRow, Serialize)]
+pub struct TeamMember {
+    pub team_uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub username: String,
+    pub email: String,
+    pub open_tickets: i64,
+    pub created_at: OurDateTime,
+}
+
+impl TeamMember {
+    pub async fn find_by_team(
+        connection: &mut PgConnection,
+        team_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT team_members.team_uuid, team_members.user_uuid, users.username, users.email,
+(SELECT COUNT(*) FROM issues_reported
+    WHERE issues_reported.team_uuid = team_members.team_uuid
+    AND issues_reported.ticket_owner = users.username AND issues_reported.status = 'open') AS open_tickets,
+team_members.created_at
+FROM team_members
+INNER JOIN users ON users.uuid = team_members.user_uuid
+WHERE team_members.team_uuid = $1 AND users.deleted_at IS NULL
+ORDER BY users.username"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(team_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // ticket owners are usernames, so membership is checked the same way
+    pub async fn is_member(
+        connection: &mut PgConnection,
+        team_uuid: &Uuid,
+        username: &str,
+    ) -> Result<bool, OurError> {
+        if username == UNASSIGNED_OWNER {
+            return Ok(false);
+        }
+        let query_str = r#"SELECT EXISTS (SELECT 1 FROM team_members
+INNER JOIN users ON users.uuid = team_members.user_uuid
+WHERE team_members.team_uuid = $1 AND users.username = $2)"#;
+        Ok(sqlx::query_scalar::<_, bool>(query_str)
+            .bind(team_uuid)
+            .bind(username)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn create(
+        connection: &mut PgConnection,
+        team_uuid: &Uuid,
+        user_uuid: &Uuid,
+    ) -> Result<(), OurError> {
+        let query_str = r#"INSERT INTO team_members
+(team_uuid, user_uuid)
+VALUES
+($1, $2)
+ON CONFLICT DO NOTHING"#;
+        sqlx::query(query_str)
+            .bind(team_uuid)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn destroy(
+        connection: &mut PgConnection,
+        team_uuid: &Uuid,
+        user_uuid: &Uuid,
+    ) -> Result<(), OurError> {
+        let query_str = "DELETE FROM team_members WHERE team_uuid = $1 AND user_uuid = $2";
+        sqlx::query(query_str)
+            .bind(team_uuid)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
+
+#[derive(FromForm)]
+pub struct NewTeamMember<'r> {
+    pub username: &'r str,
+    pub authenticity_token: &'r str,
+}
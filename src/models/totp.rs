@@ -0,0 +1,144 @@
+use crate::errors::our_error::OurError;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base32::Alphabet;
+use chrono::offset::Utc;
+use hmac::{Hmac, Mac};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rocket::http::RawStr;
+use sha1::Sha1;
+
+// RFC 6238 with the parameters every authenticator app supports
+const SECRET_LENGTH: usize = 20;
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// codes from the step before and after are accepted too, clocks drift
+const ALLOWED_DRIFT: i64 = 1;
+const BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+pub fn generate_secret() -> String {
+    let mut key = vec![0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut key);
+    base32::encode(BASE32, &key)
+}
+
+// Time step the code belongs to, None when the code is wrong.
+// Steps not after last_step are refused so a code works only once.
+pub fn verify(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    verify_at(secret, code, last_step, Utc::now().timestamp())
+}
+
+fn verify_at(secret: &str, code: &str, last_step: Option<i64>, timestamp: i64) -> Option<i64> {
+    let key = base32::decode(BASE32, secret)?;
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let current_step = timestamp / STEP_SECONDS;
+    (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT)
+        .filter(|step| last_step.map_or(true, |last_step| *step > last_step))
+        .find(|step| code_at(&key, *step) == code)
+}
+
+fn code_at(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+// what authenticator apps read from the QR code
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = RawStr::new(issuer).percent_encode();
+    let account = RawStr::new(account).percent_encode();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, DIGITS, STEP_SECONDS
+    )
+}
+
+pub fn qr_code_svg(uri: &str) -> Result<String, OurError> {
+    let code = QrCode::new(uri.as_bytes()).map_err(|e| {
+        OurError::new_internal_server_error(String::from("Cannot build QR code"), Some(Box::new(e)))
+    })?;
+    Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the SHA1 secret of the RFC 6238 test vectors, "12345678901234567890"
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn code_at_time(timestamp: i64) -> String {
+        let key = base32::decode(BASE32, SECRET).unwrap();
+        code_at(&key, timestamp / STEP_SECONDS)
+    }
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        // last six digits of the eight digit codes of the RFC
+        assert_eq!(code_at_time(59), "287082");
+        assert_eq!(code_at_time(1111111109), "081804");
+        assert_eq!(code_at_time(1111111111), "050471");
+        assert_eq!(code_at_time(1234567890), "005924");
+        assert_eq!(code_at_time(2000000000), "279037");
+    }
+
+    #[test]
+    fn code_is_accepted_one_step_around_the_clock() {
+        let now = 1234567890;
+        let step = now / STEP_SECONDS;
+        let code = code_at_time(now);
+        assert_eq!(verify_at(SECRET, &code, None, now), Some(step));
+        assert_eq!(
+            verify_at(SECRET, &code, None, now - STEP_SECONDS),
+            Some(step)
+        );
+        assert_eq!(
+            verify_at(SECRET, &code, None, now + STEP_SECONDS),
+            Some(step)
+        );
+        assert_eq!(verify_at(SECRET, &code, None, now + 2 * STEP_SECONDS), None);
+        assert_eq!(verify_at(SECRET, &code, None, now - 2 * STEP_SECONDS), None);
+    }
+
+    #[test]
+    fn code_is_accepted_with_spaces_only_when_well_formed() {
+        let now = 1234567890;
+        let code = code_at_time(now);
+        let spaced = format!(" {} {} ", &code[..3], &code[3..]);
+        assert!(verify_at(SECRET, &spaced, None, now).is_some());
+        assert_eq!(verify_at(SECRET, &code[..5], None, now), None);
+        assert_eq!(verify_at(SECRET, "12345a", None, now), None);
+        assert_eq!(verify_at("not base32!", &code, None, now), None);
+    }
+
+    #[test]
+    fn code_works_only_once() {
+        let now = 1234567890;
+        let code = code_at_time(now);
+        let step = verify_at(SECRET, &code, None, now).unwrap();
+        assert_eq!(verify_at(SECRET, &code, Some(step), now), None);
+        // nor any older code once a newer one was used
+        let older = code_at_time(now - STEP_SECONDS);
+        assert_eq!(verify_at(SECRET, &older, Some(step), now), None);
+        let newer = code_at_time(now + STEP_SECONDS);
+        assert_eq!(
+            verify_at(SECRET, &newer, Some(step), now + STEP_SECONDS),
+            Some(step + 1)
+        );
+    }
+}
@@ -1,15 +1,14 @@
-use super::bool_wrapper::BoolWrapper;
-use super::clean_html;
 use super::our_date_time::OurDateTime;
-use super::pagination::{Pagination, DEFAULT_LIMIT};
+use super::scim::{ProvisionedUser, UserFilter};
+use super::user_invitation::UserInvitation;
+use super::user_role::UserRole;
 use super::user_status::UserStatus;
+use super::user_status_change::UserStatusChange;
+use super::{clean_html, generate_token};
 use crate::errors::our_error::OurError;
 use crate::fairings::db::DBConnection;
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
-};
-use chrono::offset::Utc;
+use crate::states::password_policy::PasswordPolicy;
+use chrono::{offset::Utc, Duration};
 use regex::Regex;
 use rocket::form::{self, Error as FormError, FromForm};
 use rocket::serde::Serialize;
@@ -18,146 +17,200 @@ use rocket_db_pools::Connection;
 use uuid::Uuid;
 use zxcvbn::zxcvbn;
 
-#[derive(Debug, FromRow, FromForm, Serialize)]
+#[derive(Debug, Clone, FromRow, FromForm, Serialize)]
 pub struct User {
     pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
     pub username: String,
     pub email: String,
     pub password_hash: String,
     pub description: Option<String>,
     pub status: UserStatus,
+    pub status_reason: Option<String>,
+    pub status_changed_at: Option<OurDateTime>,
+    pub role: UserRole,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>,
+    pub totp_required: bool,
+    pub failed_login_count: i32,
+    pub locked_until: Option<OurDateTime>,
+    pub oidc_subject: Option<String>,
+    pub scim_external_id: Option<String>,
+    pub deleted_at: Option<OurDateTime>,
     pub created_at: OurDateTime,
     pub updated_at: OurDateTime,
 }
 
+// Every query is scoped by the organisation of the request,
+// a user of another organisation is as good as missing.
 impl User {
-    pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "SELECT * FROM users WHERE uuid = $1";
+        let query_str = "SELECT * FROM users WHERE organisation_uuid = $1 AND uuid = $2";
         Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
             .bind(parsed_uuid)
             .fetch_one(connection)
             .await
             .map_err(OurError::from_sqlx_error)?)
     }
 
-    pub async fn find_by_login<'r>(
+    // unknown users get a password hashed all the same, timing does not tell them apart
+    pub async fn check_login<'r>(
         connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
         login: &'r Login<'r>,
-    ) -> Result<Self, OurError> {
-        let query_str = "SELECT * FROM users WHERE username = $1";
+        policy: &PasswordPolicy,
+    ) -> Result<LoginCheck, OurError> {
+        let query_str = "SELECT * FROM users WHERE organisation_uuid = $1 AND username = $2";
         let user = sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
             .bind(&login.username)
-            .fetch_one(connection)
+            .fetch_optional(connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
-        let argon2 = Argon2::default();
-        verify_password(&argon2, &user.password_hash, &login.password)?;
-        Ok(user)
-    }
-
-    pub async fn find_all(
-        db: &mut Connection<DBConnection>,
-        pagination: Option<Pagination>,
-    ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
-        if pagination.is_some() {
-            return Self::find_all_with_pagination(db, &(pagination.unwrap())).await;
-        } else {
-            return Self::find_all_without_pagination(db).await;
+        match user {
+            // a locked account does not get its password checked, guessing on is pointless
+            Some(user) if user.is_locked() => {
+                policy.verify_dummy(login.password);
+                Ok(LoginCheck::Locked(user))
+            }
+            Some(user) => match policy.verify(&user.password_hash, login.password) {
+                Ok(()) => Ok(LoginCheck::Valid(user)),
+                Err(_) => Ok(LoginCheck::WrongPassword(user)),
+            },
+            None => {
+                policy.verify_dummy(login.password);
+                Ok(LoginCheck::UnknownUser)
+            }
         }
     }
 
-    async fn find_all_without_pagination(
-        db: &mut Connection<DBConnection>,
-    ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
-        let query_str = "SELECT * FROM users ORDER BY created_at DESC LIMIT $1";
-        let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let users = sqlx::query_as::<_, Self>(query_str)
-            .bind(DEFAULT_LIMIT as i32)
-            .fetch_all(connection)
+    // no password check, for sign in methods proving the user some other way
+    pub async fn find_by_username(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        username: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM users WHERE organisation_uuid = $1 AND username = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(username)
+            .fetch_one(connection)
             .await
-            .map_err(OurError::from_sqlx_error)?;
-        let mut new_pagination: Option<Pagination> = None;
-        if users.len() == DEFAULT_LIMIT {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
-            let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-            let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
-                .bind(&users.last().unwrap().created_at)
-                .fetch_one(connection)
-                .await
-                .map_err(OurError::from_sqlx_error)?;
-            if exists.0 {
-                new_pagination = Some(Pagination {
-                    next: users.last().unwrap().created_at.to_owned(),
-                    limit: DEFAULT_LIMIT,
-                });
-            }
-        }
-        Ok((users, new_pagination))
+            .map_err(OurError::from_sqlx_error)?)
     }
 
-    async fn find_all_with_pagination(
-        db: &mut Connection<DBConnection>,
-        pagination: &Pagination,
-    ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
+    pub async fn find_by_email(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        email: &str,
+    ) -> Result<Self, OurError> {
         let query_str =
-            "SELECT * FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT $2";
-        let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let users = sqlx::query_as::<_, Self>(query_str)
-            .bind(&pagination.next)
-            .bind(pagination.limit as i32)
-            .fetch_all(connection)
+            "SELECT * FROM users WHERE organisation_uuid = $1 AND LOWER(email) = LOWER($2)";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(email.trim())
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // page of users for the admin index, with the count of all users matching
+    pub async fn search(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        search: &UserSearch,
+    ) -> Result<(Vec<Self>, i64), OurError> {
+        let mut conditions = vec![
+            String::from("deleted_at IS NULL"),
+            String::from("organisation_uuid = $1"),
+        ];
+        let pattern = search.prefix_pattern();
+        if pattern.is_some() {
+            conditions.push(format!(
+                "(LOWER(username) LIKE ${0} OR LOWER(email) LIKE ${0})",
+                conditions.len()
+            ));
+        }
+        if search.status.is_some() {
+            conditions.push(format!("status = ${}", conditions.len()));
+        }
+        if search.role.is_some() {
+            conditions.push(format!("role = ${}", conditions.len()));
+        }
+        let condition = conditions.join(" AND ");
+        let query_str = format!(
+            "SELECT * FROM users WHERE {} ORDER BY {} OFFSET ${} LIMIT ${}",
+            condition,
+            search.sort.unwrap_or_default().order_by(),
+            conditions.len(),
+            conditions.len() + 1
+        );
+        let mut query = sqlx::query_as::<_, Self>(&query_str).bind(organisation_uuid);
+        if let Some(pattern) = &pattern {
+            query = query.bind(pattern);
+        }
+        if let Some(status) = search.status {
+            query = query.bind(status);
+        }
+        if let Some(role) = search.role {
+            query = query.bind(role);
+        }
+        let users = query
+            .bind((search.page() - 1).saturating_mul(USERS_PER_PAGE))
+            .bind(USERS_PER_PAGE)
+            .fetch_all(&mut *connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
-        let mut new_pagination: Option<Pagination> = None;
-        if users.len() == pagination.limit {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
-            let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-            let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
-                .bind(&users.last().unwrap().created_at)
-                .fetch_one(connection)
-                .await
-                .map_err(OurError::from_sqlx_error)?;
-            if exists.0 {
-                new_pagination = Some(Pagination {
-                    next: users.last().unwrap().created_at.to_owned(),
-                    limit: pagination.limit,
-                });
-            }
+        let count_str = format!("SELECT COUNT(*) FROM users WHERE {}", condition);
+        let mut count_query = sqlx::query_scalar::<_, i64>(&count_str).bind(organisation_uuid);
+        if let Some(pattern) = &pattern {
+            count_query = count_query.bind(pattern);
+        }
+        if let Some(status) = search.status {
+            count_query = count_query.bind(status);
+        }
+        if let Some(role) = search.role {
+            count_query = count_query.bind(role);
         }
-        Ok((users, new_pagination))
+        let total = count_query
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok((users, total))
     }
 
-    pub async fn create<'r>(
+    // account of an invitee in the organisation of the invitation, active right away since it proved the email
+    pub async fn create_invited(
         connection: &mut PgConnection,
-        new_user: &'r NewUser<'r>,
+        username: &str,
+        invitation: &UserInvitation,
+        password: &str,
+        policy: &PasswordPolicy,
     ) -> Result<Self, OurError> {
         let uuid = Uuid::new_v4();
-        let username = &(clean_html(new_user.username));
-        let description = &(new_user.description.map(|desc| clean_html(desc)));
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2
-            .hash_password(new_user.password.as_bytes(), &salt)
-            .map_err(|e| {
-                OurError::new_internal_server_error(
-                    String::from("Something went wrong"),
-                    Some(Box::new(e)),
-                )
-            })?;
-
+        let username = &(clean_html(username));
+        let password_hash = policy.hash(password)?;
         let query_str = r#"INSERT INTO users
-(uuid, username, email, password_hash, description, status)
+(uuid, organisation_uuid, username, email, password_hash, status, role)
 VALUES
-($1, $2, $3, $4, $5, $6)
+($1, $2, $3, $4, $5, $6, $7)
 RETURNING *"#;
         Ok(sqlx::query_as::<_, Self>(query_str)
             .bind(uuid)
+            .bind(&invitation.organisation_uuid)
             .bind(username)
-            .bind(new_user.email)
-            .bind(password_hash.to_string())
-            .bind(description)
-            .bind(UserStatus::Inactive)
+            .bind(&invitation.email)
+            .bind(password_hash)
+            .bind(UserStatus::Active)
+            .bind(invitation.role)
             .fetch_one(connection)
             .await
             .map_err(OurError::from_sqlx_error)?)
@@ -165,11 +218,13 @@ RETURNING *"#;
 
     pub async fn update<'r>(
         db: &mut Connection<DBConnection>,
+        organisation_uuid: &Uuid,
         uuid: &'r str,
         user: &'r EditedUser<'r>,
+        policy: &PasswordPolicy,
     ) -> Result<Self, OurError> {
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let old_user = Self::find(connection, uuid).await?;
+        let old_user = Self::find(connection, organisation_uuid, uuid).await?;
         let now = OurDateTime(Utc::now());
         let username = &(clean_html(user.username));
         let description = &(user.description.map(|desc| clean_html(desc)));
@@ -179,27 +234,18 @@ RETURNING *"#;
             "description = $3",
             "updated_at = $4",
         ];
-        let mut where_string = "$5";
+        let mut where_string = "organisation_uuid = $5 AND uuid = $6";
         let mut password_string = String::new();
         let is_with_password = !user.old_password.is_empty();
         if is_with_password {
-            let argon2 = Argon2::default();
-            verify_password(&argon2, &old_user.password_hash, user.old_password)?;
-            let salt = SaltString::generate(&mut OsRng);
-            let new_hash = argon2
-                .hash_password(user.password.as_bytes(), &salt)
-                .map_err(|e| {
-                    OurError::new_internal_server_error(
-                        String::from("Something went wrong"),
-                        Some(Box::new(e)),
-                    )
-                })?;
-            password_string.push_str(new_hash.to_string().as_ref());
+            policy.verify(&old_user.password_hash, user.old_password)?;
+            let new_hash = policy.hash(user.password)?;
+            password_string.push_str(new_hash.as_ref());
             set_strings.push("password_hash = $5");
-            where_string = "$6";
+            where_string = "organisation_uuid = $6 AND uuid = $7";
         }
         let query_str = format!(
-            r#"UPDATE users SET {} WHERE uuid = {} RETURNING *"#,
+            r#"UPDATE users SET {} WHERE {} RETURNING *"#,
             set_strings.join(", "),
             where_string,
         );
@@ -214,21 +260,529 @@ RETURNING *"#;
         }
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
         Ok(binded
+            .bind(organisation_uuid)
             .bind(parsed_uuid)
             .fetch_one(connection)
             .await
             .map_err(OurError::from_sqlx_error)?)
     }
 
-    pub async fn destroy(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
+    // users who can take over the tickets of a user being removed
+    pub async fn find_inheritors(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT * FROM users
+WHERE organisation_uuid = $3 AND uuid <> $1 AND status = $2 AND deleted_at IS NULL
+ORDER BY username ASC"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(UserStatus::Active)
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // Strips what identifies or authenticates the user but keeps the row and its username,
+    // so comments, status history and login attempts still say who it was.
+    pub async fn tombstone(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        // nothing of a user of another organisation is touched
+        Self::find(connection, organisation_uuid, &uuid.to_string()).await?;
+        for query_str in [
+            "DELETE FROM passkeys WHERE user_uuid = $1",
+            "DELETE FROM recovery_codes WHERE user_uuid = $1",
+            "DELETE FROM password_resets WHERE user_uuid = $1",
+            "DELETE FROM team_members WHERE user_uuid = $1",
+        ]
+        .iter()
+        {
+            sqlx::query(query_str)
+                .bind(uuid)
+                .execute(&mut *connection)
+                .await
+                .map_err(OurError::from_sqlx_error)?;
+        }
+        // email is unique, the placeholder keeps it so without being deliverable
+        let query_str = r#"UPDATE users SET
+email = 'removed-' || uuid || '@invalid', password_hash = '', description = NULL,
+totp_secret = NULL, totp_last_step = NULL, totp_required = FALSE,
+oidc_subject = NULL, scim_external_id = NULL, deleted_at = $1, updated_at = $1
+WHERE organisation_uuid = $2 AND uuid = $3 AND deleted_at IS NULL
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // only pending accounts, an activation link used twice does nothing the second time
+    pub async fn activate(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "DELETE FROM users WHERE uuid = $1";
-        sqlx::query(query_str)
+        let query_str = r#"UPDATE users SET status = $1, status_reason = NULL, status_changed_at = $2, updated_at = $2
+WHERE uuid = $3 AND status = $4 AND organisation_uuid = $5
+RETURNING *"#;
+        let user = sqlx::query_as::<_, Self>(query_str)
+            .bind(UserStatus::Active)
+            .bind(OurDateTime(Utc::now()))
             .bind(parsed_uuid)
-            .execute(connection)
+            .bind(UserStatus::Pending)
+            .bind(organisation_uuid)
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        UserStatusChange::create(
+            connection,
+            &user.uuid,
+            UserStatus::Pending,
+            UserStatus::Active,
+            Some("Email address confirmed"),
+            None,
+        )
+        .await?;
+        Ok(user)
+    }
+
+    // every other change of status goes through here so the history stays complete
+    pub async fn change_status(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        status: UserStatus,
+        reason: Option<&str>,
+        changed_by: Option<&Uuid>,
+    ) -> Result<Self, OurError> {
+        let old_user = Self::find(connection, organisation_uuid, &uuid.to_string()).await?;
+        let reason = reason
+            .map(|reason| clean_html(reason.trim()))
+            .filter(|reason| !reason.is_empty());
+        // a removed user stays removed
+        let query_str = r#"UPDATE users SET status = $1, status_reason = $2, status_changed_at = $3, updated_at = $3
+WHERE uuid = $4 AND organisation_uuid = $5 AND deleted_at IS NULL
+RETURNING *"#;
+        let user = sqlx::query_as::<_, Self>(query_str)
+            .bind(status)
+            .bind(&reason)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(&mut *connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
-        Ok(())
+        UserStatusChange::create(
+            connection,
+            uuid,
+            old_user.status,
+            status,
+            reason.as_deref(),
+            changed_by,
+        )
+        .await?;
+        Ok(user)
+    }
+
+    // also how a hash made with a weaker cost than configured is upgraded
+    pub async fn set_password(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        password: &str,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        let password_hash = policy.hash(password)?;
+        let query_str = r#"UPDATE users SET password_hash = $1, updated_at = $2
+WHERE uuid = $3 AND organisation_uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(password_hash)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .as_ref()
+            .map(|locked_until| locked_until.0 > Utc::now())
+            .unwrap_or(false)
+    }
+
+    // counts a wrong password, the account locks for lock_duration once threshold is reached
+    pub async fn record_failed_login(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        threshold: i32,
+        lock_duration: Duration,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET failed_login_count = failed_login_count + 1,
+locked_until = CASE WHEN failed_login_count + 1 >= $1 THEN $2 ELSE locked_until END
+WHERE uuid = $3 AND organisation_uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(threshold)
+            .bind(OurDateTime(Utc::now() + lock_duration))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn record_successful_login(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET failed_login_count = 0, locked_until = NULL
+WHERE uuid = $1 AND organisation_uuid = $2
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // admin action, the failed attempts are forgotten too
+    pub async fn unlock(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE users SET failed_login_count = 0, locked_until = NULL, updated_at = $1
+WHERE uuid = $2 AND organisation_uuid = $3
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn totp_enabled(&self) -> bool {
+        self.totp_secret.is_some()
+    }
+
+    pub async fn enable_totp(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        secret: &str,
+        step: i64,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET totp_secret = $1, totp_last_step = $2, updated_at = $3
+WHERE uuid = $4 AND organisation_uuid = $5
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(secret)
+            .bind(step)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn disable_totp(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET totp_secret = NULL, totp_last_step = NULL, updated_at = $1
+WHERE uuid = $2 AND organisation_uuid = $3
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // fails when the step was already used, two requests cannot both succeed with one code
+    pub async fn record_totp_step(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        step: i64,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET totp_last_step = $1
+WHERE uuid = $2 AND organisation_uuid = $3 AND (totp_last_step IS NULL OR totp_last_step < $1)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(step)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn set_totp_required(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+        required: bool,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE users SET totp_required = $1, updated_at = $2
+WHERE uuid = $3 AND organisation_uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(required)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn set_role(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+        role: UserRole,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE users SET role = $1, updated_at = $2
+WHERE uuid = $3 AND organisation_uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(role)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_oidc_subject(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        subject: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM users WHERE organisation_uuid = $1 AND oidc_subject = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(subject)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // just in time account for somebody the identity provider vouches for, active right away
+    pub async fn create_from_oidc(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        subject: &str,
+        username: &str,
+        email: &str,
+        role: UserRole,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        let uuid = Uuid::new_v4();
+        let username = &(clean_html(username));
+        // never shown to anybody, the account is only reachable through the provider or a reset
+        let password_hash = policy.hash(&generate_token())?;
+        let query_str = r#"INSERT INTO users
+(uuid, organisation_uuid, username, email, password_hash, status, role, oidc_subject)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .bind(username)
+            .bind(email)
+            .bind(password_hash)
+            .bind(UserStatus::Active)
+            .bind(role)
+            .bind(subject)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // only links an account not linked yet, a subject never moves to another account
+    pub async fn link_oidc(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        subject: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET oidc_subject = $1, updated_at = $2
+WHERE uuid = $3 AND organisation_uuid = $4 AND oidc_subject IS NULL
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(subject)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // the provider is the source of truth for email and role of linked accounts
+    pub async fn sync_oidc(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        email: &str,
+        role: UserRole,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE users SET email = $1, role = $2, updated_at = $3
+WHERE uuid = $4 AND organisation_uuid = $5
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(email)
+            .bind(role)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // page of users for a provisioning client, with the count of all users matching
+    pub async fn find_for_scim(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        filter: Option<&UserFilter>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Self>, i64), OurError> {
+        let (condition, value) = match filter {
+            None => ("TRUE", None),
+            Some(UserFilter::Id(id)) => ("CAST(uuid AS VARCHAR) = LOWER($2)", Some(id)),
+            Some(UserFilter::UserName(username)) => ("LOWER(username) = LOWER($2)", Some(username)),
+            Some(UserFilter::Email(email)) => ("LOWER(email) = LOWER($2)", Some(email)),
+            Some(UserFilter::ExternalId(external_id)) => {
+                ("scim_external_id = $2", Some(external_id))
+            }
+        };
+        let placeholder = if value.is_some() { 3 } else { 2 };
+        let query_str = format!(
+            "SELECT * FROM users WHERE organisation_uuid = $1 AND deleted_at IS NULL AND {} ORDER BY created_at ASC OFFSET ${} LIMIT ${}",
+            condition,
+            placeholder,
+            placeholder + 1
+        );
+        let mut query = sqlx::query_as::<_, Self>(&query_str).bind(organisation_uuid);
+        if let Some(value) = value {
+            query = query.bind(value);
+        }
+        let users = query
+            .bind(offset)
+            .bind(limit)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let count_str = format!(
+            "SELECT COUNT(*) FROM users WHERE organisation_uuid = $1 AND deleted_at IS NULL AND {}",
+            condition
+        );
+        let mut count_query = sqlx::query_scalar::<_, i64>(&count_str).bind(organisation_uuid);
+        if let Some(value) = value {
+            count_query = count_query.bind(value);
+        }
+        let total = count_query
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok((users, total))
+    }
+
+    pub async fn find_by_role(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        role: UserRole,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM users WHERE organisation_uuid = $1 AND role = $2 ORDER BY created_at ASC";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(role)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // account created by the provisioning client, its user signs in through single sign-on or a reset
+    pub async fn create_provisioned(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        user: &ProvisionedUser,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, OurError> {
+        let uuid = Uuid::new_v4();
+        let username = &(clean_html(&user.username));
+        let password_hash = policy.hash(&generate_token())?;
+        let query_str = r#"INSERT INTO users
+(uuid, organisation_uuid, username, email, password_hash, status, scim_external_id)
+VALUES
+($1, $2, $3, $4, $5, $6, $7)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .bind(username)
+            .bind(&user.email)
+            .bind(password_hash)
+            .bind(user.status)
+            .bind(&user.external_id)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn update_provisioned(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &Uuid,
+        user: &ProvisionedUser,
+    ) -> Result<Self, OurError> {
+        let username = &(clean_html(&user.username));
+        // the status changes through change_status, which keeps its history
+        let query_str = r#"UPDATE users SET
+username = $1, email = $2, scim_external_id = $3, updated_at = $4
+WHERE uuid = $5 AND organisation_uuid = $6
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(username)
+            .bind(&user.email)
+            .bind(&user.external_id)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
     }
 
     pub fn to_html_string(&self) -> String {
@@ -237,7 +791,8 @@ RETURNING *"#;
 <div>Username: {username}</div>
 <div>Email: {email}</div>
 <div>Description: {description}</div>
-<div>Status: {status}</div>
+<div>Status: {status}{status_reason}</div>
+<div>Role: {role}</div>
 <div>Created At: {created_at}</div>
 <div>Updated At: {updated_at}</div>"#,
             uuid = self.uuid,
@@ -245,27 +800,18 @@ RETURNING *"#;
             email = self.email,
             description = self.description.as_ref().unwrap_or(&String::from("")),
             status = self.status.to_string(),
+            status_reason = self
+                .status_reason
+                .as_ref()
+                .map(|reason| format!(" ({})", reason))
+                .unwrap_or_default(),
+            role = self.role.to_string(),
             created_at = self.created_at.0.to_rfc3339(),
             updated_at = self.updated_at.0.to_rfc3339(),
         )
     }
 }
 
-#[derive(Debug, FromForm)]
-pub struct NewUser<'r> {
-    #[field(validate = len(5..20).or_else(msg!("name cannot be empty")))]
-    pub username: &'r str,
-    #[field(validate = validate_email().or_else(msg!("invalid email")))]
-    pub email: &'r str,
-    #[field(validate = validate_password().or_else(msg!("weak password")))]
-    pub password: &'r str,
-    #[field(validate = eq(self.password).or_else(msg!("password confirmation mismatch")))]
-    pub password_confirmation: &'r str,
-    #[field(default = "")]
-    pub description: Option<&'r str>,
-    pub authenticity_token: &'r str,
-}
-
 #[derive(Debug, FromForm)]
 pub struct EditedUser<'r> {
     #[field(name = "_METHOD")]
@@ -275,7 +821,12 @@ pub struct EditedUser<'r> {
     #[field(validate = validate_email().or_else(msg!("invalid email")))]
     pub email: &'r str,
     pub old_password: &'r str,
-    #[field(validate = skip_validate_password(self.old_password, self.password_confirmation))]
+    #[field(validate = skip_validate_password(
+        self.old_password,
+        self.password_confirmation,
+        self.username,
+        self.email
+    ))]
     pub password: &'r str,
     pub password_confirmation: &'r str,
     #[field(default = "")]
@@ -283,7 +834,7 @@ pub struct EditedUser<'r> {
     pub authenticity_token: &'r str,
 }
 
-fn validate_email(email: &str) -> form::Result<'_, ()> {
+pub(crate) fn validate_email(email: &str) -> form::Result<'_, ()> {
     const EMAIL_REGEX: &str = r#"(?:[a-z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-z0-9!#$%&'*+/=?^_`{|}~-]+)*|"(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21\x23-\x5b\x5d-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])*")@(?:(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z0-9](?:[a-z0-9-]*[a-z0-9])?|\[(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?|[a-z0-9-]*[a-z0-9]:(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21-\x5a\x53-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])+)\])"#;
     let email_regex = Regex::new(EMAIL_REGEX).unwrap();
     if !email_regex.is_match(email) {
@@ -292,8 +843,22 @@ fn validate_email(email: &str) -> form::Result<'_, ()> {
     Ok(())
 }
 
-fn validate_password(password: &str) -> form::Result<'_, ()> {
-    let entropy = zxcvbn(password, &[]);
+pub(crate) fn validate_password(password: &str) -> form::Result<'_, ()> {
+    validate_password_for(password, "", "")
+}
+
+// the account's own name and email make a password easier to guess
+pub(crate) fn validate_password_for<'v>(
+    password: &'v str,
+    name: &str,
+    email: &str,
+) -> form::Result<'v, ()> {
+    let user_inputs = [name, email]
+        .iter()
+        .copied()
+        .filter(|input| !input.is_empty())
+        .collect::<Vec<_>>();
+    let entropy = zxcvbn(password, &user_inputs);
     if entropy.is_err() || entropy.unwrap().score() < 3 {
         return Err(FormError::validation("weak password").into());
     }
@@ -304,34 +869,186 @@ fn skip_validate_password<'v>(
     password: &'v str,
     old_password: &'v str,
     password_confirmation: &'v str,
+    username: &'v str,
+    email: &'v str,
 ) -> form::Result<'v, ()> {
     if old_password.is_empty() {
         return Ok(());
     }
-    validate_password(password)?;
+    validate_password_for(password, username, email)?;
     if password.ne(password_confirmation) {
         return Err(FormError::validation("password confirmation mismatch").into());
     }
     Ok(())
 }
 
-fn verify_password(ag: &Argon2, reference: &str, password: &str) -> Result<(), OurError> {
-    let reference_hash = PasswordHash::new(reference).map_err(|e| {
-        OurError::new_internal_server_error(String::from("Input error"), Some(Box::new(e)))
-    })?;
-    Ok(ag
-        .verify_password(password.as_bytes(), &reference_hash)
-        .map_err(|e| {
-            OurError::new_internal_server_error(
-                String::from("Cannot verify password"),
-                Some(Box::new(e)),
-            )
-        })?)
+pub enum LoginCheck {
+    Valid(User),
+    WrongPassword(User),
+    Locked(User),
+    UnknownUser,
+}
+
+#[derive(FromForm)]
+pub struct RoleChange<'r> {
+    pub role: UserRole,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct StatusChange<'r> {
+    pub status: UserStatus,
+    #[field(validate = len(..200).or_else(msg!("reason is too long")))]
+    pub reason: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+// who takes over the tickets of a user being removed
+#[derive(FromForm)]
+pub struct UserRemoval<'r> {
+    pub inheritor: &'r str,
+    #[field(validate = len(..200).or_else(msg!("reason is too long")))]
+    pub reason: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+pub const USERS_PER_PAGE: i64 = 25;
+// pages past this are empty anyway, the cap keeps the offset from overflowing
+const MAX_PAGE: i64 = 100_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, FromFormField, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum UserSort {
+    #[default]
+    #[field(value = "newest")]
+    Newest,
+    #[field(value = "oldest")]
+    Oldest,
+    #[field(value = "username")]
+    Username,
+    #[field(value = "email")]
+    Email,
+}
+
+impl UserSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserSort::Newest => "newest",
+            UserSort::Oldest => "oldest",
+            UserSort::Username => "username",
+            UserSort::Email => "email",
+        }
+    }
+
+    // uuid last so that pages do not overlap when the first column has ties
+    fn order_by(&self) -> &'static str {
+        match self {
+            UserSort::Newest => "created_at DESC, uuid",
+            UserSort::Oldest => "created_at ASC, uuid",
+            UserSort::Username => "LOWER(username) ASC, uuid",
+            UserSort::Email => "LOWER(email) ASC, uuid",
+        }
+    }
+}
+
+pub const ALL_SORTS: [UserSort; 4] = [
+    UserSort::Newest,
+    UserSort::Oldest,
+    UserSort::Username,
+    UserSort::Email,
+];
+
+// query of the admin users index, every part optional
+#[derive(Debug, Default, FromForm, Serialize)]
+pub struct UserSearch {
+    // prefix of the username or of the email, case insensitive
+    pub q: Option<String>,
+    pub status: Option<UserStatus>,
+    pub role: Option<UserRole>,
+    pub sort: Option<UserSort>,
+    pub page: Option<i64>,
+}
+
+impl UserSearch {
+    pub fn page(&self) -> i64 {
+        self.page
+            .filter(|page| *page > 0)
+            .unwrap_or(1)
+            .min(MAX_PAGE)
+    }
+
+    // the search without its page, for the links to the other pages
+    pub fn filters_query(&self) -> String {
+        let mut pairs = Vec::new();
+        if let Some(q) = &self.q {
+            pairs.push(("q", q.clone()));
+        }
+        if let Some(status) = self.status {
+            pairs.push(("status", status.to_string()));
+        }
+        if let Some(role) = self.role {
+            pairs.push(("role", role.to_string()));
+        }
+        if let Some(sort) = self.sort {
+            pairs.push(("sort", String::from(sort.as_str())));
+        }
+        serde_urlencoded::to_string(pairs).unwrap_or_default()
+    }
+
+    // LIKE pattern with the wildcards typed by the admin taken literally
+    fn prefix_pattern(&self) -> Option<String> {
+        let q = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty())?;
+        let escaped = q
+            .to_lowercase()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Some(format!("{}%", escaped))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum BulkAction {
+    #[field(value = "activate")]
+    Activate,
+    #[field(value = "suspend")]
+    Suspend,
+    #[field(value = "change_role")]
+    ChangeRole,
+}
+
+// the same action on every user ticked on the admin users index
+#[derive(FromForm)]
+pub struct BulkUserAction<'r> {
+    pub users: Vec<Uuid>,
+    pub action: BulkAction,
+    // only for change_role
+    pub role: Option<UserRole>,
+    #[field(validate = len(..200).or_else(msg!("reason is too long")))]
+    pub reason: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct ActivationRequest<'r> {
+    pub email: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct TotpCode<'r> {
+    // six digit code, or a recovery code where accepted
+    pub code: &'r str,
+    pub authenticity_token: &'r str,
 }
 
 #[derive(FromForm)]
 pub struct Login<'r> {
     pub username: &'r str,
     pub password: &'r str,
+    #[field(default = false)]
+    pub remember_me: bool,
+    // page to go back to once logged in
+    pub next: Option<&'r str>,
     pub authenticity_token: &'r str,
 }
@@ -0,0 +1,169 @@
+use super::our_date_time::OurDateTime;
+use super::user::{validate_email, validate_password};
+use super::user_role::UserRole;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::form::FromForm;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const STATUS_PENDING: &str = "Pending";
+pub const STATUS_ACCEPTED: &str = "Accepted";
+pub const STATUS_REVOKED: &str = "Revoked";
+pub const STATUS_EXPIRED: &str = "Expired";
+
+// staff account offered to an email address, the invitee picks username and password
+#[derive(Debug, FromRow, Serialize)]
+pub struct UserInvitation {
+    pub uuid: Uuid,
+    // the account is created in this organisation
+    pub organisation_uuid: Uuid,
+    pub email: String,
+    pub role: UserRole,
+    pub invited_by: Option<Uuid>,
+    pub user_uuid: Option<Uuid>,
+    pub expires_at: OurDateTime,
+    pub accepted_at: Option<OurDateTime>,
+    pub revoked_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+}
+
+impl UserInvitation {
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM user_invitations WHERE organisation_uuid = $1 AND uuid = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_recent(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        limit: i64,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM user_invitations WHERE organisation_uuid = $1 ORDER BY created_at DESC LIMIT $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // earlier pending invitations of the same email stop working
+    pub async fn create<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        invitation: &'r NewInvitation<'r>,
+        invited_by: &Uuid,
+        lifetime: Duration,
+    ) -> Result<Self, OurError> {
+        let now = OurDateTime(Utc::now());
+        let email = invitation.email.trim().to_lowercase();
+        let query_str = r#"UPDATE user_invitations SET revoked_at = $1
+WHERE organisation_uuid = $3 AND email = $2 AND accepted_at IS NULL AND revoked_at IS NULL"#;
+        sqlx::query(query_str)
+            .bind(&now)
+            .bind(&email)
+            .bind(organisation_uuid)
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let query_str = r#"INSERT INTO user_invitations
+(uuid, organisation_uuid, email, role, invited_by, expires_at)
+VALUES
+($1, $2, $3, $4, $5, $6)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(organisation_uuid)
+            .bind(&email)
+            .bind(invitation.role)
+            .bind(invited_by)
+            .bind(OurDateTime(Utc::now() + lifetime))
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // only a pending invitation can be revoked
+    pub async fn revoke(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE user_invitations SET revoked_at = $1
+WHERE organisation_uuid = $2 AND uuid = $3 AND accepted_at IS NULL AND revoked_at IS NULL
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // marks the invitation used by the new account, only one acceptance can ever succeed
+    pub async fn accept(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        user_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE user_invitations SET accepted_at = $1, user_uuid = $2
+WHERE uuid = $3 AND accepted_at IS NULL AND revoked_at IS NULL AND expires_at > $1
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(user_uuid)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn status(&self) -> &'static str {
+        if self.accepted_at.is_some() {
+            STATUS_ACCEPTED
+        } else if self.revoked_at.is_some() {
+            STATUS_REVOKED
+        } else if self.expires_at.0 < Utc::now() {
+            STATUS_EXPIRED
+        } else {
+            STATUS_PENDING
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status() == STATUS_PENDING
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewInvitation<'r> {
+    #[field(validate = validate_email().or_else(msg!("invalid email")))]
+    pub email: &'r str,
+    pub role: UserRole,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Debug, FromForm)]
+pub struct AcceptedUserInvitation<'r> {
+    #[field(validate = len(5..20).or_else(msg!("username must be 5 to 20 characters")))]
+    pub username: &'r str,
+    #[field(validate = validate_password().or_else(msg!("weak password")))]
+    pub password: &'r str,
+    #[field(validate = eq(self.password).or_else(msg!("password confirmation mismatch")))]
+    pub password_confirmation: &'r str,
+    pub authenticity_token: &'r str,
+}
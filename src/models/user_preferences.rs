@@ -0,0 +1,187 @@
+use crate::errors::our_error::OurError;
+use chrono_tz::{Tz, TZ_VARIANTS};
+use rocket::form::{self, Error as FormError, FromForm, FromFormField};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{self, FromRow, PgConnection};
+use std::fmt;
+use uuid::Uuid;
+
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+// offered on the preferences page, code and name in that language
+pub const LANGUAGES: [(&str, &str); 4] = [
+    ("en", "English"),
+    ("fr", "Français"),
+    ("de", "Deutsch"),
+    ("es", "Español"),
+];
+
+#[derive(sqlx::Type, Debug, Default, Clone, Copy, PartialEq, Eq, FromFormField, Serialize)]
+#[repr(i32)]
+pub enum DateFormat {
+    #[default]
+    Iso = 0,
+    European = 1,
+    American = 2,
+    Long = 3,
+}
+
+pub const ALL_DATE_FORMATS: [DateFormat; 4] = [
+    DateFormat::Iso,
+    DateFormat::European,
+    DateFormat::American,
+    DateFormat::Long,
+];
+
+impl DateFormat {
+    // the name it is serialized with, the local_time filter gets it back from the templates
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Iso" => Some(DateFormat::Iso),
+            "European" => Some(DateFormat::European),
+            "American" => Some(DateFormat::American),
+            "Long" => Some(DateFormat::Long),
+            _ => None,
+        }
+    }
+
+    // chrono format string, always ending with the timezone so nobody mistakes it for UTC
+    pub fn pattern(&self) -> &'static str {
+        match *self {
+            DateFormat::Iso => "%Y-%m-%d %H:%M %Z",
+            DateFormat::European => "%d/%m/%Y %H:%M %Z",
+            DateFormat::American => "%m/%d/%Y %I:%M %p %Z",
+            DateFormat::Long => "%A %-d %B %Y, %H:%M %Z",
+        }
+    }
+}
+
+impl fmt::Display for DateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DateFormat::Iso => write!(f, "2026-12-31 23:59"),
+            DateFormat::European => write!(f, "31/12/2026 23:59"),
+            DateFormat::American => write!(f, "12/31/2026 11:59 PM"),
+            DateFormat::Long => write!(f, "Thursday 31 December 2026, 23:59"),
+        }
+    }
+}
+
+// where the user lands after logging in and following the Tickets link
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, FromFormField, Serialize)]
+#[repr(i32)]
+pub enum TicketView {
+    Manage = 0,
+    Open = 1,
+}
+
+pub const ALL_TICKET_VIEWS: [TicketView; 2] = [TicketView::Manage, TicketView::Open];
+
+impl TicketView {
+    pub fn path(&self) -> &'static str {
+        match *self {
+            TicketView::Manage => "/issues/manage_tickets",
+            TicketView::Open => "/issues/open",
+        }
+    }
+}
+
+impl fmt::Display for TicketView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TicketView::Manage => write!(f, "All tickets"),
+            TicketView::Open => write!(f, "Open tickets"),
+        }
+    }
+}
+
+// how a user wants the application shown to them, users who never saved any get the defaults
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserPreferences {
+    pub user_uuid: Uuid,
+    pub timezone: String,
+    pub date_format: DateFormat,
+    pub language: String,
+    pub ticket_view: TicketView,
+}
+
+impl UserPreferences {
+    pub fn defaults(user_uuid: &Uuid) -> Self {
+        Self {
+            user_uuid: *user_uuid,
+            timezone: String::from(DEFAULT_TIMEZONE),
+            date_format: DateFormat::default(),
+            language: String::from(DEFAULT_LANGUAGE),
+            ticket_view: TicketView::Manage,
+        }
+    }
+
+    pub async fn find(connection: &mut PgConnection, user_uuid: &Uuid) -> Result<Self, OurError> {
+        let query_str = r#"SELECT user_uuid, timezone, date_format, language, ticket_view
+FROM user_preferences WHERE user_uuid = $1"#;
+        let preferences = sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(preferences.unwrap_or_else(|| Self::defaults(user_uuid)))
+    }
+
+    pub async fn save<'r>(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        edited: &'r EditedPreferences<'r>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO user_preferences
+(user_uuid, timezone, date_format, language, ticket_view)
+VALUES
+($1, $2, $3, $4, $5)
+ON CONFLICT (user_uuid) DO UPDATE SET
+timezone = EXCLUDED.timezone, date_format = EXCLUDED.date_format,
+language = EXCLUDED.language, ticket_view = EXCLUDED.ticket_view,
+updated_at = CURRENT_TIMESTAMP
+RETURNING user_uuid, timezone, date_format, language, ticket_view"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .bind(edited.timezone)
+            .bind(edited.date_format)
+            .bind(edited.language)
+            .bind(edited.ticket_view)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+// every timezone the preferences page offers, by IANA name
+pub fn timezone_names() -> Vec<&'static str> {
+    TZ_VARIANTS.iter().map(|tz| tz.name()).collect()
+}
+
+#[derive(Debug, FromForm)]
+pub struct EditedPreferences<'r> {
+    #[field(validate = validate_timezone())]
+    pub timezone: &'r str,
+    pub date_format: DateFormat,
+    #[field(validate = validate_language())]
+    pub language: &'r str,
+    pub ticket_view: TicketView,
+    // "event:channel" of every notification opted in to
+    pub notifications: Vec<&'r str>,
+    pub authenticity_token: &'r str,
+}
+
+fn validate_timezone(timezone: &str) -> form::Result<'_, ()> {
+    if timezone.parse::<Tz>().is_err() {
+        return Err(FormError::validation("unknown timezone").into());
+    }
+    Ok(())
+}
+
+fn validate_language(language: &str) -> form::Result<'_, ()> {
+    if !LANGUAGES.iter().any(|(code, _)| *code == language) {
+        return Err(FormError::validation("unknown language").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use rocket::form::FromFormField;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+// ordered, every role can do what the roles below it can
+#[derive(
+    sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromFormField, Serialize,
+)]
+#[repr(i32)]
+pub enum UserRole {
+    Agent = 0,
+    Supervisor = 1,
+    Admin = 2,
+}
+
+pub const ALL_ROLES: [UserRole; 3] = [UserRole::Agent, UserRole::Supervisor, UserRole::Admin];
+
+// what the templates need to know to hide what the user cannot use
+#[derive(Debug, Serialize)]
+pub struct Permissions {
+    pub manage_tickets: bool,
+    pub delete_tickets: bool,
+    pub manage_reporters: bool,
+    pub manage_teams: bool,
+    pub manage_users: bool,
+    pub manage_webhooks: bool,
+    pub impersonate: bool,
+}
+
+impl UserRole {
+    // case insensitive, for names coming from configuration or other systems
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL_ROLES
+            .iter()
+            .copied()
+            .find(|role| role.to_string().eq_ignore_ascii_case(name))
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions {
+            manage_tickets: true,
+            delete_tickets: *self >= UserRole::Supervisor,
+            manage_reporters: *self >= UserRole::Supervisor,
+            manage_teams: *self >= UserRole::Supervisor,
+            manage_users: *self >= UserRole::Admin,
+            manage_webhooks: *self >= UserRole::Admin,
+            impersonate: *self >= UserRole::Supervisor,
+        }
+    }
+}
+
+impl fmt::Display for UserRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UserRole::Agent => write!(f, "Agent"),
+            UserRole::Supervisor => write!(f, "Supervisor"),
+            UserRole::Admin => write!(f, "Admin"),
+        }
+    }
+}
@@ -3,18 +3,44 @@ use rocket::serde::Serialize;
 use rocket_db_pools::sqlx;
 use std::fmt;
 
-#[derive(sqlx::Type, Debug, FromFormField, Serialize)]
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, FromFormField, Serialize)]
 #[repr(i32)]
 pub enum UserStatus {
-    Inactive = 0,
+    // waiting for the email address to be confirmed
+    Pending = 0,
     Active = 1,
+    // deprovisioned, unlike a pending account it cannot be activated again by its user
+    Deactivated = 2,
+    // kept away for a while by an admin, the account comes back as it was
+    Suspended = 3,
+    // kept away by an admin over a security concern,
+    // unlike the lockout after failed logins it does not expire
+    Locked = 4,
+}
+
+pub const ALL_STATUSES: [UserStatus; 5] = [
+    UserStatus::Pending,
+    UserStatus::Active,
+    UserStatus::Deactivated,
+    UserStatus::Suspended,
+    UserStatus::Locked,
+];
+
+impl UserStatus {
+    // every status but Active keeps the user out, sessions included
+    pub fn can_log_in(&self) -> bool {
+        *self == UserStatus::Active
+    }
 }
 
 impl fmt::Display for UserStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            UserStatus::Inactive => write!(f, "Inactive"),
+            UserStatus::Pending => write!(f, "Pending"),
             UserStatus::Active => write!(f, "Active"),
+            UserStatus::Deactivated => write!(f, "Deactivated"),
+            UserStatus::Suspended => write!(f, "Suspended"),
+            UserStatus::Locked => write!(f, "Locked"),
         }
     }
 }
@@ -0,0 +1,64 @@
+use super::our_date_time::OurDateTime;
+use super::user_status::UserStatus;
+use crate::errors::our_error::OurError;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// one step in the life of an account, who took it and why
+#[derive(Debug, FromRow, Serialize)]
+pub struct UserStatusChange {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub from_status: UserStatus,
+    pub to_status: UserStatus,
+    pub reason: Option<String>,
+    pub changed_by: Option<Uuid>,
+    // username of changed_by, joined for display
+    pub changed_by_username: Option<String>,
+    pub created_at: OurDateTime,
+}
+
+impl UserStatusChange {
+    pub async fn create(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        from_status: UserStatus,
+        to_status: UserStatus,
+        reason: Option<&str>,
+        changed_by: Option<&Uuid>,
+    ) -> Result<(), OurError> {
+        let query_str = r#"INSERT INTO user_status_changes
+(uuid, user_uuid, from_status, to_status, reason, changed_by)
+VALUES
+($1, $2, $3, $4, $5, $6)"#;
+        sqlx::query(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .bind(from_status)
+            .bind(to_status)
+            .bind(reason)
+            .bind(changed_by)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // newest first
+    pub async fn find_by_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT user_status_changes.*, users.username AS changed_by_username
+FROM user_status_changes
+LEFT JOIN users ON users.uuid = user_status_changes.changed_by
+WHERE user_status_changes.user_uuid = $1
+ORDER BY user_status_changes.created_at DESC"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
@@ -0,0 +1,330 @@
+use super::clean_html;
+use super::issues_reported::Issue;
+use super::our_date_time::OurDateTime;
+use super::webhook_delivery::WebhookDelivery;
+use crate::errors::our_error::OurError;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::offset::Utc;
+use hmac::{Hmac, Mac};
+use rocket::form::{self, Error as FormError, FromForm};
+use rocket::serde::json::json;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use sha2::Sha256;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+pub const ISSUE_CREATED: &str = "issue.created";
+pub const ISSUE_UPDATED: &str = "issue.updated";
+pub const ISSUE_STATUS_CHANGED: &str = "issue.status_changed";
+pub const ISSUE_ASSIGNED: &str = "issue.assigned";
+pub const ISSUE_DELETED: &str = "issue.deleted";
+pub const ALL_EVENTS: [&str; 5] = [
+    ISSUE_CREATED,
+    ISSUE_UPDATED,
+    ISSUE_STATUS_CHANGED,
+    ISSUE_ASSIGNED,
+    ISSUE_DELETED,
+];
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature-256";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+const SECRET_LENGTH: usize = 32;
+
+// endpoint registered by an admin to receive ticket lifecycle events
+#[derive(Debug, FromRow, Serialize)]
+pub struct Webhook {
+    pub uuid: Uuid,
+    pub organisation_uuid: Uuid,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+}
+
+impl Webhook {
+    pub async fn find(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM webhooks WHERE organisation_uuid = $1 AND uuid = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // for the delivery worker, which sends the queued deliveries of every organisation
+    pub async fn find_for_delivery(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = "SELECT * FROM webhooks WHERE uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "SELECT * FROM webhooks WHERE organisation_uuid = $1 ORDER BY created_at DESC";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // webhooks of the organisation listening to a given event
+    pub async fn find_subscribed(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        event: &str,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM webhooks WHERE organisation_uuid = $1 AND $2 = ANY(events)";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(organisation_uuid)
+            .bind(event)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn create<'r>(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        new_webhook: &'r NewWebhook<'r>,
+    ) -> Result<Self, OurError> {
+        let uuid = Uuid::new_v4();
+        let name = &(clean_html(new_webhook.name));
+        // receivers need the secret to check signatures, generate one when the admin left it blank
+        let secret = if new_webhook.secret.is_empty() {
+            generate_secret()
+        } else {
+            new_webhook.secret.to_string()
+        };
+        let events: Vec<String> = new_webhook.events.iter().map(|e| e.to_string()).collect();
+        let query_str = r#"INSERT INTO webhooks
+(uuid, organisation_uuid, name, url, secret, events)
+VALUES
+($1, $2, $3, $4, $5, $6)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(organisation_uuid)
+            .bind(name)
+            .bind(new_webhook.url)
+            .bind(secret)
+            .bind(events)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy(
+        connection: &mut PgConnection,
+        organisation_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<(), OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "DELETE FROM webhooks WHERE organisation_uuid = $1 AND uuid = $2";
+        sqlx::query(query_str)
+            .bind(organisation_uuid)
+            .bind(parsed_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // Queues a delivery of the event for every subscribed webhook of the ticket's organisation,
+    // the background worker sends them.
+    // A webhook problem must never fail the ticket itself, so errors are only logged.
+    pub async fn dispatch(connection: &mut PgConnection, event: &str, issue: &Issue) {
+        let webhooks =
+            match Self::find_subscribed(connection, &issue.organisation_uuid, event).await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    log::error!("Cannot find webhooks for {}: {}", event, e);
+                    return;
+                }
+            };
+        if webhooks.is_empty() {
+            return;
+        }
+        let payload = json!({
+            "event": event,
+            "occurred_at": OurDateTime(Utc::now()),
+            "issue": issue,
+        })
+        .to_string();
+        for webhook in webhooks.iter() {
+            if let Err(e) =
+                WebhookDelivery::create(connection, &webhook.uuid, event, &payload).await
+            {
+                log::error!("Cannot queue {} for webhook {}: {}", event, webhook.uuid, e);
+            }
+        }
+    }
+}
+
+// value of the signature header, hex encoded HMAC-SHA256 of "<timestamp>.<raw body>",
+// signing the timestamp lets receivers refuse replays of old deliveries
+pub fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn generate_secret() -> String {
+    let mut key = vec![0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut key);
+    hex::encode(key)
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewWebhook<'r> {
+    #[field(validate = len(1..50).or_else(msg!("name cannot be empty")))]
+    pub name: &'r str,
+    #[field(validate = validate_url().or_else(msg!("invalid url")))]
+    pub url: &'r str,
+    #[field(default = "")]
+    pub secret: &'r str,
+    #[field(validate = validate_events())]
+    pub events: Vec<&'r str>,
+    pub authenticity_token: &'r str,
+}
+
+// deleting a webhook or redelivering one of its deliveries only carries the csrf token
+#[derive(Debug, FromForm)]
+pub struct WebhookAction<'r> {
+    pub authenticity_token: &'r str,
+}
+
+// deliveries come from inside our network, so the url must not point back into it
+fn validate_url(url: &str) -> form::Result<'_, ()> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| FormError::validation("invalid url"))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(FormError::validation("invalid url").into());
+    }
+    match parsed.host_str() {
+        Some(host) if !is_internal_host(host) => Ok(()),
+        _ => Err(FormError::validation("url must be reachable from the internet").into()),
+    }
+}
+
+// localhost or a literal address of ours, hostnames are checked again when they are resolved
+pub fn is_internal_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let lowercase = host.trim_end_matches('.').to_lowercase();
+    if lowercase == "localhost" || lowercase.ends_with(".localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_internal_address(&ip),
+        Err(_) => false,
+    }
+}
+
+// loopback, private, link-local and other addresses not routed on the internet
+pub fn is_internal_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                || octets[0] == 0
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            match ip.to_ipv4_mapped() {
+                Some(mapped) => is_internal_address(&IpAddr::V4(mapped)),
+                None => {
+                    ip.is_loopback()
+                        || ip.is_unspecified()
+                        // unique local fc00::/7 and link-local fe80::/10
+                        || (first & 0xfe00) == 0xfc00
+                        || (first & 0xffc0) == 0xfe80
+                }
+            }
+        }
+    }
+}
+
+fn validate_events<'v>(events: &[&'v str]) -> form::Result<'v, ()> {
+    if events.is_empty() {
+        return Err(FormError::validation("pick at least one event").into());
+    }
+    if events.iter().any(|event| !ALL_EVENTS.contains(event)) {
+        return Err(FormError::validation("unknown event").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &str = r#"{"event":"issue.created"}"#;
+
+    #[test]
+    fn signature_covers_the_timestamp_and_the_body() {
+        assert_eq!(
+            sign("secret", 1700000000, PAYLOAD),
+            "sha256=6e409ad1459f2f96b9fba76cfb55a6d4fdc1c605bb69b316b33facc8effdd96e"
+        );
+        assert_ne!(
+            sign("secret", 1700000001, PAYLOAD),
+            sign("secret", 1700000000, PAYLOAD)
+        );
+        assert_ne!(
+            sign("other secret", 1700000000, PAYLOAD),
+            sign("secret", 1700000000, PAYLOAD)
+        );
+    }
+
+    #[test]
+    fn urls_must_be_public_http() {
+        assert!(validate_url("https://hooks.example.com/tickets").is_ok());
+        assert!(validate_url("http://203.0.114.1:8080/").is_ok());
+        assert!(validate_url("ftp://hooks.example.com/").is_err());
+        assert!(validate_url("not a url").is_err());
+        assert!(validate_url("http://localhost:8000/").is_err());
+        assert!(validate_url("http://api.localhost/").is_err());
+        assert!(validate_url("http://127.0.0.1/").is_err());
+        assert!(validate_url("http://10.1.2.3/").is_err());
+        assert!(validate_url("http://172.16.0.1/").is_err());
+        assert!(validate_url("http://192.168.1.1/").is_err());
+        assert!(validate_url("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(validate_url("http://[::1]/").is_err());
+        assert!(validate_url("http://[fd00::1]/").is_err());
+        assert!(validate_url("http://[fe80::1]/").is_err());
+        assert!(validate_url("http://[::ffff:127.0.0.1]/").is_err());
+    }
+
+    #[test]
+    fn public_addresses_are_not_internal() {
+        assert!(!is_internal_address(&"93.184.216.34".parse().unwrap()));
+        assert!(!is_internal_address(&"2606:2800:220:1::1".parse().unwrap()));
+        assert!(is_internal_address(&"100.64.0.1".parse().unwrap()));
+        assert!(is_internal_address(&"0.0.0.0".parse().unwrap()));
+    }
+}
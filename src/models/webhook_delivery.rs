@@ -0,0 +1,159 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_DELIVERED: &str = "delivered";
+pub const STATUS_FAILED: &str = "failed";
+
+// one queued event for one webhook, kept afterwards as the delivery log
+#[derive(Debug, FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub uuid: Uuid,
+    pub webhook_uuid: Uuid,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: OurDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+}
+
+impl WebhookDelivery {
+    pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM webhook_deliveries WHERE uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_by_webhook(
+        connection: &mut PgConnection,
+        webhook_uuid: &Uuid,
+        limit: i32,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM webhook_deliveries WHERE webhook_uuid = $1 ORDER BY created_at DESC LIMIT $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(webhook_uuid)
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn create(
+        connection: &mut PgConnection,
+        webhook_uuid: &Uuid,
+        event: &str,
+        payload: &str,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO webhook_deliveries
+(uuid, webhook_uuid, event, payload)
+VALUES
+($1, $2, $3, $4)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(webhook_uuid)
+            .bind(event)
+            .bind(payload)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // queues the same payload again as a new delivery so the log keeps the original attempts
+    pub async fn redeliver(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+        let delivery = Self::find(connection, uuid).await?;
+        Self::create(
+            connection,
+            &delivery.webhook_uuid,
+            &delivery.event,
+            &delivery.payload,
+        )
+        .await
+    }
+
+    // Picks due deliveries and leases them by pushing next_attempt_at forward,
+    // a worker that dies mid-delivery leaves them to be picked up again once the lease ends.
+    pub async fn claim_due(
+        connection: &mut PgConnection,
+        limit: i32,
+        lease: Duration,
+    ) -> Result<Vec<Self>, OurError> {
+        let now = Utc::now();
+        let query_str = r#"UPDATE webhook_deliveries SET next_attempt_at = $1
+WHERE uuid IN (
+    SELECT uuid FROM webhook_deliveries
+    WHERE status = $2 AND next_attempt_at <= $3
+    ORDER BY next_attempt_at
+    LIMIT $4
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(now + lease))
+            .bind(STATUS_PENDING)
+            .bind(OurDateTime(now))
+            .bind(limit)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn mark_delivered(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        status_code: i32,
+    ) -> Result<(), OurError> {
+        let query_str = r#"UPDATE webhook_deliveries
+SET status = $1, attempts = attempts + 1, last_status_code = $2, last_error = NULL, delivered_at = $3
+WHERE uuid = $4"#;
+        sqlx::query(query_str)
+            .bind(STATUS_DELIVERED)
+            .bind(status_code)
+            .bind(OurDateTime(Utc::now()))
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    // records a failed attempt, next_attempt_at is None once we gave up on the delivery
+    pub async fn mark_attempt_failed(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        status_code: Option<i32>,
+        error: &str,
+        next_attempt_at: Option<OurDateTime>,
+    ) -> Result<(), OurError> {
+        let status = if next_attempt_at.is_some() {
+            STATUS_PENDING
+        } else {
+            STATUS_FAILED
+        };
+        let query_str = r#"UPDATE webhook_deliveries
+SET status = $1, attempts = attempts + 1, last_status_code = $2, last_error = $3, next_attempt_at = COALESCE($4, next_attempt_at)
+WHERE uuid = $5"#;
+        sqlx::query(query_str)
+            .bind(status)
+            .bind(status_code)
+            .bind(error)
+            .bind(next_attempt_at)
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::client::ClientInfo;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::organisation::Organisation;
+use crate::models::user::{ActivationRequest, User};
+use crate::models::user_status::UserStatus;
+use crate::states::mail_sender::MailSender;
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::signer::Signer;
+use chrono::{offset::Utc, Duration};
+use rocket::form::{Contextual, Form};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+use std::time::Duration as StdDuration;
+
+const ACTIVATION_PURPOSE: &str = "activation";
+const ACTIVATION_HOURS: i64 = 48;
+const RESEND_BUCKET: &str = "activation_resend";
+const RESEND_LIMIT: usize = 5;
+const RESEND_WINDOW: StdDuration = StdDuration::from_secs(60 * 60);
+const RESEND_MESSAGE: &str = "If that account is waiting for activation, a new link is on its way";
+
+// Emails the user a link activating the account, tells whether the email went out.
+pub async fn send_activation(
+    signer: &Signer,
+    mail_sender: &MailSender,
+    organisation: &Organisation,
+    user: &User,
+) -> bool {
+    let expires_at = (Utc::now() + Duration::hours(ACTIVATION_HOURS)).timestamp();
+    let token = signer.sign(ACTIVATION_PURPOSE, &user.uuid.to_string(), expires_at);
+    let link = mail_sender.url(&organisation.subdomain, &format!("/activate/{}", token));
+    let body = format!(
+        "Hello {},\n\nAn account was created for you. Activate it within {} hours:\n\n{}\n",
+        user.username, ACTIVATION_HOURS, link
+    );
+    mail_sender
+        .send(&user.email, "Activate your account", &body)
+        .await
+}
+
+#[get("/activate/<token>", format = "text/html")]
+pub async fn activate(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    signer: &State<Signer>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let organisation_uuid = &organisation.organisation.uuid;
+    let invalid = || {
+        Flash::error(
+            Redirect::to("/activate"),
+            "This activation link is invalid or expired, you can ask for a new one",
+        )
+    };
+    let uuid = signer
+        .verify(ACTIVATION_PURPOSE, token)
+        .ok_or_else(invalid)?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/activate"), "Something went wrong"))?;
+    let activated = User::activate(connection, organisation_uuid, &uuid)
+        .await
+        .is_ok();
+    if !activated {
+        // the link was already used
+        let user = User::find(connection, organisation_uuid, &uuid)
+            .await
+            .map_err(|_| invalid())?;
+        if !matches!(user.status, UserStatus::Active) {
+            return Err(invalid());
+        }
+    }
+    Ok(Flash::success(
+        Redirect::to("/login"),
+        "Your account is active, you can log in",
+    ))
+}
+
+// Displays the form asking for a new activation link.
+#[get("/activate", format = "text/html")]
+pub async fn resend_form(flash: Option<FlashMessage<'_>>, csrf_token: CsrfToken) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("activation/form", context))
+}
+
+#[post(
+    "/activate",
+    format = "application/x-www-form-urlencoded",
+    data = "<request_context>"
+)]
+pub async fn resend<'r>(
+    mut db: Connection<DBConnection>,
+    request_context: Form<Contextual<'r, ActivationRequest<'r>>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    mail_sender: &State<MailSender>,
+    limiter: &State<RateLimiter>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let client_key = client.ip_address.as_deref().unwrap_or("unknown");
+    if !limiter.check(RESEND_BUCKET, client_key, RESEND_LIMIT, RESEND_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/activate"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let request = request_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to("/activate"), "Invalid email"))?;
+    csrf_token
+        .verify(&request.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to("/activate"), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/activate"), "Something went wrong"))?;
+    // same answer whether the email is known or not, accounts cannot be probed
+    let user = User::find_by_email(connection, &organisation.organisation.uuid, request.email)
+        .await
+        .ok();
+    if let Some(user) = user {
+        if matches!(user.status, UserStatus::Pending) {
+            send_activation(signer, mail_sender, &organisation.organisation, &user).await;
+        }
+    }
+    Ok(Flash::success(Redirect::to("/login"), RESEND_MESSAGE))
+}
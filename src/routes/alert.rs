@@ -0,0 +1,132 @@
+use crate::errors::our_error::OurError;
+use crate::fairings::db::DBConnection;
+use crate::guards::api_token::{AlertsScope, ApiToken};
+use crate::models::alert::{
+    Alert, AlertStatus, AlertmanagerPayload, GenericAlertPayload, SOURCE_ALERTMANAGER,
+    SOURCE_GENERIC,
+};
+use crate::models::alert_ticket::AlertTicket;
+use crate::models::comment::Comment;
+use crate::models::issues_reported::{Issue, NewIssue};
+use crate::models::webhook::{Webhook, ISSUE_CREATED, ISSUE_STATUS_CHANGED};
+use crate::states::alert_mapper::AlertMapper;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use uuid::Uuid;
+
+// what happened to the alerts of one notification, returned to the sender
+#[derive(Default, Serialize)]
+pub struct AlertSummary {
+    created: usize,
+    appended: usize,
+    resolved: usize,
+    ignored: usize,
+}
+
+// Receives Prometheus Alertmanager webhook notifications.
+#[post("/alerts/alertmanager", format = "json", data = "<payload>")]
+pub async fn alertmanager(
+    token: ApiToken<AlertsScope>,
+    mut db: Connection<DBConnection>,
+    mapper: &State<AlertMapper>,
+    payload: Json<AlertmanagerPayload>,
+) -> Result<Json<AlertSummary>, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let alerts = payload.into_inner().into_alerts();
+    let summary = process_alerts(
+        connection,
+        &token.organisation.uuid,
+        mapper,
+        SOURCE_ALERTMANAGER,
+        &alerts,
+    )
+    .await
+    .map_err(|e| e.status)?;
+    Ok(Json(summary))
+}
+
+// Receives alerts from any system able to post a small JSON document.
+#[post("/alerts/generic", format = "json", data = "<payload>")]
+pub async fn generic(
+    token: ApiToken<AlertsScope>,
+    mut db: Connection<DBConnection>,
+    mapper: &State<AlertMapper>,
+    payload: Json<GenericAlertPayload>,
+) -> Result<Json<AlertSummary>, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let alerts = payload.into_inner().into_alerts();
+    let summary = process_alerts(
+        connection,
+        &token.organisation.uuid,
+        mapper,
+        SOURCE_GENERIC,
+        &alerts,
+    )
+    .await
+    .map_err(|e| e.status)?;
+    Ok(Json(summary))
+}
+
+// Opens a ticket for a new fingerprint, appends to the open ticket of a known one
+// and closes that ticket when the alert is resolved.
+async fn process_alerts(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    mapper: &AlertMapper,
+    source: &str,
+    alerts: &[Alert],
+) -> Result<AlertSummary, OurError> {
+    let mut summary = AlertSummary::default();
+    for alert in alerts.iter() {
+        let open_issue =
+            AlertTicket::find_open_issue(connection, organisation_uuid, &alert.fingerprint).await?;
+        match (alert.status, open_issue) {
+            (AlertStatus::Firing, Some(issue)) => {
+                let body = mapper.describe(alert, "fired again");
+                Comment::create(connection, &issue.uuid, source, &body, false).await?;
+                summary.appended += 1;
+            }
+            (AlertStatus::Firing, None) => {
+                let mapped = mapper.map(alert);
+                let new_issue = NewIssue {
+                    issue_name: &mapped.issue_name,
+                    description: &mapped.description,
+                    reported_by: &mapped.reported_by,
+                    company_name: &mapped.company_name,
+                    contact_number: &mapped.contact_number,
+                    authenticity_token: "",
+                };
+                let issue = Issue::create(connection, organisation_uuid, &new_issue).await?;
+                AlertTicket::create(connection, &alert.fingerprint, &issue.uuid, source).await?;
+                let body = mapper.describe(alert, "fired");
+                Comment::create(connection, &issue.uuid, source, &body, false).await?;
+                Webhook::dispatch(connection, ISSUE_CREATED, &issue).await;
+                summary.created += 1;
+            }
+            (AlertStatus::Resolved, Some(issue)) => {
+                let body = mapper.describe(alert, "resolved");
+                Comment::create(connection, &issue.uuid, source, &body, false).await?;
+                let (issue, closed) =
+                    Issue::complete(connection, organisation_uuid, &issue.uuid.to_string()).await?;
+                if closed {
+                    Webhook::dispatch(connection, ISSUE_STATUS_CHANGED, &issue).await;
+                }
+                summary.resolved += 1;
+            }
+            (AlertStatus::Resolved, None) => {
+                summary.ignored += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,210 @@
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::impersonation::{NotImpersonating, IMPERSONATION_COOKIE_NAME};
+use crate::guards::role::{RequireRole, Supervisor};
+use crate::models::impersonation::{Impersonation, ImpersonationRequest};
+use crate::models::reporter::Reporter;
+use crate::models::user::User;
+use crate::models::user_status::UserStatus;
+use crate::states::session_policy::SessionPolicy;
+use rocket::form::{Contextual, Form};
+use rocket::http::{Cookie, CookieJar};
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+
+const USER_LANDING: &str = "/tickets";
+const REPORTER_LANDING: &str = "/portal";
+
+fn form_errors(request_context: &Contextual<'_, ImpersonationRequest<'_>>) -> String {
+    request_context
+        .context
+        .errors()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("<br/>")
+}
+
+// Starts viewing the application as an agent with a lower role, until it ends or expires.
+#[post(
+    "/users/impersonate/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<request_context>"
+)]
+pub async fn impersonate_user<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    request_context: Form<Contextual<'r, ImpersonationRequest<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    policy: &State<SessionPolicy>,
+    role: RequireRole<Supervisor>,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(user_url.clone()),
+            "Something went wrong when starting impersonation",
+        )
+    };
+    let impersonation_request = match &request_context.value {
+        Some(impersonation_request) => impersonation_request,
+        None => {
+            return Err(Flash::error(
+                Redirect::to(user_url.clone()),
+                form_errors(&request_context),
+            ))
+        }
+    };
+    csrf_token
+        .verify(&impersonation_request.authenticity_token)
+        .map_err(|_| failed())?;
+    let impersonator = &role.current_user.user;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let user = User::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    // only someone below, impersonating would otherwise hand out permissions
+    if user.role >= impersonator.role {
+        return Err(Flash::error(
+            Redirect::to(user_url.clone()),
+            "You can only impersonate users with a lower role than yours",
+        ));
+    }
+    if !user.status.can_log_in() || user.deleted_at.is_some() {
+        return Err(Flash::error(
+            Redirect::to(user_url.clone()),
+            "This user cannot log in, there is nothing to see as them",
+        ));
+    }
+    let impersonation = Impersonation::create(
+        connection,
+        &impersonator.uuid,
+        Some(&user.uuid),
+        None,
+        impersonation_request.reason,
+        policy.impersonation_timeout,
+    )
+    .await
+    .map_err(|_| failed())?;
+    cookies.add_private(Cookie::new(
+        IMPERSONATION_COOKIE_NAME,
+        impersonation.uuid.to_string(),
+    ));
+    Ok(Flash::success(
+        Redirect::to(USER_LANDING),
+        format!("You are now viewing as {}", user.username),
+    ))
+}
+
+// Starts viewing the portal as a reporter, until it ends or expires.
+#[post(
+    "/reporters/impersonate/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<request_context>"
+)]
+pub async fn impersonate_reporter<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    request_context: Form<Contextual<'r, ImpersonationRequest<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    policy: &State<SessionPolicy>,
+    role: RequireRole<Supervisor>,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/reporters"),
+            "Something went wrong when starting impersonation",
+        )
+    };
+    let impersonation_request = match &request_context.value {
+        Some(impersonation_request) => impersonation_request,
+        None => {
+            return Err(Flash::error(
+                Redirect::to("/reporters"),
+                form_errors(&request_context),
+            ))
+        }
+    };
+    csrf_token
+        .verify(&impersonation_request.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    // reporters are looked up by uuid alone, the ones of other organisations are not ours to see
+    let reporter = Reporter::find(connection, uuid)
+        .await
+        .ok()
+        .filter(|reporter| reporter.organisation_uuid == role.current_user.organisation.uuid)
+        .ok_or_else(failed)?;
+    if !matches!(reporter.status, UserStatus::Active) {
+        return Err(Flash::error(
+            Redirect::to("/reporters"),
+            "This reporter cannot log in, there is nothing to see as them",
+        ));
+    }
+    let impersonation = Impersonation::create(
+        connection,
+        &role.current_user.user.uuid,
+        None,
+        Some(&reporter.uuid),
+        impersonation_request.reason,
+        policy.impersonation_timeout,
+    )
+    .await
+    .map_err(|_| failed())?;
+    cookies.add_private(Cookie::new(
+        IMPERSONATION_COOKIE_NAME,
+        impersonation.uuid.to_string(),
+    ));
+    Ok(Flash::success(
+        Redirect::to(REPORTER_LANDING),
+        format!("You are now viewing as {}", reporter.name),
+    ))
+}
+
+// Back to being oneself, also after the impersonation expired.
+#[post(
+    "/impersonation/end",
+    format = "application/x-www-form-urlencoded",
+    data = "<end_context>"
+)]
+pub async fn end<'r>(
+    mut db: Connection<DBConnection>,
+    end_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+) -> Flash<Redirect> {
+    let verified = end_context
+        .value
+        .as_ref()
+        .map(|end| csrf_token.verify(&end.authenticity_token).is_ok())
+        .unwrap_or(false);
+    if !verified {
+        return Flash::error(Redirect::to(USER_LANDING), "Something went wrong");
+    }
+    let cookie = match cookies.get_private(IMPERSONATION_COOKIE_NAME) {
+        Some(cookie) => cookie,
+        None => return Flash::success(Redirect::to(USER_LANDING), "You are not impersonating"),
+    };
+    cookies.remove_private(Cookie::named(IMPERSONATION_COOKIE_NAME));
+    let connection = match db.acquire().await {
+        Ok(connection) => connection,
+        Err(_) => {
+            log::error!("Cannot end impersonation {}", cookie.value());
+            return Flash::error(Redirect::to(USER_LANDING), "Something went wrong");
+        }
+    };
+    let ended = Impersonation::end(connection, cookie.value()).await.ok();
+    let back = match ended.flatten() {
+        Some(Impersonation {
+            user_uuid: Some(user_uuid),
+            ..
+        }) => format!("/users/{}", user_uuid),
+        Some(_) => String::from("/reporters"),
+        None => String::from(USER_LANDING),
+    };
+    Flash::success(Redirect::to(back), "You are yourself again")
+}
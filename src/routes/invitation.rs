@@ -0,0 +1,334 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::guards::role::{Admin, RequireRole};
+use crate::models::user::User;
+use crate::models::user_invitation::{AcceptedUserInvitation, NewInvitation, UserInvitation};
+use crate::models::user_role::ALL_ROLES;
+use crate::states::mail_sender::MailSender;
+use crate::states::password_policy::PasswordPolicy;
+use crate::states::signer::Signer;
+use chrono::Duration;
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use uuid::Uuid;
+
+const INVITATION_PURPOSE: &str = "user_invitation";
+const INVITATION_DAYS: i64 = 7;
+const INVITATIONS_SHOWN: i64 = 100;
+const INVALID_INVITATION_MESSAGE: &str = "This invitation is no longer valid";
+
+#[get("/users/invitations", format = "text/html")]
+pub async fn get_invitations(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let invitations = UserInvitation::find_recent(
+        connection,
+        &role.current_user.organisation.uuid,
+        INVITATIONS_SHOWN,
+    )
+    .await
+    .map_err(|e| e.status)?;
+    #[derive(Serialize)]
+    struct InvitationRow {
+        invitation: UserInvitation,
+        status: &'static str,
+        pending: bool,
+    }
+    let invitations = invitations
+        .into_iter()
+        .map(|invitation| InvitationRow {
+            status: invitation.status(),
+            pending: invitation.is_pending(),
+            invitation,
+        })
+        .collect::<Vec<_>>();
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        invitations,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("invitations/index", context))
+}
+
+#[get("/users/invitations/new", format = "text/html")]
+pub async fn new_invitation(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        roles: ALL_ROLES,
+        permissions: role.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("invitations/form", context))
+}
+
+// Records the invitation and emails a signed link to accept it.
+#[post(
+    "/users/invitations",
+    format = "application/x-www-form-urlencoded",
+    data = "<invitation_context>"
+)]
+pub async fn create_invitation<'r>(
+    mut db: Connection<DBConnection>,
+    invitation_context: Form<Contextual<'r, NewInvitation<'r>>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    mail_sender: &State<MailSender>,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/users/invitations/new"),
+            "Something went wrong when inviting user",
+        )
+    };
+    if invitation_context.value.is_none() {
+        let error_message = invitation_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(
+            Redirect::to("/users/invitations/new"),
+            error_message,
+        ));
+    }
+    let new_invitation = invitation_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_invitation.authenticity_token)
+        .map_err(|_| failed())?;
+    let organisation = &role.current_user.organisation;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    if User::find_by_email(connection, &organisation.uuid, new_invitation.email)
+        .await
+        .is_ok()
+    {
+        return Err(Flash::error(
+            Redirect::to("/users/invitations/new"),
+            "A user with this email already exists",
+        ));
+    }
+    let invitation = UserInvitation::create(
+        connection,
+        &organisation.uuid,
+        new_invitation,
+        &role.current_user.user.uuid,
+        Duration::days(INVITATION_DAYS),
+    )
+    .await
+    .map_err(|_| failed())?;
+    let token = signer.sign(
+        INVITATION_PURPOSE,
+        &invitation.uuid.to_string(),
+        invitation.expires_at.0.timestamp(),
+    );
+    let link = mail_sender.url(&organisation.subdomain, &format!("/invitations/{}", token));
+    let body = format!(
+        "Hello,\n\nYou have been invited to join the ticket desk as {}. Choose your username and password here within {} days:\n\n{}\n",
+        invitation.role, INVITATION_DAYS, link
+    );
+    if !mail_sender
+        .send(&invitation.email, "Your ticket desk invitation", &body)
+        .await
+    {
+        return Ok(Flash::warning(
+            Redirect::to("/users/invitations"),
+            format!(
+                "Invitation created but the email failed, send them this link: {}",
+                link
+            ),
+        ));
+    }
+    Ok(Flash::success(
+        Redirect::to("/users/invitations"),
+        "Successfully invited user",
+    ))
+}
+
+#[post(
+    "/users/invitations/revoke/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<revoke_context>"
+)]
+pub async fn revoke_invitation<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    revoke_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let broken = || {
+        Flash::error(
+            Redirect::to("/users/invitations"),
+            "Something went wrong when revoking the invitation",
+        )
+    };
+    let revoke = revoke_context.value.as_ref().ok_or_else(broken)?;
+    csrf_token
+        .verify(&revoke.authenticity_token)
+        .map_err(|_| broken())?;
+    let failed = || {
+        Flash::error(
+            Redirect::to("/users/invitations"),
+            "Only pending invitations can be revoked",
+        )
+    };
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    UserInvitation::revoke(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to("/users/invitations"),
+        "Invitation revoked",
+    ))
+}
+
+// pending invitation behind a link, the signature alone does not tell if it was revoked or used
+async fn find_pending(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    signer: &Signer,
+    token: &str,
+) -> Option<UserInvitation> {
+    let uuid = signer.verify(INVITATION_PURPOSE, token)?;
+    let invitation = UserInvitation::find(connection, organisation_uuid, &uuid)
+        .await
+        .ok()?;
+    if !invitation.is_pending() {
+        return None;
+    }
+    Some(invitation)
+}
+
+#[get("/invitations/<token>", format = "text/html")]
+pub async fn invitation_form(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    organisation: CurrentOrganisation,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let invitation = find_pending(connection, &organisation.organisation.uuid, signer, token)
+        .await
+        .ok_or(Status::NotFound)?;
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: format!("/invitations/{}", token),
+        invitation,
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("invitations/accept", context))
+}
+
+#[post(
+    "/invitations/<token>",
+    format = "application/x-www-form-urlencoded",
+    data = "<accepted_context>"
+)]
+pub async fn accept_invitation<'r>(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    accepted_context: Form<Contextual<'r, AcceptedUserInvitation<'r>>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    policy: &State<PasswordPolicy>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let form_url = format!("/invitations/{}", token);
+    let invalid = || Flash::error(Redirect::to("/login"), INVALID_INVITATION_MESSAGE);
+    if accepted_context.value.is_none() {
+        let error_message = accepted_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(form_url), error_message));
+    }
+    let accepted = accepted_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&accepted.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let invitation = find_pending(connection, &organisation.organisation.uuid, signer, token)
+        .await
+        .ok_or_else(invalid)?;
+    policy
+        .check_new_password(
+            accepted.password,
+            &[accepted.username, invitation.email.as_str()],
+        )
+        .await
+        .map_err(|message| Flash::error(Redirect::to(form_url.clone()), message))?;
+    if User::find_by_username(connection, &invitation.organisation_uuid, accepted.username)
+        .await
+        .is_ok()
+    {
+        return Err(Flash::error(
+            Redirect::to(form_url),
+            "This username is already taken",
+        ));
+    }
+    // the account and the used invitation go together, or neither does
+    let mut transaction = connection
+        .begin()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let user = User::create_invited(
+        &mut transaction,
+        accepted.username,
+        &invitation,
+        accepted.password,
+        policy,
+    )
+    .await
+    .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    UserInvitation::accept(&mut transaction, &invitation.uuid, &user.uuid)
+        .await
+        .map_err(|_| invalid())?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    Ok(Flash::success(
+        Redirect::to("/login"),
+        "Welcome, your account is ready, you can log in",
+    ))
+}
@@ -1,38 +1,105 @@
-use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
 use crate::fairings::db::DBConnection;
-use crate::models::issues_reported::{Issue, NewIssue, EditedIssue};
+use crate::guards::auth::CurrentUser;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::guards::role::{Agent, RequireRole, Supervisor};
+use crate::models::comment::{Comment, NewComment};
+use crate::models::issues_reported::{Issue, NewIssue, EditedIssue, UNASSIGNED_OWNER};
+use crate::models::notification_setting::{
+    NotificationSetting, CHANNEL_EMAIL, EVENT_TICKET_ASSIGNED, EVENT_TICKET_CLOSED,
+    EVENT_TICKET_COMMENTED,
+};
+use crate::models::organisation::Organisation;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::sms_notification::SmsNotification;
+use crate::models::team::Team;
+use crate::models::team_member::TeamMember;
+use crate::models::user_preferences::UserPreferences;
+use crate::models::user_role::Permissions;
+use crate::models::webhook::{
+    Webhook, ISSUE_ASSIGNED, ISSUE_CREATED, ISSUE_DELETED, ISSUE_STATUS_CHANGED, ISSUE_UPDATED,
+};
 use crate::rocket::serde::json::json;
+use crate::states::mail_sender::MailSender;
+use crate::states::signer::Signer;
+use crate::states::sms_notifier::SmsNotifier;
 
+use super::track::track_path;
+use chrono::{offset::Utc, Duration};
 use super::HtmlResponse;
 use rocket::form::{Contextual, Form};
 use rocket::http::Status;
 use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
 use rocket::serde::Serialize;
-use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket::State;
+use rocket_db_pools::{
+    sqlx::{Acquire, PgConnection},
+    Connection,
+};
 use rocket_dyn_templates::{context, Template};
 
+// Sends the agent to the ticket list they picked in their preferences.
+#[get("/tickets")]
+pub async fn get_tickets(role: RequireRole<Agent>) -> Redirect {
+    Redirect::to(role.current_user.preferences.ticket_view.path())
+}
+
 // Retrieves an issue with a given uuid and displays it on an HTML page.
 #[get("/issues/<uuid>", format = "text/html")]
 pub async fn get_issue( 
     mut db: Connection<DBConnection>,
     uuid: &str,
     flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    role: RequireRole<Agent>,
 ) -> HtmlResponse {
     let connection = db
         .acquire()
         .await
         .map_err(|_| Status::InternalServerError)?;
-    let issue = Issue::find(connection, uuid).await.map_err(|e| e.status)?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let issue = Issue::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let sms_notifications = SmsNotification::find_by_issue(connection, &issue.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let comments = Comment::find_by_issue(connection, &issue.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let team = match issue.team_uuid {
+        Some(team_uuid) => Some(
+            Team::find(connection, organisation_uuid, &team_uuid.to_string())
+                .await
+                .map_err(|e| e.status)?,
+        ),
+        None => None,
+    };
     #[derive(Serialize)]
     struct GetIssue {
+        track_path: String,
         issue: Issue,
+        team: Option<Team>,
+        comments: Vec<Comment>,
+        sms_notifications: Vec<SmsNotification>,
+        permissions: Permissions,
+        preferences: UserPreferences,
         flash: Option<String>,
+        csrf_token: CsrfToken,
     }
     let flash_message = flash.map(|fm| String::from(fm.message()));
     let context = GetIssue {
+        track_path: track_path(signer, &issue),
         issue,
+        team,
+        comments,
+        sms_notifications,
+        permissions: role.permissions(),
+        preferences: role.current_user.preferences.clone(),
         flash: flash_message,
+        csrf_token,
     };
     Ok(Template::render("issues/show", &context))
 }
@@ -41,11 +108,18 @@ pub async fn get_issue(
 #[get("/issues?", format = "text/html")]
 pub async fn get_issues(
     mut db: Connection<DBConnection>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
 ) -> HtmlResponse {
-    let issues = Issue::find_all(&mut db)
+    let issues = Issue::find_all(&mut db, &role.current_user.organisation.uuid)
         .await
         .map_err(|e| e.status)?;
-    let context = context! {issues: issues};
+    let context = context! {
+        issues: issues,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+    };
     Ok(Template::render("issues/index", context))
 }
 
@@ -53,19 +127,29 @@ pub async fn get_issues(
 #[get("/issues/manage_tickets", format = "text/html")]
 pub async fn manage_issues(
     mut db: Connection<DBConnection>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
 ) -> HtmlResponse {
-    let issues = Issue::find_all(&mut db)
+    let issues = Issue::find_all(&mut db, &role.current_user.organisation.uuid)
         .await
         .map_err(|e| e.status)?;
-    let context = context! {issues: issues};
+    let context = context! {
+        issues: issues,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+    };
     Ok(Template::render("issues/manage_tickets", context))
 }
 
 // Displays a form to create a new issue on an HTML page.
+// Open to everybody, reporters submit their issues here without an account.
 #[get("/issues/new", format = "text/html")]
 pub async fn new_issue(
     flash: Option<FlashMessage<'_>>,
- csrf_token: CsrfToken) -> HtmlResponse {
+    csrf_token: CsrfToken,
+    current_user: Option<CurrentUser>,
+) -> HtmlResponse {
     let flash_string = flash
         .map(|fl| format!("{}", fl.message()))
         .unwrap_or_else(|| "".to_string());
@@ -73,6 +157,7 @@ pub async fn new_issue(
         edit: false,
         form_url: "/issues",
         legend: "New Issue",
+        permissions: current_user.map(|current_user| current_user.permissions()),
         flash: flash_string,
         csrf_token: csrf_token,
     };
@@ -90,22 +175,10 @@ pub async fn create_issue<'r>(
     mut db: Connection<DBConnection>,
     issue_context: Form<Contextual<'r, NewIssue<'r>>>,
     csrf_token: CsrfToken,
+    sms: &State<SmsNotifier>,
+    signer: &State<Signer>,
+    organisation: CurrentOrganisation,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    // check whether there are existing tickets
-    let last_ticket_no = Issue::find_last_token(&mut db)
-    .await
-    .map_err(|e| {
-        let status = e;
-        Flash::error(Redirect::to("/links/new"), status.to_string())
-    })?;
-
-    // set ticket to 1 if no tickets exist, else +=1
-    let ticket_number = if last_ticket_no.is_empty() {
-        1
-    } else {
-        last_ticket_no[last_ticket_no.len() - 1].ticket_number + 1
-    };
-
     if issue_context.value.is_none() {
         let error_message = issue_context
             .context
@@ -132,16 +205,22 @@ pub async fn create_issue<'r>(
         )
     })?;
 
-    Issue::create(connection, new_issue, ticket_number).await.map_err(|_| {
-        Flash::error(
-            Redirect::to("/issues/new"),
-            "Something went wrong when creating your ticket",
-        )
-    })?;
+    // numbered after the last ticket of the organisation
+    let issue = Issue::create(connection, &organisation.organisation.uuid, new_issue)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/issues/new"),
+                "Something went wrong when creating your ticket",
+            )
+        })?;
+    sms.ticket_created(connection, &issue).await;
+    Webhook::dispatch(connection, ISSUE_CREATED, &issue).await;
 
     let success_message = format!(
-        "Successfully created ticket. Your ticket number is {}",
-        ticket_number
+        "Successfully created ticket. Your ticket number is {}. Follow it on <a href=\"{}\">your tracking page</a>",
+        issue.ticket_number,
+        track_path(signer, &issue)
     );
 
     
@@ -158,19 +237,25 @@ pub async fn edit_issue(
     uuid: &str,
     flash: Option<FlashMessage<'_>>,
     csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
 ) -> HtmlResponse {
     let connection = db
         .acquire()
         .await
         .map_err(|_| Status::InternalServerError)?;
-    let issue = Issue::find(connection, uuid).await.map_err(|e| e.status)?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let issue = Issue::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let teams = Team::find_all(connection, organisation_uuid)
+        .await
+        .map_err(|e| e.status)?;
     #[derive(Serialize)]
         struct GetIssue {
         issue: Issue,
         flash: Option<String>,
         }
 
-        println!("row: {:#?}", issue);
     let flash_string = flash
         .map(|fl| format!("{}", fl.message()))
         .unwrap_or_else(|| "".to_string());
@@ -178,8 +263,10 @@ pub async fn edit_issue(
         form_url: format!("/issues/{}", &issue.uuid),
         edit: true,
         legend: "Edit Issue",
+        permissions: role.permissions(),
         flash: flash_string,
         issue,
+        teams,
         csrf_token: csrf_token,
     };
 
@@ -197,6 +284,9 @@ pub async fn update_issue<'r>(
     uuid: &str,
     issue_context: Form<Contextual<'r, EditedIssue<'r>>>,
     csrf_token: CsrfToken,
+    sms: &State<SmsNotifier>,
+    mail: &State<MailSender>,
+    role: RequireRole<Agent>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     if issue_context.value.is_none() {
         let error_message = issue_context
@@ -212,10 +302,9 @@ pub async fn update_issue<'r>(
     }
 
     let issue_value = issue_context.value.as_ref().unwrap();
-    print!("error: {}",issue_value.method );
     match issue_value.method {
-        "PUT" => put_issue(db, uuid, issue_context, csrf_token).await,
-        "PATCH" => patch_issue(db, uuid, issue_context, csrf_token).await,
+        "PUT" => put_issue(db, uuid, issue_context, csrf_token, sms, mail, role).await,
+        "PATCH" => patch_issue(db, uuid, issue_context, csrf_token, sms, mail, role).await,
         _ => Err(Flash::error(
             Redirect::to(format!("/isues/edit/{}", uuid)),
             "Something went wrong when updating your ticket",
@@ -234,6 +323,9 @@ pub async fn put_issue<'r>(
     uuid: &str,
     issue_context: Form<Contextual<'r, EditedIssue<'r>>>,
     csrf_token: CsrfToken,
+    sms: &State<SmsNotifier>,
+    mail: &State<MailSender>,
+    role: RequireRole<Agent>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let issue_value = issue_context.value.as_ref().unwrap();
     csrf_token
@@ -244,13 +336,80 @@ pub async fn put_issue<'r>(
                 "Something went wrong when updating your ticket1",
             )
         })?;
-        
-    let issue = Issue::update(&mut db, uuid, issue_value).await.map_err(|_| {
-                Flash::error(
+
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
             Redirect::to(format!("/issues/edit/{}", uuid)),
-            "Something went wrong when updating your ticket2",
+            "Something went wrong when updating your ticket",
         )
     })?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let old_issue = Issue::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(format!("/issues/edit/{}", uuid)),
+                "Something went wrong when updating your ticket",
+            )
+        })?;
+    // a ticket in a team queue can only be claimed by, or handed to, a member of the team
+    if let Some(team_uuid) = issue_value.team_uuid {
+        // and only queued for a team of the organisation
+        if Team::find(connection, organisation_uuid, &team_uuid.to_string())
+            .await
+            .is_err()
+        {
+            return Err(Flash::error(
+                Redirect::to(format!("/issues/edit/{}", uuid)),
+                "Team not found",
+            ));
+        }
+        let owner_changed = old_issue.ticket_owner != issue_value.ticket_owner
+            || old_issue.team_uuid != issue_value.team_uuid;
+        if owner_changed && issue_value.ticket_owner != UNASSIGNED_OWNER {
+            let is_member = TeamMember::is_member(connection, &team_uuid, issue_value.ticket_owner)
+                .await
+                .unwrap_or(false);
+            if !is_member {
+                return Err(Flash::error(
+                    Redirect::to(format!("/issues/edit/{}", uuid)),
+                    "Only members of the team can own tickets from its queue",
+                ));
+            }
+        }
+    }
+        
+    let issue = Issue::update(&mut db, organisation_uuid, uuid, issue_value)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(format!("/issues/edit/{}", uuid)),
+                "Something went wrong when updating your ticket2",
+            )
+        })?;
+
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to(format!("/issues/{}", issue.uuid)),
+            "Ticket updated but the notifications could not be sent",
+        )
+    })?;
+    Webhook::dispatch(connection, ISSUE_UPDATED, &issue).await;
+    if old_issue.status != issue.status {
+        Webhook::dispatch(connection, ISSUE_STATUS_CHANGED, &issue).await;
+    }
+    let actor = &role.current_user.user.username;
+    if old_issue.ticket_owner != issue.ticket_owner || old_issue.team_uuid != issue.team_uuid {
+        Webhook::dispatch(connection, ISSUE_ASSIGNED, &issue).await;
+    }
+    if old_issue.ticket_owner != issue.ticket_owner {
+        notify_owner(connection, mail, &issue, EVENT_TICKET_ASSIGNED, actor).await;
+    }
+    // text the reporter only when this update is the one closing the ticket
+    if old_issue.status != "closed" && issue.status == "closed" {
+        sms.ticket_closed(connection, &issue).await;
+        notify_owner(connection, mail, &issue, EVENT_TICKET_CLOSED, actor).await;
+    }
     Ok(Flash::success(
         Redirect::to(format!("/issues/{}", issue.uuid)),
         "Successfully updated issue",
@@ -268,8 +427,11 @@ pub async fn patch_issue<'r>(
     uuid: &str,
     issue_context: Form<Contextual<'r, EditedIssue<'r>>>,
     csrf_token: CsrfToken,
+    sms: &State<SmsNotifier>,
+    mail: &State<MailSender>,
+    role: RequireRole<Agent>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    put_issue(db, uuid, issue_context, csrf_token).await
+    put_issue(db, uuid, issue_context, csrf_token, sms, mail, role).await
 }
 
 // Function to delete an issue from database
@@ -277,8 +439,9 @@ pub async fn patch_issue<'r>(
 pub async fn delete_issue_entry_point(
     db: Connection<DBConnection>,
     uuid: &str,
+    role: RequireRole<Supervisor>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    delete_issue(db, uuid).await
+    delete_issue(db, uuid, role).await
 }
 
 // Function to delete an issue from database
@@ -286,6 +449,7 @@ pub async fn delete_issue_entry_point(
 pub async fn delete_issue(
     mut db: Connection<DBConnection>,
     uuid: &str,
+    role: RequireRole<Supervisor>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let connection = db.acquire().await.map_err(|_| {
         Flash::error(
@@ -293,12 +457,24 @@ pub async fn delete_issue(
             "Something went wrong when deleting issue",
         )
     })?;
-    Issue::destroy(connection, uuid).await.map_err(|_| {
-        Flash::error(
-            Redirect::to("/issues/manage_tickets"),
-            "Something went wrong when deleting issue",
-        )
-    })?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let issue = Issue::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/issues/manage_tickets"),
+                "Something went wrong when deleting issue",
+            )
+        })?;
+    Issue::destroy(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/issues/manage_tickets"),
+                "Something went wrong when deleting issue",
+            )
+        })?;
+    Webhook::dispatch(connection, ISSUE_DELETED, &issue).await;
 Ok(Flash::success(
     Redirect::to("/issues/manage_tickets"),
     "Successfully deleted issue",
@@ -308,16 +484,23 @@ Ok(Flash::success(
 
 // Function to retrieve all open issues
 #[get("/issues/open?", format = "text/html")]
-pub async fn get_open(mut db: Connection<DBConnection>) -> HtmlResponse {
-    let tickets = Issue::find_all(&mut db)
+pub async fn get_open(
+    mut db: Connection<DBConnection>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    // tickets routed to a team are in the team queue instead
+    let open_tickets = Issue::find_open(connection, &role.current_user.organisation.uuid, None)
+        .await
+        .map_err(|e| e.status)?;
+    let my_teams = Team::find_by_member(connection, &role.current_user.user.uuid)
         .await
         .map_err(|e| e.status)?;
-    println!("open tickets: {:#?}",tickets  );
-    
-    // Filter tickets where is_open is true
-    let open_tickets = tickets.iter().filter(|t| t.status == "open").collect::<Vec<_>>();
 
-    
     let context = context! {
         issues: open_tickets.iter().map(|t| {
             json!({
@@ -335,36 +518,300 @@ pub async fn get_open(mut db: Connection<DBConnection>) -> HtmlResponse {
                 // add other fields as necessary
             })
         }).collect::<Vec<_>>(),
+        my_teams,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
     };
-    println!("open tickets: {:#?}",context  );
-    
     Ok(Template::render("issues/open", context))
 }
 
+// Open tickets routed to a team, its members claim them from here.
+#[get("/issues/queue/<uuid>", format = "text/html")]
+pub async fn get_queue(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let team = Team::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let is_member = TeamMember::is_member(connection, &team.uuid, &role.current_user.user.username)
+        .await
+        .map_err(|e| e.status)?;
+    #[derive(Serialize)]
+    struct QueuedIssue {
+        issue: Issue,
+        due_at: OurDateTime,
+        overdue: bool,
+    }
+    let now = Utc::now();
+    let sla = Duration::hours(team.sla_hours.into());
+    let issues = Issue::find_open(connection, organisation_uuid, Some(&team.uuid))
+        .await
+        .map_err(|e| e.status)?
+        .into_iter()
+        .map(|issue| {
+            let due_at = issue.created_at.0 + sla;
+            QueuedIssue {
+                issue,
+                due_at: OurDateTime(due_at),
+                overdue: due_at < now,
+            }
+        })
+        .collect::<Vec<_>>();
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        team,
+        issues,
+        is_member,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
+    };
+    Ok(Template::render("issues/queue", context))
+}
+
+// where an open ticket waits to be claimed
+fn queue_path(issue: &Issue) -> String {
+    match issue.team_uuid {
+        Some(team_uuid) => format!("/issues/queue/{}", team_uuid),
+        None => String::from("/issues/open"),
+    }
+}
+
+// Takes an unclaimed open ticket, one in a team queue only for members of the team.
+#[post(
+    "/issues/claim/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<claim_context>"
+)]
+pub async fn claim<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    claim_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/issues/open"),
+            "Something went wrong when claiming the ticket",
+        )
+    };
+    let claim = claim_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&claim.authenticity_token)
+        .map_err(|_| failed())?;
+    let username = &role.current_user.user.username;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let issue = Issue::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    let queue_url = queue_path(&issue);
+    if let Some(team_uuid) = issue.team_uuid {
+        let is_member = TeamMember::is_member(connection, &team_uuid, username)
+            .await
+            .unwrap_or(false);
+        if !is_member {
+            return Err(Flash::error(
+                Redirect::to(queue_url),
+                "Only members of the team can claim from its queue",
+            ));
+        }
+    }
+    let claimed = Issue::claim(connection, organisation_uuid, &issue.uuid, username)
+        .await
+        .map_err(|_| failed())?
+        .ok_or_else(|| Flash::error(Redirect::to(queue_url), "This ticket was already claimed"))?;
+    Webhook::dispatch(connection, ISSUE_ASSIGNED, &claimed).await;
+    Ok(Flash::success(
+        Redirect::to(format!("/issues/{}", claimed.uuid)),
+        "Ticket claimed",
+    ))
+}
 
 // Function to mark issue as complete
 #[post(
     "/issues/complete/<uuid>",
     format = "application/x-www-form-urlencoded",
+    data = "<complete_context>"
 )]
-pub async fn complete(
+pub async fn complete<'r>(
     mut db: Connection<DBConnection>,
     uuid: &str,
+    complete_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    sms: &State<SmsNotifier>,
+    mail: &State<MailSender>,
+    role: RequireRole<Agent>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    let connection = db.acquire().await.map_err(|_| {
+    let failed = || {
         Flash::error(
             Redirect::to("/issues/complete"),
             "Something went wrong when completing issue",
         )
+    };
+    let complete = complete_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&complete.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let (issue, closed) = Issue::complete(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    // a repeated request finds the ticket closed already, the reporter is only told once
+    if !closed {
+        return Ok(Flash::success(
+            Redirect::to("/issues/open"),
+            "This ticket is closed already",
+        ));
+    }
+    sms.ticket_closed(connection, &issue).await;
+    Webhook::dispatch(connection, ISSUE_STATUS_CHANGED, &issue).await;
+    notify_owner(
+        connection,
+        mail,
+        &issue,
+        EVENT_TICKET_CLOSED,
+        &role.current_user.user.username,
+    )
+    .await;
+    Ok(Flash::success(
+        Redirect::to("/issues/open"),
+        "Successfully completing issue",
+    ))
+}
+
+// Adds a comment to an issue, public ones show up on the reporter's tracking page.
+#[post(
+    "/issues/comments/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<comment_context>"
+)]
+pub async fn create_comment<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    comment_context: Form<Contextual<'r, NewComment<'r>>>,
+    csrf_token: CsrfToken,
+    mail: &State<MailSender>,
+    role: RequireRole<Agent>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if comment_context.value.is_none() {
+        let error_message = comment_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(
+            Redirect::to(format!("/issues/{}", uuid)),
+            error_message,
+        ));
+    }
+    let new_comment = comment_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_comment.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(format!("/issues/{}", uuid)),
+                "Something went wrong when adding your comment",
+            )
+        })?;
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to(format!("/issues/{}", uuid)),
+            "Something went wrong when adding your comment",
+        )
     })?;
-    Issue::complete(connection, uuid).await.map_err(|_| {
+    let issue = Issue::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(format!("/issues/{}", uuid)),
+                "Something went wrong when adding your comment",
+            )
+        })?;
+    Comment::create(
+        connection,
+        &issue.uuid,
+        &role.current_user.user.username,
+        new_comment.body,
+        new_comment.is_public,
+    )
+    .await
+    .map_err(|_| {
         Flash::error(
-            Redirect::to("/issues/complete"),
-            "Something went wrong when completing issue",
+            Redirect::to(format!("/issues/{}", uuid)),
+            "Something went wrong when adding your comment",
         )
     })?;
+    Webhook::dispatch(connection, ISSUE_UPDATED, &issue).await;
+    notify_owner(
+        connection,
+        mail,
+        &issue,
+        EVENT_TICKET_COMMENTED,
+        &role.current_user.user.username,
+    )
+    .await;
     Ok(Flash::success(
-        Redirect::to("/issues/open"),
-        "Successfully completing issue",
+        Redirect::to(format!("/issues/{}", issue.uuid)),
+        "Successfully added comment",
     ))
-}
\ No newline at end of file
+}
+
+// Emails the agent owning the ticket when they opted in to the event.
+// Nobody hears about what they did themselves, a failed email does not fail the request.
+pub(crate) async fn notify_owner(
+    connection: &mut PgConnection,
+    mail: &MailSender,
+    issue: &Issue,
+    event: &str,
+    actor: &str,
+) {
+    if issue.ticket_owner == actor {
+        return;
+    }
+    let owner = match NotificationSetting::find_subscriber(
+        connection,
+        &issue.organisation_uuid,
+        &issue.ticket_owner,
+        event,
+        CHANNEL_EMAIL,
+    )
+    .await
+    {
+        Ok(Some(owner)) => owner,
+        _ => return,
+    };
+    let organisation = match Organisation::find(connection, &issue.organisation_uuid).await {
+        Ok(organisation) => organisation,
+        Err(_) => return,
+    };
+    let what = match event {
+        EVENT_TICKET_ASSIGNED => "assigned you",
+        EVENT_TICKET_COMMENTED => "commented on",
+        EVENT_TICKET_CLOSED => "closed",
+        _ => return,
+    };
+    let subject = format!("Ticket #{}: {}", issue.ticket_number, issue.issue_name);
+    let body = format!(
+        "{} {} ticket #{} \"{}\".\n\n{}",
+        actor,
+        what,
+        issue.ticket_number,
+        issue.issue_name,
+        mail.url(&organisation.subdomain, &format!("/issues/{}", issue.uuid))
+    );
+    mail.send(&owner.email, &subject, &body).await;
+}
@@ -2,8 +2,26 @@ use rocket::http::Status;
 use rocket::Shutdown;
 use rocket_dyn_templates::Template;
 
+pub mod activation;
+pub mod alert;
+pub mod impersonation;
+pub mod invitation;
 pub mod issues_reported;
+pub mod oidc;
+pub mod organisation;
+pub mod passkey;
+pub mod password_reset;
+pub mod portal;
+pub mod preferences;
+pub mod reporter;
+pub mod scim;
+pub mod session;
+pub mod sms;
+pub mod team;
+pub mod track;
+pub mod two_factor;
 pub mod user;
+pub mod webhook;
 
 type HtmlResponse = Result<Template, Status>;
 
@@ -0,0 +1,270 @@
+use super::session::{record_attempt, refused_status_message, safe_next, start_session};
+use crate::fairings::db::DBConnection;
+use crate::guards::api_token::constant_time_eq;
+use crate::guards::client::ClientInfo;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::generate_token;
+use crate::models::login_attempt::{
+    METHOD_OIDC, OUTCOME_INACTIVE, OUTCOME_NOT_ALLOWED, OUTCOME_SUCCEEDED,
+};
+use crate::models::user::User;
+use crate::models::user_role::UserRole;
+use crate::states::oidc_client::{Identity, OidcClient};
+use crate::states::password_policy::PasswordPolicy;
+use crate::states::session_policy::SessionPolicy;
+use chrono::offset::Utc;
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::response::{Flash, Redirect};
+use rocket::serde::{json, Deserialize, Serialize};
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const OIDC_COOKIE_NAME: &str = "oidc_login";
+const OIDC_MINUTES: i64 = 10;
+const SSO_FAILED_MESSAGE: &str = "Single sign-on failed, please try again";
+
+// login sent to the identity provider, checked when the browser comes back
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PendingOidcLogin {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    next: String,
+    // the organisation the login was started for, the callback must be for the same one
+    organisation_uuid: Uuid,
+    expires_at: i64,
+}
+
+fn pending_oidc_login(cookies: &CookieJar<'_>) -> Option<PendingOidcLogin> {
+    let cookie = cookies.get_private(OIDC_COOKIE_NAME)?;
+    // one use only, a replayed callback finds nothing
+    cookies.remove_private(Cookie::named(OIDC_COOKIE_NAME));
+    let pending = json::from_str::<PendingOidcLogin>(cookie.value()).ok()?;
+    if pending.expires_at < Utc::now().timestamp() {
+        return None;
+    }
+    Some(pending)
+}
+
+fn sso_failed() -> Flash<Redirect> {
+    Flash::error(Redirect::to("/login"), SSO_FAILED_MESSAGE)
+}
+
+#[get("/login/oidc?<next>")]
+pub async fn start(
+    next: Option<&str>,
+    cookies: &CookieJar<'_>,
+    oidc: &State<OidcClient>,
+    organisation: CurrentOrganisation,
+) -> Result<Redirect, Flash<Redirect>> {
+    if oidc.settings_for(&organisation.organisation).is_none() {
+        return Err(Flash::error(
+            Redirect::to("/login"),
+            "Single sign-on is not configured",
+        ));
+    }
+    let pending = PendingOidcLogin {
+        state: generate_token(),
+        nonce: generate_token(),
+        // 43 characters, the shortest verifier PKCE allows
+        code_verifier: generate_token(),
+        next: safe_next(next).to_string(),
+        organisation_uuid: organisation.organisation.uuid,
+        expires_at: Utc::now().timestamp() + OIDC_MINUTES * 60,
+    };
+    let code_challenge = base64::encode_config(
+        Sha256::digest(pending.code_verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    );
+    let url = match oidc
+        .authorization_url(&pending.state, &pending.nonce, &code_challenge)
+        .await
+    {
+        Ok(url) => url,
+        Err(_) => {
+            log::error!("Cannot reach the OIDC provider");
+            return Err(Flash::error(
+                Redirect::to("/login"),
+                "Single sign-on is unavailable, please try again later",
+            ));
+        }
+    };
+    let value = json::to_string(&pending).map_err(|_| sso_failed())?;
+    let mut cookie = Cookie::new(OIDC_COOKIE_NAME, value);
+    // the provider sends the browser back with a cross site navigation, a strict cookie would stay behind
+    cookie.set_same_site(SameSite::Lax);
+    cookies.add_private(cookie);
+    Ok(Redirect::to(url))
+}
+
+#[get("/login/oidc/callback?<code>&<state>&<error>")]
+pub async fn callback(
+    mut db: Connection<DBConnection>,
+    code: Option<&str>,
+    state: Option<&str>,
+    error: Option<&str>,
+    cookies: &CookieJar<'_>,
+    oidc: &State<OidcClient>,
+    policy: &State<SessionPolicy>,
+    password_policy: &State<PasswordPolicy>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Template, Flash<Redirect>> {
+    let pending = pending_oidc_login(cookies).ok_or_else(sso_failed)?;
+    // the provider only signs in the staff of its organisation, whatever host the callback is on
+    if oidc.settings_for(&organisation.organisation).is_none()
+        || pending.organisation_uuid != organisation.organisation.uuid
+    {
+        return Err(sso_failed());
+    }
+    if let Some(error) = error {
+        log::warn!("OIDC provider refused the login: {}", error);
+        return Err(sso_failed());
+    }
+    let state = state.unwrap_or("");
+    if !constant_time_eq(state.as_bytes(), pending.state.as_bytes()) {
+        return Err(sso_failed());
+    }
+    let code = code.ok_or_else(sso_failed)?;
+    let identity = match oidc
+        .exchange_code(code, &pending.code_verifier, &pending.nonce)
+        .await
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::warn!("Invalid OIDC login: {}", e);
+            return Err(sso_failed());
+        }
+    };
+    let connection = db.acquire().await.map_err(|_| sso_failed())?;
+    let role = match identity.role {
+        Some(role) => role,
+        None => {
+            record_attempt(
+                connection,
+                &organisation.organisation.uuid,
+                &identity.username,
+                None,
+                &client,
+                METHOD_OIDC,
+                OUTCOME_NOT_ALLOWED,
+            )
+            .await;
+            return Err(Flash::error(
+                Redirect::to("/login"),
+                "Your company account has no access to the ticket desk",
+            ));
+        }
+    };
+    let user = find_or_create_user(
+        connection,
+        &organisation.organisation.uuid,
+        &identity,
+        role,
+        password_policy,
+    )
+    .await
+    .ok_or_else(|| {
+        Flash::error(
+            Redirect::to("/login"),
+            "Cannot sign you in, an account with your username or email already exists, ask an admin to link it",
+        )
+    })?;
+    if !user.status.can_log_in() {
+        record_attempt(
+            connection,
+            &organisation.organisation.uuid,
+            &user.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_OIDC,
+            OUTCOME_INACTIVE,
+        )
+        .await;
+        return Err(Flash::error(
+            Redirect::to("/login"),
+            refused_status_message(user.status),
+        ));
+    }
+    record_attempt(
+        connection,
+        &organisation.organisation.uuid,
+        &user.username,
+        Some(&user.uuid),
+        &client,
+        METHOD_OIDC,
+        OUTCOME_SUCCEEDED,
+    )
+    .await;
+    // second factors are the provider's business, it already asked for them
+    start_session(connection, cookies, policy, &client, &user.uuid, false)
+        .await
+        .map_err(|_| sso_failed())?;
+    // a redirect would still be part of the cross site navigation and leave the strict session cookie behind
+    Ok(Template::render(
+        "sessions/sso_redirect",
+        context! { next: pending.next },
+    ))
+}
+
+// Linked account kept in sync, else an account with the same verified email gets linked, else a new one.
+async fn find_or_create_user(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    identity: &Identity,
+    role: UserRole,
+    password_policy: &PasswordPolicy,
+) -> Option<User> {
+    let existing =
+        match User::find_by_oidc_subject(connection, organisation_uuid, &identity.subject)
+            .await
+            .ok()
+        {
+            Some(user) => Some(user),
+            // an unverified email could be anybody's, it never takes over an account
+            None if identity.email_verified => {
+                match User::find_by_email(connection, organisation_uuid, &identity.email)
+                    .await
+                    .ok()
+                {
+                    Some(user) => User::link_oidc(
+                        connection,
+                        organisation_uuid,
+                        &user.uuid,
+                        &identity.subject,
+                    )
+                    .await
+                    .ok(),
+                    None => None,
+                }
+            }
+            None => None,
+        };
+    match existing {
+        Some(user) => User::sync_oidc(
+            connection,
+            organisation_uuid,
+            &user.uuid,
+            &identity.email,
+            role,
+        )
+        .await
+        .ok(),
+        None => User::create_from_oidc(
+            connection,
+            organisation_uuid,
+            &identity.subject,
+            &identity.username,
+            &identity.email,
+            role,
+            password_policy,
+        )
+        .await
+        .ok(),
+    }
+}
@@ -0,0 +1,155 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::api_token::TOKEN_SCOPES;
+use crate::guards::role::{Admin, RequireRole};
+use crate::models::organisation::{Organisation, OrganisationSettings};
+use crate::models::organisation_token::{OrganisationToken, TokenForm};
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+
+const SETTINGS_PATH: &str = "/organisation/settings";
+
+#[get("/organisation/settings", format = "text/html")]
+pub async fn get_settings(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let tokens = OrganisationToken::find_all(connection, &role.current_user.organisation.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: SETTINGS_PATH,
+        token_scopes: TOKEN_SCOPES,
+        tokens,
+        organisation: &role.current_user.organisation,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("organisation/settings", context))
+}
+
+// Admins only change their own organisation, the one they are logged in to.
+#[post(
+    "/organisation/settings",
+    format = "application/x-www-form-urlencoded",
+    data = "<settings_context>"
+)]
+pub async fn update_settings<'r>(
+    mut db: Connection<DBConnection>,
+    settings_context: Form<Contextual<'r, OrganisationSettings<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to(SETTINGS_PATH),
+            "Something went wrong when saving the settings",
+        )
+    };
+    if settings_context.value.is_none() {
+        let error_message = settings_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(SETTINGS_PATH), error_message));
+    }
+    let settings = settings_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&settings.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    Organisation::update_settings(connection, &role.current_user.organisation.uuid, settings)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(SETTINGS_PATH),
+        "Settings saved",
+    ))
+}
+
+// Creates the token of a scope, replacing the earlier one, and shows it this once.
+#[post(
+    "/organisation/tokens",
+    format = "application/x-www-form-urlencoded",
+    data = "<token_context>"
+)]
+pub async fn create_token<'r>(
+    mut db: Connection<DBConnection>,
+    token_context: Form<Contextual<'r, TokenForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Template, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to(SETTINGS_PATH),
+            "Something went wrong when creating the token",
+        )
+    };
+    let form = token_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&form.authenticity_token)
+        .map_err(|_| failed())?;
+    if !TOKEN_SCOPES.contains(&form.scope) {
+        return Err(failed());
+    }
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let token =
+        OrganisationToken::issue(connection, &role.current_user.organisation.uuid, form.scope)
+            .await
+            .map_err(|_| failed())?;
+    let context = context! {
+        scope: form.scope,
+        token,
+        continue_url: SETTINGS_PATH,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+    };
+    Ok(Template::render("organisation/token", context))
+}
+
+#[post(
+    "/organisation/tokens/revoke",
+    format = "application/x-www-form-urlencoded",
+    data = "<token_context>"
+)]
+pub async fn revoke_token<'r>(
+    mut db: Connection<DBConnection>,
+    token_context: Form<Contextual<'r, TokenForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to(SETTINGS_PATH),
+            "Something went wrong when revoking the token",
+        )
+    };
+    let form = token_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&form.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    OrganisationToken::revoke(connection, &role.current_user.organisation.uuid, form.scope)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(Redirect::to(SETTINGS_PATH), "Token revoked"))
+}
@@ -0,0 +1,408 @@
+use super::session::{record_attempt, safe_next, start_session};
+use super::HtmlResponse;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::guards::client::ClientInfo;
+use crate::guards::impersonation::NotImpersonating;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::login_attempt::{
+    METHOD_PASSKEY, OUTCOME_INACTIVE, OUTCOME_INVALID_PASSKEY, OUTCOME_SSO_REQUIRED,
+    OUTCOME_SUCCEEDED,
+};
+use crate::models::passkey::{
+    FinishedRegistration, NewPasskey, Passkey, PasskeyCeremony, PasskeyLogin, NAME_LENGTH,
+};
+use crate::models::user::User;
+use crate::models::user_role::UserRole;
+use crate::states::oidc_client::OidcClient;
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::session_policy::SessionPolicy;
+use chrono::Duration;
+use rocket::form::{Contextual, Form};
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use std::time::Duration as StdDuration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+    RequestChallengeResponse, Uuid as WebauthnUuid,
+};
+use webauthn_rs::Webauthn;
+
+const CEREMONY_COOKIE_NAME: &str = "passkey_ceremony";
+const CEREMONY_MINUTES: i64 = 5;
+const LOGIN_BUCKET: &str = "passkey_login";
+const LOGIN_LIMIT: usize = 10;
+const LOGIN_WINDOW: StdDuration = StdDuration::from_secs(15 * 60);
+
+// where the page goes once the browser is done with the authenticator
+#[derive(Serialize)]
+pub struct CeremonyOutcome {
+    redirect: String,
+}
+
+// webauthn-rs has its own uuid version, the bytes are the same
+fn webauthn_user_id(uuid: &Uuid) -> WebauthnUuid {
+    WebauthnUuid::from_bytes(*uuid.as_bytes())
+}
+
+fn remember_ceremony(cookies: &CookieJar<'_>, ceremony: &PasskeyCeremony) {
+    let mut cookie = Cookie::new(CEREMONY_COOKIE_NAME, ceremony.uuid.to_string());
+    cookie.set_expires(OffsetDateTime::now_utc() + time::Duration::minutes(CEREMONY_MINUTES));
+    cookies.add_private(cookie);
+}
+
+// the ceremony started by this browser, gone from the database once read
+async fn take_ceremony(
+    connection: &mut PgConnection,
+    cookies: &CookieJar<'_>,
+) -> Result<PasskeyCeremony, Status> {
+    let uuid = cookies
+        .get_private(CEREMONY_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .ok_or(Status::BadRequest)?;
+    cookies.remove_private(Cookie::named(CEREMONY_COOKIE_NAME));
+    PasskeyCeremony::take(connection, &uuid)
+        .await
+        .map_err(|_| Status::BadRequest)
+}
+
+// Lists the passkeys of the logged in user and lets them add one.
+#[get("/account/passkeys", format = "text/html")]
+pub async fn get_passkeys(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let passkeys = Passkey::find_by_user(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        passkeys,
+        name_length: NAME_LENGTH,
+        permissions: current_user.permissions(),
+        preferences: &current_user.preferences,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("passkeys/index", context))
+}
+
+// First half of a registration: the options the browser hands to the authenticator.
+#[post(
+    "/account/passkeys/register/start",
+    format = "json",
+    data = "<new_passkey>"
+)]
+pub async fn register_start(
+    mut db: Connection<DBConnection>,
+    new_passkey: Json<NewPasskey>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    webauthn: &State<Webauthn>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Json<CreationChallengeResponse>, Status> {
+    csrf_token
+        .verify(&new_passkey.authenticity_token)
+        .map_err(|_| Status::Forbidden)?;
+    let name = new_passkey.name.trim();
+    if name.is_empty() || name.chars().count() > NAME_LENGTH {
+        return Err(Status::UnprocessableEntity);
+    }
+    let user = &current_user.user;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let existing = Passkey::find_by_user(connection, &user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    if existing.iter().any(|passkey| passkey.name == name) {
+        return Err(Status::Conflict);
+    }
+    // the authenticator refuses to register the same device twice
+    let exclude = existing
+        .iter()
+        .filter_map(|passkey| passkey.to_credential().ok())
+        .map(|credential| credential.cred_id().clone())
+        .collect::<Vec<_>>();
+    let (challenge, state) = webauthn
+        .start_passkey_registration(
+            webauthn_user_id(&user.uuid),
+            &user.username,
+            &user.username,
+            Some(exclude),
+        )
+        .map_err(|_| Status::InternalServerError)?;
+    let ceremony = PasskeyCeremony::create(
+        connection,
+        &user.uuid,
+        Some(name),
+        &state,
+        Duration::minutes(CEREMONY_MINUTES),
+    )
+    .await
+    .map_err(|e| e.status)?;
+    remember_ceremony(cookies, &ceremony);
+    Ok(Json(challenge))
+}
+
+// Second half: checks the authenticator answer, origin and RP ID included, and stores the passkey.
+#[post(
+    "/account/passkeys/register/finish",
+    format = "json",
+    data = "<finished>"
+)]
+pub async fn register_finish(
+    mut db: Connection<DBConnection>,
+    finished: Json<FinishedRegistration>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    webauthn: &State<Webauthn>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Json<CeremonyOutcome>, Status> {
+    csrf_token
+        .verify(&finished.authenticity_token)
+        .map_err(|_| Status::Forbidden)?;
+    let user = &current_user.user;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let ceremony = take_ceremony(connection, cookies).await?;
+    if ceremony.user_uuid != user.uuid {
+        return Err(Status::BadRequest);
+    }
+    let name = ceremony.name.clone().ok_or(Status::BadRequest)?;
+    let state = ceremony
+        .to_state::<PasskeyRegistration>()
+        .map_err(|e| e.status)?;
+    let passkey = webauthn
+        .finish_passkey_registration(&finished.credential, &state)
+        .map_err(|_| Status::BadRequest)?;
+    Passkey::create(connection, &user.uuid, &name, &passkey)
+        .await
+        .map_err(|e| e.status)?;
+    Ok(Json(CeremonyOutcome {
+        redirect: String::from("/account/passkeys"),
+    }))
+}
+
+#[post(
+    "/account/passkeys/delete/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<delete_context>"
+)]
+pub async fn delete_passkey<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    delete_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/account/passkeys"),
+            "Something went wrong when removing passkey",
+        )
+    };
+    let delete = delete_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&delete.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    Passkey::destroy(connection, uuid, &current_user.user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to("/account/passkeys"),
+        "Passkey removed",
+    ))
+}
+
+// First half of a sign in: the challenge for the passkeys of the given user.
+#[post("/login/passkey/start", format = "json", data = "<login>")]
+pub async fn login_start(
+    mut db: Connection<DBConnection>,
+    login: Json<PasskeyLogin>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    limiter: &State<RateLimiter>,
+    webauthn: &State<Webauthn>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Json<RequestChallengeResponse>, Status> {
+    let client_key = client.ip_address.as_deref().unwrap_or("unknown");
+    if !limiter.check(LOGIN_BUCKET, client_key, LOGIN_LIMIT, LOGIN_WINDOW) {
+        return Err(Status::TooManyRequests);
+    }
+    csrf_token
+        .verify(&login.authenticity_token)
+        .map_err(|_| Status::Forbidden)?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    // unknown user, inactive user and no passkey look the same from outside
+    let user = User::find_by_username(
+        connection,
+        &organisation.organisation.uuid,
+        login.username.trim(),
+    )
+    .await
+    .map_err(|_| Status::BadRequest)?;
+    if !user.status.can_log_in() {
+        return Err(Status::BadRequest);
+    }
+    let credentials = Passkey::find_by_user(connection, &user.uuid)
+        .await
+        .map_err(|e| e.status)?
+        .iter()
+        .filter_map(|passkey| passkey.to_credential().ok())
+        .collect::<Vec<_>>();
+    if credentials.is_empty() {
+        return Err(Status::BadRequest);
+    }
+    let (challenge, state) = webauthn
+        .start_passkey_authentication(&credentials)
+        .map_err(|_| Status::InternalServerError)?;
+    let ceremony = PasskeyCeremony::create(
+        connection,
+        &user.uuid,
+        None,
+        &state,
+        Duration::minutes(CEREMONY_MINUTES),
+    )
+    .await
+    .map_err(|e| e.status)?;
+    remember_ceremony(cookies, &ceremony);
+    Ok(Json(challenge))
+}
+
+// Second half: a valid signature starts a session. A passkey already proves possession
+// and user verification, so no second factor is asked on top of it.
+#[post("/login/passkey/finish?<next>", format = "json", data = "<credential>")]
+pub async fn login_finish(
+    mut db: Connection<DBConnection>,
+    next: Option<&str>,
+    credential: Json<PublicKeyCredential>,
+    cookies: &CookieJar<'_>,
+    webauthn: &State<Webauthn>,
+    policy: &State<SessionPolicy>,
+    oidc: &State<OidcClient>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Json<CeremonyOutcome>, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let ceremony = take_ceremony(connection, cookies).await?;
+    // a registration left half done is no sign in
+    if ceremony.name.is_some() {
+        return Err(Status::BadRequest);
+    }
+    let user = User::find(
+        connection,
+        &organisation.organisation.uuid,
+        &ceremony.user_uuid.to_string(),
+    )
+    .await
+    .map_err(|_| Status::BadRequest)?;
+    let state = ceremony
+        .to_state::<PasskeyAuthentication>()
+        .map_err(|e| e.status)?;
+    let result = match webauthn.finish_passkey_authentication(&credential, &state) {
+        Ok(result) => result,
+        Err(_) => {
+            record_attempt(
+                connection,
+                &organisation.organisation.uuid,
+                &user.username,
+                Some(&user.uuid),
+                &client,
+                METHOD_PASSKEY,
+                OUTCOME_INVALID_PASSKEY,
+            )
+            .await;
+            return Err(Status::BadRequest);
+        }
+    };
+    // single sign-on leaves only the admins a way in without the provider, as for passwords
+    if oidc.break_glass_only(&organisation.organisation) && user.role != UserRole::Admin {
+        record_attempt(
+            connection,
+            &organisation.organisation.uuid,
+            &user.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_PASSKEY,
+            OUTCOME_SSO_REQUIRED,
+        )
+        .await;
+        return Err(Status::Forbidden);
+    }
+    // status only, a lock stops password guessing and a passkey cannot be guessed
+    if !user.status.can_log_in() {
+        record_attempt(
+            connection,
+            &organisation.organisation.uuid,
+            &user.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_PASSKEY,
+            OUTCOME_INACTIVE,
+        )
+        .await;
+        return Err(Status::BadRequest);
+    }
+    let passkeys = Passkey::find_by_user(connection, &user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    for passkey in passkeys.iter() {
+        if let Ok(mut stored) = passkey.to_credential() {
+            if stored.cred_id() == result.cred_id() {
+                // keeps the signature counter, a cloned authenticator shows up as a counter going back
+                stored.update_credential(&result);
+                Passkey::record_use(connection, &stored)
+                    .await
+                    .map_err(|e| e.status)?;
+            }
+        }
+    }
+    record_attempt(
+        connection,
+        &organisation.organisation.uuid,
+        &user.username,
+        Some(&user.uuid),
+        &client,
+        METHOD_PASSKEY,
+        OUTCOME_SUCCEEDED,
+    )
+    .await;
+    start_session(connection, cookies, policy, &client, &user.uuid, false)
+        .await
+        .map_err(|e| e.status)?;
+    Ok(Json(CeremonyOutcome {
+        redirect: safe_next(next).to_string(),
+    }))
+}
@@ -0,0 +1,227 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::client::ClientInfo;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::password_reset::{NewPassword, PasswordReset, ResetRequest};
+use crate::models::session::Session;
+use crate::models::user::User;
+use crate::states::mail_sender::MailSender;
+use crate::states::password_policy::PasswordPolicy;
+use crate::states::rate_limiter::RateLimiter;
+use chrono::Duration;
+use rocket::form::{Contextual, Form};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+use std::time::Duration as StdDuration;
+
+const RESET_MINUTES: i64 = 60;
+const REQUEST_BUCKET: &str = "password_reset";
+const REQUEST_LIMIT: usize = 5;
+const REQUEST_WINDOW: StdDuration = StdDuration::from_secs(60 * 60);
+const REQUEST_MESSAGE: &str = "If an account uses that email, a reset link is on its way";
+const INVALID_MESSAGE: &str = "This reset link is invalid or expired, please ask for a new one";
+
+// Displays the form asking for a reset link.
+#[get("/password/forgot", format = "text/html")]
+pub async fn forgot_form(flash: Option<FlashMessage<'_>>, csrf_token: CsrfToken) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("passwords/forgot", context))
+}
+
+// Emails a reset link, the answer is the same whether the email is known or not.
+#[post(
+    "/password/forgot",
+    format = "application/x-www-form-urlencoded",
+    data = "<request_context>"
+)]
+pub async fn forgot<'r>(
+    mut db: Connection<DBConnection>,
+    request_context: Form<Contextual<'r, ResetRequest<'r>>>,
+    csrf_token: CsrfToken,
+    mail_sender: &State<MailSender>,
+    limiter: &State<RateLimiter>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let organisation = &organisation.organisation;
+    let client_key = client.ip_address.as_deref().unwrap_or("unknown");
+    if !limiter.check(REQUEST_BUCKET, client_key, REQUEST_LIMIT, REQUEST_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/password/forgot"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let request = request_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to("/password/forgot"), "Invalid email"))?;
+    csrf_token
+        .verify(&request.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), "Something went wrong"))?;
+    let user = User::find_by_email(connection, &organisation.uuid, request.email)
+        .await
+        .ok();
+    if let Some(user) = user {
+        let created =
+            PasswordReset::create(connection, &user.uuid, Duration::minutes(RESET_MINUTES))
+                .await
+                .ok();
+        match created {
+            Some(token) => {
+                let link = mail_sender.url(
+                    &organisation.subdomain,
+                    &format!("/password/reset/{}", token),
+                );
+                let body = format!(
+                    "Hello {},\n\nSomebody asked to reset your password. Choose a new one within {} minutes:\n\n{}\n\nIf it was not you, ignore this email.\n",
+                    user.username, RESET_MINUTES, link
+                );
+                mail_sender
+                    .send(&user.email, "Reset your password", &body)
+                    .await;
+            }
+            None => log::error!("Cannot create password reset for {}", user.uuid),
+        }
+    }
+    Ok(Flash::success(Redirect::to("/login"), REQUEST_MESSAGE))
+}
+
+#[get("/password/reset/<token>", format = "text/html")]
+pub async fn reset_form(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+) -> Result<Template, Flash<Redirect>> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), "Something went wrong"))?;
+    PasswordReset::find_valid(connection, token)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), INVALID_MESSAGE))?;
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: format!("/password/reset/{}", token),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("passwords/reset", context))
+}
+
+// Sets the new password and logs the user out everywhere.
+#[post(
+    "/password/reset/<token>",
+    format = "application/x-www-form-urlencoded",
+    data = "<password_context>"
+)]
+pub async fn reset<'r>(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    password_context: Form<Contextual<'r, NewPassword<'r>>>,
+    csrf_token: CsrfToken,
+    policy: &State<PasswordPolicy>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let form_url = format!("/password/reset/{}", token);
+    if password_context.value.is_none() {
+        let error_message = password_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(form_url), error_message));
+    }
+    let new_password = password_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_password.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    // checked before the token is used up, so a refused password can be retried
+    let pending_reset = PasswordReset::find_valid(connection, token)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), INVALID_MESSAGE))?;
+    let user = User::find(
+        connection,
+        &organisation.organisation.uuid,
+        &pending_reset.user_uuid.to_string(),
+    )
+    .await
+    .map_err(|_| Flash::error(Redirect::to("/password/forgot"), INVALID_MESSAGE))?;
+    policy
+        .check_new_password(
+            new_password.password,
+            &[user.username.as_str(), user.email.as_str()],
+        )
+        .await
+        .map_err(|message| Flash::error(Redirect::to(form_url.clone()), message))?;
+    let password_reset = PasswordReset::consume(connection, token)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/password/forgot"), INVALID_MESSAGE))?;
+    User::set_password(
+        connection,
+        &user.organisation_uuid,
+        &password_reset.user_uuid,
+        new_password.password,
+        policy,
+    )
+    .await
+    .map_err(|_| {
+        Flash::error(
+            Redirect::to("/password/forgot"),
+            "Something went wrong, please ask for a new link",
+        )
+    })?;
+    // the new password ends a lockout after failed logins, the lock is only told now
+    let was_locked = user.is_locked();
+    if was_locked
+        && User::unlock(
+            connection,
+            &user.organisation_uuid,
+            &password_reset.user_uuid.to_string(),
+        )
+        .await
+        .is_err()
+    {
+        log::error!(
+            "Cannot unlock after password reset of {}",
+            password_reset.user_uuid
+        );
+    }
+    // whoever knew the old password must not stay logged in
+    if Session::revoke_all_for_user(connection, &password_reset.user_uuid)
+        .await
+        .is_err()
+    {
+        log::error!(
+            "Cannot revoke sessions after password reset of {}",
+            password_reset.user_uuid
+        );
+    }
+    let message = if was_locked {
+        "Your password was changed and your account, locked after too many failed attempts, is unlocked, you can log in"
+    } else {
+        "Your password was changed, you can log in"
+    };
+    Ok(Flash::success(Redirect::to("/login"), message))
+}
@@ -0,0 +1,724 @@
+use super::issues_reported::notify_owner;
+use super::HtmlResponse;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::guards::reporter::{CurrentReporter, REPORTER_COOKIE_NAME};
+use crate::models::comment::{Comment, NewComment};
+use crate::models::issues_reported::Issue;
+use crate::models::notification_setting::EVENT_TICKET_COMMENTED;
+use crate::models::reporter::{
+    AcceptedInvitation, NewReporter, Reporter, ReporterLogin, VerificationCode, VerificationRequest,
+};
+use crate::models::user_status::UserStatus;
+use crate::models::webhook::{Webhook, ISSUE_STATUS_CHANGED, ISSUE_UPDATED};
+use crate::states::mail_sender::MailSender;
+use crate::states::password_policy::PasswordPolicy;
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::signer::Signer;
+use crate::states::sms_notifier::SmsNotifier;
+use chrono::{offset::Utc, Duration};
+use rocket::form::{Contextual, Form};
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::{
+    sqlx::{Acquire, PgConnection},
+    Connection,
+};
+use rocket_dyn_templates::{context, Template};
+use std::net::IpAddr;
+use std::time::Duration as StdDuration;
+use uuid::Uuid;
+
+pub const INVITATION_PURPOSE: &str = "reporter_invitation";
+// closed tickets can be reopened by the reporter for this many days
+const REOPEN_DAYS: i64 = 7;
+const LOGIN_BUCKET: &str = "portal_login";
+const LOGIN_LIMIT: usize = 10;
+const LOGIN_WINDOW: StdDuration = StdDuration::from_secs(15 * 60);
+// reporter whose contact number is being verified, a new registration is not logged in until then
+const VERIFICATION_COOKIE_NAME: &str = "reporter_verification";
+const VERIFY_BUCKET: &str = "portal_verify";
+const VERIFY_LIMIT: usize = 5;
+const SEND_BUCKET: &str = "portal_verify_send";
+const SEND_LIMIT: usize = 3;
+const VERIFY_WINDOW: StdDuration = StdDuration::from_secs(15 * 60);
+const CODE_SENT_MESSAGE: &str = "We texted a code to your contact number, please enter it";
+
+// tickets are only matched on a contact number the reporter proved they own
+fn can_see(current_reporter: &CurrentReporter, issue: &Issue) -> bool {
+    if current_reporter.reporter.verified_contact_number() == Some(issue.contact_number) {
+        return true;
+    }
+    match current_reporter.company_scope() {
+        Some(company_name) => issue.company_name.to_lowercase() == company_name.to_lowercase(),
+        None => false,
+    }
+}
+
+fn can_reopen(issue: &Issue) -> bool {
+    issue.status == "closed" && issue.updated_at.0 > Utc::now() - Duration::days(REOPEN_DAYS)
+}
+
+fn log_in(cookies: &CookieJar<'_>, reporter: &Reporter) {
+    cookies.add_private(Cookie::new(REPORTER_COOKIE_NAME, reporter.uuid.to_string()));
+}
+
+// Texts a new code to the reporter's number and remembers whom /portal/verify is for.
+async fn send_verification(
+    connection: &mut PgConnection,
+    sms: &SmsNotifier,
+    limiter: &RateLimiter,
+    cookies: &CookieJar<'_>,
+    reporter: &Reporter,
+) -> Result<(), &'static str> {
+    let reporter_uuid = reporter.uuid.to_string();
+    if !limiter.check(SEND_BUCKET, &reporter_uuid, SEND_LIMIT, VERIFY_WINDOW) {
+        return Err("Too many codes sent, please try again later");
+    }
+    let code = Reporter::start_verification(connection, &reporter.uuid)
+        .await
+        .map_err(|_| "Something went wrong when sending your code")?;
+    cookies.add_private(Cookie::new(VERIFICATION_COOKIE_NAME, reporter_uuid));
+    if !sms
+        .verification_code(connection, reporter.contact_number, &code)
+        .await
+    {
+        return Err("We could not text your code, please ask for a new one");
+    }
+    Ok(())
+}
+
+// organisations can keep the portal to the reporters they invite
+const REGISTRATION_CLOSED_MESSAGE: &str = "Registration is closed, please ask for an invitation";
+
+#[get("/portal/register", format = "text/html")]
+pub async fn register_form(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    organisation: CurrentOrganisation,
+) -> HtmlResponse {
+    if !organisation.organisation.reporter_registration {
+        return Err(Status::NotFound);
+    }
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("portal/register", context))
+}
+
+#[post(
+    "/portal/register",
+    format = "application/x-www-form-urlencoded",
+    data = "<reporter_context>"
+)]
+pub async fn register<'r>(
+    mut db: Connection<DBConnection>,
+    reporter_context: Form<Contextual<'r, NewReporter<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    policy: &State<PasswordPolicy>,
+    sms: &State<SmsNotifier>,
+    limiter: &State<RateLimiter>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if !organisation.organisation.reporter_registration {
+        return Err(Flash::error(
+            Redirect::to("/portal/login"),
+            REGISTRATION_CLOSED_MESSAGE,
+        ));
+    }
+    if reporter_context.value.is_none() {
+        let error_message = reporter_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(
+            Redirect::to("/portal/register"),
+            error_message,
+        ));
+    }
+    let new_reporter = reporter_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_reporter.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/portal/register"),
+                "Something went wrong when creating your account",
+            )
+        })?;
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to("/portal/register"),
+            "Something went wrong when creating your account",
+        )
+    })?;
+    policy
+        .check_new_password(
+            new_reporter.password,
+            &[new_reporter.name, new_reporter.email],
+        )
+        .await
+        .map_err(|message| Flash::error(Redirect::to("/portal/register"), message))?;
+    let reporter = Reporter::create(
+        connection,
+        &organisation.organisation.uuid,
+        new_reporter,
+        policy,
+    )
+    .await
+    .map_err(|_| {
+        Flash::error(
+            Redirect::to("/portal/register"),
+            "Something went wrong when creating your account",
+        )
+    })?;
+    // the account stays pending until the number is verified, nobody is logged in yet
+    send_verification(connection, sms, limiter, cookies, &reporter)
+        .await
+        .map_err(|message| Flash::error(Redirect::to("/portal/verify"), message))?;
+    Ok(Flash::success(
+        Redirect::to("/portal/verify"),
+        CODE_SENT_MESSAGE,
+    ))
+}
+
+#[get("/portal/login", format = "text/html")]
+pub async fn login_form(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    organisation: CurrentOrganisation,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+        organisation: &organisation.organisation,
+    };
+    Ok(Template::render("portal/login", context))
+}
+
+#[post(
+    "/portal/login",
+    format = "application/x-www-form-urlencoded",
+    data = "<login_context>"
+)]
+pub async fn login<'r>(
+    mut db: Connection<DBConnection>,
+    login_context: Form<Contextual<'r, ReporterLogin<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    limiter: &State<RateLimiter>,
+    policy: &State<PasswordPolicy>,
+    sms: &State<SmsNotifier>,
+    ip: Option<IpAddr>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let client = ip
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| String::from("unknown"));
+    if !limiter.check(LOGIN_BUCKET, &client, LOGIN_LIMIT, LOGIN_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/portal/login"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let login = login_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to("/portal/login"), "Invalid email or password"))?;
+    csrf_token
+        .verify(&login.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to("/portal/login"), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/portal/login"), "Something went wrong"))?;
+    let reporter =
+        Reporter::find_by_login(connection, &organisation.organisation.uuid, login, policy)
+            .await
+            .map_err(|_| {
+                Flash::error(Redirect::to("/portal/login"), "Invalid email or password")
+            })?;
+    if reporter.status == UserStatus::Pending {
+        send_verification(connection, sms, limiter, cookies, &reporter)
+            .await
+            .map_err(|message| Flash::error(Redirect::to("/portal/verify"), message))?;
+        return Ok(Flash::success(
+            Redirect::to("/portal/verify"),
+            CODE_SENT_MESSAGE,
+        ));
+    }
+    log_in(cookies, &reporter);
+    Ok(Flash::success(Redirect::to("/portal"), "You are logged in"))
+}
+
+#[get("/portal/verify", format = "text/html")]
+pub async fn verify_form(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+) -> Result<Template, Redirect> {
+    if cookies.get_private(VERIFICATION_COOKIE_NAME).is_none() {
+        return Err(Redirect::to("/portal/login"));
+    }
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("portal/verify", context))
+}
+
+#[post(
+    "/portal/verify",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn verify<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, VerificationCode<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    limiter: &State<RateLimiter>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let invalid = || Flash::error(Redirect::to("/portal/verify"), "Invalid or expired code");
+    let reporter_uuid = cookies
+        .get_private(VERIFICATION_COOKIE_NAME)
+        .and_then(|cookie| Uuid::parse_str(cookie.value()).ok())
+        .ok_or_else(|| Flash::error(Redirect::to("/portal/login"), "Please log in"))?;
+    if !limiter.check(
+        VERIFY_BUCKET,
+        &reporter_uuid.to_string(),
+        VERIFY_LIMIT,
+        VERIFY_WINDOW,
+    ) {
+        return Err(Flash::error(
+            Redirect::to("/portal/verify"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    let connection = db.acquire().await.map_err(|_| invalid())?;
+    let reporter = Reporter::verify_contact_number(
+        connection,
+        &organisation.organisation.uuid,
+        &reporter_uuid,
+        code.code,
+    )
+    .await
+    .map_err(|_| invalid())?;
+    cookies.remove_private(Cookie::named(VERIFICATION_COOKIE_NAME));
+    if reporter.status != UserStatus::Active {
+        return Err(Flash::error(
+            Redirect::to("/portal/login"),
+            "Your account cannot log in",
+        ));
+    }
+    log_in(cookies, &reporter);
+    Ok(Flash::success(
+        Redirect::to("/portal"),
+        "Your contact number is verified",
+    ))
+}
+
+// A new code, for the registration being verified or else the logged in reporter.
+#[post(
+    "/portal/verify/send",
+    format = "application/x-www-form-urlencoded",
+    data = "<request_context>"
+)]
+pub async fn send_code<'r>(
+    mut db: Connection<DBConnection>,
+    request_context: Form<Contextual<'r, VerificationRequest<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    sms: &State<SmsNotifier>,
+    limiter: &State<RateLimiter>,
+    current_reporter: Option<CurrentReporter>,
+    organisation: CurrentOrganisation,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || Flash::error(Redirect::to("/portal/login"), "Something went wrong");
+    let request = request_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&request.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let pending_uuid = cookies
+        .get_private(VERIFICATION_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string());
+    let reporter = match (pending_uuid, current_reporter) {
+        (Some(uuid), _) => Reporter::find(connection, &uuid)
+            .await
+            .ok()
+            .filter(|reporter| reporter.organisation_uuid == organisation.organisation.uuid)
+            .ok_or_else(failed)?,
+        (None, Some(current_reporter)) => current_reporter.reporter,
+        (None, None) => return Err(failed()),
+    };
+    send_verification(connection, sms, limiter, cookies, &reporter)
+        .await
+        .map_err(|message| Flash::error(Redirect::to("/portal/verify"), message))?;
+    Ok(Flash::success(
+        Redirect::to("/portal/verify"),
+        CODE_SENT_MESSAGE,
+    ))
+}
+
+#[post(
+    "/portal/logout",
+    format = "application/x-www-form-urlencoded",
+    data = "<logout_context>"
+)]
+pub async fn logout<'r>(
+    logout_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/portal"),
+            "Something went wrong when logging out",
+        )
+    };
+    let logout = logout_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&logout.authenticity_token)
+        .map_err(|_| failed())?;
+    cookies.remove_private(Cookie::named(REPORTER_COOKIE_NAME));
+    Ok(Flash::success(
+        Redirect::to("/portal/login"),
+        "You are logged out",
+    ))
+}
+
+// Lists every ticket the reporter may see.
+#[get("/portal", format = "text/html")]
+pub async fn get_tickets(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_reporter: CurrentReporter,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let issues = Issue::find_for_reporter(
+        connection,
+        &current_reporter.reporter.organisation_uuid,
+        current_reporter.reporter.verified_contact_number(),
+        current_reporter.company_scope(),
+    )
+    .await
+    .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        issues,
+        current_reporter: &current_reporter,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("portal/index", context))
+}
+
+#[get("/portal/tickets/<uuid>", format = "text/html")]
+pub async fn get_ticket(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_reporter: CurrentReporter,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let issue = Issue::find(
+        connection,
+        &current_reporter.reporter.organisation_uuid,
+        uuid,
+    )
+    .await
+    .map_err(|e| e.status)?;
+    if !can_see(&current_reporter, &issue) {
+        return Err(Status::NotFound);
+    }
+    let replies = Comment::find_public_by_issue(connection, &issue.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        can_reopen: can_reopen(&issue),
+        issue_uuid: issue.uuid,
+        ticket_number: issue.ticket_number,
+        issue_name: issue.issue_name,
+        description: issue.description,
+        status: issue.status,
+        created_at: issue.created_at,
+        updated_at: issue.updated_at,
+        replies,
+        current_reporter: &current_reporter,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("portal/show", context))
+}
+
+#[post(
+    "/portal/replies/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<comment_context>"
+)]
+pub async fn reply<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    comment_context: Form<Contextual<'r, NewComment<'r>>>,
+    csrf_token: CsrfToken,
+    mail: &State<MailSender>,
+    current_reporter: CurrentReporter,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let ticket_url = format!("/portal/tickets/{}", uuid);
+    if comment_context.value.is_none() {
+        let error_message = comment_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(ticket_url), error_message));
+    }
+    let new_comment = comment_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_comment.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(ticket_url.clone()),
+                "Something went wrong when sending your reply",
+            )
+        })?;
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to(ticket_url.clone()),
+            "Something went wrong when sending your reply",
+        )
+    })?;
+    let issue = Issue::find(
+        connection,
+        &current_reporter.reporter.organisation_uuid,
+        uuid,
+    )
+    .await
+    .ok()
+    .filter(|issue| can_see(&current_reporter, issue))
+    .ok_or_else(|| Flash::error(Redirect::to("/portal"), "Ticket not found"))?;
+    // reporter replies are always public, they are the reporter's side of the conversation
+    Comment::create(
+        connection,
+        &issue.uuid,
+        &current_reporter.reporter.name,
+        new_comment.body,
+        true,
+    )
+    .await
+    .map_err(|_| {
+        Flash::error(
+            Redirect::to(ticket_url.clone()),
+            "Something went wrong when sending your reply",
+        )
+    })?;
+    Webhook::dispatch(connection, ISSUE_UPDATED, &issue).await;
+    notify_owner(
+        connection,
+        mail,
+        &issue,
+        EVENT_TICKET_COMMENTED,
+        &current_reporter.reporter.name,
+    )
+    .await;
+    Ok(Flash::success(Redirect::to(ticket_url), "Reply sent"))
+}
+
+#[post(
+    "/portal/reopen/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<reopen_context>"
+)]
+pub async fn reopen<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    reopen_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    current_reporter: CurrentReporter,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let ticket_url = format!("/portal/tickets/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(ticket_url.clone()),
+            "Something went wrong when reopening your ticket",
+        )
+    };
+    let reopen = reopen_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&reopen.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let issue = Issue::find(
+        connection,
+        &current_reporter.reporter.organisation_uuid,
+        uuid,
+    )
+    .await
+    .ok()
+    .filter(|issue| can_see(&current_reporter, issue))
+    .ok_or_else(|| Flash::error(Redirect::to("/portal"), "Ticket not found"))?;
+    if !can_reopen(&issue) {
+        return Err(Flash::error(
+            Redirect::to(ticket_url),
+            format!(
+                "Only tickets closed in the last {} days can be reopened",
+                REOPEN_DAYS
+            ),
+        ));
+    }
+    let issue = Issue::reopen(
+        connection,
+        &current_reporter.reporter.organisation_uuid,
+        uuid,
+    )
+    .await
+    .map_err(|_| failed())?;
+    let note = format!("Reopened by {}", current_reporter.reporter.name);
+    Comment::create(
+        connection,
+        &issue.uuid,
+        &current_reporter.reporter.name,
+        &note,
+        true,
+    )
+    .await
+    .map_err(|_| failed())?;
+    Webhook::dispatch(connection, ISSUE_STATUS_CHANGED, &issue).await;
+    Ok(Flash::success(Redirect::to(ticket_url), "Ticket reopened"))
+}
+
+#[get("/portal/invitations/<token>", format = "text/html")]
+pub async fn invitation_form(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+) -> HtmlResponse {
+    let uuid = signer
+        .verify(INVITATION_PURPOSE, token)
+        .ok_or(Status::NotFound)?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let reporter = Reporter::find(connection, &uuid)
+        .await
+        .map_err(|e| e.status)?;
+    if reporter.password_hash.is_some() {
+        return Err(Status::NotFound);
+    }
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: format!("/portal/invitations/{}", token),
+        reporter,
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("portal/invitation", context))
+}
+
+#[post(
+    "/portal/invitations/<token>",
+    format = "application/x-www-form-urlencoded",
+    data = "<invitation_context>"
+)]
+pub async fn accept_invitation<'r>(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    invitation_context: Form<Contextual<'r, AcceptedInvitation<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    signer: &State<Signer>,
+    policy: &State<PasswordPolicy>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let form_url = format!("/portal/invitations/{}", token);
+    if invitation_context.value.is_none() {
+        let error_message = invitation_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(form_url), error_message));
+    }
+    let accepted = invitation_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&accepted.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let uuid = signer.verify(INVITATION_PURPOSE, token).ok_or_else(|| {
+        Flash::error(
+            Redirect::to("/portal/login"),
+            "This invitation is no longer valid",
+        )
+    })?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(form_url.clone()), "Something went wrong"))?;
+    let invited = Reporter::find(connection, &uuid).await.map_err(|_| {
+        Flash::error(
+            Redirect::to("/portal/login"),
+            "This invitation is no longer valid",
+        )
+    })?;
+    policy
+        .check_new_password(
+            accepted.password,
+            &[invited.name.as_str(), invited.email.as_str()],
+        )
+        .await
+        .map_err(|message| Flash::error(Redirect::to(form_url.clone()), message))?;
+    let reporter = Reporter::accept_invitation(
+        connection,
+        &invited.organisation_uuid,
+        &uuid,
+        accepted.password,
+        policy,
+    )
+    .await
+    .map_err(|_| {
+        Flash::error(
+            Redirect::to("/portal/login"),
+            "This invitation is no longer valid",
+        )
+    })?;
+    log_in(cookies, &reporter);
+    Ok(Flash::success(
+        Redirect::to("/portal"),
+        "Welcome, your account is ready",
+    ))
+}
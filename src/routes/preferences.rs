@@ -0,0 +1,115 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::guards::impersonation::NotImpersonating;
+use crate::models::notification_setting::{NotificationSetting, ALL_CHANNELS, ALL_EVENTS};
+use crate::models::user_preferences::{
+    timezone_names, EditedPreferences, UserPreferences, ALL_DATE_FORMATS, ALL_TICKET_VIEWS,
+    LANGUAGES,
+};
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+
+const PREFERENCES_PATH: &str = "/account/preferences";
+
+#[get("/account/preferences", format = "text/html")]
+pub async fn get_preferences(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let enabled_notifications =
+        NotificationSetting::find_by_user(connection, &current_user.user.uuid)
+            .await
+            .map_err(|e| e.status)?
+            .iter()
+            .map(|setting| NotificationSetting::key(&setting.event, &setting.channel))
+            .collect::<Vec<_>>();
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: PREFERENCES_PATH,
+        timezones: timezone_names(),
+        date_formats: ALL_DATE_FORMATS
+            .iter()
+            .map(|format| (format, format.to_string()))
+            .collect::<Vec<_>>(),
+        languages: LANGUAGES,
+        ticket_views: ALL_TICKET_VIEWS
+            .iter()
+            .map(|view| (view, view.to_string()))
+            .collect::<Vec<_>>(),
+        notification_events: ALL_EVENTS,
+        notification_channels: ALL_CHANNELS,
+        enabled_notifications,
+        preferences: &current_user.preferences,
+        permissions: current_user.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("preferences/form", context))
+}
+
+// Saves the preferences and notification opt-ins together, the page shows them as one form.
+// An impersonator looks at the agent's preferences but does not get to change them.
+#[post(
+    "/account/preferences",
+    format = "application/x-www-form-urlencoded",
+    data = "<preferences_context>"
+)]
+pub async fn update_preferences<'r>(
+    mut db: Connection<DBConnection>,
+    preferences_context: Form<Contextual<'r, EditedPreferences<'r>>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to(PREFERENCES_PATH),
+            "Something went wrong when saving your preferences",
+        )
+    };
+    if preferences_context.value.is_none() {
+        let error_message = preferences_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(PREFERENCES_PATH), error_message));
+    }
+    let edited = preferences_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&edited.authenticity_token)
+        .map_err(|_| failed())?;
+    let notifications = edited
+        .notifications
+        .iter()
+        .filter_map(|key| NotificationSetting::parse(key))
+        .collect::<Vec<_>>();
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let mut transaction = connection.begin().await.map_err(|_| failed())?;
+    UserPreferences::save(&mut transaction, &current_user.user.uuid, edited)
+        .await
+        .map_err(|_| failed())?;
+    NotificationSetting::replace(&mut transaction, &current_user.user.uuid, &notifications)
+        .await
+        .map_err(|_| failed())?;
+    transaction.commit().await.map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(PREFERENCES_PATH),
+        "Preferences saved",
+    ))
+}
@@ -0,0 +1,136 @@
+use super::portal::INVITATION_PURPOSE;
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::role::{RequireRole, Supervisor};
+use crate::models::reporter::{Reporter, ReporterInvitation};
+use crate::states::mail_sender::MailSender;
+use crate::states::signer::Signer;
+use chrono::{offset::Utc, Duration};
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+
+const INVITATION_DAYS: i64 = 7;
+
+#[get("/reporters", format = "text/html")]
+pub async fn get_reporters(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let reporters = Reporter::find_all(connection, &role.current_user.organisation.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        reporters: reporters,
+        permissions: role.permissions(),
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("reporters/index", context))
+}
+
+#[get("/reporters/new", format = "text/html")]
+pub async fn new_reporter(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: "/reporters",
+        legend: "Invite Reporter",
+        permissions: role.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("reporters/form", context))
+}
+
+// Creates the reporter account and emails them a link to pick a password.
+#[post(
+    "/reporters",
+    format = "application/x-www-form-urlencoded",
+    data = "<invitation_context>"
+)]
+pub async fn invite_reporter<'r>(
+    mut db: Connection<DBConnection>,
+    invitation_context: Form<Contextual<'r, ReporterInvitation<'r>>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    mail_sender: &State<MailSender>,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if invitation_context.value.is_none() {
+        let error_message = invitation_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to("/reporters/new"), error_message));
+    }
+    let invitation = invitation_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&invitation.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/reporters/new"),
+                "Something went wrong when inviting reporter",
+            )
+        })?;
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to("/reporters/new"),
+            "Something went wrong when inviting reporter",
+        )
+    })?;
+    let organisation = &role.current_user.organisation;
+    let reporter = Reporter::invite(connection, &organisation.uuid, invitation)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/reporters/new"),
+                "Something went wrong when inviting reporter",
+            )
+        })?;
+    let expires_at = (Utc::now() + Duration::days(INVITATION_DAYS)).timestamp();
+    let token = signer.sign(INVITATION_PURPOSE, &reporter.uuid.to_string(), expires_at);
+    let link = mail_sender.url(
+        &organisation.subdomain,
+        &format!("/portal/invitations/{}", token),
+    );
+    let body = format!(
+        "Hello {},\n\nYou have been invited to follow your tickets online. Choose a password here within {} days:\n\n{}\n",
+        reporter.name, INVITATION_DAYS, link
+    );
+    if !mail_sender
+        .send(&reporter.email, "Your ticket portal invitation", &body)
+        .await
+    {
+        return Ok(Flash::warning(
+            Redirect::to("/reporters"),
+            format!(
+                "Reporter created but the invitation email failed, send them this link: {}",
+                link
+            ),
+        ));
+    }
+    Ok(Flash::success(
+        Redirect::to("/reporters"),
+        "Successfully invited reporter",
+    ))
+}
@@ -0,0 +1,452 @@
+use crate::errors::our_error::OurError;
+use crate::fairings::db::DBConnection;
+use crate::guards::api_token::{ApiToken, ScimScope};
+use crate::models::scim::{
+    error_response, filter_groups, find_group, group_resource, list_response, member_changes,
+    user_resource, MemberChange, PatchRequest, ProvisionedUser, ScimGroupRequest, ScimUserRequest,
+    UserFilter, CONFIG_SCHEMA,
+};
+use crate::models::session::Session;
+use crate::models::user::User;
+use crate::models::user_role::UserRole;
+use crate::models::user_status::UserStatus;
+use crate::states::password_policy::PasswordPolicy;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::{json, Json, Value};
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use uuid::Uuid;
+
+const DEFAULT_COUNT: i64 = 100;
+const MAX_COUNT: i64 = 200;
+const PROVISIONED_REASON: &str = "Activated by the provisioning client";
+const DEPROVISIONED_REASON: &str = "Deprovisioned by the provisioning client";
+
+// SCIM clients expect application/scim+json, errors included
+pub struct ScimResponse {
+    status: Status,
+    body: Value,
+}
+
+impl ScimResponse {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: Status::Ok,
+            body,
+        }
+    }
+
+    fn created(body: Value) -> Self {
+        Self {
+            status: Status::Created,
+            body,
+        }
+    }
+
+    pub fn error(status: Status, scim_type: Option<&str>, detail: &str) -> Self {
+        Self {
+            status,
+            body: error_response(status.code, scim_type, detail),
+        }
+    }
+
+    fn from_error(e: OurError, scim_type: Option<&str>) -> Self {
+        Self::error(e.status, scim_type, &e.message)
+    }
+
+    fn internal() -> Self {
+        Self::error(Status::InternalServerError, None, "Something went wrong")
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ScimResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.body).respond_to(request)?)
+            .status(self.status)
+            .header(ContentType::new("application", "scim+json"))
+            .ok()
+    }
+}
+
+type ScimResult = Result<ScimResponse, ScimResponse>;
+
+#[derive(FromForm)]
+pub struct ListQuery<'r> {
+    filter: Option<&'r str>,
+    #[field(name = "startIndex")]
+    start_index: Option<i64>,
+    count: Option<i64>,
+}
+
+#[get("/scim/v2/ServiceProviderConfig")]
+pub fn service_provider_config(_token: ApiToken<ScimScope>) -> ScimResponse {
+    ScimResponse::ok(json!({
+        "schemas": [CONFIG_SCHEMA],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_COUNT },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Bearer token",
+            "description": "A SCIM token created in the organisation settings",
+        }],
+    }))
+}
+
+#[get("/scim/v2/Users?<query..>")]
+pub async fn get_users(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    query: ListQuery<'_>,
+) -> ScimResult {
+    let filter = match query.filter {
+        Some(filter) => Some(
+            UserFilter::parse(filter)
+                .map_err(|e| ScimResponse::from_error(e, Some("invalidFilter")))?,
+        ),
+        None => None,
+    };
+    // startIndex counts from 1
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(DEFAULT_COUNT).max(0).min(MAX_COUNT);
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let (users, total) = User::find_for_scim(
+        connection,
+        &token.organisation.uuid,
+        filter.as_ref(),
+        start_index - 1,
+        count,
+    )
+    .await
+    .map_err(|e| ScimResponse::from_error(e, None))?;
+    let resources = users.iter().map(user_resource).collect();
+    Ok(ScimResponse::ok(list_response(
+        resources,
+        total,
+        start_index,
+    )))
+}
+
+#[get("/scim/v2/Users/<id>")]
+pub async fn get_user(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+) -> ScimResult {
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let user = find_user(connection, &token.organisation.uuid, id).await?;
+    Ok(ScimResponse::ok(user_resource(&user)))
+}
+
+#[post("/scim/v2/Users", data = "<request>")]
+pub async fn create_user(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    request: Json<ScimUserRequest>,
+    password_policy: &State<PasswordPolicy>,
+) -> ScimResult {
+    let provisioned = request
+        .to_provisioned(None)
+        .map_err(|e| ScimResponse::from_error(e, Some("invalidValue")))?;
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    check_unique(connection, &token.organisation.uuid, None, &provisioned).await?;
+    let user = User::create_provisioned(
+        connection,
+        &token.organisation.uuid,
+        &provisioned,
+        password_policy,
+    )
+    .await
+    .map_err(|e| ScimResponse::from_error(e, None))?;
+    Ok(ScimResponse::created(user_resource(&user)))
+}
+
+#[put("/scim/v2/Users/<id>", data = "<request>")]
+pub async fn replace_user(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+    request: Json<ScimUserRequest>,
+) -> ScimResult {
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let user = find_user(connection, &token.organisation.uuid, id).await?;
+    let provisioned = request
+        .to_provisioned(Some(user.status))
+        .map_err(|e| ScimResponse::from_error(e, Some("invalidValue")))?;
+    let user = save_user(connection, &token.organisation.uuid, &user, &provisioned).await?;
+    Ok(ScimResponse::ok(user_resource(&user)))
+}
+
+#[patch("/scim/v2/Users/<id>", data = "<request>")]
+pub async fn patch_user(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+    request: Json<PatchRequest>,
+) -> ScimResult {
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let user = find_user(connection, &token.organisation.uuid, id).await?;
+    let mut provisioned = ProvisionedUser::from_user(&user);
+    for operation in request.operations.iter() {
+        provisioned
+            .apply(operation)
+            .map_err(|e| ScimResponse::from_error(e, Some("invalidValue")))?;
+    }
+    let user = save_user(connection, &token.organisation.uuid, &user, &provisioned).await?;
+    Ok(ScimResponse::ok(user_resource(&user)))
+}
+
+// deprovisioning deactivates, the user and what they did stay in the database
+#[delete("/scim/v2/Users/<id>")]
+pub async fn delete_user(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+) -> Result<Status, ScimResponse> {
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let user = find_user(connection, &token.organisation.uuid, id).await?;
+    User::change_status(
+        connection,
+        &token.organisation.uuid,
+        &user.uuid,
+        UserStatus::Deactivated,
+        Some(DEPROVISIONED_REASON),
+        None,
+    )
+    .await
+    .map_err(|e| ScimResponse::from_error(e, None))?;
+    end_sessions(connection, &user.uuid).await;
+    Ok(Status::NoContent)
+}
+
+#[get("/scim/v2/Groups?<query..>")]
+pub async fn get_groups(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    query: ListQuery<'_>,
+) -> ScimResult {
+    let roles = filter_groups(query.filter)
+        .map_err(|e| ScimResponse::from_error(e, Some("invalidFilter")))?;
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    let mut resources = Vec::new();
+    for role in roles {
+        let members = User::find_by_role(connection, &token.organisation.uuid, role)
+            .await
+            .map_err(|e| ScimResponse::from_error(e, None))?;
+        resources.push(group_resource(role, &members));
+    }
+    let total = resources.len() as i64;
+    Ok(ScimResponse::ok(list_response(resources, total, 1)))
+}
+
+#[get("/scim/v2/Groups/<id>")]
+pub async fn get_group(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+) -> ScimResult {
+    let role = find_role(id)?;
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    group_response(connection, &token.organisation.uuid, role).await
+}
+
+#[patch("/scim/v2/Groups/<id>", data = "<request>")]
+pub async fn patch_group(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+    request: Json<PatchRequest>,
+) -> ScimResult {
+    let role = find_role(id)?;
+    let changes =
+        member_changes(&request).map_err(|e| ScimResponse::from_error(e, Some("invalidValue")))?;
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    for change in changes.iter() {
+        change_members(connection, &token.organisation.uuid, role, change).await?;
+    }
+    group_response(connection, &token.organisation.uuid, role).await
+}
+
+#[put("/scim/v2/Groups/<id>", data = "<request>")]
+pub async fn replace_group(
+    token: ApiToken<ScimScope>,
+    mut db: Connection<DBConnection>,
+    id: &str,
+    request: Json<ScimGroupRequest>,
+) -> ScimResult {
+    let role = find_role(id)?;
+    let members = request
+        .members
+        .iter()
+        .map(|member| member.value.clone())
+        .collect();
+    let connection = db.acquire().await.map_err(|_| ScimResponse::internal())?;
+    change_members(
+        connection,
+        &token.organisation.uuid,
+        role,
+        &MemberChange::Replace(members),
+    )
+    .await?;
+    group_response(connection, &token.organisation.uuid, role).await
+}
+
+async fn find_user(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    id: &str,
+) -> Result<User, ScimResponse> {
+    // ids are ours, anything that is not a uuid is simply unknown
+    if Uuid::parse_str(id).is_err() {
+        return Err(ScimResponse::error(
+            Status::NotFound,
+            None,
+            "User not found",
+        ));
+    }
+    // a removed user is only kept for history, provisioning cannot see it
+    User::find(connection, organisation_uuid, id)
+        .await
+        .ok()
+        .filter(|user| user.deleted_at.is_none())
+        .ok_or_else(|| ScimResponse::error(Status::NotFound, None, "User not found"))
+}
+
+fn find_role(id: &str) -> Result<UserRole, ScimResponse> {
+    find_group(id).ok_or_else(|| ScimResponse::error(Status::NotFound, None, "Group not found"))
+}
+
+async fn group_response(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    role: UserRole,
+) -> ScimResult {
+    let members = User::find_by_role(connection, organisation_uuid, role)
+        .await
+        .map_err(|e| ScimResponse::from_error(e, None))?;
+    Ok(ScimResponse::ok(group_resource(role, &members)))
+}
+
+// username and email belong to one user only
+async fn check_unique(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    uuid: Option<&Uuid>,
+    provisioned: &ProvisionedUser,
+) -> Result<(), ScimResponse> {
+    let by_username = User::find_by_username(connection, organisation_uuid, &provisioned.username)
+        .await
+        .ok();
+    let by_email = User::find_by_email(connection, organisation_uuid, &provisioned.email)
+        .await
+        .ok();
+    let taken = by_username
+        .iter()
+        .chain(by_email.iter())
+        .any(|user| Some(&user.uuid) != uuid);
+    if taken {
+        return Err(ScimResponse::error(
+            Status::Conflict,
+            Some("uniqueness"),
+            "userName or email already used by another user",
+        ));
+    }
+    Ok(())
+}
+
+async fn save_user(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    user: &User,
+    provisioned: &ProvisionedUser,
+) -> Result<User, ScimResponse> {
+    check_unique(connection, organisation_uuid, Some(&user.uuid), provisioned).await?;
+    let mut saved =
+        User::update_provisioned(connection, organisation_uuid, &user.uuid, provisioned)
+            .await
+            .map_err(|e| ScimResponse::from_error(e, None))?;
+    if provisioned.status != user.status {
+        let reason = if provisioned.status.can_log_in() {
+            PROVISIONED_REASON
+        } else {
+            DEPROVISIONED_REASON
+        };
+        saved = User::change_status(
+            connection,
+            organisation_uuid,
+            &user.uuid,
+            provisioned.status,
+            Some(reason),
+            None,
+        )
+        .await
+        .map_err(|e| ScimResponse::from_error(e, None))?;
+    }
+    if !saved.status.can_log_in() {
+        end_sessions(connection, &saved.uuid).await;
+    }
+    Ok(saved)
+}
+
+async fn end_sessions(connection: &mut PgConnection, uuid: &Uuid) {
+    if Session::revoke_all_for_user(connection, uuid)
+        .await
+        .is_err()
+    {
+        log::error!("Cannot revoke sessions of deprovisioned user {}", uuid);
+    }
+}
+
+// A user holds one role, so members of a group are the users with exactly its role.
+// Leaving a group makes the user an agent again, leaving the agents group changes nothing.
+async fn change_members(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    role: UserRole,
+    change: &MemberChange,
+) -> Result<(), ScimResponse> {
+    let (added, removed) = match change {
+        MemberChange::Add(ids) => (ids.clone(), Vec::new()),
+        MemberChange::Remove(ids) => (Vec::new(), ids.clone()),
+        MemberChange::Replace(ids) => {
+            let members = User::find_by_role(connection, organisation_uuid, role)
+                .await
+                .map_err(|e| ScimResponse::from_error(e, None))?;
+            let removed = members
+                .iter()
+                .map(|user| user.uuid.to_string())
+                .filter(|id| !ids.contains(id))
+                .collect();
+            (ids.clone(), removed)
+        }
+    };
+    for id in removed.iter() {
+        let user = find_user(connection, organisation_uuid, id).await?;
+        if user.role == role && role != UserRole::Agent {
+            set_role(connection, organisation_uuid, id, UserRole::Agent).await?;
+        }
+    }
+    for id in added.iter() {
+        find_user(connection, organisation_uuid, id).await?;
+        set_role(connection, organisation_uuid, id, role).await?;
+    }
+    Ok(())
+}
+
+async fn set_role(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    id: &str,
+    role: UserRole,
+) -> Result<(), ScimResponse> {
+    User::set_role(connection, organisation_uuid, id, role)
+        .await
+        .map_err(|e| ScimResponse::from_error(e, None))?;
+    Ok(())
+}
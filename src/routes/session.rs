@@ -0,0 +1,516 @@
+use super::two_factor::start_pending_login;
+use super::HtmlResponse;
+use crate::errors::our_error::OurError;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::{CurrentUser, LOGIN_COOKIE_NAME};
+use crate::guards::client::ClientInfo;
+use crate::guards::impersonation::{NotImpersonating, IMPERSONATION_COOKIE_NAME};
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::impersonation::Impersonation;
+use crate::models::login_attempt::{
+    LoginAttempt, METHOD_PASSWORD, OUTCOME_INACTIVE, OUTCOME_LOCKED, OUTCOME_SSO_REQUIRED,
+    OUTCOME_SUCCEEDED, OUTCOME_THROTTLED, OUTCOME_UNKNOWN_USER, OUTCOME_WRONG_PASSWORD,
+};
+use crate::models::session::Session;
+use crate::models::user::{Login, LoginCheck, User};
+use crate::models::user_role::UserRole;
+use crate::models::user_status::UserStatus;
+use crate::states::oidc_client::OidcClient;
+use crate::states::password_policy::PasswordPolicy;
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::session_policy::SessionPolicy;
+use chrono::offset::Utc;
+use rocket::form::{Contextual, Form};
+use rocket::http::{cookie::Expiration, Cookie, CookieJar, RawStr, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+const LOGIN_BUCKET: &str = "login";
+const LOGIN_LIMIT: usize = 10;
+const LOGIN_WINDOW: StdDuration = StdDuration::from_secs(15 * 60);
+// the ticket list the user picked in their preferences
+const DEFAULT_NEXT: &str = "/tickets";
+const INVALID_LOGIN_MESSAGE: &str = "Invalid username or password";
+// failures older than this are forgotten by the throttling below
+const FAILURE_WINDOW_MINUTES: i64 = 15;
+const IP_FAILURE_LIMIT: i64 = 20;
+const DELAY_AFTER_FAILURES: i64 = 3;
+const MAX_DELAY_SECONDS: u64 = 16;
+const LOCKOUT_THRESHOLD: i32 = 10;
+const LOCKOUT_MINUTES: i64 = 30;
+
+// only paths of this application, anything else would make /login an open redirect
+pub fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next
+        }
+        _ => DEFAULT_NEXT,
+    }
+}
+
+// what a user whose account is not active is told once they proved who they are
+pub fn refused_status_message(status: UserStatus) -> &'static str {
+    match status {
+        UserStatus::Pending => "Your account is not activated yet, follow the link we emailed you or <a href=\"/activate\">get a new one</a>",
+        UserStatus::Suspended => "Your account is suspended, ask an admin",
+        UserStatus::Locked => "Your account is locked, ask an admin",
+        _ => "Your account is deactivated, ask an admin",
+    }
+}
+
+// login page coming back to next afterwards
+pub fn login_url(next: Option<&str>) -> String {
+    match next {
+        Some(next) => format!("/login?next={}", RawStr::new(next).percent_encode()),
+        None => String::from("/login"),
+    }
+}
+
+#[get("/login?<next>", format = "text/html")]
+pub async fn login_form(
+    next: Option<&str>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    oidc: &State<OidcClient>,
+    organisation: CurrentOrganisation,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        next: safe_next(next),
+        flash: flash_string,
+        csrf_token,
+        sso_label: oidc
+            .settings_for(&organisation.organisation)
+            .map(|settings| settings.label.as_str()),
+    };
+    Ok(Template::render("sessions/login", context))
+}
+
+#[post(
+    "/login",
+    format = "application/x-www-form-urlencoded",
+    data = "<login_context>"
+)]
+pub async fn login<'r>(
+    mut db: Connection<DBConnection>,
+    login_context: Form<Contextual<'r, Login<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    limiter: &State<RateLimiter>,
+    policy: &State<SessionPolicy>,
+    password_policy: &State<PasswordPolicy>,
+    oidc: &State<OidcClient>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Redirect, Flash<Redirect>> {
+    let organisation_uuid = &organisation.organisation.uuid;
+    let client_key = client.ip_address.as_deref().unwrap_or("unknown");
+    let next = login_context.value.as_ref().and_then(|login| login.next);
+    let back = login_url(next);
+    let invalid = || Flash::error(Redirect::to(back.clone()), INVALID_LOGIN_MESSAGE);
+    let login = login_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&login.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to(back.clone()), "Something went wrong"))?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to(back.clone()), "Something went wrong"))?;
+    let window = chrono::Duration::minutes(FAILURE_WINDOW_MINUTES);
+    // the in memory limiter is per process, failures stored in the database are seen by all
+    let ip_failures = match client.ip_address.as_deref() {
+        Some(ip_address) => LoginAttempt::count_failures_for_ip(connection, ip_address, window)
+            .await
+            .unwrap_or(0),
+        None => 0,
+    };
+    if !limiter.check(LOGIN_BUCKET, client_key, LOGIN_LIMIT, LOGIN_WINDOW)
+        || ip_failures >= IP_FAILURE_LIMIT
+    {
+        record_attempt(
+            connection,
+            organisation_uuid,
+            login.username,
+            None,
+            &client,
+            METHOD_PASSWORD,
+            OUTCOME_THROTTLED,
+        )
+        .await;
+        return Err(Flash::error(
+            Redirect::to(back),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let failures = LoginAttempt::count_failures_for_username(
+        connection,
+        organisation_uuid,
+        login.username,
+        window,
+    )
+    .await
+    .unwrap_or(0);
+    // refused rather than slept on, a sleeping request would hold its pooled connection
+    let wait = match login_delay(failures) {
+        0 => 0,
+        delay => {
+            LoginAttempt::last_failure_for_username(connection, organisation_uuid, login.username)
+                .await
+                .ok()
+                .flatten()
+                .map(|last| delay as i64 - (Utc::now() - last.0).num_seconds())
+                .unwrap_or(0)
+        }
+    };
+    if wait > 0 {
+        record_attempt(
+            connection,
+            organisation_uuid,
+            login.username,
+            None,
+            &client,
+            METHOD_PASSWORD,
+            OUTCOME_THROTTLED,
+        )
+        .await;
+        return Err(Flash::error(
+            Redirect::to(back),
+            format!(
+                "Too many failed attempts, please try again in {} seconds",
+                wait
+            ),
+        ));
+    }
+    let check = User::check_login(connection, organisation_uuid, login, password_policy)
+        .await
+        .map_err(|_| Flash::error(Redirect::to(back.clone()), "Something went wrong"))?;
+    let user = match check {
+        LoginCheck::Valid(user) => user,
+        LoginCheck::WrongPassword(user) => {
+            record_attempt(
+                connection,
+                organisation_uuid,
+                login.username,
+                Some(&user.uuid),
+                &client,
+                METHOD_PASSWORD,
+                OUTCOME_WRONG_PASSWORD,
+            )
+            .await;
+            if User::record_failed_login(
+                connection,
+                organisation_uuid,
+                &user.uuid,
+                LOCKOUT_THRESHOLD,
+                chrono::Duration::minutes(LOCKOUT_MINUTES),
+            )
+            .await
+            .is_err()
+            {
+                log::error!("Cannot count failed login");
+            }
+            return Err(invalid());
+        }
+        // answered like a wrong password, the lock is only told after a password reset
+        LoginCheck::Locked(user) => {
+            record_attempt(
+                connection,
+                organisation_uuid,
+                login.username,
+                Some(&user.uuid),
+                &client,
+                METHOD_PASSWORD,
+                OUTCOME_LOCKED,
+            )
+            .await;
+            return Err(invalid());
+        }
+        LoginCheck::UnknownUser => {
+            record_attempt(
+                connection,
+                organisation_uuid,
+                login.username,
+                None,
+                &client,
+                METHOD_PASSWORD,
+                OUTCOME_UNKNOWN_USER,
+            )
+            .await;
+            return Err(invalid());
+        }
+    };
+    // with single sign-on the local password is kept for admins getting in while the provider is down
+    if oidc.break_glass_only(&organisation.organisation) && user.role != UserRole::Admin {
+        record_attempt(
+            connection,
+            organisation_uuid,
+            login.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_PASSWORD,
+            OUTCOME_SSO_REQUIRED,
+        )
+        .await;
+        return Err(Flash::error(
+            Redirect::to(back),
+            "Please log in with single sign-on",
+        ));
+    }
+    // checked after the password so the message tells nothing to someone guessing
+    if !user.status.can_log_in() {
+        record_attempt(
+            connection,
+            organisation_uuid,
+            login.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_PASSWORD,
+            OUTCOME_INACTIVE,
+        )
+        .await;
+        return Err(Flash::error(
+            Redirect::to(back),
+            refused_status_message(user.status),
+        ));
+    }
+    User::record_successful_login(connection, organisation_uuid, &user.uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to(back.clone()), "Something went wrong"))?;
+    // the plain password is only known now, the moment to upgrade a hash made with a lower cost
+    if password_policy.needs_rehash(&user.password_hash)
+        && User::set_password(
+            connection,
+            organisation_uuid,
+            &user.uuid,
+            login.password,
+            password_policy,
+        )
+        .await
+        .is_err()
+    {
+        log::error!("Cannot upgrade password hash of {}", user.uuid);
+    }
+    record_attempt(
+        connection,
+        organisation_uuid,
+        login.username,
+        Some(&user.uuid),
+        &client,
+        METHOD_PASSWORD,
+        OUTCOME_SUCCEEDED,
+    )
+    .await;
+    let next = safe_next(login.next);
+    // the session only starts once the second factor is checked, or set up when required
+    if user.totp_enabled() || user.totp_required {
+        start_pending_login(cookies, &user.uuid, login.remember_me, next);
+        return Ok(Redirect::to("/login/2fa"));
+    }
+    start_session(
+        connection,
+        cookies,
+        policy,
+        &client,
+        &user.uuid,
+        login.remember_me,
+    )
+    .await
+    .map_err(|_| Flash::error(Redirect::to(back.clone()), "Something went wrong"))?;
+    Ok(Redirect::to(next.to_string()))
+}
+
+// Audit trail of login attempts, a failure to write it does not block the login.
+pub async fn record_attempt(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    username: &str,
+    user_uuid: Option<&Uuid>,
+    client: &ClientInfo,
+    method: &str,
+    outcome: &str,
+) {
+    if LoginAttempt::create(
+        connection,
+        organisation_uuid,
+        username,
+        user_uuid,
+        client.ip_address.as_deref(),
+        client.user_agent.as_deref(),
+        method,
+        outcome,
+    )
+    .await
+    .is_err()
+    {
+        log::error!("Cannot record login attempt");
+    }
+}
+
+// seconds to wait after the latest failure before the next password of the username is checked,
+// doubling with every recent failure past the first few
+fn login_delay(failures: i64) -> u64 {
+    if failures < DELAY_AFTER_FAILURES {
+        return 0;
+    }
+    let doublings = (failures - DELAY_AFTER_FAILURES).min(8) as u32;
+    (1u64 << doublings).min(MAX_DELAY_SECONDS)
+}
+
+// Records a new session for the user and hands its token to the browser.
+pub async fn start_session(
+    connection: &mut PgConnection,
+    cookies: &CookieJar<'_>,
+    policy: &SessionPolicy,
+    client: &ClientInfo,
+    user_uuid: &Uuid,
+    remember_me: bool,
+) -> Result<(), OurError> {
+    let lifetime = policy.lifetime(remember_me);
+    let (_, token) = Session::create(
+        connection,
+        user_uuid,
+        client.ip_address.as_deref(),
+        client.user_agent.as_deref(),
+        remember_me,
+        lifetime,
+    )
+    .await?;
+    let mut cookie = Cookie::new(LOGIN_COOKIE_NAME, token);
+    if remember_me {
+        cookie.set_expires(OffsetDateTime::now_utc() + Duration::seconds(lifetime.num_seconds()));
+    } else {
+        // gone when the browser closes
+        cookie.set_expires(Expiration::Session);
+    }
+    cookies.add_private(cookie);
+    Ok(())
+}
+
+#[post(
+    "/logout",
+    format = "application/x-www-form-urlencoded",
+    data = "<logout_context>"
+)]
+pub async fn logout<'r>(
+    mut db: Connection<DBConnection>,
+    logout_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/sessions"),
+            "Something went wrong when logging out",
+        )
+    };
+    let logout = logout_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&logout.authenticity_token)
+        .map_err(|_| failed())?;
+    if let Some(cookie) = cookies.get_private(LOGIN_COOKIE_NAME) {
+        // the cookie goes anyway, a session left behind still ends with its timeouts
+        match db.acquire().await {
+            Ok(connection) => {
+                if Session::revoke_by_token(connection, cookie.value())
+                    .await
+                    .is_err()
+                {
+                    log::error!("Cannot revoke session on logout");
+                }
+            }
+            Err(_) => log::error!("Cannot revoke session on logout"),
+        }
+    }
+    // logging out while impersonating ends the impersonation too
+    if let Some(cookie) = cookies.get_private(IMPERSONATION_COOKIE_NAME) {
+        match db.acquire().await {
+            Ok(connection) => {
+                if Impersonation::end(connection, cookie.value())
+                    .await
+                    .is_err()
+                {
+                    log::error!("Cannot end impersonation on logout");
+                }
+            }
+            Err(_) => log::error!("Cannot end impersonation on logout"),
+        }
+    }
+    cookies.remove_private(Cookie::named(LOGIN_COOKIE_NAME));
+    cookies.remove_private(Cookie::named(IMPERSONATION_COOKIE_NAME));
+    Ok(Flash::success(Redirect::to("/login"), "You are logged out"))
+}
+
+// Lists the devices the current user is logged in on.
+#[get("/sessions", format = "text/html")]
+pub async fn get_sessions(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let sessions = Session::find_by_user(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        sessions,
+        current_session: current_user.session.uuid,
+        csrf_token,
+        permissions: current_user.permissions(),
+        preferences: &current_user.preferences,
+        flash: flash_message,
+    };
+    Ok(Template::render("sessions/index", context))
+}
+
+#[post(
+    "/sessions/revoke/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<revoke_context>"
+)]
+pub async fn revoke_session<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    revoke_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/sessions"),
+            "Something went wrong when revoking session",
+        )
+    };
+    let revoke = revoke_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&revoke.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    Session::revoke(connection, uuid, &current_user.user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    if current_user.session.uuid.to_string() == uuid {
+        return Ok(Flash::success(Redirect::to("/login"), "You are logged out"));
+    }
+    Ok(Flash::success(
+        Redirect::to("/sessions"),
+        "Successfully revoked session",
+    ))
+}
@@ -0,0 +1,186 @@
+use crate::errors::our_error::OurError;
+use crate::fairings::db::DBConnection;
+use crate::guards::api_token::{ApiToken, InboundSmsScope};
+use crate::models::comment::Comment;
+use crate::models::inbound_sms::{InboundSms, SmsCommand};
+use crate::models::issues_reported::{Issue, NewIssue};
+use crate::models::webhook::{Webhook, ISSUE_CREATED, ISSUE_UPDATED};
+use crate::states::sms_notifier::SmsNotifier;
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use uuid::Uuid;
+
+const UNKNOWN_COMPANY: &str = "Unknown";
+
+// what we did with the message, returned to the gateway
+#[derive(Serialize)]
+pub struct InboundSmsResult {
+    action: &'static str,
+    ticket_number: Option<i64>,
+}
+
+// Receives text messages from gateways posting form data.
+#[post(
+    "/sms/inbound",
+    format = "application/x-www-form-urlencoded",
+    data = "<message>"
+)]
+pub async fn inbound_form(
+    token: ApiToken<InboundSmsScope>,
+    mut db: Connection<DBConnection>,
+    sms: &State<SmsNotifier>,
+    message: Form<InboundSms>,
+) -> Result<Json<InboundSmsResult>, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let result = handle(connection, &token.organisation.uuid, sms, &message)
+        .await
+        .map_err(|e| e.status)?;
+    Ok(Json(result))
+}
+
+// Receives text messages from gateways posting JSON.
+#[post("/sms/inbound", format = "json", data = "<message>")]
+pub async fn inbound_json(
+    token: ApiToken<InboundSmsScope>,
+    mut db: Connection<DBConnection>,
+    sms: &State<SmsNotifier>,
+    message: Json<InboundSms>,
+) -> Result<Json<InboundSmsResult>, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let result = handle(connection, &token.organisation.uuid, sms, &message)
+        .await
+        .map_err(|e| e.status)?;
+    Ok(Json(result))
+}
+
+async fn handle(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    sms: &SmsNotifier,
+    message: &InboundSms,
+) -> Result<InboundSmsResult, OurError> {
+    let contact_number = message
+        .contact_number()
+        .ok_or_else(|| OurError::new_bad_request_error(String::from("Invalid sender"), None))?;
+    if message.body.trim().is_empty() {
+        return Err(OurError::new_bad_request_error(
+            String::from("Empty message"),
+            None,
+        ));
+    }
+
+    match message.command() {
+        SmsCommand::Status(ticket_number) => {
+            status(
+                connection,
+                organisation_uuid,
+                sms,
+                message,
+                contact_number,
+                ticket_number,
+            )
+            .await
+        }
+        SmsCommand::Report => {
+            report(connection, organisation_uuid, sms, message, contact_number).await
+        }
+    }
+}
+
+// Answers with the state of the ticket, only to the number that reported it.
+async fn status(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    sms: &SmsNotifier,
+    message: &InboundSms,
+    contact_number: i64,
+    ticket_number: i64,
+) -> Result<InboundSmsResult, OurError> {
+    let issue =
+        match Issue::find_by_ticket_number(connection, organisation_uuid, ticket_number).await {
+            Ok(issue) if issue.contact_number == contact_number => Some(issue),
+            Ok(_) => None,
+            Err(e) if e.status == Status::NotFound => None,
+            Err(e) => return Err(e),
+        };
+    match issue {
+        Some(issue) => {
+            let body = format!(
+                "Ticket #{} ({}) is {}. Last update {}.",
+                issue.ticket_number,
+                issue.issue_name,
+                issue.status,
+                issue.updated_at.0.format("%Y-%m-%d %H:%M UTC")
+            );
+            sms.reply(connection, &message.from, &body, Some(&issue))
+                .await;
+            Ok(InboundSmsResult {
+                action: "status",
+                ticket_number: Some(issue.ticket_number),
+            })
+        }
+        None => {
+            let body = format!("We could not find ticket #{} for this number.", ticket_number);
+            sms.reply(connection, &message.from, &body, None).await;
+            Ok(InboundSmsResult {
+                action: "status",
+                ticket_number: None,
+            })
+        }
+    }
+}
+
+// Appends to the open ticket of the number, or opens a new ticket.
+async fn report(
+    connection: &mut PgConnection,
+    organisation_uuid: &Uuid,
+    sms: &SmsNotifier,
+    message: &InboundSms,
+    contact_number: i64,
+) -> Result<InboundSmsResult, OurError> {
+    if let Some(issue) =
+        Issue::find_open_by_contact_number(connection, organisation_uuid, contact_number).await?
+    {
+        Comment::create(connection, &issue.uuid, &message.from, &message.body, true).await?;
+        Webhook::dispatch(connection, ISSUE_UPDATED, &issue).await;
+        let body = format!(
+            "Your message was added to ticket #{}.",
+            issue.ticket_number
+        );
+        sms.reply(connection, &message.from, &body, Some(&issue))
+            .await;
+        return Ok(InboundSmsResult {
+            action: "appended",
+            ticket_number: Some(issue.ticket_number),
+        });
+    }
+
+    let issue_name = message.issue_name();
+    let contact_number = contact_number.to_string();
+    let new_issue = NewIssue {
+        issue_name: &issue_name,
+        description: &message.body,
+        reported_by: &message.from,
+        company_name: UNKNOWN_COMPANY,
+        contact_number: &contact_number,
+        authenticity_token: "",
+    };
+    let issue = Issue::create(connection, organisation_uuid, &new_issue).await?;
+    sms.ticket_created(connection, &issue).await;
+    Webhook::dispatch(connection, ISSUE_CREATED, &issue).await;
+    Ok(InboundSmsResult {
+        action: "created",
+        ticket_number: Some(issue.ticket_number),
+    })
+}
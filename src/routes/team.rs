@@ -0,0 +1,337 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::role::{Agent, RequireRole, Supervisor};
+use crate::models::team::{NewTeam, Team, TeamSla, SLA_WINDOW_DAYS};
+use crate::models::team_member::{NewTeamMember, TeamMember};
+use crate::models::user::User;
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+use uuid::Uuid;
+
+fn form_errors(team_context: &Contextual<'_, NewTeam<'_>>) -> String {
+    team_context
+        .context
+        .errors()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("<br/>")
+}
+
+// SLA dashboard of every team, agents see how their queues are doing too.
+#[get("/teams", format = "text/html")]
+pub async fn get_teams(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let slas = TeamSla::find(connection, &role.current_user.organisation.uuid, None)
+        .await
+        .map_err(|e| e.status)?;
+    let my_teams = Team::find_by_member(connection, &role.current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?
+        .into_iter()
+        .map(|team| team.uuid)
+        .collect::<Vec<_>>();
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        slas,
+        my_teams,
+        window_days: SLA_WINDOW_DAYS,
+        csrf_token,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
+    };
+    Ok(Template::render("teams/index", context))
+}
+
+#[get("/teams/new", format = "text/html")]
+pub async fn new_team(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: "/teams",
+        legend: "New Team",
+        permissions: role.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("teams/form", context))
+}
+
+#[post(
+    "/teams",
+    format = "application/x-www-form-urlencoded",
+    data = "<team_context>"
+)]
+pub async fn create_team<'r>(
+    mut db: Connection<DBConnection>,
+    team_context: Form<Contextual<'r, NewTeam<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/teams/new"),
+            "Something went wrong when creating the team",
+        )
+    };
+    let new_team = team_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to("/teams/new"), form_errors(&team_context)))?;
+    csrf_token
+        .verify(&new_team.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let team = Team::create(connection, &role.current_user.organisation.uuid, new_team)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/teams/{}", team.uuid)),
+        "Successfully created team",
+    ))
+}
+
+// SLA dashboard and members of one team.
+#[get("/teams/<uuid>", format = "text/html")]
+pub async fn get_team(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Agent>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let team = Team::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let sla = TeamSla::find(connection, organisation_uuid, Some(&team.uuid))
+        .await
+        .map_err(|e| e.status)?
+        .pop();
+    let members = TeamMember::find_by_team(connection, &team.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let is_member = members
+        .iter()
+        .any(|member| member.user_uuid == role.current_user.user.uuid);
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        team,
+        sla,
+        members,
+        is_member,
+        window_days: SLA_WINDOW_DAYS,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("teams/show", context))
+}
+
+#[get("/teams/edit/<uuid>", format = "text/html")]
+pub async fn edit_team(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let team = Team::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: format!("/teams/{}", team.uuid),
+        legend: "Edit Team",
+        team,
+        permissions: role.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("teams/form", context))
+}
+
+#[post(
+    "/teams/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<team_context>"
+)]
+pub async fn update_team<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    team_context: Form<Contextual<'r, NewTeam<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let form_url = format!("/teams/edit/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(form_url.clone()),
+            "Something went wrong when updating the team",
+        )
+    };
+    let edited = team_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to(form_url.clone()), form_errors(&team_context)))?;
+    csrf_token
+        .verify(&edited.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let team = Team::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/teams"), "Team not found"))?;
+    Team::update(connection, &team.uuid, edited)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/teams/{}", team.uuid)),
+        "Successfully updated team",
+    ))
+}
+
+// The team's tickets go back to the shared open page, with whoever owned them.
+#[post(
+    "/teams/delete/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<delete_context>"
+)]
+pub async fn delete_team<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    delete_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/teams"),
+            "Something went wrong when deleting the team",
+        )
+    };
+    let delete = delete_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&delete.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let team = Team::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/teams"), "Team not found"))?;
+    Team::destroy(connection, &team.uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to("/teams"),
+        format!("Deleted team {}", team.name),
+    ))
+}
+
+#[post(
+    "/teams/members/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<member_context>"
+)]
+pub async fn add_member<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    member_context: Form<Contextual<'r, NewTeamMember<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let team_url = format!("/teams/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(team_url.clone()),
+            "Something went wrong when adding the member",
+        )
+    };
+    let new_member = member_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&new_member.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let team = Team::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/teams"), "Team not found"))?;
+    // only agents who can work tickets, removed users keep their username but not their access
+    let user = User::find_by_username(
+        connection,
+        &role.current_user.organisation.uuid,
+        new_member.username.trim(),
+    )
+    .await
+    .ok()
+    .filter(|user| user.status.can_log_in() && user.deleted_at.is_none())
+    .ok_or_else(|| Flash::error(Redirect::to(team_url.clone()), "User not found"))?;
+    TeamMember::create(connection, &team.uuid, &user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(team_url),
+        format!("Added {} to {}", user.username, team.name),
+    ))
+}
+
+// Their tickets stay in the team queue, a member claims them again if they are handed back.
+#[post(
+    "/teams/members/delete/<uuid>/<user_uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<remove_context>"
+)]
+pub async fn remove_member<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    user_uuid: Uuid,
+    remove_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Supervisor>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let team_url = format!("/teams/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(team_url.clone()),
+            "Something went wrong when removing the member",
+        )
+    };
+    let remove = remove_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&remove.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let team = Team::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/teams"), "Team not found"))?;
+    TeamMember::destroy(connection, &team.uuid, &user_uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(Redirect::to(team_url), "Member removed"))
+}
@@ -0,0 +1,184 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::models::comment::Comment;
+use crate::models::issues_reported::Issue;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::sms_message::parse_contact_number;
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::signer::Signer;
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+use std::net::IpAddr;
+use std::time::Duration;
+
+const TRACK_PURPOSE: &str = "track";
+const VIEW_BUCKET: &str = "track_view";
+const LOOKUP_LIMIT: usize = 10;
+const LOOKUP_WINDOW: Duration = Duration::from_secs(15 * 60);
+const VIEW_LIMIT: usize = 60;
+const VIEW_WINDOW: Duration = Duration::from_secs(60);
+const NOT_FOUND_MESSAGE: &str = "We could not find a ticket with that number and contact number";
+
+// Path of the public tracking page of an issue, safe to hand to the reporter.
+pub fn track_path(signer: &Signer, issue: &Issue) -> String {
+    format!(
+        "/track/{}",
+        signer.sign(TRACK_PURPOSE, &issue.uuid.to_string(), 0)
+    )
+}
+
+// what a reporter may see, internal fields such as ticket_owner stay out
+#[derive(Serialize)]
+struct PublicIssue {
+    ticket_number: i64,
+    issue_name: String,
+    description: String,
+    status: String,
+    created_at: OurDateTime,
+    updated_at: OurDateTime,
+}
+
+#[derive(Serialize)]
+struct PublicReply {
+    author: String,
+    body: String,
+    created_at: OurDateTime,
+}
+
+#[derive(FromForm)]
+pub struct TrackLookup<'r> {
+    #[field(validate = len(1..20).or_else(msg!("ticket number cannot be empty")))]
+    pub ticket_number: &'r str,
+    #[field(validate = len(1..20).or_else(msg!("contact number cannot be empty")))]
+    pub contact_number: &'r str,
+    pub authenticity_token: &'r str,
+}
+
+fn client_key(ip: Option<IpAddr>) -> String {
+    ip.map(|ip| ip.to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+// Displays the form where a reporter enters ticket number and contact number.
+#[get("/track", format = "text/html")]
+pub async fn track_form(flash: Option<FlashMessage<'_>>, csrf_token: CsrfToken) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("track/form", context))
+}
+
+// Looks the ticket up and redirects to its signed tracking link.
+#[post(
+    "/track",
+    format = "application/x-www-form-urlencoded",
+    data = "<lookup_context>"
+)]
+pub async fn track_lookup<'r>(
+    mut db: Connection<DBConnection>,
+    lookup_context: Form<Contextual<'r, TrackLookup<'r>>>,
+    csrf_token: CsrfToken,
+    signer: &State<Signer>,
+    limiter: &State<RateLimiter>,
+    ip: Option<IpAddr>,
+    organisation: CurrentOrganisation,
+) -> Result<Redirect, Flash<Redirect>> {
+    if !limiter.check(TRACK_PURPOSE, &client_key(ip), LOOKUP_LIMIT, LOOKUP_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/track"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    if lookup_context.value.is_none() {
+        let error_message = lookup_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to("/track"), error_message));
+    }
+    let lookup = lookup_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&lookup.authenticity_token)
+        .map_err(|_| Flash::error(Redirect::to("/track"), "Something went wrong"))?;
+
+    let not_found = || Flash::error(Redirect::to("/track"), NOT_FOUND_MESSAGE);
+    let ticket_number = lookup
+        .ticket_number
+        .trim()
+        .trim_start_matches('#')
+        .parse::<i64>()
+        .map_err(|_| not_found())?;
+    let contact_number = parse_contact_number(lookup.contact_number).ok_or_else(not_found)?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/track"), "Something went wrong"))?;
+    // same answer for a wrong number and a wrong contact so ticket numbers cannot be probed
+    let issue =
+        Issue::find_by_ticket_number(connection, &organisation.organisation.uuid, ticket_number)
+            .await
+            .map_err(|_| not_found())?;
+    if issue.contact_number != contact_number {
+        return Err(not_found());
+    }
+    Ok(Redirect::to(track_path(signer, &issue)))
+}
+
+// Displays status, public replies and timestamps of the ticket behind a signed link.
+#[get("/track/<token>", format = "text/html")]
+pub async fn track_issue(
+    mut db: Connection<DBConnection>,
+    token: &str,
+    signer: &State<Signer>,
+    limiter: &State<RateLimiter>,
+    ip: Option<IpAddr>,
+    organisation: CurrentOrganisation,
+) -> HtmlResponse {
+    if !limiter.check(VIEW_BUCKET, &client_key(ip), VIEW_LIMIT, VIEW_WINDOW) {
+        return Err(Status::TooManyRequests);
+    }
+    let uuid = signer
+        .verify(TRACK_PURPOSE, token)
+        .ok_or(Status::NotFound)?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let issue = Issue::find(connection, &organisation.organisation.uuid, &uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let replies = Comment::find_public_by_issue(connection, &issue.uuid)
+        .await
+        .map_err(|e| e.status)?
+        .into_iter()
+        .map(|comment| PublicReply {
+            author: comment.author,
+            body: comment.body,
+            created_at: comment.created_at,
+        })
+        .collect::<Vec<_>>();
+    let issue = PublicIssue {
+        ticket_number: issue.ticket_number,
+        issue_name: issue.issue_name,
+        description: issue.description,
+        status: issue.status,
+        created_at: issue.created_at,
+        updated_at: issue.updated_at,
+    };
+    let context = context! {issue, replies};
+    Ok(Template::render("track/show", context))
+}
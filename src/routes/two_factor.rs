@@ -0,0 +1,596 @@
+use super::session::{record_attempt, start_session};
+use super::HtmlResponse;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::guards::client::ClientInfo;
+use crate::guards::impersonation::NotImpersonating;
+use crate::guards::organisation::CurrentOrganisation;
+use crate::guards::role::{Admin, RequireRole};
+use crate::models::login_attempt::{METHOD_TOTP, OUTCOME_SUCCEEDED, OUTCOME_WRONG_CODE};
+use crate::models::recovery_code::RecoveryCode;
+use crate::models::session::Session;
+use crate::models::totp;
+use crate::models::user::{TotpCode, User};
+use crate::states::rate_limiter::RateLimiter;
+use crate::states::session_policy::SessionPolicy;
+use chrono::offset::Utc;
+use rocket::form::{Contextual, Form};
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::{json, Deserialize, Serialize};
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use std::time::Duration as StdDuration;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+const ISSUER: &str = "Ticket Desk";
+const PENDING_COOKIE_NAME: &str = "pending_login";
+const ENROLMENT_COOKIE_NAME: &str = "totp_enrolment";
+const PENDING_MINUTES: i64 = 10;
+const CODE_BUCKET: &str = "two_factor";
+const CODE_LIMIT: usize = 5;
+const CODE_WINDOW: StdDuration = StdDuration::from_secs(5 * 60);
+const INVALID_CODE_MESSAGE: &str = "Invalid code";
+
+// user who passed the password step and still owes the second factor
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PendingLogin {
+    user_uuid: String,
+    remember_me: bool,
+    next: String,
+    expires_at: i64,
+}
+
+pub fn start_pending_login(
+    cookies: &CookieJar<'_>,
+    user_uuid: &Uuid,
+    remember_me: bool,
+    next: &str,
+) {
+    let pending = PendingLogin {
+        user_uuid: user_uuid.to_string(),
+        remember_me,
+        next: next.to_string(),
+        expires_at: Utc::now().timestamp() + PENDING_MINUTES * 60,
+    };
+    if let Ok(value) = json::to_string(&pending) {
+        cookies.add_private(Cookie::new(PENDING_COOKIE_NAME, value));
+    }
+}
+
+fn pending_login(cookies: &CookieJar<'_>) -> Option<PendingLogin> {
+    let cookie = cookies.get_private(PENDING_COOKIE_NAME)?;
+    let pending = json::from_str::<PendingLogin>(cookie.value()).ok()?;
+    if pending.expires_at < Utc::now().timestamp() {
+        return None;
+    }
+    Some(pending)
+}
+
+// secret being enrolled, kept until the user proves their app has it
+fn enrolment_secret(cookies: &CookieJar<'_>) -> String {
+    if let Some(cookie) = cookies.get_private(ENROLMENT_COOKIE_NAME) {
+        return cookie.value().to_string();
+    }
+    let secret = totp::generate_secret();
+    let mut cookie = Cookie::new(ENROLMENT_COOKIE_NAME, secret.clone());
+    cookie.set_expires(OffsetDateTime::now_utc() + Duration::minutes(PENDING_MINUTES));
+    cookies.add_private(cookie);
+    secret
+}
+
+fn setup_context(user: &User, secret: &str) -> Result<(String, String), Status> {
+    let uri = totp::otpauth_uri(ISSUER, &user.username, secret);
+    let qr_code = totp::qr_code_svg(&uri).map_err(|e| e.status)?;
+    Ok((uri, qr_code))
+}
+
+// a TOTP code, or else one of the recovery codes
+async fn check_code(connection: &mut PgConnection, user: &User, code: &str) -> bool {
+    if let Some(secret) = user.totp_secret.as_ref() {
+        if let Some(step) = totp::verify(secret, code, user.totp_last_step) {
+            return User::record_totp_step(connection, &user.organisation_uuid, &user.uuid, step)
+                .await
+                .is_ok();
+        }
+    }
+    RecoveryCode::consume(connection, &user.uuid, code)
+        .await
+        .unwrap_or(false)
+}
+
+// turns on two-factor authentication from the enrolment cookie, returns the recovery codes
+async fn enable(
+    connection: &mut PgConnection,
+    cookies: &CookieJar<'_>,
+    user: &User,
+    code: &str,
+) -> Option<Vec<String>> {
+    let secret = cookies
+        .get_private(ENROLMENT_COOKIE_NAME)?
+        .value()
+        .to_string();
+    let step = totp::verify(&secret, code, None)?;
+    User::enable_totp(
+        connection,
+        &user.organisation_uuid,
+        &user.uuid,
+        &secret,
+        step,
+    )
+    .await
+    .ok()?;
+    let codes = RecoveryCode::regenerate(connection, &user.uuid)
+        .await
+        .ok()?;
+    cookies.remove_private(Cookie::named(ENROLMENT_COOKIE_NAME));
+    Some(codes)
+}
+
+// Second login step: asks for the code, or sets two-factor up when an admin requires it.
+#[get("/login/2fa", format = "text/html")]
+pub async fn login_form(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    organisation: CurrentOrganisation,
+) -> Result<Template, Flash<Redirect>> {
+    let expired = || Flash::error(Redirect::to("/login"), "Please log in again");
+    let pending = pending_login(cookies).ok_or_else(expired)?;
+    let connection = db.acquire().await.map_err(|_| expired())?;
+    let user = User::find(
+        connection,
+        &organisation.organisation.uuid,
+        &pending.user_uuid,
+    )
+    .await
+    .map_err(|_| expired())?;
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    if user.totp_enabled() {
+        let context = context! {
+            form_url: "/login/2fa",
+            flash: flash_string,
+            csrf_token,
+        };
+        return Ok(Template::render("two_factor/verify", context));
+    }
+    let secret = enrolment_secret(cookies);
+    let (uri, qr_code) = setup_context(&user, &secret).map_err(|_| expired())?;
+    let context = context! {
+        form_url: "/login/2fa/setup",
+        required: true,
+        secret,
+        uri,
+        qr_code,
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("two_factor/setup", context))
+}
+
+#[post(
+    "/login/2fa",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn login<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, TotpCode<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    limiter: &State<RateLimiter>,
+    policy: &State<SessionPolicy>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Redirect, Flash<Redirect>> {
+    let expired = || Flash::error(Redirect::to("/login"), "Please log in again");
+    let invalid = || Flash::error(Redirect::to("/login/2fa"), INVALID_CODE_MESSAGE);
+    let pending = pending_login(cookies).ok_or_else(expired)?;
+    // per account, codes are short enough to be guessed otherwise
+    if !limiter.check(CODE_BUCKET, &pending.user_uuid, CODE_LIMIT, CODE_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/login/2fa"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    let connection = db.acquire().await.map_err(|_| expired())?;
+    let user = User::find(
+        connection,
+        &organisation.organisation.uuid,
+        &pending.user_uuid,
+    )
+    .await
+    .map_err(|_| expired())?;
+    if !check_code(connection, &user, code.code).await {
+        record_attempt(
+            connection,
+            &organisation.organisation.uuid,
+            &user.username,
+            Some(&user.uuid),
+            &client,
+            METHOD_TOTP,
+            OUTCOME_WRONG_CODE,
+        )
+        .await;
+        return Err(invalid());
+    }
+    record_attempt(
+        connection,
+        &organisation.organisation.uuid,
+        &user.username,
+        Some(&user.uuid),
+        &client,
+        METHOD_TOTP,
+        OUTCOME_SUCCEEDED,
+    )
+    .await;
+    start_session(
+        connection,
+        cookies,
+        policy,
+        &client,
+        &user.uuid,
+        pending.remember_me,
+    )
+    .await
+    .map_err(|_| expired())?;
+    cookies.remove_private(Cookie::named(PENDING_COOKIE_NAME));
+    Ok(Redirect::to(pending.next))
+}
+
+// Finishes the enrolment an admin required, then logs the user in.
+#[post(
+    "/login/2fa/setup",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn login_setup<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, TotpCode<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    policy: &State<SessionPolicy>,
+    client: ClientInfo,
+    organisation: CurrentOrganisation,
+) -> Result<Template, Flash<Redirect>> {
+    let expired = || Flash::error(Redirect::to("/login"), "Please log in again");
+    let invalid = || Flash::error(Redirect::to("/login/2fa"), INVALID_CODE_MESSAGE);
+    let pending = pending_login(cookies).ok_or_else(expired)?;
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    let connection = db.acquire().await.map_err(|_| expired())?;
+    let user = User::find(
+        connection,
+        &organisation.organisation.uuid,
+        &pending.user_uuid,
+    )
+    .await
+    .map_err(|_| expired())?;
+    if user.totp_enabled() {
+        return Err(Flash::error(
+            Redirect::to("/login/2fa"),
+            "Two-factor authentication is already set up",
+        ));
+    }
+    let codes = match enable(connection, cookies, &user, code.code).await {
+        Some(codes) => codes,
+        None => {
+            record_attempt(
+                connection,
+                &organisation.organisation.uuid,
+                &user.username,
+                Some(&user.uuid),
+                &client,
+                METHOD_TOTP,
+                OUTCOME_WRONG_CODE,
+            )
+            .await;
+            return Err(invalid());
+        }
+    };
+    record_attempt(
+        connection,
+        &organisation.organisation.uuid,
+        &user.username,
+        Some(&user.uuid),
+        &client,
+        METHOD_TOTP,
+        OUTCOME_SUCCEEDED,
+    )
+    .await;
+    start_session(
+        connection,
+        cookies,
+        policy,
+        &client,
+        &user.uuid,
+        pending.remember_me,
+    )
+    .await
+    .map_err(|_| expired())?;
+    cookies.remove_private(Cookie::named(PENDING_COOKIE_NAME));
+    let context = context! {
+        codes,
+        continue_url: pending.next,
+    };
+    Ok(Template::render("two_factor/recovery_codes", context))
+}
+
+// Two-factor settings of the logged in user.
+#[get("/account/2fa", format = "text/html")]
+pub async fn account(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    if current_user.user.totp_enabled() {
+        let context = context! {
+            required: current_user.user.totp_required,
+            permissions: current_user.permissions(),
+            flash: flash_string,
+            csrf_token,
+        };
+        return Ok(Template::render("two_factor/account", context));
+    }
+    let secret = enrolment_secret(cookies);
+    let (uri, qr_code) = setup_context(&current_user.user, &secret)?;
+    let context = context! {
+        form_url: "/account/2fa",
+        required: current_user.user.totp_required,
+        secret,
+        uri,
+        qr_code,
+        permissions: current_user.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("two_factor/setup", context))
+}
+
+#[post(
+    "/account/2fa",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn enable_account<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, TotpCode<'r>>>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Template, Flash<Redirect>> {
+    let invalid = || Flash::error(Redirect::to("/account/2fa"), INVALID_CODE_MESSAGE);
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    if current_user.user.totp_enabled() {
+        return Err(Flash::error(
+            Redirect::to("/account/2fa"),
+            "Two-factor authentication is already set up",
+        ));
+    }
+    let connection = db.acquire().await.map_err(|_| invalid())?;
+    let codes = enable(connection, cookies, &current_user.user, code.code)
+        .await
+        .ok_or_else(invalid)?;
+    let context = context! {
+        codes,
+        continue_url: "/account/2fa",
+        csrf_token,
+        permissions: current_user.permissions(),
+    };
+    Ok(Template::render("two_factor/recovery_codes", context))
+}
+
+// Replaces the recovery codes, the old ones stop working.
+#[post(
+    "/account/2fa/recovery_codes",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn regenerate_codes<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, TotpCode<'r>>>,
+    csrf_token: CsrfToken,
+    limiter: &State<RateLimiter>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Template, Flash<Redirect>> {
+    let invalid = || Flash::error(Redirect::to("/account/2fa"), INVALID_CODE_MESSAGE);
+    let user = &current_user.user;
+    if !limiter.check(CODE_BUCKET, &user.uuid.to_string(), CODE_LIMIT, CODE_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/account/2fa"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    let connection = db.acquire().await.map_err(|_| invalid())?;
+    if !user.totp_enabled() || !check_code(connection, user, code.code).await {
+        return Err(invalid());
+    }
+    let codes = RecoveryCode::regenerate(connection, &user.uuid)
+        .await
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/account/2fa"),
+                "Something went wrong when creating recovery codes",
+            )
+        })?;
+    let context = context! {
+        codes,
+        continue_url: "/account/2fa",
+        csrf_token,
+        permissions: current_user.permissions(),
+    };
+    Ok(Template::render("two_factor/recovery_codes", context))
+}
+
+#[post(
+    "/account/2fa/disable",
+    format = "application/x-www-form-urlencoded",
+    data = "<code_context>"
+)]
+pub async fn disable_account<'r>(
+    mut db: Connection<DBConnection>,
+    code_context: Form<Contextual<'r, TotpCode<'r>>>,
+    csrf_token: CsrfToken,
+    limiter: &State<RateLimiter>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let invalid = || Flash::error(Redirect::to("/account/2fa"), INVALID_CODE_MESSAGE);
+    let user = &current_user.user;
+    if user.totp_required {
+        return Err(Flash::error(
+            Redirect::to("/account/2fa"),
+            "An admin requires two-factor authentication on your account",
+        ));
+    }
+    if !limiter.check(CODE_BUCKET, &user.uuid.to_string(), CODE_LIMIT, CODE_WINDOW) {
+        return Err(Flash::error(
+            Redirect::to("/account/2fa"),
+            "Too many attempts, please try again later",
+        ));
+    }
+    let code = code_context.value.as_ref().ok_or_else(invalid)?;
+    csrf_token
+        .verify(&code.authenticity_token)
+        .map_err(|_| invalid())?;
+    let connection = db.acquire().await.map_err(|_| invalid())?;
+    if !check_code(connection, user, code.code).await {
+        return Err(invalid());
+    }
+    let disabled = User::disable_totp(connection, &user.organisation_uuid, &user.uuid)
+        .await
+        .is_ok()
+        && RecoveryCode::destroy_all(connection, &user.uuid)
+            .await
+            .is_ok();
+    if !disabled {
+        return Err(Flash::error(
+            Redirect::to("/account/2fa"),
+            "Something went wrong when turning two-factor authentication off",
+        ));
+    }
+    Ok(Flash::success(
+        Redirect::to("/account/2fa"),
+        "Two-factor authentication is off",
+    ))
+}
+
+// Admin reset for a user who lost both the authenticator and the recovery codes.
+// Their sessions end, and they enrol again on next login when it is required.
+#[post(
+    "/users/2fa/reset/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<reset_context>"
+)]
+pub async fn reset<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    reset_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(user_url.clone()),
+            "Something went wrong when resetting two-factor authentication",
+        )
+    };
+    let form = reset_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&form.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let user = User::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    let reset = User::disable_totp(connection, organisation_uuid, &user.uuid)
+        .await
+        .is_ok()
+        && RecoveryCode::destroy_all(connection, &user.uuid)
+            .await
+            .is_ok()
+        && Session::revoke_all_for_user(connection, &user.uuid)
+            .await
+            .is_ok();
+    if !reset {
+        return Err(failed());
+    }
+    Ok(Flash::success(
+        Redirect::to(user_url.clone()),
+        format!("Two-factor authentication of {} was reset", user.username),
+    ))
+}
+
+// Makes two-factor authentication mandatory for the user, or optional again.
+#[post(
+    "/users/2fa/require/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<required_context>"
+)]
+pub async fn toggle_required<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    required_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(user_url.clone()),
+            "Something went wrong when changing two-factor requirement",
+        )
+    };
+    let form = required_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&form.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let user = User::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    let user = User::set_totp_required(connection, organisation_uuid, uuid, !user.totp_required)
+        .await
+        .map_err(|_| failed())?;
+    let message = if user.totp_required {
+        format!("{} must now use two-factor authentication", user.username)
+    } else {
+        format!(
+            "Two-factor authentication is optional for {}",
+            user.username
+        )
+    };
+    Ok(Flash::success(Redirect::to(user_url.clone()), message))
+}
@@ -1,113 +1,253 @@
 use super::HtmlResponse;
-use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::csrf::{CsrfForm, Token as CsrfToken};
 use crate::fairings::db::DBConnection;
 use crate::guards::auth::CurrentUser;
+use crate::guards::impersonation::NotImpersonating;
+use crate::guards::role::{Admin, RequireRole};
 use crate::models::{
-    pagination::Pagination,
-    user::{EditedUser, NewUser, User},
+    impersonation_event::ImpersonationEvent,
+    issues_reported::{Issue, UNASSIGNED_OWNER},
+    login_attempt::LoginAttempt,
+    session::Session,
+    user::{
+        BulkAction, BulkUserAction, EditedUser, RoleChange, StatusChange, User, UserRemoval,
+        UserSearch, ALL_SORTS, USERS_PER_PAGE,
+    },
+    user_preferences::UserPreferences,
+    user_role::{Permissions, UserRole, ALL_ROLES},
+    user_status::{UserStatus, ALL_STATUSES},
+    user_status_change::UserStatusChange,
+    webhook::{Webhook, ISSUE_ASSIGNED},
 };
+use crate::states::password_policy::PasswordPolicy;
 use rocket::form::{Contextual, Form};
 use rocket::http::Status;
 use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
 use rocket::serde::Serialize;
+use rocket::State;
 use rocket_db_pools::{sqlx::Acquire, Connection};
 use rocket_dyn_templates::{context, Template};
 
+const LOGIN_ATTEMPTS_SHOWN: i64 = 20;
+const IMPERSONATION_EVENTS_SHOWN: i64 = 50;
+
 #[get("/users/<uuid>", format = "text/html")]
 pub async fn get_user(
     mut db: Connection<DBConnection>,
     uuid: &str,
     flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
 ) -> HtmlResponse {
+    let permissions = current_user.permissions();
+    // supervisors come here to impersonate, the page only shows them the basics
+    if !current_user.can_manage_user(uuid) && !permissions.impersonate {
+        return Err(Status::Forbidden);
+    }
     let connection = db
         .acquire()
         .await
         .map_err(|_| Status::InternalServerError)?;
-    let user = User::find(connection, uuid).await.map_err(|e| e.status)?;
+    let user = User::find(connection, &current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    // the audit trail is for admins, not for the user looking at their own page
+    let (login_attempts, status_changes, impersonation_events) = if permissions.manage_users {
+        let login_attempts =
+            LoginAttempt::find_by_user(connection, &user.uuid, LOGIN_ATTEMPTS_SHOWN)
+                .await
+                .map_err(|e| e.status)?;
+        let status_changes = UserStatusChange::find_by_user(connection, &user.uuid)
+            .await
+            .map_err(|e| e.status)?;
+        let impersonation_events =
+            ImpersonationEvent::find_by_user(connection, &user.uuid, IMPERSONATION_EVENTS_SHOWN)
+                .await
+                .map_err(|e| e.status)?;
+        (login_attempts, status_changes, impersonation_events)
+    } else {
+        (Vec::new(), Vec::new(), Vec::new())
+    };
+    let can_impersonate = permissions.impersonate
+        && user.role < current_user.user.role
+        && user.status.can_log_in()
+        && user.deleted_at.is_none();
     #[derive(Serialize)]
     struct GetUser {
         user: User,
+        totp_enabled: bool,
+        locked: bool,
+        login_attempts: Vec<LoginAttempt>,
+        status_changes: Vec<UserStatusChange>,
+        impersonation_events: Vec<ImpersonationEvent>,
+        can_impersonate: bool,
+        roles: [UserRole; 3],
+        statuses: [UserStatus; 5],
+        permissions: Permissions,
+        preferences: UserPreferences,
         flash: Option<String>,
+        csrf_token: CsrfToken,
     }
     let flash_message = flash.map(|fm| String::from(fm.message()));
     let context = GetUser {
+        totp_enabled: user.totp_enabled(),
+        locked: user.is_locked(),
+        login_attempts,
+        status_changes,
+        impersonation_events,
+        can_impersonate,
         user,
+        roles: ALL_ROLES,
+        statuses: ALL_STATUSES,
+        permissions,
+        preferences: current_user.preferences.clone(),
         flash: flash_message,
+        csrf_token,
     };
     Ok(Template::render("users/show", &context))
 }
 
-#[get("/users?<pagination>", format = "text/html")]
+#[get("/users?<search..>", format = "text/html")]
 pub async fn get_users(
     mut db: Connection<DBConnection>,
-    pagination: Option<Pagination>,
+    search: UserSearch,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
 ) -> HtmlResponse {
-    let (users, new_pagination) = User::find_all(&mut db, pagination)
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let (users, total) = User::search(connection, &role.current_user.organisation.uuid, &search)
         .await
         .map_err(|e| e.status)?;
-    let context = context! {users: users, pagination: new_pagination.map(|pg|pg.to_context())};
-    Ok(Template::render("users/index", context))
-}
-
-#[get("/users/new", format = "text/html")]
-pub async fn new_user(flash: Option<FlashMessage<'_>>, csrf_token: CsrfToken) -> HtmlResponse {
-    let flash_string = flash
-        .map(|fl| format!("{}", fl.message()))
-        .unwrap_or_else(|| "".to_string());
+    let flash_message = flash.map(|fm| String::from(fm.message()));
     let context = context! {
-        edit: false,
-        form_url: "/users",
-        legend: "New User",
-        flash: flash_string,
+        users,
+        total,
+        page: search.page(),
+        // a count is never negative, the signed div_ceil is not stable
+        last_page: (total as u64).div_ceil(USERS_PER_PAGE as u64).max(1),
+        filters: search.filters_query(),
+        search,
+        statuses: ALL_STATUSES,
+        roles: ALL_ROLES,
+        sorts: ALL_SORTS,
+        current_user_uuid: role.current_user.user.uuid,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
         csrf_token,
     };
-    Ok(Template::render("users/form", context))
+    Ok(Template::render("users/index", context))
 }
 
+// Activates, suspends or changes the role of every user ticked on the index at once.
 #[post(
-    "/users",
+    "/users/bulk",
     format = "application/x-www-form-urlencoded",
-    data = "<user_context>"
+    data = "<bulk_context>"
 )]
-pub async fn create_user<'r>(
+pub async fn bulk_update<'r>(
     mut db: Connection<DBConnection>,
-    user_context: Form<Contextual<'r, NewUser<'r>>>,
+    bulk_context: Form<Contextual<'r, BulkUserAction<'r>>>,
     csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    if user_context.value.is_none() {
-        let error_message = user_context
+    let failed = || {
+        Flash::error(
+            Redirect::to("/users"),
+            "Something went wrong when updating users",
+        )
+    };
+    if bulk_context.value.is_none() {
+        let error_message = bulk_context
             .context
             .errors()
             .map(|e| e.to_string())
             .collect::<Vec<_>>()
             .join("<br/>");
-        return Err(Flash::error(Redirect::to("/users/new"), error_message));
+        return Err(Flash::error(Redirect::to("/users"), error_message));
     }
-    let new_user = user_context.value.as_ref().unwrap();
+    let bulk = bulk_context.value.as_ref().unwrap();
     csrf_token
-        .verify(&new_user.authenticity_token)
-        .map_err(|_| {
-            Flash::error(
-                Redirect::to("/users/new"),
-                "Something went wrong when creating user",
-            )
-        })?;
-    let connection = db.acquire().await.map_err(|_| {
-        Flash::error(
-            Redirect::to("/users/new"),
-            "Something went wrong when creating user",
-        )
-    })?;
-    let user = User::create(connection, new_user).await.map_err(|_| {
-        Flash::error(
-            Redirect::to("/users/new"),
-            "Something went wrong when creating user",
-        )
-    })?;
+        .verify(&bulk.authenticity_token)
+        .map_err(|_| failed())?;
+    let new_role = match (bulk.action, bulk.role) {
+        (BulkAction::ChangeRole, None) => {
+            return Err(Flash::error(Redirect::to("/users"), "Pick the new role"))
+        }
+        (_, new_role) => new_role,
+    };
+    let admin_uuid = role.current_user.user.uuid;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    // an admin changing themselves could leave nobody able to manage users
+    if bulk.users.contains(&admin_uuid) {
+        return Err(Flash::error(
+            Redirect::to("/users"),
+            "You cannot change your own status or role",
+        ));
+    }
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    // all of them or none, a half applied bulk action is hard to spot
+    let mut transaction = connection.begin().await.map_err(|_| failed())?;
+    let mut changed = 0;
+    for uuid in bulk.users.iter() {
+        let user = User::find(&mut transaction, organisation_uuid, &uuid.to_string())
+            .await
+            .map_err(|_| failed())?;
+        if user.deleted_at.is_some() {
+            continue;
+        }
+        match bulk.action {
+            BulkAction::Activate | BulkAction::Suspend => {
+                let status = if bulk.action == BulkAction::Activate {
+                    UserStatus::Active
+                } else {
+                    UserStatus::Suspended
+                };
+                if user.status == status {
+                    continue;
+                }
+                User::change_status(
+                    &mut transaction,
+                    organisation_uuid,
+                    &user.uuid,
+                    status,
+                    Some(bulk.reason),
+                    Some(&admin_uuid),
+                )
+                .await
+                .map_err(|_| failed())?;
+                if !status.can_log_in() {
+                    Session::revoke_all_for_user(&mut transaction, &user.uuid)
+                        .await
+                        .map_err(|_| failed())?;
+                }
+            }
+            BulkAction::ChangeRole => {
+                let new_role = new_role.unwrap_or(user.role);
+                if user.role == new_role {
+                    continue;
+                }
+                User::set_role(
+                    &mut transaction,
+                    organisation_uuid,
+                    &user.uuid.to_string(),
+                    new_role,
+                )
+                .await
+                .map_err(|_| failed())?;
+            }
+        }
+        changed += 1;
+    }
+    transaction.commit().await.map_err(|_| failed())?;
     Ok(Flash::success(
-        Redirect::to(format!("/users/{}", user.uuid)),
-        "Successfully created user",
+        Redirect::to("/users"),
+        format!("Updated {} user(s)", changed),
     ))
 }
 
@@ -117,7 +257,12 @@ pub async fn edit_user(
     flash: Option<FlashMessage<'_>>,
     csrf_token: CsrfToken,
     current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
 ) -> HtmlResponse {
+    // password changes need the old password, so only the account owner edits it
+    if current_user.user.uuid.to_string() != uuid {
+        return Err(Status::Forbidden);
+    }
     let flash_string = flash
         .map(|fl| format!("{}", fl.message()))
         .unwrap_or_else(|| "".to_string());
@@ -128,6 +273,7 @@ pub async fn edit_user(
         flash: flash_string,
         user: &current_user.user,
         current_user: &current_user,
+        permissions: current_user.permissions(),
         csrf_token,
     };
 
@@ -144,7 +290,9 @@ pub async fn update_user<'r>(
     uuid: &str,
     user_context: Form<Contextual<'r, EditedUser<'r>>>,
     csrf_token: CsrfToken,
+    policy: &State<PasswordPolicy>,
     current_user: CurrentUser,
+    not_impersonating: NotImpersonating,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     if user_context.value.is_none() {
         let error_message = user_context
@@ -160,8 +308,30 @@ pub async fn update_user<'r>(
     }
     let user_value = user_context.value.as_ref().unwrap();
     match user_value.method {
-        "PUT" => put_user(db, uuid, user_context, csrf_token, current_user).await,
-        "PATCH" => patch_user(db, uuid, user_context, csrf_token, current_user).await,
+        "PUT" => {
+            put_user(
+                db,
+                uuid,
+                user_context,
+                csrf_token,
+                policy,
+                current_user,
+                not_impersonating,
+            )
+            .await
+        }
+        "PATCH" => {
+            patch_user(
+                db,
+                uuid,
+                user_context,
+                csrf_token,
+                policy,
+                current_user,
+                not_impersonating,
+            )
+            .await
+        }
         _ => Err(Flash::error(
             Redirect::to(format!("/users/edit/{}", uuid)),
             "Something went wrong when updating user",
@@ -179,8 +349,16 @@ pub async fn put_user<'r>(
     uuid: &str,
     user_context: Form<Contextual<'r, EditedUser<'r>>>,
     csrf_token: CsrfToken,
-    _current_user: CurrentUser,
+    policy: &State<PasswordPolicy>,
+    current_user: CurrentUser,
+    _not_impersonating: NotImpersonating,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if current_user.user.uuid.to_string() != uuid {
+        return Err(Flash::error(
+            Redirect::to(format!("/users/{}", current_user.user.uuid)),
+            "You can only edit your own account",
+        ));
+    }
     let user_value = user_context.value.as_ref().unwrap();
     csrf_token
         .verify(&user_value.authenticity_token)
@@ -190,7 +368,26 @@ pub async fn put_user<'r>(
                 "Something went wrong when updating user",
             )
         })?;
-    let user = User::update(&mut db, uuid, user_value).await.map_err(|_| {
+    if !user_value.old_password.is_empty() {
+        policy
+            .check_new_password(
+                user_value.password,
+                &[user_value.username, user_value.email],
+            )
+            .await
+            .map_err(|message| {
+                Flash::error(Redirect::to(format!("/users/edit/{}", uuid)), message)
+            })?;
+    }
+    let user = User::update(
+        &mut db,
+        &current_user.organisation.uuid,
+        uuid,
+        user_value,
+        policy,
+    )
+    .await
+    .map_err(|_| {
         Flash::error(
             Redirect::to(format!("/users/edit/{}", uuid)),
             "Something went wrong when updating user",
@@ -212,44 +409,370 @@ pub async fn patch_user<'r>(
     uuid: &str,
     user_context: Form<Contextual<'r, EditedUser<'r>>>,
     csrf_token: CsrfToken,
+    policy: &State<PasswordPolicy>,
     current_user: CurrentUser,
+    not_impersonating: NotImpersonating,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    put_user(db, uuid, user_context, csrf_token, current_user).await
+    put_user(
+        db,
+        uuid,
+        user_context,
+        csrf_token,
+        policy,
+        current_user,
+        not_impersonating,
+    )
+    .await
+}
+
+#[get("/users/delete/<uuid>", format = "text/html")]
+pub async fn remove_user_form(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let user = User::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    if user.deleted_at.is_some() {
+        return Err(Status::NotFound);
+    }
+    let ticket_count = Issue::count_by_owner(connection, organisation_uuid, &user.username)
+        .await
+        .map_err(|e| e.status)?;
+    let inheritors = User::find_inheritors(connection, organisation_uuid, &user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        user,
+        ticket_count,
+        inheritors,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("users/delete", context))
 }
 
 #[post(
     "/users/delete/<uuid>",
     format = "application/x-www-form-urlencoded",
+    data = "<removal_context>",
     rank = 2
 )]
-pub async fn delete_user_entry_point(
+pub async fn delete_user_entry_point<'r>(
     db: Connection<DBConnection>,
     uuid: &str,
-    current_user: CurrentUser,
+    removal_context: Form<Contextual<'r, UserRemoval<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    delete_user(db, uuid, current_user).await
+    delete_user(db, uuid, removal_context, csrf_token, role).await
 }
 
-#[delete("/users/<uuid>", format = "application/x-www-form-urlencoded")]
-pub async fn delete_user(
+// Ends every session of the user, for someone leaving or a lost device.
+#[post(
+    "/users/logout/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<logout_context>"
+)]
+pub async fn force_logout<'r>(
     mut db: Connection<DBConnection>,
     uuid: &str,
-    _current_user: CurrentUser,
+    logout_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(user_url.clone()),
+            "Something went wrong when logging user out",
+        )
+    };
+    let logout = logout_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&logout.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let user = User::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/users"), "User not found"))?;
+    let revoked = Session::revoke_all_for_user(connection, &user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(user_url),
+        format!("Ended {} session(s) of {}", revoked, user.username),
+    ))
+}
+
+// Lifts a lockout after failed logins before it runs out.
+#[post(
+    "/users/unlock/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<unlock_context>"
+)]
+pub async fn unlock_user<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    unlock_context: Form<Contextual<'r, CsrfForm<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(user_url.clone()),
+            "Something went wrong when unlocking user",
+        )
+    };
+    let unlock = unlock_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&unlock.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let user = User::unlock(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| Flash::error(Redirect::to("/users"), "User not found"))?;
+    Ok(Flash::success(
+        Redirect::to(user_url),
+        format!("{} can log in again", user.username),
+    ))
+}
+
+#[post(
+    "/users/role/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<role_context>"
+)]
+pub async fn change_role<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    role_context: Form<Contextual<'r, RoleChange<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let user_url = format!("/users/{}", uuid);
+    let role_change = role_context
+        .value
+        .as_ref()
+        .ok_or_else(|| Flash::error(Redirect::to(user_url.clone()), "Invalid role"))?;
+    csrf_token
+        .verify(&role_change.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to(user_url.clone()),
+                "Something went wrong when changing role",
+            )
+        })?;
+    // an admin demoting themselves could leave nobody able to manage users
+    if role.current_user.user.uuid.to_string() == uuid {
+        return Err(Flash::error(
+            Redirect::to(user_url),
+            "You cannot change your own role",
+        ));
+    }
     let connection = db.acquire().await.map_err(|_| {
         Flash::error(
-            Redirect::to("/users"),
-            "Something went wrong when deleting user",
+            Redirect::to(user_url.clone()),
+            "Something went wrong when changing role",
         )
     })?;
-    User::destroy(connection, uuid).await.map_err(|_| {
+    let user = User::set_role(
+        connection,
+        &role.current_user.organisation.uuid,
+        uuid,
+        role_change.role,
+    )
+    .await
+    .map_err(|_| {
         Flash::error(
-            Redirect::to("/users"),
-            "Something went wrong when deleting user",
+            Redirect::to(user_url.clone()),
+            "Something went wrong when changing role",
         )
     })?;
+    Ok(Flash::success(
+        Redirect::to(user_url),
+        format!("{} is now {}", user.username, user.role),
+    ))
+}
+
+// Suspends, locks, deactivates or reactivates a user, recording who did it and why.
+#[post(
+    "/users/status/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<status_context>"
+)]
+pub async fn change_status<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    status_context: Form<Contextual<'r, StatusChange<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/users"),
+            "Something went wrong when changing status",
+        )
+    };
+    if status_context.value.is_none() {
+        let error_message = status_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to("/users"), error_message));
+    }
+    let status_change = status_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&status_change.authenticity_token)
+        .map_err(|_| failed())?;
+    // an admin suspending themselves could leave nobody able to manage users
+    if role.current_user.user.uuid.to_string() == uuid {
+        return Err(Flash::error(
+            Redirect::to("/users"),
+            "You cannot change your own status",
+        ));
+    }
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let user = User::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    let user = User::change_status(
+        connection,
+        organisation_uuid,
+        &user.uuid,
+        status_change.status,
+        Some(status_change.reason),
+        Some(&role.current_user.user.uuid),
+    )
+    .await
+    .map_err(|_| failed())?;
+    if !user.status.can_log_in()
+        && Session::revoke_all_for_user(connection, &user.uuid)
+            .await
+            .is_err()
+    {
+        log::error!("Cannot revoke sessions of {}", user.uuid);
+    }
+    Ok(Flash::success(
+        Redirect::to("/users"),
+        format!("{} is now {}", user.username, user.status),
+    ))
+}
+
+// Hands the tickets of a user to somebody else, then deactivates and tombstones the user.
+#[delete(
+    "/users/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<removal_context>"
+)]
+pub async fn delete_user<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    removal_context: Form<Contextual<'r, UserRemoval<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let form_url = format!("/users/delete/{}", uuid);
+    let failed = || {
+        Flash::error(
+            Redirect::to(form_url.clone()),
+            "Something went wrong when removing user",
+        )
+    };
+    if removal_context.value.is_none() {
+        let error_message = removal_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to(form_url.clone()), error_message));
+    }
+    let removal = removal_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&removal.authenticity_token)
+        .map_err(|_| failed())?;
+    if role.current_user.user.uuid.to_string() == uuid {
+        return Err(Flash::error(
+            Redirect::to(form_url.clone()),
+            "You cannot remove yourself",
+        ));
+    }
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    let organisation_uuid = &role.current_user.organisation.uuid;
+    let user = User::find(connection, organisation_uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    let inheritor = User::find(connection, organisation_uuid, removal.inheritor)
+        .await
+        .ok()
+        .filter(|inheritor| {
+            inheritor.uuid != user.uuid
+                && inheritor.deleted_at.is_none()
+                && inheritor.status == UserStatus::Active
+        })
+        .ok_or_else(|| {
+            Flash::error(
+                Redirect::to(form_url.clone()),
+                "Pick an active user to take over the tickets",
+            )
+        })?;
+    // the tickets move and the user goes together, or neither does
+    let mut transaction = connection.begin().await.map_err(|_| failed())?;
+    let issues = Issue::reassign_owner(
+        &mut transaction,
+        organisation_uuid,
+        &user.username,
+        &inheritor.username,
+        &inheritor.uuid,
+    )
+    .await
+    .map_err(|_| failed())?;
+    User::change_status(
+        &mut transaction,
+        organisation_uuid,
+        &user.uuid,
+        UserStatus::Deactivated,
+        Some(removal.reason),
+        Some(&role.current_user.user.uuid),
+    )
+    .await
+    .map_err(|_| failed())?;
+    User::tombstone(&mut transaction, organisation_uuid, &user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    Session::revoke_all_for_user(&mut transaction, &user.uuid)
+        .await
+        .map_err(|_| failed())?;
+    transaction.commit().await.map_err(|_| failed())?;
+    for issue in &issues {
+        Webhook::dispatch(connection, ISSUE_ASSIGNED, issue).await;
+    }
+    let requeued = issues
+        .iter()
+        .filter(|issue| issue.ticket_owner == UNASSIGNED_OWNER)
+        .count();
     Ok(Flash::success(
         Redirect::to("/users"),
-        "Successfully deleted user",
+        format!(
+            "Removed {}, {} ticket(s) now belong to {} and {} went back to their team queue",
+            user.username,
+            issues.len() - requeued,
+            inheritor.username,
+            requeued
+        ),
     ))
 }
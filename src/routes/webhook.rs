@@ -0,0 +1,220 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::role::{Admin, RequireRole};
+use crate::models::webhook::{
+    NewWebhook, Webhook, WebhookAction, ALL_EVENTS, SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+use crate::models::webhook_delivery::WebhookDelivery;
+use rocket::form::{Contextual, Form};
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+
+const DELIVERY_LOG_LIMIT: i32 = 50;
+
+#[get("/webhooks", format = "text/html")]
+pub async fn get_webhooks(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let webhooks = Webhook::find_all(connection, &role.current_user.organisation.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        webhooks: webhooks,
+        csrf_token,
+        permissions: role.permissions(),
+        flash: flash_message,
+    };
+    Ok(Template::render("webhooks/index", context))
+}
+
+#[get("/webhooks/<uuid>", format = "text/html")]
+pub async fn get_webhook(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let webhook = Webhook::find(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let deliveries = WebhookDelivery::find_by_webhook(connection, &webhook.uuid, DELIVERY_LOG_LIMIT)
+        .await
+        .map_err(|e| e.status)?;
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let context = context! {
+        webhook,
+        deliveries,
+        signature_header: SIGNATURE_HEADER,
+        timestamp_header: TIMESTAMP_HEADER,
+        permissions: role.permissions(),
+        preferences: &role.current_user.preferences,
+        flash: flash_message,
+        csrf_token,
+    };
+    Ok(Template::render("webhooks/show", context))
+}
+
+#[get("/webhooks/new", format = "text/html")]
+pub async fn new_webhook(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> HtmlResponse {
+    let flash_string = flash
+        .map(|fl| format!("{}", fl.message()))
+        .unwrap_or_else(|| "".to_string());
+    let context = context! {
+        form_url: "/webhooks",
+        legend: "New Webhook",
+        events: ALL_EVENTS,
+        permissions: role.permissions(),
+        flash: flash_string,
+        csrf_token,
+    };
+    Ok(Template::render("webhooks/form", context))
+}
+
+#[post(
+    "/webhooks",
+    format = "application/x-www-form-urlencoded",
+    data = "<webhook_context>"
+)]
+pub async fn create_webhook<'r>(
+    mut db: Connection<DBConnection>,
+    webhook_context: Form<Contextual<'r, NewWebhook<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    if webhook_context.value.is_none() {
+        let error_message = webhook_context
+            .context
+            .errors()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        return Err(Flash::error(Redirect::to("/webhooks/new"), error_message));
+    }
+    let new_webhook = webhook_context.value.as_ref().unwrap();
+    csrf_token
+        .verify(&new_webhook.authenticity_token)
+        .map_err(|_| {
+            Flash::error(
+                Redirect::to("/webhooks/new"),
+                "Something went wrong when creating webhook",
+            )
+        })?;
+    let connection = db.acquire().await.map_err(|_| {
+        Flash::error(
+            Redirect::to("/webhooks/new"),
+            "Something went wrong when creating webhook",
+        )
+    })?;
+    let webhook = Webhook::create(
+        connection,
+        &role.current_user.organisation.uuid,
+        new_webhook,
+    )
+    .await
+    .map_err(|_| {
+        Flash::error(
+            Redirect::to("/webhooks/new"),
+            "Something went wrong when creating webhook",
+        )
+    })?;
+    Ok(Flash::success(
+        Redirect::to(format!("/webhooks/{}", webhook.uuid)),
+        "Successfully created webhook",
+    ))
+}
+
+#[post(
+    "/webhooks/delete/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<action_context>"
+)]
+pub async fn delete_webhook<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    action_context: Form<Contextual<'r, WebhookAction<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/webhooks"),
+            "Something went wrong when deleting webhook",
+        )
+    };
+    let action = action_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&action.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    Webhook::destroy(connection, &role.current_user.organisation.uuid, uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to("/webhooks"),
+        "Successfully deleted webhook",
+    ))
+}
+
+#[post(
+    "/webhooks/deliveries/redeliver/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<action_context>"
+)]
+pub async fn redeliver<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    action_context: Form<Contextual<'r, WebhookAction<'r>>>,
+    csrf_token: CsrfToken,
+    role: RequireRole<Admin>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let failed = || {
+        Flash::error(
+            Redirect::to("/webhooks"),
+            "Something went wrong when queueing the delivery",
+        )
+    };
+    let action = action_context.value.as_ref().ok_or_else(failed)?;
+    csrf_token
+        .verify(&action.authenticity_token)
+        .map_err(|_| failed())?;
+    let connection = db.acquire().await.map_err(|_| failed())?;
+    // only deliveries of the organisation's own webhooks
+    let delivery = WebhookDelivery::find(connection, uuid)
+        .await
+        .map_err(|_| failed())?;
+    Webhook::find(
+        connection,
+        &role.current_user.organisation.uuid,
+        &delivery.webhook_uuid.to_string(),
+    )
+    .await
+    .map_err(|_| failed())?;
+    let delivery = WebhookDelivery::redeliver(connection, uuid)
+        .await
+        .map_err(|_| failed())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/webhooks/{}", delivery.webhook_uuid)),
+        "Delivery queued again",
+    ))
+}
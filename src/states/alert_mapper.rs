@@ -0,0 +1,71 @@
+use crate::models::alert::Alert;
+
+// ticket fields produced from an alert
+pub struct MappedIssue {
+    pub issue_name: String,
+    pub description: String,
+    pub reported_by: String,
+    pub company_name: String,
+    pub contact_number: String,
+}
+
+// managed state holding the configured templates used to turn alerts into tickets
+pub struct AlertMapper {
+    title_template: String,
+    description_template: String,
+    reported_by_template: String,
+    company_name_template: String,
+    contact_number: i64,
+}
+
+impl AlertMapper {
+    pub fn new(
+        title_template: String,
+        description_template: String,
+        reported_by_template: String,
+        company_name_template: String,
+        contact_number: i64,
+    ) -> Self {
+        Self {
+            title_template,
+            description_template,
+            reported_by_template,
+            company_name_template,
+            contact_number,
+        }
+    }
+
+    pub fn map(&self, alert: &Alert) -> MappedIssue {
+        MappedIssue {
+            issue_name: non_empty(alert.fill(&self.title_template), &alert.fingerprint),
+            description: non_empty(alert.fill(&self.description_template), "Alert received"),
+            reported_by: non_empty(alert.fill(&self.reported_by_template), "Monitoring"),
+            company_name: non_empty(alert.fill(&self.company_name_template), "Unknown"),
+            contact_number: self.contact_number.to_string(),
+        }
+    }
+
+    // text of the comment appended to the ticket every time the alert is received again
+    pub fn describe(&self, alert: &Alert, verb: &str) -> String {
+        let mut lines = vec![format!(
+            "Alert {}: {}",
+            verb,
+            alert.fill(&self.title_template)
+        )];
+        if let Some(starts_at) = &alert.starts_at {
+            lines.push(format!("Started at {}", starts_at));
+        }
+        if let Some(url) = &alert.generator_url {
+            lines.push(format!("Source: {}", url));
+        }
+        lines.join("\n")
+    }
+}
+
+fn non_empty(value: String, fallback: &str) -> String {
+    if value.trim().is_empty() {
+        String::from(fallback)
+    } else {
+        value
+    }
+}
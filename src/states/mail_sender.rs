@@ -0,0 +1,42 @@
+use crate::traits::mailer::{Mailer, OutgoingMail};
+
+// managed state used by the routes to email people, links in emails start with base_url
+// where {subdomain} is replaced by the one of the organisation the email is about
+pub struct MailSender {
+    mailer: Box<dyn Mailer>,
+    base_url: String,
+}
+
+impl MailSender {
+    pub fn new(mailer: Box<dyn Mailer>, base_url: String) -> Self {
+        Self {
+            mailer,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // absolute url of a path of this application, as served for the organisation on subdomain
+    pub fn url(&self, subdomain: &str, path: &str) -> String {
+        format!(
+            "{}{}",
+            self.base_url.replace("{subdomain}", subdomain),
+            path
+        )
+    }
+
+    // tells whether the email went out, callers decide how much that matters
+    pub async fn send(&self, to: &str, subject: &str, body: &str) -> bool {
+        let mail = OutgoingMail {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        };
+        match self.mailer.send(&mail).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Cannot email {}: {}", to, e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub mod alert_mapper;
+pub mod mail_sender;
+pub mod oidc_client;
+pub mod password_policy;
+pub mod sms_notifier;
+pub mod rate_limiter;
+pub mod session_policy;
+pub mod signer;
+pub mod tenancy;
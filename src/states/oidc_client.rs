@@ -0,0 +1,302 @@
+use crate::errors::our_error::OurError;
+use crate::guards::api_token::constant_time_eq;
+use crate::models::organisation::Organisation;
+use crate::models::user_role::UserRole;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use rocket::serde::json::Value;
+use rocket::serde::Deserialize;
+use rocket::tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+// asymmetric only, a token signed with the client secret or not signed at all is refused
+const ALLOWED_ALGORITHMS: [Algorithm; 6] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::ES256,
+    Algorithm::ES384,
+];
+
+// the oidc section of Rocket.toml, checked by the fairing
+pub struct OidcSettings {
+    // subdomain of the one organisation whose staff the provider signs in
+    pub organisation: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    pub username_claim: String,
+    pub role_claim: String,
+    // claim value to role, the highest matching role wins
+    pub role_mapping: HashMap<String, UserRole>,
+    // role of users matching no mapping, None refuses them
+    pub default_role: Option<UserRole>,
+    pub label: String,
+    // password login for admins only, everybody else goes through the provider
+    pub break_glass_only: bool,
+}
+
+// endpoints read from the discovery document
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Provider {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TokenResponse {
+    id_token: String,
+}
+
+// what a validated ID token tells about the user
+pub struct Identity {
+    pub subject: String,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub role: Option<UserRole>,
+}
+
+// Managed state talking to the company identity provider, disabled without configuration.
+// Discovery and keys are fetched on first use, so the application starts while the provider is down.
+pub struct OidcClient {
+    settings: Option<OidcSettings>,
+    http: reqwest::Client,
+    provider: RwLock<Option<Provider>>,
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+impl OidcClient {
+    pub fn new(settings: Option<OidcSettings>) -> Result<Self, OurError> {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|e| {
+                OurError::new_internal_server_error(
+                    String::from("Cannot build OIDC client"),
+                    Some(Box::new(e)),
+                )
+            })?;
+        Ok(Self {
+            settings,
+            http,
+            provider: RwLock::new(None),
+            jwks: RwLock::new(None),
+        })
+    }
+
+    // the settings when the provider signs in the staff of the organisation, None for the others
+    pub fn settings_for(&self, organisation: &Organisation) -> Option<&OidcSettings> {
+        self.settings
+            .as_ref()
+            .filter(|settings| settings.organisation == organisation.subdomain)
+    }
+
+    pub fn break_glass_only(&self, organisation: &Organisation) -> bool {
+        self.settings_for(organisation)
+            .map(|settings| settings.break_glass_only)
+            .unwrap_or(false)
+    }
+
+    fn configured(&self) -> Result<&OidcSettings, OurError> {
+        self.settings.as_ref().ok_or_else(|| {
+            OurError::new_not_found_error(String::from("Single sign-on is not configured"), None)
+        })
+    }
+
+    async fn provider(&self) -> Result<Provider, OurError> {
+        if let Some(provider) = self.provider.read().await.as_ref() {
+            return Ok(provider.clone());
+        }
+        let settings = self.configured()?;
+        let url = format!(
+            "{}{}",
+            settings.issuer_url.trim_end_matches('/'),
+            DISCOVERY_PATH
+        );
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(oidc_error)?;
+        let provider = response.json::<Provider>().await.map_err(oidc_error)?;
+        // the document of another issuer would make every token check pointless
+        if provider.issuer.trim_end_matches('/') != settings.issuer_url.trim_end_matches('/') {
+            return Err(OurError::new_internal_server_error(
+                String::from("OIDC discovery issuer mismatch"),
+                None,
+            ));
+        }
+        *self.provider.write().await = Some(provider.clone());
+        Ok(provider)
+    }
+
+    // where to send the browser, PKCE challenge included
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_challenge: &str,
+    ) -> Result<String, OurError> {
+        let settings = self.configured()?;
+        let provider = self.provider().await?;
+        let url = Url::parse_with_params(
+            &provider.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", settings.client_id.as_str()),
+                ("redirect_uri", settings.redirect_url.as_str()),
+                ("scope", settings.scopes.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(oidc_error)?;
+        Ok(url.to_string())
+    }
+
+    // trades the code for an ID token and validates its signature, issuer, audience, expiry and nonce
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<Identity, OurError> {
+        let settings = self.configured()?;
+        let provider = self.provider().await?;
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", settings.redirect_url.as_str()),
+            ("client_id", settings.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        let mut request = self.http.post(&provider.token_endpoint).form(&form);
+        if let Some(client_secret) = settings.client_secret.as_ref() {
+            request = request.basic_auth(&settings.client_id, Some(client_secret));
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(oidc_error)?;
+        let token = response.json::<TokenResponse>().await.map_err(oidc_error)?;
+        let header = decode_header(&token.id_token).map_err(oidc_error)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(OurError::new_bad_request_error(
+                String::from("ID token algorithm not allowed"),
+                None,
+            ));
+        }
+        let key = self
+            .decoding_key(&provider.jwks_uri, header.kid.as_deref())
+            .await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[settings.client_id.as_str()]);
+        validation.set_issuer(&[provider.issuer.as_str()]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<HashMap<String, Value>>(&token.id_token, &key, &validation)
+            .map_err(oidc_error)?
+            .claims;
+        let token_nonce = claims.get("nonce").and_then(Value::as_str).unwrap_or("");
+        if !constant_time_eq(token_nonce.as_bytes(), nonce.as_bytes()) {
+            return Err(OurError::new_bad_request_error(
+                String::from("ID token nonce mismatch"),
+                None,
+            ));
+        }
+        identity(settings, &claims)
+    }
+
+    async fn decoding_key(
+        &self,
+        jwks_uri: &str,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, OurError> {
+        if let Some(key) = self.find_key(kid).await {
+            return Ok(key);
+        }
+        // unknown key id, the provider may have rotated its keys
+        let response = self
+            .http
+            .get(jwks_uri)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(oidc_error)?;
+        let jwks = response.json::<JwkSet>().await.map_err(oidc_error)?;
+        *self.jwks.write().await = Some(jwks);
+        self.find_key(kid).await.ok_or_else(|| {
+            OurError::new_bad_request_error(
+                String::from("ID token signed with an unknown key"),
+                None,
+            )
+        })
+    }
+
+    async fn find_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let jwks = self.jwks.read().await;
+        let jwks = jwks.as_ref()?;
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid)?,
+            None => jwks.keys.first()?,
+        };
+        DecodingKey::from_jwk(jwk).ok()
+    }
+}
+
+fn identity(
+    settings: &OidcSettings,
+    claims: &HashMap<String, Value>,
+) -> Result<Identity, OurError> {
+    let claim = |name: &str| claims.get(name).and_then(Value::as_str).map(str::to_string);
+    let missing = |name: &str| {
+        OurError::new_bad_request_error(format!("ID token without {} claim", name), None)
+    };
+    let subject = claim("sub").ok_or_else(|| missing("sub"))?;
+    let email = claim("email").ok_or_else(|| missing("email"))?;
+    let username = claim(&settings.username_claim)
+        .or_else(|| email.split('@').next().map(str::to_string))
+        .ok_or_else(|| missing(&settings.username_claim))?;
+    let email_verified = claims
+        .get("email_verified")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    // groups usually come as a list, some providers send a single string
+    let values = match claims.get(&settings.role_claim) {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect::<Vec<_>>(),
+        Some(Value::String(value)) => vec![value.as_str()],
+        _ => Vec::new(),
+    };
+    let role = values
+        .iter()
+        .filter_map(|value| settings.role_mapping.get(*value).copied())
+        .max()
+        .or(settings.default_role);
+    Ok(Identity {
+        subject,
+        username,
+        email,
+        email_verified,
+        role,
+    })
+}
+
+fn oidc_error<E: std::error::Error + 'static>(e: E) -> OurError {
+    OurError::new_internal_server_error(String::from("OIDC request failed"), Some(Box::new(e)))
+}
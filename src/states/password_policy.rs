@@ -0,0 +1,225 @@
+use crate::errors::our_error::OurError;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rocket::tokio::fs;
+use sha1::{Digest, Sha1};
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use zxcvbn::zxcvbn;
+
+pub const WEAK_PASSWORD_MESSAGE: &str = "weak password";
+pub const BREACHED_PASSWORD_MESSAGE: &str =
+    "this password appeared in a data breach, please choose another one";
+const MIN_SCORE: u8 = 3;
+const PREFIX_LENGTH: usize = 5;
+
+// Managed state hashing passwords with the configured Argon2 cost and deciding
+// whether a new password is acceptable.
+pub struct PasswordPolicy {
+    params: Params,
+    // one file per SHA-1 prefix, as served by k-anonymity range APIs
+    breached_passwords_dir: Option<PathBuf>,
+    // compared against when the username is unknown, so timing does not tell
+    dummy_hash: String,
+}
+
+impl PasswordPolicy {
+    pub fn new(params: Params, breached_passwords_dir: Option<PathBuf>) -> Result<Self, OurError> {
+        let mut policy = Self {
+            params,
+            breached_passwords_dir,
+            dummy_hash: String::new(),
+        };
+        policy.dummy_hash = policy.hash("not a real password")?;
+        Ok(policy)
+    }
+
+    fn hasher(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, OurError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = self
+            .hasher()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| {
+                OurError::new_internal_server_error(
+                    String::from("Something went wrong"),
+                    Some(Box::new(e)),
+                )
+            })?;
+        Ok(password_hash.to_string())
+    }
+
+    // a wrong password is no server error, nothing gets logged for it
+    pub fn verify(&self, reference: &str, password: &str) -> Result<(), OurError> {
+        let reference_hash = PasswordHash::new(reference).map_err(|e| {
+            OurError::new_internal_server_error(String::from("Input error"), Some(Box::new(e)))
+        })?;
+        // the cost stored in the hash is used, not the configured one
+        self.hasher()
+            .verify_password(password.as_bytes(), &reference_hash)
+            .map_err(|_| {
+                OurError::new_unauthorized_error(String::from("Invalid credentials"), None)
+            })
+    }
+
+    pub fn verify_dummy(&self, password: &str) {
+        let _ = self.verify(&self.dummy_hash, password);
+    }
+
+    // true when the hash was made with another algorithm or a lower cost than configured
+    pub fn needs_rehash(&self, reference: &str) -> bool {
+        let reference_hash = match PasswordHash::new(reference) {
+            Ok(reference_hash) => reference_hash,
+            Err(_) => return false,
+        };
+        if !matches!(
+            Algorithm::try_from(reference_hash.algorithm),
+            Ok(Algorithm::Argon2id)
+        ) {
+            return true;
+        }
+        match Params::try_from(&reference_hash) {
+            Ok(params) => {
+                params.m_cost() < self.params.m_cost()
+                    || params.t_cost() < self.params.t_cost()
+                    || params.p_cost() < self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+
+    // the form already checked strength, this one also knows the account and the breach list
+    pub async fn check_new_password(
+        &self,
+        password: &str,
+        user_inputs: &[&str],
+    ) -> Result<(), &'static str> {
+        match zxcvbn(password, user_inputs) {
+            Ok(entropy) if entropy.score() >= MIN_SCORE => {}
+            _ => return Err(WEAK_PASSWORD_MESSAGE),
+        }
+        if self.is_breached(password).await {
+            return Err(BREACHED_PASSWORD_MESSAGE);
+        }
+        Ok(())
+    }
+
+    // looks the SHA-1 suffix up in the file of its prefix, lines are "SUFFIX:COUNT"
+    async fn is_breached(&self, password: &str) -> bool {
+        let dir = match self.breached_passwords_dir.as_ref() {
+            Some(dir) => dir,
+            None => return false,
+        };
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(PREFIX_LENGTH);
+        let content = match fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+            Ok(content) => content,
+            // no file, no breached password with that prefix
+            Err(e) if e.kind() == ErrorKind::NotFound => return false,
+            Err(e) => {
+                log::error!("Cannot read breached passwords list: {}", e);
+                return false;
+            }
+        };
+        content.lines().any(|line| {
+            line.split(':')
+                .next()
+                .map(|candidate| candidate.trim().eq_ignore_ascii_case(suffix))
+                .unwrap_or(false)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs as std_fs;
+    use uuid::Uuid;
+
+    // SHA-1 is 91F2EA6494E980F4CD8ED2922A07C229EF34BFC2
+    const PASSWORD: &str = "gentle-Quartz-harbor-71-ember";
+
+    // cheap parameters, the tests are about the policy, not the cost
+    fn params(memory_cost: u32) -> Params {
+        Params::new(memory_cost, 1, 1, None).unwrap()
+    }
+
+    fn policy(breached_passwords_dir: Option<PathBuf>) -> PasswordPolicy {
+        PasswordPolicy::new(params(1024), breached_passwords_dir).unwrap()
+    }
+
+    // range file for the prefix of PASSWORD
+    fn breach_list(lines: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("breached-{}", Uuid::new_v4()));
+        std_fs::create_dir(&dir).unwrap();
+        std_fs::write(dir.join("91F2E.txt"), lines).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hashes_of_the_configured_cost_are_kept() {
+        let policy = policy(None);
+        let hash = policy.hash(PASSWORD).unwrap();
+        assert!(policy.verify(&hash, PASSWORD).is_ok());
+        assert!(policy.verify(&hash, "another password").is_err());
+        assert!(!policy.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn cheaper_or_older_hashes_are_rehashed() {
+        let policy = policy(None);
+        let salt = SaltString::generate(&mut OsRng);
+        let cheaper = Argon2::new(Algorithm::Argon2id, Version::V0x13, params(512))
+            .hash_password(PASSWORD.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, params(1024))
+            .hash_password(PASSWORD.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        assert!(policy.needs_rehash(&cheaper));
+        assert!(policy.needs_rehash(&argon2i));
+        // still verifies, the user is not locked out before the rehash
+        assert!(policy.verify(&cheaper, PASSWORD).is_ok());
+        assert!(!policy.needs_rehash("not a password hash"));
+    }
+
+    #[rocket::async_test]
+    async fn breached_suffix_is_found_in_the_prefix_file() {
+        // some mirrors serve lowercase suffixes
+        let dir = breach_list(
+            "0018A45C4D1DEF81644B54AB7F969B88D65:1\na6494e980f4cd8ed2922a07c229ef34bfc2:3\n",
+        );
+        let policy = policy(Some(dir.clone()));
+        assert!(policy.is_breached(PASSWORD).await);
+        assert_eq!(
+            policy.check_new_password(PASSWORD, &[]).await,
+            Err(BREACHED_PASSWORD_MESSAGE)
+        );
+        std_fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn other_suffixes_and_missing_prefixes_are_not_breached() {
+        let dir = breach_list("0018A45C4D1DEF81644B54AB7F969B88D65:1\n");
+        let policy = policy(Some(dir.clone()));
+        assert!(!policy.is_breached(PASSWORD).await);
+        assert_eq!(policy.check_new_password(PASSWORD, &[]).await, Ok(()));
+        // no file for the prefix of this one
+        assert!(!policy.is_breached("Tr0ub4dor&3 but longer").await);
+        std_fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn weak_passwords_are_refused_first() {
+        assert_eq!(
+            policy(None).check_new_password("password", &[]).await,
+            Err(WEAK_PASSWORD_MESSAGE)
+        );
+    }
+}
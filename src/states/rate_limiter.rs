@@ -0,0 +1,60 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// past this many tracked keys we drop the ones without recent hits
+const PRUNE_THRESHOLD: usize = 10_000;
+
+// Managed state counting hits per key in a sliding window, kept in memory
+// so limits are per process and reset on restart.
+#[derive(Default)]
+pub struct RateLimiter {
+    hits: Mutex<HashMap<String, Hits>>,
+}
+
+// the window is kept with the hits, buckets of other routes are pruned by their own
+struct Hits {
+    window: Duration,
+    times: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // records a hit and tells whether the key is still within max hits per window
+    pub fn check(&self, bucket: &str, key: &str, max: usize, window: Duration) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|e| e.into_inner());
+        if hits.len() > PRUNE_THRESHOLD {
+            hits.retain(|_, hits| {
+                hits.times
+                    .back()
+                    .map(|last| now.duration_since(*last) < hits.window)
+                    .unwrap_or(false)
+            });
+        }
+        let entry = hits
+            .entry(format!("{}:{}", bucket, key))
+            .or_insert_with(|| Hits {
+                window,
+                times: VecDeque::new(),
+            });
+        entry.window = window;
+        let times = &mut entry.times;
+        while let Some(first) = times.front() {
+            if now.duration_since(*first) < window {
+                break;
+            }
+            times.pop_front();
+        }
+        if times.len() >= max {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
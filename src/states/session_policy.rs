@@ -0,0 +1,23 @@
+use chrono::Duration;
+
+// how long agent sessions live, read from the sessions section of Rocket.toml
+pub struct SessionPolicy {
+    // a session unused for this long ends, "remember me" sessions excepted
+    pub idle_timeout: Duration,
+    // no session outlives this, whatever its activity
+    pub absolute_timeout: Duration,
+    // lifetime of "remember me" sessions
+    pub remember_me_timeout: Duration,
+    // how long someone can view the application as another user before it ends by itself
+    pub impersonation_timeout: Duration,
+}
+
+impl SessionPolicy {
+    pub fn lifetime(&self, persistent: bool) -> Duration {
+        if persistent {
+            self.remember_me_timeout
+        } else {
+            self.absolute_timeout
+        }
+    }
+}
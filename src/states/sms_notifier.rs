@@ -0,0 +1,126 @@
+use crate::models::issues_reported::Issue;
+use crate::models::sms_message;
+use crate::models::sms_notification::{
+    NewSmsNotification, SmsNotification, KIND_REPLY, KIND_TICKET_CLOSED, KIND_TICKET_CREATED,
+    KIND_VERIFICATION,
+};
+use crate::traits::sms_gateway::{OutgoingSms, SmsGateway};
+use rocket_db_pools::sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// managed state used by the routes to text reporters about their tickets,
+// the messages are queued and the SMS worker hands them to the gateway
+pub struct SmsNotifier {
+    gateway: Arc<dyn SmsGateway>,
+    created_template: String,
+    closed_template: String,
+    max_segments: usize,
+}
+
+impl SmsNotifier {
+    pub fn new(
+        gateway: Arc<dyn SmsGateway>,
+        created_template: String,
+        closed_template: String,
+        max_segments: usize,
+    ) -> Self {
+        Self {
+            gateway,
+            created_template,
+            closed_template,
+            max_segments,
+        }
+    }
+
+    // shared with the SMS worker, which does the sending
+    pub fn gateway(&self) -> Arc<dyn SmsGateway> {
+        self.gateway.clone()
+    }
+
+    pub async fn ticket_created(&self, connection: &mut PgConnection, issue: &Issue) {
+        self.notify(connection, KIND_TICKET_CREATED, &self.created_template, issue)
+            .await
+    }
+
+    pub async fn ticket_closed(&self, connection: &mut PgConnection, issue: &Issue) {
+        self.notify(connection, KIND_TICKET_CLOSED, &self.closed_template, issue)
+            .await
+    }
+
+    // Answers an inbound text message. The reply is recorded against the ticket when there is one,
+    // a number asking about a ticket that is not theirs only gets the text.
+    pub async fn reply(
+        &self,
+        connection: &mut PgConnection,
+        to: &str,
+        body: &str,
+        issue: Option<&Issue>,
+    ) {
+        let message = OutgoingSms {
+            to: to.to_string(),
+            body: sms_message::truncate(body, self.max_segments),
+        };
+        self.queue(
+            connection,
+            KIND_REPLY,
+            &message,
+            issue.map(|issue| issue.uuid),
+        )
+        .await;
+    }
+
+    // queues a portal registration its code, tells whether the message will go out
+    pub async fn verification_code(
+        &self,
+        connection: &mut PgConnection,
+        contact_number: i64,
+        code: &str,
+    ) -> bool {
+        let message = OutgoingSms {
+            to: sms_message::recipient(contact_number),
+            body: format!("Your verification code is {}", code),
+        };
+        self.queue(connection, KIND_VERIFICATION, &message, None)
+            .await
+    }
+
+    async fn notify(
+        &self,
+        connection: &mut PgConnection,
+        kind: &str,
+        template: &str,
+        issue: &Issue,
+    ) {
+        let message = OutgoingSms {
+            to: sms_message::recipient(issue.contact_number),
+            body: sms_message::render(template, issue, self.max_segments),
+        };
+        self.queue(connection, kind, &message, Some(issue.uuid))
+            .await;
+    }
+
+    // a text message that cannot be queued must never fail the ticket itself, errors are only logged
+    async fn queue(
+        &self,
+        connection: &mut PgConnection,
+        kind: &str,
+        message: &OutgoingSms,
+        issue_uuid: Option<Uuid>,
+    ) -> bool {
+        let notification = NewSmsNotification {
+            issue_uuid,
+            kind,
+            recipient: &message.to,
+            body: &message.body,
+            gateway: self.gateway.name(),
+        };
+        match SmsNotification::create(connection, &notification).await {
+            Ok(_) => true,
+            Err(e) => {
+                log::error!("Cannot queue SMS notification to {}: {}", message.to, e);
+                false
+            }
+        }
+    }
+}
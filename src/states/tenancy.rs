@@ -0,0 +1,24 @@
+// how a request is matched to its organisation, read from the tenancy section of Rocket.toml
+pub struct TenancyPolicy {
+    // hosts under this domain name their organisation in their first label, None when unused
+    pub base_domain: Option<String>,
+    // organisation of the requests naming none, with nobody logged in to tell it either
+    pub default_subdomain: Option<String>,
+}
+
+impl TenancyPolicy {
+    // "acme" for acme.tickets.example.com:8000 when base_domain is tickets.example.com
+    pub fn subdomain<'h>(&self, host: &'h str) -> Option<&'h str> {
+        let base_domain = self.base_domain.as_deref()?;
+        let hostname = host.split(':').next().unwrap_or(host);
+        let label = hostname
+            .strip_suffix(base_domain)?
+            .strip_suffix('.')
+            .filter(|label| !label.is_empty())?;
+        // only one level, a.b.tickets.example.com is no organisation
+        if label.contains('.') {
+            return None;
+        }
+        Some(label)
+    }
+}
@@ -0,0 +1,14 @@
+use crate::errors::our_error::OurError;
+
+// plain text email
+#[derive(Debug, Clone)]
+pub struct OutgoingMail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[rocket::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), OurError>;
+}
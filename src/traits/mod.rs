@@ -0,0 +1,2 @@
+pub mod mailer;
+pub mod sms_gateway;
@@ -0,0 +1,22 @@
+use crate::errors::our_error::OurError;
+
+// message handed to a gateway, recipient is in E.164 format (+264812345678)
+#[derive(Debug, Clone)]
+pub struct OutgoingSms {
+    pub to: String,
+    pub body: String,
+}
+
+// what the provider answered, message_id is whatever the provider uses to track the message
+#[derive(Debug, Clone)]
+pub struct SmsReceipt {
+    pub message_id: Option<String>,
+}
+
+#[rocket::async_trait]
+pub trait SmsGateway: Send + Sync {
+    // name stored with every notification so we know which adapter sent it
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, message: &OutgoingSms) -> Result<SmsReceipt, OurError>;
+}
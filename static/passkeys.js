@@ -0,0 +1,109 @@
+// Browser side of the passkey ceremonies, the server only speaks base64url.
+(function () {
+  function toBuffer(value) {
+    var base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+    var binary = atob(base64 + "===".slice((base64.length + 3) % 4));
+    return Uint8Array.from(binary, function (c) { return c.charCodeAt(0); }).buffer;
+  }
+
+  function fromBuffer(buffer) {
+    var binary = String.fromCharCode.apply(null, new Uint8Array(buffer));
+    return btoa(binary).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+  }
+
+  function post(url, body) {
+    return fetch(url, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      credentials: "same-origin",
+      redirect: "manual",
+      body: JSON.stringify(body)
+    }).then(function (response) {
+      if (!response.ok) {
+        throw new Error(response.status);
+      }
+      return response.json();
+    });
+  }
+
+  function fail(element, message) {
+    var output = element.querySelector("[data-passkey-error]");
+    if (output) {
+      output.textContent = message;
+    }
+  }
+
+  var register = document.querySelector("[data-passkey-register]");
+  if (register) {
+    register.addEventListener("submit", function (event) {
+      event.preventDefault();
+      post("/account/passkeys/register/start", {
+        name: register.elements.name.value,
+        authenticity_token: register.elements.authenticity_token.value
+      }).then(function (options) {
+        var publicKey = options.publicKey;
+        publicKey.challenge = toBuffer(publicKey.challenge);
+        publicKey.user.id = toBuffer(publicKey.user.id);
+        (publicKey.excludeCredentials || []).forEach(function (credential) {
+          credential.id = toBuffer(credential.id);
+        });
+        return navigator.credentials.create({ publicKey: publicKey });
+      }).then(function (credential) {
+        return post("/account/passkeys/register/finish", {
+          credential: {
+            id: credential.id,
+            rawId: fromBuffer(credential.rawId),
+            type: credential.type,
+            response: {
+              attestationObject: fromBuffer(credential.response.attestationObject),
+              clientDataJSON: fromBuffer(credential.response.clientDataJSON)
+            },
+            extensions: credential.getClientExtensionResults()
+          },
+          authenticity_token: register.elements.authenticity_token.value
+        });
+      }).then(function (outcome) {
+        window.location = outcome.redirect;
+      }).catch(function () {
+        fail(register, "The passkey could not be added, the name may already be taken.");
+      });
+    });
+  }
+
+  var login = document.querySelector("[data-passkey-login]");
+  if (login) {
+    login.addEventListener("click", function (event) {
+      event.preventDefault();
+      var form = login.form;
+      post("/login/passkey/start", {
+        username: form.elements.username.value,
+        authenticity_token: form.elements.authenticity_token.value
+      }).then(function (options) {
+        var publicKey = options.publicKey;
+        publicKey.challenge = toBuffer(publicKey.challenge);
+        (publicKey.allowCredentials || []).forEach(function (credential) {
+          credential.id = toBuffer(credential.id);
+        });
+        return navigator.credentials.get({ publicKey: publicKey });
+      }).then(function (credential) {
+        var next = encodeURIComponent(form.elements.next.value);
+        return post("/login/passkey/finish?next=" + next, {
+          id: credential.id,
+          rawId: fromBuffer(credential.rawId),
+          type: credential.type,
+          response: {
+            authenticatorData: fromBuffer(credential.response.authenticatorData),
+            clientDataJSON: fromBuffer(credential.response.clientDataJSON),
+            signature: fromBuffer(credential.response.signature),
+            userHandle: credential.response.userHandle ? fromBuffer(credential.response.userHandle) : null
+          },
+          extensions: credential.getClientExtensionResults()
+        });
+      }).then(function (outcome) {
+        window.location = outcome.redirect;
+      }).catch(function () {
+        fail(form, "Passkey sign in failed, enter your username and try again.");
+      });
+    });
+  }
+})();
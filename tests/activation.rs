@@ -0,0 +1,178 @@
+mod common;
+
+use chrono::{offset::Utc, Duration};
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::models::organisation::Organisation;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::models::user_status::UserStatus;
+use our_application::routes::{activation, session};
+use our_application::states::signer::Signer;
+use rocket::http::{Accept, ContentType, Status};
+use rocket::local::asynchronous::Client;
+use sqlx::PgConnection;
+
+const PASSWORD: &str = "gentle-Quartz-harbor-71-ember";
+// purpose the activation links are signed for
+const ACTIVATION_PURPOSE: &str = "activation";
+
+async fn client() -> Client {
+    let rocket = common::rocket(common::figment()).mount(
+        "/",
+        rocket::routes![activation::activate, session::login_form, session::login],
+    );
+    Client::tracked(rocket).await.expect("invalid rocket")
+}
+
+// as a new user is created, waiting for the link of the activation email
+async fn pending_user(connection: &mut PgConnection, organisation: &Organisation) -> User {
+    let user = common::create_user(
+        connection,
+        organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    sqlx::query("UPDATE users SET status = $1 WHERE uuid = $2")
+        .bind(UserStatus::Pending)
+        .bind(user.uuid)
+        .execute(&mut *connection)
+        .await
+        .expect("cannot make user pending");
+    common::set_password(connection, organisation, &user, PASSWORD).await
+}
+
+fn link(client: &Client, purpose: &str, user: &User, expires_in: Duration) -> String {
+    let signer = client.rocket().state::<Signer>().expect("no signer");
+    let expires_at = (Utc::now() + expires_in).timestamp();
+    let token = signer.sign(purpose, &user.uuid.to_string(), expires_at);
+    format!("/activate/{}", token)
+}
+
+// where following the link sends the browser
+async fn follow(client: &Client, organisation: &Organisation, link: &str) -> Option<String> {
+    let response = client
+        .get(link.to_string())
+        .header(common::host(organisation))
+        .header(Accept::HTML)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    response.headers().get_one("Location").map(String::from)
+}
+
+async fn status(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    user: &User,
+) -> UserStatus {
+    User::find(connection, &organisation.uuid, &user.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap()
+        .status
+}
+
+async fn log_in(client: &Client, organisation: &Organisation, user: &User) -> bool {
+    let token = common::authenticity_token(client, organisation, "/login").await;
+    let body = serde_urlencoded::to_string(&[
+        ("username", user.username.as_str()),
+        ("password", PASSWORD),
+        ("authenticity_token", token.as_str()),
+    ])
+    .unwrap();
+    client
+        .post("/login")
+        .header(common::host(organisation))
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await;
+    client.cookies().get_private(LOGIN_COOKIE_NAME).is_some()
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn activation_link_lets_a_pending_user_in() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = pending_user(&mut connection, &organisation).await;
+    let client = client().await;
+
+    // the right password still does not open a pending account
+    assert!(!log_in(&client, &organisation, &user).await);
+    let html = common::page(&client, &organisation, "/login").await;
+    assert!(html.contains("Your account is not activated yet"));
+
+    let link = link(&client, ACTIVATION_PURPOSE, &user, Duration::hours(48));
+    assert_eq!(
+        follow(&client, &organisation, &link).await.as_deref(),
+        Some("/login")
+    );
+    assert_eq!(
+        status(&mut connection, &organisation, &user).await,
+        UserStatus::Active
+    );
+    // opening the email twice is no error
+    assert_eq!(
+        follow(&client, &organisation, &link).await.as_deref(),
+        Some("/login")
+    );
+    assert!(log_in(&client, &organisation, &user).await);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn expired_forged_or_foreign_links_activate_nothing() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let other_organisation = common::create_organisation(&mut connection).await;
+    let user = pending_user(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let expired = link(&client, ACTIVATION_PURPOSE, &user, Duration::seconds(-1));
+    let other_purpose = link(&client, "tracking", &user, Duration::hours(48));
+    let valid = link(&client, ACTIVATION_PURPOSE, &user, Duration::hours(48));
+    for bad_link in [expired, other_purpose, format!("{}x", valid)] {
+        assert_eq!(
+            follow(&client, &organisation, &bad_link).await.as_deref(),
+            Some("/activate")
+        );
+    }
+    // a genuine link is only good on the host of the user's organisation
+    assert_eq!(
+        follow(&client, &other_organisation, &valid)
+            .await
+            .as_deref(),
+        Some("/activate")
+    );
+    assert_eq!(
+        status(&mut connection, &organisation, &user).await,
+        UserStatus::Pending
+    );
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn deactivated_users_cannot_activate_again() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = pending_user(&mut connection, &organisation).await;
+    sqlx::query("UPDATE users SET status = $1 WHERE uuid = $2")
+        .bind(UserStatus::Deactivated)
+        .bind(user.uuid)
+        .execute(&mut connection)
+        .await
+        .unwrap();
+    let client = client().await;
+
+    let link = link(&client, ACTIVATION_PURPOSE, &user, Duration::hours(48));
+    assert_eq!(
+        follow(&client, &organisation, &link).await.as_deref(),
+        Some("/activate")
+    );
+    assert_eq!(
+        status(&mut connection, &organisation, &user).await,
+        UserStatus::Deactivated
+    );
+}
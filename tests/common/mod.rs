@@ -0,0 +1,227 @@
+// Shared by the tests running against a database, DATABASE_URL names one with the migrations applied.
+// Every test works in organisations of its own, so the tests never see each other's data.
+#![allow(dead_code)]
+
+use argon2::Params;
+use chrono::Duration;
+use our_application::fairings::{
+    csrf::Csrf, db::DBConnection, mail::Mail, oidc::Oidc, passwords::Passwords, sessions::Sessions,
+    signing::Signing, sms::Sms, tenancy::Tenancy,
+};
+use our_application::filters;
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::models::issues_reported::Issue;
+use our_application::models::organisation::Organisation;
+use our_application::models::session::Session;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::models::user_status::UserStatus;
+use our_application::states::password_policy::PasswordPolicy;
+use our_application::states::rate_limiter::RateLimiter;
+use rocket::figment::providers::Serialized;
+use rocket::figment::Figment;
+use rocket::http::{Accept, Cookie, Header};
+use rocket::local::asynchronous::Client;
+use rocket::{Build, Config, Rocket};
+use rocket_db_pools::Database;
+use rocket_dyn_templates::Template;
+use sqlx::{Connection, PgConnection};
+use std::env;
+use uuid::Uuid;
+
+pub const BASE_DOMAIN: &str = "tickets.test";
+pub const DATABASE_TEST: &str = "needs DATABASE_URL of a migrated database";
+
+pub fn database_url() -> String {
+    env::var("DATABASE_URL").expect(DATABASE_TEST)
+}
+
+// Rocket.toml with the test database, hosts under BASE_DOMAIN name their organisation
+pub fn figment() -> Figment {
+    Config::figment()
+        .merge(Serialized::global(
+            "databases.main_connection.url",
+            database_url(),
+        ))
+        .merge(Serialized::global("tenancy.base_domain", BASE_DOMAIN))
+}
+
+// the fairings of the application, each test mounts the routes it exercises
+pub fn rocket(figment: Figment) -> Rocket<Build> {
+    rocket::custom(figment)
+        .attach(DBConnection::init())
+        .attach(Template::custom(|engines| {
+            engines
+                .tera
+                .register_filter("local_time", filters::local_time);
+        }))
+        .attach(Csrf::new())
+        .attach(Signing::new())
+        .attach(Sms::new())
+        .attach(Mail::new())
+        .attach(Sessions::new())
+        .attach(Passwords::new())
+        .attach(Oidc::new())
+        .attach(Tenancy::new())
+        .manage(RateLimiter::new())
+}
+
+pub async fn connection() -> PgConnection {
+    PgConnection::connect(&database_url())
+        .await
+        .expect("cannot connect to DATABASE_URL")
+}
+
+fn unique(prefix: &str) -> String {
+    format!("{}{}", prefix, Uuid::new_v4().to_simple())
+}
+
+pub async fn create_organisation(connection: &mut PgConnection) -> Organisation {
+    let subdomain = unique("test");
+    sqlx::query("INSERT INTO organisations (uuid, name, subdomain) VALUES ($1, $2, $3)")
+        .bind(Uuid::new_v4())
+        .bind(&subdomain)
+        .bind(&subdomain)
+        .execute(&mut *connection)
+        .await
+        .expect("cannot create organisation");
+    Organisation::find_by_subdomain(connection, &subdomain)
+        .await
+        .map_err(|e| e.to_string())
+        .expect("cannot find organisation")
+}
+
+// requests on this host are for the organisation
+pub fn host(organisation: &Organisation) -> Header<'static> {
+    Header::new(
+        "Host",
+        format!("{}.{}", organisation.subdomain, BASE_DOMAIN),
+    )
+}
+
+// active user who never logs in with a password
+pub async fn create_user(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    email: &str,
+    role: UserRole,
+) -> User {
+    let uuid = Uuid::new_v4();
+    let query_str = r#"INSERT INTO users
+(uuid, organisation_uuid, username, email, password_hash, status, role)
+VALUES
+($1, $2, $3, $4, $5, $6, $7)"#;
+    sqlx::query(query_str)
+        .bind(uuid)
+        .bind(organisation.uuid)
+        .bind(unique("user"))
+        .bind(email)
+        .bind("not a password hash")
+        .bind(UserStatus::Active)
+        .bind(role)
+        .execute(&mut *connection)
+        .await
+        .expect("cannot create user");
+    User::find(connection, &organisation.uuid, &uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .expect("cannot find user")
+}
+
+// open ticket nobody has claimed yet
+pub async fn create_issue(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    ticket_number: i64,
+    contact_number: i64,
+) -> Issue {
+    let uuid = Uuid::new_v4();
+    let query_str = r#"INSERT INTO issues_reported
+(uuid, organisation_uuid, issue_name, description, reported_by, company_name,
+contact_number, ticket_number, ticket_owner, status)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#;
+    sqlx::query(query_str)
+        .bind(uuid)
+        .bind(organisation.uuid)
+        .bind("Printer on fire")
+        .bind("Third floor")
+        .bind("Jane")
+        .bind(&organisation.name)
+        .bind(contact_number)
+        .bind(ticket_number)
+        .bind("orphan")
+        .bind("open")
+        .execute(&mut *connection)
+        .await
+        .expect("cannot create issue");
+    Issue::find(connection, &organisation.uuid, &uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .expect("cannot find issue")
+}
+
+// the user can now log in with this password
+pub async fn set_password(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    user: &User,
+    password: &str,
+) -> User {
+    let policy = PasswordPolicy::new(Params::default(), None)
+        .map_err(|e| e.to_string())
+        .expect("cannot make password policy");
+    User::set_password(
+        connection,
+        &organisation.uuid,
+        &user.uuid,
+        password,
+        &policy,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .expect("cannot set password")
+}
+
+// html of a page of the organisation, flash messages included
+pub async fn page(client: &Client, organisation: &Organisation, uri: &str) -> String {
+    client
+        .get(uri.to_string())
+        .header(host(organisation))
+        .header(Accept::HTML)
+        .dispatch()
+        .await
+        .into_string()
+        .await
+        .expect("page without body")
+}
+
+// token of the first form of the page, a tracked client keeps the csrf cookie it matches
+pub async fn authenticity_token(client: &Client, organisation: &Organisation, uri: &str) -> String {
+    let html = page(client, organisation, uri).await;
+    let marker = r#"name="authenticity_token" value=""#;
+    let start = html.find(marker).expect("page without csrf token") + marker.len();
+    let end = start + html[start..].find('"').expect("unterminated csrf token");
+    // templates escape the slashes of the base64 token
+    html[start..end].replace("&#x2F;", "/")
+}
+
+pub fn unique_email() -> String {
+    format!("{}@example.com", unique("user"))
+}
+
+// session cookie of the user, as the login would set it
+pub async fn log_in(connection: &mut PgConnection, user: &User) -> Cookie<'static> {
+    let (_, token) = Session::create(
+        connection,
+        &user.uuid,
+        None,
+        None,
+        false,
+        Duration::hours(1),
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .expect("cannot create session");
+    Cookie::new(LOGIN_COOKIE_NAME, token)
+}
@@ -0,0 +1,592 @@
+mod common;
+
+use chrono::offset::Utc;
+use jsonwebtoken::{encode, Algorithm, EncodingKey};
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::routes::oidc;
+use our_application::states::oidc_client::{OidcClient, OidcSettings};
+use reqwest::Url;
+use rocket::figment::providers::Serialized;
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const CLIENT_ID: &str = "ticket-desk";
+const REDIRECT_URL: &str = "http://localhost:8000/login/oidc/callback";
+const KEY_ID: &str = "stub-key";
+const PRIVATE_KEY: &[u8] = include_bytes!("fixtures/oidc_private_key.pem");
+const JWKS: &str = include_str!("fixtures/oidc_jwks.json");
+// the only authorization code the stub takes
+const CODE: &str = "stub-code";
+const VERIFIER: &str = "a-verifier-of-forty-three-characters-at-least";
+
+// what the next token request gets, set by the test as the real provider would from the login
+#[derive(Default)]
+struct StubLogin {
+    nonce: String,
+    code_challenge: String,
+    // claims of the ID token, they replace the valid defaults
+    claims: Map<String, Value>,
+    // issuer of the discovery document, the stub itself when None
+    issuer: Option<String>,
+}
+
+// Identity provider on a local port: discovery, keys and a token endpoint checking the PKCE verifier.
+struct StubProvider {
+    issuer_url: String,
+    login: Arc<Mutex<StubLogin>>,
+}
+
+impl StubProvider {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind stub provider");
+        let issuer_url = format!("http://{}", listener.local_addr().unwrap());
+        let login = Arc::new(Mutex::new(StubLogin::default()));
+        let (base_url, shared) = (issuer_url.clone(), Arc::clone(&login));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                respond(stream, &base_url, &shared);
+            }
+        });
+        Self { issuer_url, login }
+    }
+
+    fn expect_login(&self, nonce: &str, code_challenge: &str, claims: Value) {
+        let mut login = self.login.lock().unwrap();
+        login.nonce = nonce.to_string();
+        login.code_challenge = code_challenge.to_string();
+        login.claims = claims.as_object().cloned().unwrap_or_default();
+    }
+
+    fn settings(&self, organisation: &str) -> OidcSettings {
+        let mut role_mapping = HashMap::new();
+        role_mapping.insert(String::from("helpdesk-supervisors"), UserRole::Supervisor);
+        role_mapping.insert(String::from("helpdesk-admins"), UserRole::Admin);
+        OidcSettings {
+            organisation: organisation.to_string(),
+            issuer_url: self.issuer_url.clone(),
+            client_id: String::from(CLIENT_ID),
+            client_secret: None,
+            redirect_url: String::from(REDIRECT_URL),
+            scopes: String::from("openid email profile"),
+            username_claim: String::from("preferred_username"),
+            role_claim: String::from("groups"),
+            role_mapping,
+            default_role: None,
+            label: String::from("company account"),
+            break_glass_only: true,
+        }
+    }
+
+    fn oidc_config(&self, organisation: &str) -> Value {
+        json!({
+            "organisation": organisation,
+            "issuer_url": self.issuer_url,
+            "client_id": CLIENT_ID,
+            "redirect_url": REDIRECT_URL,
+            "default_role": "agent",
+            "role_mapping": { "helpdesk-admins": "admin" },
+        })
+    }
+}
+
+fn respond(stream: TcpStream, base_url: &str, login: &Mutex<StubLogin>) {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() {
+            return;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let login = login.lock().unwrap();
+    let (status, response) = match path {
+        "/.well-known/openid-configuration" => (
+            "200 OK",
+            json!({
+                "issuer": login.issuer.as_deref().unwrap_or(base_url),
+                "authorization_endpoint": format!("{}/authorize", base_url),
+                "token_endpoint": format!("{}/token", base_url),
+                "jwks_uri": format!("{}/jwks", base_url),
+            })
+            .to_string(),
+        ),
+        "/jwks" => ("200 OK", JWKS.to_string()),
+        "/token" => token(&body, base_url, &login),
+        _ => ("404 Not Found", String::from("{}")),
+    };
+    let mut stream = reader.into_inner();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        response.len(),
+        response
+    );
+}
+
+fn token(body: &[u8], base_url: &str, login: &StubLogin) -> (&'static str, String) {
+    let form = serde_urlencoded::from_bytes::<HashMap<String, String>>(body).unwrap_or_default();
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or("");
+    if field("grant_type") != "authorization_code"
+        || field("code") != CODE
+        || challenge(field("code_verifier")) != login.code_challenge
+    {
+        return (
+            "400 Bad Request",
+            json!({ "error": "invalid_grant" }).to_string(),
+        );
+    }
+    let mut claims = json!({
+        "iss": base_url,
+        "aud": CLIENT_ID,
+        "exp": Utc::now().timestamp() + 300,
+        "nonce": login.nonce,
+    })
+    .as_object()
+    .cloned()
+    .unwrap();
+    claims.extend(login.claims.clone());
+    let mut header = jsonwebtoken::Header::new(Algorithm::RS256);
+    header.kid = Some(String::from(KEY_ID));
+    let key = EncodingKey::from_rsa_pem(PRIVATE_KEY).expect("invalid stub key");
+    let id_token = encode(&header, &claims, &key).expect("cannot sign ID token");
+    (
+        "200 OK",
+        json!({ "id_token": id_token, "access_token": "stub", "token_type": "Bearer" }).to_string(),
+    )
+}
+
+fn challenge(code_verifier: &str) -> String {
+    base64::encode_config(
+        Sha256::digest(code_verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+fn query_params(url: &str) -> HashMap<String, String> {
+    Url::parse(url)
+        .expect("invalid url")
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+fn employee(subject: &str, email: &str, email_verified: bool, groups: Value) -> Value {
+    json!({
+        "sub": subject,
+        "email": email,
+        "email_verified": email_verified,
+        "preferred_username": subject,
+        "groups": groups,
+    })
+}
+
+fn oidc_client(settings: OidcSettings) -> OidcClient {
+    OidcClient::new(Some(settings))
+        .map_err(|e| e.to_string())
+        .expect("cannot build client")
+}
+
+#[rocket::async_test]
+async fn authorization_url_carries_state_nonce_and_pkce_challenge() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    let url = client
+        .authorization_url("the-state", "the-nonce", &challenge(VERIFIER))
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(url.starts_with(&format!("{}/authorize?", provider.issuer_url)));
+    let params = query_params(&url);
+    assert_eq!(params["response_type"], "code");
+    assert_eq!(params["client_id"], CLIENT_ID);
+    assert_eq!(params["redirect_uri"], REDIRECT_URL);
+    assert_eq!(params["state"], "the-state");
+    assert_eq!(params["nonce"], "the-nonce");
+    assert_eq!(params["code_challenge"], challenge(VERIFIER));
+    assert_eq!(params["code_challenge_method"], "S256");
+}
+
+#[rocket::async_test]
+async fn valid_token_gives_identity_with_highest_mapped_role() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    provider.expect_login(
+        "the-nonce",
+        &challenge(VERIFIER),
+        employee(
+            "subject-1",
+            "jane@example.com",
+            true,
+            json!(["everyone", "helpdesk-admins", "helpdesk-supervisors"]),
+        ),
+    );
+    let identity = client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(identity.subject, "subject-1");
+    assert_eq!(identity.username, "subject-1");
+    assert_eq!(identity.email, "jane@example.com");
+    assert!(identity.email_verified);
+    assert_eq!(identity.role, Some(UserRole::Admin));
+}
+
+#[rocket::async_test]
+async fn role_claim_may_be_a_single_string() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    provider.expect_login(
+        "the-nonce",
+        &challenge(VERIFIER),
+        employee(
+            "subject-1",
+            "jane@example.com",
+            true,
+            json!("helpdesk-supervisors"),
+        ),
+    );
+    let identity = client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(identity.role, Some(UserRole::Supervisor));
+}
+
+#[rocket::async_test]
+async fn unmapped_groups_get_the_default_role_or_none() {
+    let provider = StubProvider::start();
+    let claims = employee("subject-1", "jane@example.com", true, json!(["everyone"]));
+
+    let client = oidc_client(provider.settings("default"));
+    provider.expect_login("the-nonce", &challenge(VERIFIER), claims.clone());
+    let identity = client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(identity.role, None);
+
+    let mut settings = provider.settings("default");
+    settings.default_role = Some(UserRole::Agent);
+    let client = oidc_client(settings);
+    provider.expect_login("the-nonce", &challenge(VERIFIER), claims);
+    let identity = client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(identity.role, Some(UserRole::Agent));
+}
+
+#[rocket::async_test]
+async fn username_falls_back_to_the_email() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    let mut claims = employee("subject-1", "jane@example.com", true, json!([]));
+    claims.as_object_mut().unwrap().remove("preferred_username");
+    provider.expect_login("the-nonce", &challenge(VERIFIER), claims);
+    let identity = client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(identity.username, "jane");
+}
+
+#[rocket::async_test]
+async fn token_with_another_nonce_is_refused() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    provider.expect_login(
+        "another-nonce",
+        &challenge(VERIFIER),
+        employee("subject-1", "jane@example.com", true, json!([])),
+    );
+    assert!(client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .is_err());
+}
+
+#[rocket::async_test]
+async fn code_with_the_wrong_verifier_is_refused() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    provider.expect_login(
+        "the-nonce",
+        &challenge(VERIFIER),
+        employee("subject-1", "jane@example.com", true, json!([])),
+    );
+    assert!(client
+        .exchange_code(
+            CODE,
+            "another-verifier-of-forty-three-characters",
+            "the-nonce"
+        )
+        .await
+        .is_err());
+}
+
+#[rocket::async_test]
+async fn token_for_another_client_or_expired_is_refused() {
+    let provider = StubProvider::start();
+    let client = oidc_client(provider.settings("default"));
+    let mut claims = employee("subject-1", "jane@example.com", true, json!([]));
+    claims["aud"] = json!("another-client");
+    provider.expect_login("the-nonce", &challenge(VERIFIER), claims);
+    assert!(client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .is_err());
+
+    let mut claims = employee("subject-1", "jane@example.com", true, json!([]));
+    claims["exp"] = json!(Utc::now().timestamp() - 3600);
+    provider.expect_login("the-nonce", &challenge(VERIFIER), claims);
+    assert!(client
+        .exchange_code(CODE, VERIFIER, "the-nonce")
+        .await
+        .is_err());
+}
+
+#[rocket::async_test]
+async fn discovery_of_another_issuer_is_refused() {
+    let provider = StubProvider::start();
+    provider.login.lock().unwrap().issuer = Some(String::from("https://login.example.com"));
+    let client = oidc_client(provider.settings("default"));
+    assert!(client
+        .authorization_url("the-state", "the-nonce", &challenge(VERIFIER))
+        .await
+        .is_err());
+}
+
+// the routes, with the provider signing in the staff of the organisation
+
+async fn sso_client(provider: &StubProvider, subdomain: &str) -> Client {
+    let figment =
+        common::figment().merge(Serialized::global("oidc", provider.oidc_config(subdomain)));
+    let rocket = common::rocket(figment).mount("/", rocket::routes![oidc::start, oidc::callback]);
+    Client::tracked(rocket).await.expect("invalid rocket")
+}
+
+// starts the login and hands the provider what the browser would bring it, returns the state
+async fn start_login(
+    client: &Client,
+    provider: &StubProvider,
+    host: Header<'static>,
+    claims: Value,
+) -> String {
+    let response = client.get("/login/oidc").header(host).dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let params = query_params(&location);
+    // the stub only knows the challenge, the verifier stays in the cookie
+    provider.expect_login(&params["nonce"], &params["code_challenge"], claims);
+    params["state"].clone()
+}
+
+fn callback_uri(state: &str) -> String {
+    format!("/login/oidc/callback?code={}&state={}", CODE, state)
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn callback_with_another_state_is_refused() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(&subject, &common::unique_email(), true, json!([]));
+
+    start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri("forged-state"))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+    assert!(
+        User::find_by_oidc_subject(&mut connection, &organisation.uuid, &subject)
+            .await
+            .is_err()
+    );
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn callback_works_once() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(&subject, &common::unique_email(), true, json!([]));
+
+    let state = start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    // the pending login is gone with its first use
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn new_employee_gets_an_account_with_the_mapped_role() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(
+        &subject,
+        &common::unique_email(),
+        true,
+        json!(["helpdesk-admins"]),
+    );
+
+    let state = start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let user = User::find_by_oidc_subject(&mut connection, &organisation.uuid, &subject)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(user.role, UserRole::Admin);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn verified_email_links_the_existing_account() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let email = common::unique_email();
+    let existing =
+        common::create_user(&mut connection, &organisation, &email, UserRole::Admin).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(&subject, &email, true, json!([]));
+
+    let state = start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let linked = User::find_by_oidc_subject(&mut connection, &organisation.uuid, &subject)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(linked.uuid, existing.uuid);
+    // the provider decides the role of linked accounts
+    assert_eq!(linked.role, UserRole::Agent);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn unverified_email_never_takes_over_an_account() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let email = common::unique_email();
+    let existing =
+        common::create_user(&mut connection, &organisation, &email, UserRole::Admin).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(&subject, &email, false, json!([]));
+
+    let state = start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&organisation))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let user = User::find(
+        &mut connection,
+        &organisation.uuid,
+        &existing.uuid.to_string(),
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    assert_eq!(user.oidc_subject, None);
+    assert_eq!(user.role, UserRole::Admin);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn provider_of_one_organisation_signs_nobody_into_another() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let other = common::create_organisation(&mut connection).await;
+    let provider = StubProvider::start();
+    let client = sso_client(&provider, &organisation.subdomain).await;
+
+    let response = client
+        .get("/login/oidc")
+        .header(common::host(&other))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    // a login started for the organisation cannot finish on the host of the other
+    let subject = format!("subject-{}", uuid::Uuid::new_v4());
+    let claims = employee(&subject, &common::unique_email(), true, json!([]));
+    let state = start_login(&client, &provider, common::host(&organisation), claims).await;
+    let response = client
+        .get(callback_uri(&state))
+        .header(common::host(&other))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+    assert!(
+        User::find_by_oidc_subject(&mut connection, &other.uuid, &subject)
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use chrono::Duration;
+use our_application::models::passkey::{Passkey, PasskeyCeremony};
+use our_application::models::user_role::UserRole;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_rs::prelude::{PasskeyAuthentication, PasskeyRegistration, Url, Uuid as WebauthnUuid};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+const RP_ID: &str = "localhost";
+const RP_ORIGIN: &str = "http://localhost:8000";
+
+// the relying party the Passkeys fairing builds with the default configuration
+fn webauthn() -> Webauthn {
+    let origin = Url::parse(RP_ORIGIN).unwrap();
+    WebauthnBuilder::new(RP_ID, &origin)
+        .unwrap()
+        .rp_name("Ticket Desk")
+        .build()
+        .unwrap()
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn passkey_registers_and_signs_in() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = common::create_user(
+        &mut connection,
+        &organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    let webauthn = webauthn();
+    let origin = Url::parse(RP_ORIGIN).unwrap();
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new());
+
+    // registration, the state goes through the database between the two halves as in the routes
+    let (challenge, state) = webauthn
+        .start_passkey_registration(
+            WebauthnUuid::from_bytes(*user.uuid.as_bytes()),
+            &user.username,
+            &user.username,
+            None,
+        )
+        .unwrap();
+    let ceremony = PasskeyCeremony::create(
+        &mut connection,
+        &user.uuid,
+        Some("Work laptop"),
+        &state,
+        Duration::minutes(5),
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    let answer = authenticator
+        .do_registration(origin.clone(), challenge)
+        .unwrap();
+    let ceremony = PasskeyCeremony::take(&mut connection, &ceremony.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let state = ceremony
+        .to_state::<PasskeyRegistration>()
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let credential = webauthn
+        .finish_passkey_registration(&answer, &state)
+        .unwrap();
+    Passkey::create(&mut connection, &user.uuid, "Work laptop", &credential)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+
+    // sign in with the stored passkey
+    let stored = Passkey::find_by_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+    assert!(stored[0].last_used_at.is_none());
+    let credentials = stored
+        .iter()
+        .map(|passkey| passkey.to_credential().map_err(|e| e.to_string()).unwrap())
+        .collect::<Vec<_>>();
+    let (challenge, state) = webauthn.start_passkey_authentication(&credentials).unwrap();
+    let ceremony = PasskeyCeremony::create(
+        &mut connection,
+        &user.uuid,
+        None,
+        &state,
+        Duration::minutes(5),
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    let answer = authenticator
+        .do_authentication(origin.clone(), challenge)
+        .unwrap();
+    let ceremony = PasskeyCeremony::take(&mut connection, &ceremony.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let state = ceremony
+        .to_state::<PasskeyAuthentication>()
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let result = webauthn
+        .finish_passkey_authentication(&answer, &state)
+        .unwrap();
+    assert_eq!(result.cred_id(), credentials[0].cred_id());
+    let mut credential = credentials[0].clone();
+    credential.update_credential(&result);
+    Passkey::record_use(&mut connection, &credential)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let stored = Passkey::find_by_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(stored[0].last_used_at.is_some());
+
+    // the answer was for a challenge already taken, it cannot be replayed
+    assert!(
+        PasskeyCeremony::take(&mut connection, &ceremony.uuid.to_string())
+            .await
+            .is_err()
+    );
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn passkey_of_another_user_does_not_sign_in() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let webauthn = webauthn();
+    let origin = Url::parse(RP_ORIGIN).unwrap();
+    let mut credentials = Vec::new();
+    let mut authenticators = Vec::new();
+    for _ in 0..2 {
+        let user = common::create_user(
+            &mut connection,
+            &organisation,
+            &common::unique_email(),
+            UserRole::Agent,
+        )
+        .await;
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new());
+        let (challenge, state) = webauthn
+            .start_passkey_registration(
+                WebauthnUuid::from_bytes(*user.uuid.as_bytes()),
+                &user.username,
+                &user.username,
+                None,
+            )
+            .unwrap();
+        let answer = authenticator
+            .do_registration(origin.clone(), challenge)
+            .unwrap();
+        credentials.push(
+            webauthn
+                .finish_passkey_registration(&answer, &state)
+                .unwrap(),
+        );
+        authenticators.push(authenticator);
+    }
+
+    // the second authenticator holds no credential the challenge for the first user allows
+    let (challenge, _) = webauthn
+        .start_passkey_authentication(&credentials[..1])
+        .unwrap();
+    assert!(authenticators[1]
+        .do_authentication(origin, challenge)
+        .is_err());
+}
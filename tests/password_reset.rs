@@ -0,0 +1,168 @@
+mod common;
+
+use chrono::Duration;
+use our_application::models::password_reset::PasswordReset;
+use our_application::models::session::Session;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::routes::password_reset;
+use rocket::http::{Accept, ContentType, Status};
+use rocket::local::asynchronous::Client;
+use sqlx::PgConnection;
+
+const NEW_PASSWORD: &str = "gentle-Quartz-harbor-71-ember";
+const RESET_LIFETIME_MINUTES: i64 = 60;
+
+async fn client() -> Client {
+    let rocket = common::rocket(common::figment()).mount(
+        "/",
+        rocket::routes![password_reset::reset_form, password_reset::reset],
+    );
+    Client::tracked(rocket).await.expect("invalid rocket")
+}
+
+async fn user(connection: &mut PgConnection) -> User {
+    let organisation = common::create_organisation(connection).await;
+    common::create_user(
+        connection,
+        &organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await
+}
+
+async fn request_reset(connection: &mut PgConnection, user: &User, lifetime: Duration) -> String {
+    PasswordReset::create(connection, &user.uuid, lifetime)
+        .await
+        .map_err(|e| e.to_string())
+        .expect("cannot create password reset")
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn reset_tokens_work_once() {
+    let mut connection = common::connection().await;
+    let user = user(&mut connection).await;
+    let token = request_reset(
+        &mut connection,
+        &user,
+        Duration::minutes(RESET_LIFETIME_MINUTES),
+    )
+    .await;
+
+    // looking at the form does not use the token up
+    assert!(PasswordReset::find_valid(&mut connection, &token)
+        .await
+        .is_ok());
+    let reset = PasswordReset::consume(&mut connection, &token)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(reset.user_uuid, user.uuid);
+    assert!(PasswordReset::consume(&mut connection, &token)
+        .await
+        .is_err());
+    assert!(PasswordReset::find_valid(&mut connection, &token)
+        .await
+        .is_err());
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn expired_or_replaced_tokens_are_refused() {
+    let mut connection = common::connection().await;
+    let user = user(&mut connection).await;
+
+    let expired = request_reset(&mut connection, &user, Duration::seconds(-1)).await;
+    assert!(PasswordReset::find_valid(&mut connection, &expired)
+        .await
+        .is_err());
+    assert!(PasswordReset::consume(&mut connection, &expired)
+        .await
+        .is_err());
+
+    // asking again voids the link of the previous email
+    let first = request_reset(
+        &mut connection,
+        &user,
+        Duration::minutes(RESET_LIFETIME_MINUTES),
+    )
+    .await;
+    let second = request_reset(
+        &mut connection,
+        &user,
+        Duration::minutes(RESET_LIFETIME_MINUTES),
+    )
+    .await;
+    assert!(PasswordReset::consume(&mut connection, &first)
+        .await
+        .is_err());
+    assert!(PasswordReset::consume(&mut connection, &second)
+        .await
+        .is_ok());
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn reset_sets_the_password_and_ends_every_session() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = common::create_user(
+        &mut connection,
+        &organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    common::log_in(&mut connection, &user).await;
+    let token = request_reset(
+        &mut connection,
+        &user,
+        Duration::minutes(RESET_LIFETIME_MINUTES),
+    )
+    .await;
+    let client = client().await;
+
+    let form_url = format!("/password/reset/{}", token);
+    let authenticity_token = common::authenticity_token(&client, &organisation, &form_url).await;
+    let body = serde_urlencoded::to_string(&[
+        ("password", NEW_PASSWORD),
+        ("password_confirmation", NEW_PASSWORD),
+        ("authenticity_token", authenticity_token.as_str()),
+    ])
+    .unwrap();
+    let response = client
+        .post(form_url.clone())
+        .header(common::host(&organisation))
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    let changed = User::find(&mut connection, &organisation.uuid, &user.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_ne!(changed.password_hash, user.password_hash);
+    let sessions = Session::find_by_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(sessions.is_empty());
+
+    // the used link now sends back to asking for a new one
+    let response = client
+        .get(form_url)
+        .header(common::host(&organisation))
+        .header(Accept::HTML)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some("/password/forgot")
+    );
+}
@@ -0,0 +1,340 @@
+mod common;
+
+use chrono::{offset::Utc, Duration};
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::models::login_attempt::LoginAttempt;
+use our_application::models::organisation::Organisation;
+use our_application::models::our_date_time::OurDateTime;
+use our_application::models::session::Session;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::routes::session;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+const PASSWORD: &str = "gentle-Quartz-harbor-71-ember";
+// what the login answers to any failure, the username is not told to exist
+const INVALID_LOGIN_MESSAGE: &str = "Invalid username or password";
+// failed logins locking the account, as the login route counts them
+const LOCKOUT_THRESHOLD: i32 = 10;
+
+async fn client() -> Client {
+    let rocket = common::rocket(common::figment()).mount(
+        "/",
+        rocket::routes![session::login_form, session::login, session::logout],
+    );
+    Client::tracked(rocket).await.expect("invalid rocket")
+}
+
+async fn user_with_password(connection: &mut PgConnection, organisation: &Organisation) -> User {
+    let user = common::create_user(
+        connection,
+        organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    common::set_password(connection, organisation, &user, PASSWORD).await
+}
+
+async fn post_login<'c>(
+    client: &'c Client,
+    organisation: &Organisation,
+    username: &str,
+    password: &str,
+    next: &str,
+) -> LocalResponse<'c> {
+    let token = common::authenticity_token(client, organisation, "/login").await;
+    let body = serde_urlencoded::to_string(&[
+        ("username", username),
+        ("password", password),
+        ("next", next),
+        ("authenticity_token", token.as_str()),
+    ])
+    .unwrap();
+    client
+        .post("/login")
+        .header(common::host(organisation))
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await
+}
+
+fn location(response: &LocalResponse<'_>) -> Option<String> {
+    response.headers().get_one("Location").map(String::from)
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn password_login_starts_a_session_and_goes_next() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let response = post_login(
+        &client,
+        &organisation,
+        &user.username,
+        PASSWORD,
+        "/tickets/open",
+    )
+    .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response).as_deref(), Some("/tickets/open"));
+    assert!(client.cookies().get_private(LOGIN_COOKIE_NAME).is_some());
+    let sessions = Session::find_by_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(!sessions[0].persistent);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn login_never_redirects_off_site() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let response = post_login(
+        &client,
+        &organisation,
+        &user.username,
+        PASSWORD,
+        "//evil.example/phish",
+    )
+    .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response).as_deref(), Some("/tickets"));
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn login_without_the_csrf_token_is_refused() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let body = serde_urlencoded::to_string(&[
+        ("username", user.username.as_str()),
+        ("password", PASSWORD),
+        ("authenticity_token", "forged"),
+    ])
+    .unwrap();
+    let response = client
+        .post("/login")
+        .header(common::host(&organisation))
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response).as_deref(), Some("/login"));
+    assert!(client.cookies().get_private(LOGIN_COOKIE_NAME).is_none());
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn logout_ends_the_session() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+    post_login(&client, &organisation, &user.username, PASSWORD, "/tickets").await;
+
+    let token = common::authenticity_token(&client, &organisation, "/login").await;
+    let body = serde_urlencoded::to_string(&[("authenticity_token", token)]).unwrap();
+    let response = client
+        .post("/logout")
+        .header(common::host(&organisation))
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(location(&response).as_deref(), Some("/login"));
+    assert!(client.cookies().get_private(LOGIN_COOKIE_NAME).is_none());
+    let sessions = Session::find_by_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(sessions.is_empty());
+}
+
+async fn start_session(
+    connection: &mut PgConnection,
+    user: &User,
+    persistent: bool,
+    lifetime: Duration,
+) -> (Session, String) {
+    Session::create(connection, &user.uuid, None, None, persistent, lifetime)
+        .await
+        .map_err(|e| e.to_string())
+        .expect("cannot create session")
+}
+
+// as if the session was last used that long ago
+async fn leave_idle(connection: &mut PgConnection, session_uuid: &Uuid, idle: Duration) {
+    sqlx::query("UPDATE sessions SET last_seen_at = $1 WHERE uuid = $2")
+        .bind(OurDateTime(Utc::now() - idle))
+        .bind(session_uuid)
+        .execute(connection)
+        .await
+        .expect("cannot age session");
+}
+
+async fn is_active(connection: &mut PgConnection, token: &str, idle_timeout: Duration) -> bool {
+    Session::find_active(connection, token, idle_timeout)
+        .await
+        .is_ok()
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn idle_sessions_end_unless_remembered() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let idle_timeout = Duration::minutes(30);
+    let (session, token) = start_session(&mut connection, &user, false, Duration::hours(8)).await;
+    let (remembered, remembered_token) =
+        start_session(&mut connection, &user, true, Duration::days(30)).await;
+
+    leave_idle(&mut connection, &session.uuid, Duration::minutes(29)).await;
+    assert!(is_active(&mut connection, &token, idle_timeout).await);
+    leave_idle(&mut connection, &session.uuid, Duration::minutes(31)).await;
+    assert!(!is_active(&mut connection, &token, idle_timeout).await);
+
+    leave_idle(&mut connection, &remembered.uuid, Duration::days(3)).await;
+    assert!(is_active(&mut connection, &remembered_token, idle_timeout).await);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn no_session_outlives_its_lifetime_or_revocation() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let idle_timeout = Duration::minutes(30);
+
+    let (_, expired) = start_session(&mut connection, &user, true, Duration::seconds(-1)).await;
+    assert!(!is_active(&mut connection, &expired, idle_timeout).await);
+
+    let (_, revoked) = start_session(&mut connection, &user, false, Duration::hours(8)).await;
+    assert!(is_active(&mut connection, &revoked, idle_timeout).await);
+    Session::revoke_by_token(&mut connection, &revoked)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(!is_active(&mut connection, &revoked, idle_timeout).await);
+
+    let (_, other) = start_session(&mut connection, &user, false, Duration::hours(8)).await;
+    let ended = Session::revoke_all_for_user(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(ended, 1);
+    assert!(!is_active(&mut connection, &other, idle_timeout).await);
+}
+
+// message shown on the page the failed login went back to
+async fn refusal(
+    client: &Client,
+    organisation: &Organisation,
+    response: &LocalResponse<'_>,
+) -> String {
+    assert_eq!(response.status(), Status::SeeOther);
+    let back = location(response).expect("no redirect");
+    common::page(client, organisation, &back).await
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn wrong_password_and_unknown_user_get_the_same_answer() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let other_organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let wrong_password = post_login(
+        &client,
+        &organisation,
+        &user.username,
+        "not-the-password-at-all",
+        "/tickets",
+    )
+    .await;
+    let wrong_location = location(&wrong_password);
+    let html = refusal(&client, &organisation, &wrong_password).await;
+    assert!(html.contains(INVALID_LOGIN_MESSAGE));
+
+    let unknown_user =
+        post_login(&client, &organisation, "nobody-here", PASSWORD, "/tickets").await;
+    assert_eq!(location(&unknown_user), wrong_location);
+    let html = refusal(&client, &organisation, &unknown_user).await;
+    assert!(html.contains(INVALID_LOGIN_MESSAGE));
+    assert!(client.cookies().get_private(LOGIN_COOKIE_NAME).is_none());
+
+    // failures count against the username within its organisation only
+    let window = Duration::minutes(15);
+    let failures = LoginAttempt::count_failures_for_username(
+        &mut connection,
+        &organisation.uuid,
+        &user.username,
+        window,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    assert_eq!(failures, 1);
+    let failures = LoginAttempt::count_failures_for_username(
+        &mut connection,
+        &other_organisation.uuid,
+        &user.username,
+        window,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    assert_eq!(failures, 0);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn locked_account_answers_like_a_wrong_password() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = user_with_password(&mut connection, &organisation).await;
+    let client = client().await;
+
+    let mut counted = user.clone();
+    for _ in 0..LOCKOUT_THRESHOLD {
+        assert!(!counted.is_locked());
+        counted = User::record_failed_login(
+            &mut connection,
+            &organisation.uuid,
+            &user.uuid,
+            LOCKOUT_THRESHOLD,
+            Duration::minutes(30),
+        )
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    }
+    assert!(counted.is_locked());
+
+    // even the right password gets the generic answer, the lock is only told after a reset
+    let response = post_login(&client, &organisation, &user.username, PASSWORD, "/tickets").await;
+    let html = refusal(&client, &organisation, &response).await;
+    assert!(html.contains(INVALID_LOGIN_MESSAGE));
+    assert!(!html.contains("locked"));
+    assert!(client.cookies().get_private(LOGIN_COOKIE_NAME).is_none());
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use our_application::models::issues_reported::Issue;
+use our_application::models::organisation_token::OrganisationToken;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::routes::{issues_reported, scim, user};
+use rocket::http::{Accept, ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+
+// the same ticket number and contact number in both, as two real organisations would have
+const TICKET_NUMBER: i64 = 1;
+const CONTACT_NUMBER: i64 = 264811234567;
+
+async fn client() -> Client {
+    let rocket = common::rocket(common::figment()).mount(
+        "/",
+        rocket::routes![
+            issues_reported::get_issue,
+            issues_reported::delete_issue,
+            user::get_user,
+            scim::get_user,
+        ],
+    );
+    Client::untracked(rocket).await.expect("invalid rocket")
+}
+
+fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn lookups_find_nothing_of_another_organisation() {
+    let mut connection = common::connection().await;
+    let acme = common::create_organisation(&mut connection).await;
+    let globex = common::create_organisation(&mut connection).await;
+    let email = common::unique_email();
+    let acme_user = common::create_user(&mut connection, &acme, &email, UserRole::Admin).await;
+    let acme_issue =
+        common::create_issue(&mut connection, &acme, TICKET_NUMBER, CONTACT_NUMBER).await;
+    let globex_issue =
+        common::create_issue(&mut connection, &globex, TICKET_NUMBER, CONTACT_NUMBER).await;
+
+    let uuid = acme_issue.uuid.to_string();
+    assert!(Issue::find(&mut connection, &globex.uuid, &uuid)
+        .await
+        .is_err());
+    let found = Issue::find_by_ticket_number(&mut connection, &globex.uuid, TICKET_NUMBER)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(found.uuid, globex_issue.uuid);
+    let found = Issue::find_open_by_contact_number(&mut connection, &globex.uuid, CONTACT_NUMBER)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(found.map(|issue| issue.uuid), Some(globex_issue.uuid));
+    let open = Issue::find_open(&mut connection, &globex.uuid, None)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(open
+        .iter()
+        .all(|issue| issue.organisation_uuid == globex.uuid));
+
+    let uuid = acme_user.uuid.to_string();
+    assert!(User::find(&mut connection, &globex.uuid, &uuid)
+        .await
+        .is_err());
+    assert!(
+        User::find_by_username(&mut connection, &globex.uuid, &acme_user.username)
+            .await
+            .is_err()
+    );
+    assert!(User::find_by_email(&mut connection, &globex.uuid, &email)
+        .await
+        .is_err());
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn staff_routes_find_nothing_of_another_organisation() {
+    let mut connection = common::connection().await;
+    let acme = common::create_organisation(&mut connection).await;
+    let globex = common::create_organisation(&mut connection).await;
+    let acme_admin = common::create_user(
+        &mut connection,
+        &acme,
+        &common::unique_email(),
+        UserRole::Admin,
+    )
+    .await;
+    let globex_admin = common::create_user(
+        &mut connection,
+        &globex,
+        &common::unique_email(),
+        UserRole::Admin,
+    )
+    .await;
+    let acme_issue =
+        common::create_issue(&mut connection, &acme, TICKET_NUMBER, CONTACT_NUMBER).await;
+    let globex_issue =
+        common::create_issue(&mut connection, &globex, TICKET_NUMBER, CONTACT_NUMBER).await;
+    let session = common::log_in(&mut connection, &globex_admin).await;
+    let client = client().await;
+
+    // the admin sees the tickets of their own organisation
+    let response = client
+        .get(format!("/issues/{}", globex_issue.uuid))
+        .header(common::host(&globex))
+        .header(Accept::HTML)
+        .private_cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/issues/{}", acme_issue.uuid))
+        .header(common::host(&globex))
+        .header(Accept::HTML)
+        .private_cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get(format!("/users/{}", acme_admin.uuid))
+        .header(common::host(&globex))
+        .header(Accept::HTML)
+        .private_cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .delete(format!("/issues/{}", acme_issue.uuid))
+        .header(common::host(&globex))
+        .header(ContentType::Form)
+        .private_cookie(session.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert!(
+        Issue::find(&mut connection, &acme.uuid, &acme_issue.uuid.to_string())
+            .await
+            .is_ok()
+    );
+
+    // a session is no good on the host of another organisation
+    let acme_session = common::log_in(&mut connection, &acme_admin).await;
+    let response = client
+        .get(format!("/issues/{}", acme_issue.uuid))
+        .header(common::host(&globex))
+        .header(Accept::HTML)
+        .private_cookie(acme_session)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn api_tokens_act_for_their_organisation_only() {
+    let mut connection = common::connection().await;
+    let acme = common::create_organisation(&mut connection).await;
+    let globex = common::create_organisation(&mut connection).await;
+    let acme_user = common::create_user(
+        &mut connection,
+        &acme,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    let globex_user = common::create_user(
+        &mut connection,
+        &globex,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    let token = OrganisationToken::issue(&mut connection, &acme.uuid, "scim")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let alerts_token = OrganisationToken::issue(&mut connection, &acme.uuid, "alerts")
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    let client = client().await;
+
+    // the token names the organisation, no host needed
+    let response = client
+        .get(format!("/scim/v2/Users/{}", acme_user.uuid))
+        .header(bearer(&token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/scim/v2/Users/{}", globex_user.uuid))
+        .header(bearer(&token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get(format!("/scim/v2/Users/{}", globex_user.uuid))
+        .header(common::host(&globex))
+        .header(bearer(&token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // a token only opens the endpoints of its scope
+    let response = client
+        .get(format!("/scim/v2/Users/{}", acme_user.uuid))
+        .header(bearer(&alerts_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use our_application::models::recovery_code::RecoveryCode;
+use our_application::models::user_role::UserRole;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+async fn consume(connection: &mut PgConnection, user_uuid: &Uuid, code: &str) -> bool {
+    RecoveryCode::consume(connection, user_uuid, code)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap()
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn recovery_codes_work_once() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let user = common::create_user(
+        &mut connection,
+        &organisation,
+        &common::unique_email(),
+        UserRole::Agent,
+    )
+    .await;
+    let codes = RecoveryCode::regenerate(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(codes.len(), 10);
+
+    assert!(consume(&mut connection, &user.uuid, &codes[0].to_uppercase()).await);
+    assert!(!consume(&mut connection, &user.uuid, &codes[0]).await);
+    // the other codes are still good
+    assert!(consume(&mut connection, &user.uuid, &codes[1]).await);
+    assert!(!consume(&mut connection, &user.uuid, "00000-00000").await);
+
+    // regenerating throws the unused codes away
+    RecoveryCode::regenerate(&mut connection, &user.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(!consume(&mut connection, &user.uuid, &codes[2]).await);
+}
@@ -0,0 +1,352 @@
+mod common;
+
+use our_application::models::issues_reported::{Issue, UNASSIGNED_OWNER};
+use our_application::models::organisation::Organisation;
+use our_application::models::session::Session;
+use our_application::models::user::User;
+use our_application::models::user_role::UserRole;
+use our_application::models::user_status::UserStatus;
+use our_application::models::user_status_change::UserStatusChange;
+use our_application::routes::{session, user};
+use rocket::http::{ContentType, Cookie, Method, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+async fn client() -> Client {
+    let rocket = common::rocket(common::figment()).mount(
+        "/",
+        rocket::routes![session::login_form, user::change_status, user::delete_user],
+    );
+    Client::tracked(rocket).await.expect("invalid rocket")
+}
+
+async fn create_user(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    role: UserRole,
+) -> User {
+    common::create_user(connection, organisation, &common::unique_email(), role).await
+}
+
+async fn find(connection: &mut PgConnection, organisation: &Organisation, user: &User) -> User {
+    User::find(connection, &organisation.uuid, &user.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap()
+}
+
+// sends the form as the admin, with the csrf token the login page hands out
+async fn submit_as<'c>(
+    client: &'c Client,
+    organisation: &Organisation,
+    admin_session: &Cookie<'static>,
+    method: Method,
+    uri: String,
+    fields: &[(&str, &str)],
+) -> LocalResponse<'c> {
+    let token = common::authenticity_token(client, organisation, "/login").await;
+    let mut fields = fields.to_vec();
+    fields.push(("authenticity_token", token.as_str()));
+    client
+        .req(method, uri)
+        .header(common::host(organisation))
+        .header(ContentType::Form)
+        .private_cookie(admin_session.clone())
+        .body(serde_urlencoded::to_string(&fields).unwrap())
+        .dispatch()
+        .await
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn status_changes_are_recorded_and_end_sessions() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let admin = create_user(&mut connection, &organisation, UserRole::Admin).await;
+    let agent = create_user(&mut connection, &organisation, UserRole::Agent).await;
+    let admin_session = common::log_in(&mut connection, &admin).await;
+    common::log_in(&mut connection, &agent).await;
+    let client = client().await;
+
+    let response = submit_as(
+        &client,
+        &organisation,
+        &admin_session,
+        Method::Post,
+        format!("/users/status/{}", agent.uuid),
+        &[("status", "Suspended"), ("reason", "Laptop stolen")],
+    )
+    .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        find(&mut connection, &organisation, &agent).await.status,
+        UserStatus::Suspended
+    );
+    // a user kept away is logged out everywhere
+    let sessions = Session::find_by_user(&mut connection, &agent.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(sessions.is_empty());
+
+    submit_as(
+        &client,
+        &organisation,
+        &admin_session,
+        Method::Post,
+        format!("/users/status/{}", agent.uuid),
+        &[("status", "Active"), ("reason", "")],
+    )
+    .await;
+    assert_eq!(
+        find(&mut connection, &organisation, &agent).await.status,
+        UserStatus::Active
+    );
+
+    let history = UserStatusChange::find_by_user(&mut connection, &agent.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].from_status, UserStatus::Suspended);
+    assert_eq!(history[0].to_status, UserStatus::Active);
+    assert_eq!(history[0].reason, None);
+    assert_eq!(history[1].from_status, UserStatus::Active);
+    assert_eq!(history[1].to_status, UserStatus::Suspended);
+    assert_eq!(history[1].reason.as_deref(), Some("Laptop stolen"));
+    assert_eq!(history[1].changed_by, Some(admin.uuid));
+    assert_eq!(
+        history[1].changed_by_username.as_deref(),
+        Some(admin.username.as_str())
+    );
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn admins_cannot_change_their_own_status() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let admin = create_user(&mut connection, &organisation, UserRole::Admin).await;
+    let admin_session = common::log_in(&mut connection, &admin).await;
+    let client = client().await;
+
+    let response = submit_as(
+        &client,
+        &organisation,
+        &admin_session,
+        Method::Post,
+        format!("/users/status/{}", admin.uuid),
+        &[("status", "Locked"), ("reason", "")],
+    )
+    .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(
+        find(&mut connection, &organisation, &admin).await.status,
+        UserStatus::Active
+    );
+    let history = UserStatusChange::find_by_user(&mut connection, &admin.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(history.is_empty());
+}
+
+// a team of the organisation with the given members
+async fn create_team(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    members: &[&User],
+) -> Uuid {
+    let team_uuid = Uuid::new_v4();
+    sqlx::query("INSERT INTO teams (uuid, organisation_uuid, name) VALUES ($1, $2, $3)")
+        .bind(team_uuid)
+        .bind(organisation.uuid)
+        .bind(team_uuid.to_string())
+        .execute(&mut *connection)
+        .await
+        .expect("cannot create team");
+    for member in members {
+        sqlx::query("INSERT INTO team_members (team_uuid, user_uuid) VALUES ($1, $2)")
+            .bind(team_uuid)
+            .bind(member.uuid)
+            .execute(&mut *connection)
+            .await
+            .expect("cannot add team member");
+    }
+    team_uuid
+}
+
+// ticket claimed by the user, in the team queue when there is one
+async fn owned_issue(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    ticket_number: i64,
+    owner: &User,
+    team_uuid: Option<Uuid>,
+) -> Issue {
+    let issue = common::create_issue(connection, organisation, ticket_number, 264811234567).await;
+    sqlx::query("UPDATE issues_reported SET ticket_owner = $1, team_uuid = $2 WHERE uuid = $3")
+        .bind(&owner.username)
+        .bind(team_uuid)
+        .bind(issue.uuid)
+        .execute(&mut *connection)
+        .await
+        .expect("cannot assign issue");
+    issue
+}
+
+async fn owner(
+    connection: &mut PgConnection,
+    organisation: &Organisation,
+    issue: &Issue,
+) -> String {
+    Issue::find(connection, &organisation.uuid, &issue.uuid.to_string())
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap()
+        .ticket_owner
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn removed_users_hand_their_tickets_over() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let other_organisation = common::create_organisation(&mut connection).await;
+    let admin = create_user(&mut connection, &organisation, UserRole::Admin).await;
+    let leaving = create_user(&mut connection, &organisation, UserRole::Agent).await;
+    let inheritor = create_user(&mut connection, &organisation, UserRole::Agent).await;
+    let outsider = create_user(&mut connection, &other_organisation, UserRole::Agent).await;
+    let shared_team = create_team(&mut connection, &organisation, &[&leaving, &inheritor]).await;
+    let other_team = create_team(&mut connection, &organisation, &[&leaving]).await;
+    let no_team = owned_issue(&mut connection, &organisation, 1, &leaving, None).await;
+    let in_shared_team = owned_issue(
+        &mut connection,
+        &organisation,
+        2,
+        &leaving,
+        Some(shared_team),
+    )
+    .await;
+    let in_other_team = owned_issue(
+        &mut connection,
+        &organisation,
+        3,
+        &leaving,
+        Some(other_team),
+    )
+    .await;
+    let not_theirs = owned_issue(&mut connection, &organisation, 4, &admin, None).await;
+    // the same username may exist in another organisation, its tickets stay put
+    sqlx::query("UPDATE users SET username = $1 WHERE uuid = $2")
+        .bind(&leaving.username)
+        .bind(outsider.uuid)
+        .execute(&mut connection)
+        .await
+        .unwrap();
+    let outsider = find(&mut connection, &other_organisation, &outsider).await;
+    let elsewhere = owned_issue(&mut connection, &other_organisation, 1, &outsider, None).await;
+    common::log_in(&mut connection, &leaving).await;
+    let admin_session = common::log_in(&mut connection, &admin).await;
+    let client = client().await;
+
+    let inheritor_uuid = inheritor.uuid.to_string();
+    let response = submit_as(
+        &client,
+        &organisation,
+        &admin_session,
+        Method::Delete,
+        format!("/users/{}", leaving.uuid),
+        &[
+            ("inheritor", inheritor_uuid.as_str()),
+            ("reason", "Left the company"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some("/users"));
+
+    assert_eq!(
+        owner(&mut connection, &organisation, &no_team).await,
+        inheritor.username
+    );
+    assert_eq!(
+        owner(&mut connection, &organisation, &in_shared_team).await,
+        inheritor.username
+    );
+    // the inheritor is not in that team, the ticket goes back to the team queue
+    assert_eq!(
+        owner(&mut connection, &organisation, &in_other_team).await,
+        UNASSIGNED_OWNER
+    );
+    assert_eq!(
+        owner(&mut connection, &organisation, &not_theirs).await,
+        admin.username
+    );
+    assert_eq!(
+        owner(&mut connection, &other_organisation, &elsewhere).await,
+        leaving.username
+    );
+
+    // the record stays for the history, without anything that lets the user back in
+    let removed = find(&mut connection, &organisation, &leaving).await;
+    assert!(removed.deleted_at.is_some());
+    assert_eq!(removed.status, UserStatus::Deactivated);
+    assert_ne!(removed.email, leaving.email);
+    let sessions = Session::find_by_user(&mut connection, &leaving.uuid)
+        .await
+        .map_err(|e| e.to_string())
+        .unwrap();
+    assert!(sessions.is_empty());
+}
+
+#[rocket::async_test]
+#[ignore = "needs DATABASE_URL of a migrated database"]
+async fn tickets_only_go_to_an_active_user() {
+    let mut connection = common::connection().await;
+    let organisation = common::create_organisation(&mut connection).await;
+    let admin = create_user(&mut connection, &organisation, UserRole::Admin).await;
+    let leaving = create_user(&mut connection, &organisation, UserRole::Agent).await;
+    let suspended = create_user(&mut connection, &organisation, UserRole::Agent).await;
+    User::change_status(
+        &mut connection,
+        &organisation.uuid,
+        &suspended.uuid,
+        UserStatus::Suspended,
+        None,
+        None,
+    )
+    .await
+    .map_err(|e| e.to_string())
+    .unwrap();
+    let issue = owned_issue(&mut connection, &organisation, 1, &leaving, None).await;
+    let admin_session = common::log_in(&mut connection, &admin).await;
+    let client = client().await;
+
+    for inheritor in [&leaving, &suspended] {
+        let inheritor_uuid = inheritor.uuid.to_string();
+        let response = submit_as(
+            &client,
+            &organisation,
+            &admin_session,
+            Method::Delete,
+            format!("/users/{}", leaving.uuid),
+            &[("inheritor", inheritor_uuid.as_str()), ("reason", "")],
+        )
+        .await;
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(format!("/users/delete/{}", leaving.uuid).as_str())
+        );
+    }
+    // nothing moved and nobody was removed
+    assert_eq!(
+        owner(&mut connection, &organisation, &issue).await,
+        leaving.username
+    );
+    assert!(find(&mut connection, &organisation, &leaving)
+        .await
+        .deleted_at
+        .is_none());
+}